@@ -0,0 +1,13 @@
+//! Messages of the control protocols, which come straight from the network.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use playground::json::Value;
+
+fuzz_target!(|text: &str| {
+    if let Ok(value) = Value::parse(text) {
+        // What was read writes back to the same value.
+        assert_eq!(Value::parse(&value.to_string()).ok(), Some(value));
+    }
+});
@@ -0,0 +1,15 @@
+//! MIDI files read as groove templates.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use playground::groove::Template;
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(template) = Template::parse(bytes) {
+        for beat in [-1.0, 0.0, 0.25, 1e9] {
+            let (offset, velocity) = template.at(beat);
+            assert!(offset.is_finite() && (0.0..=1.0).contains(&velocity));
+        }
+    }
+});
@@ -0,0 +1,50 @@
+//! The text formats of the command line and the controls, and what the
+//! engine goes on to compute from the values they accept.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use playground::binaural::Position;
+use playground::humanize::HumanizeAmount;
+use playground::key::Key;
+use playground::meter::{Meter, TimeSignature};
+use playground::note::{get_note_frequency, Note};
+use playground::quantize::Quantize;
+use playground::scala;
+use playground::transport::LoopRegion;
+use playground::tuning::Tuning;
+
+fuzz_target!(|text: &str| {
+    if let Ok(note) = Note::parse(text) {
+        note.midi();
+        get_note_frequency(&note.transpose(12));
+    }
+    if let Ok(meter) = Meter::parse(text) {
+        for bar in [0, 1, 1000, usize::MAX] {
+            meter.bar_start(bar);
+            meter.signature(bar);
+        }
+    }
+    let _ = TimeSignature::parse(text);
+    if let Ok(quantize) = Quantize::parse(text) {
+        quantize.apply(1.3);
+    }
+    let _ = HumanizeAmount::parse(text);
+    let _ = LoopRegion::parse(text);
+    let _ = Position::parse(text);
+    if let Ok(key) = Key::parse(text) {
+        let note = key.name(&Note::parse("B-1+50c").unwrap());
+        assert!(Note::parse(&note).is_ok());
+    }
+    if let Ok(tuning) = scala::scale(text) {
+        tuning.frequency(-1000);
+        let _ = tuning.note_frequency(&Note::parse("H9").unwrap());
+    }
+    if let Ok(keyboard) = scala::keyboard(text) {
+        if let Ok(tuning) = Tuning::default().with_keyboard(keyboard) {
+            for key in 0..=127 {
+                let _ = tuning.key_frequency(key);
+            }
+        }
+    }
+});
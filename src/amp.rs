@@ -0,0 +1,124 @@
+//! A guitar amp for the live audio input, to practice along with the song: a
+//! preamp driven into clipping, the bass, middle and treble of a tone stack,
+//! and a cabinet, loaded as an impulse response or else a filtered one.
+
+use fundsp::hacker::*;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use crate::convolution::Response;
+use crate::param::{Param, ParamRegistry, Spec};
+
+/// Drive of the preamp in decibels, from clean to high gain.
+pub const GAIN: Spec = Spec::linear(0.0, 48.0, 20.0);
+/// Boost or cut in decibels of a band of the tone stack.
+pub const TONE: Spec = Spec::linear(-12.0, 12.0, 0.0);
+/// Level of the amp in the mix.
+pub const LEVEL: Spec = Spec {
+    random: (0.1, 0.4),
+    ..Spec::linear(0.0, 2.0, 0.5)
+};
+
+/// Centers of the bands of the tone stack.
+const BASS_HZ: f64 = 120.0;
+const MIDDLE_HZ: f64 = 800.0;
+const TREBLE_HZ: f64 = 3200.0;
+/// Below this the input is cut before the preamp, to keep it tight.
+const TIGHT_HZ: f64 = 80.0;
+/// Bias of the first stage, clipping one side harder than the other as
+/// tubes do, for the even harmonics.
+const BIAS: f64 = 0.2;
+
+/// The knobs of the amp, set by name as `amp_gain`, `amp_bass`,
+/// `amp_middle`, `amp_treble` and `amp_level`.
+pub struct Amp {
+    gain: Param,
+    bass: Param,
+    middle: Param,
+    treble: Param,
+    level: Param,
+    cabinet: Option<Response>,
+}
+
+impl Amp {
+    /// The amp, with the impulse response of a `cabinet` if there is one.
+    pub fn new(params: &mut ParamRegistry, cabinet: Option<Response>) -> Self {
+        Self {
+            gain: params.register("amp_gain", GAIN),
+            bass: params.register("amp_bass", TONE),
+            middle: params.register("amp_middle", TONE),
+            treble: params.register("amp_treble", TONE),
+            level: params.register("amp_level", LEVEL),
+            cabinet,
+        }
+    }
+
+    /// A unit from the mono input to the stereo output of the cabinet.
+    pub fn unit(&self) -> Net64 {
+        let decibels = |param: &Param| param.unit() >> map(|f: &Frame<f64, U1>| db_amp(f[0]));
+        let preamp = highpass_hz(TIGHT_HZ, FRAC_1_SQRT_2) * decibels(&self.gain)
+            >> shape_fn(|x| (x + BIAS).tanh() - BIAS.tanh())
+            >> dcblock()
+            >> shape_fn(|x| x.tanh());
+        let band = |frequency, gain| pass() | dc(frequency) | dc(FRAC_1_SQRT_2) | decibels(gain);
+        let tone = (band(BASS_HZ, &self.bass) >> lowshelf())
+            >> (band(MIDDLE_HZ, &self.middle) >> bell())
+            >> (band(TREBLE_HZ, &self.treble) >> highshelf());
+        let amp = Net64::wrap(Box::new(preamp >> tone * self.level.unit()));
+        match &self.cabinet {
+            Some(cabinet) => amp >> split::<U2>() >> Net64::wrap(Box::new(cabinet.unit())),
+            // The low resonance and the high rolloff of a closed cabinet.
+            None => {
+                amp >> Net64::wrap(Box::new(
+                    highpass_hz(90.0, FRAC_1_SQRT_2)
+                        >> bell_hz(110.0, 1.5, db_amp(4.0))
+                        >> lowpass_hz(4500.0, FRAC_1_SQRT_2)
+                        >> lowpass_hz(4500.0, FRAC_1_SQRT_2)
+                        >> split::<U2>(),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak of the left output of `amp` playing a sine at `amplitude`.
+    fn peak(amp: &Amp, amplitude: f64) -> f64 {
+        let mut unit = amp.unit();
+        unit.set_sample_rate(48000.0);
+        let mut output = [0.0; 2];
+        (0..9600)
+            .map(|i| {
+                let x = amplitude * sin(TAU * 220.0 * i as f64 / 48000.0);
+                unit.tick(&[x], &mut output);
+                output[0].abs()
+            })
+            .skip(4800)
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_preamp_clips_hard_playing() {
+        let mut params = ParamRegistry::default();
+        let amp = Amp::new(&mut params, None);
+        params.set("amp_gain", 40.0).unwrap();
+        let (soft, hard) = (peak(&amp, 0.1), peak(&amp, 1.0));
+        assert!(hard > soft && hard < 2.0 * soft);
+        assert!(hard < LEVEL.max);
+    }
+
+    #[test]
+    fn test_cabinet_response() {
+        let mut impulse = Wave64::new(1, 48000.0);
+        impulse.push(1.0);
+        let mut params = ParamRegistry::default();
+        let amp = Amp::new(&mut params, Some(Response::from_wave(&impulse)));
+        params.set("amp_gain", 0.0).unwrap();
+        params.set("amp_level", 1.0).unwrap();
+        assert!(params.set("amp_presence", 1.0).is_err());
+        // Clean, the amp passes quiet playing through about as is.
+        assert!((peak(&amp, 0.1) - 0.1).abs() < 0.02);
+    }
+}
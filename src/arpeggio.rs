@@ -0,0 +1,209 @@
+//! Arpeggiators, which break the accords of a pattern into runs of notes.
+
+use anyhow::bail;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::note::Note;
+use crate::pattern::{Pattern, Step};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    Up,
+    Down,
+    /// Alternately from the lowest and the highest note inward.
+    Converge,
+    /// From the middle outward, alternately up and down.
+    Diverge,
+    /// The lowest note alone, then the others together.
+    ChordBass,
+    /// A step up or down at random from the lowest note on.
+    RandomWalk,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arpeggio {
+    pub order: Order,
+    /// Octaves the notes are repeated in, from the one they are in up.
+    pub octaves: u32,
+    /// Beats from one note to the next.
+    pub rate: f64,
+    /// Part of `rate` that a note is held for, from 0 to 1.
+    pub gate: f64,
+    /// Seed of the random walk, which is the same every time it is played.
+    pub seed: u64,
+}
+
+impl Arpeggio {
+    /// Parses `ORDER[,OCTAVES[,RATE[,GATE]]]` with an order of `up`, `down`,
+    /// `converge`, `diverge`, `bass` or `random` and the rate in beats, such
+    /// as `converge,2,0.25,0.5`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut fields = value.split(',').map(str::trim);
+        let order = match fields.next().unwrap_or_default() {
+            "up" => Order::Up,
+            "down" => Order::Down,
+            "converge" => Order::Converge,
+            "diverge" => Order::Diverge,
+            "bass" => Order::ChordBass,
+            "random" => Order::RandomWalk,
+            order => bail!("unknown arpeggio order: {}", order),
+        };
+        let octaves = fields.next().map_or(Ok(1), str::parse)?;
+        let rate = fields.next().map_or(Ok(0.25), str::parse)?;
+        let gate = fields.next().map_or(Ok(0.5), str::parse)?;
+        if !(1..=4).contains(&octaves) {
+            bail!("arpeggios span 1 to 4 octaves");
+        }
+        if !(rate > 0.0 && rate <= 4.0) {
+            bail!("the arpeggio rate must be above 0 and at most 4 beats");
+        }
+        if !(gate > 0.0 && gate <= 1.0) {
+            bail!("the arpeggio gate must be above 0 and at most 1");
+        }
+        if fields.next().is_some() {
+            bail!("too many arpeggio fields: {}", value);
+        }
+        Ok(Self {
+            order,
+            octaves,
+            rate,
+            gate,
+            seed: 0,
+        })
+    }
+
+    /// The groups of notes played one after another for `notes`, for as many
+    /// as `length` strikes.
+    pub fn strikes(&self, notes: &[Note], length: usize) -> Vec<Vec<Note>> {
+        let mut pool: Vec<Note> = (0..self.octaves as i32)
+            .flat_map(|octave| notes.iter().map(move |note| note.transpose(12 * octave)))
+            .collect();
+        pool.sort_by_key(|note| note.midi());
+        if pool.is_empty() {
+            return vec![];
+        }
+        let last = pool.len() - 1;
+        let cycle: Vec<Vec<Note>> = match self.order {
+            Order::Up => pool.iter().map(|&note| vec![note]).collect(),
+            Order::Down => pool.iter().rev().map(|&note| vec![note]).collect(),
+            Order::Converge | Order::Diverge => {
+                let mut order: Vec<usize> = (0..pool.len())
+                    .map(|i| if i % 2 == 0 { i / 2 } else { last - i / 2 })
+                    .collect();
+                if self.order == Order::Diverge {
+                    order.reverse();
+                }
+                order.iter().map(|&i| vec![pool[i]]).collect()
+            }
+            Order::ChordBass => vec![vec![pool[0]], pool[1..].to_vec()],
+            Order::RandomWalk => {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                let mut index = 0usize;
+                return (0..length)
+                    .map(|_| {
+                        let note = vec![pool[index]];
+                        index = match (index, rng.gen::<bool>()) {
+                            (0, _) => 1.min(last),
+                            (i, _) if i == last => last - 1,
+                            (i, true) => i + 1,
+                            (i, false) => i - 1,
+                        };
+                        note
+                    })
+                    .collect();
+            }
+        };
+        cycle
+            .into_iter()
+            .filter(|notes| !notes.is_empty())
+            .cycle()
+            .take(length)
+            .collect()
+    }
+
+    /// `pattern` with the notes starting together played as arpeggios for as
+    /// long as the longest of them.
+    pub fn apply(&self, pattern: &Pattern) -> Pattern {
+        let mut beats: Vec<f64> = pattern.steps.iter().map(|step| step.beat).collect();
+        beats.sort_by(f64::total_cmp);
+        beats.dedup();
+        let mut steps = vec![];
+        for beat in beats {
+            let together = pattern.steps.iter().filter(|step| step.beat == beat);
+            let notes: Vec<Note> = together.clone().map(|step| step.note).collect();
+            let duration = together.map(|step| step.duration).fold(0.0, f64::max);
+            let length = (duration / self.rate).ceil() as usize;
+            for (i, strike) in self.strikes(&notes, length).into_iter().enumerate() {
+                let start = i as f64 * self.rate;
+                steps.extend(strike.into_iter().map(|note| Step {
+                    beat: beat + start,
+                    duration: (self.rate * self.gate).min(duration - start),
+                    note,
+                    ratchet: 1,
+                }));
+            }
+        }
+        Pattern {
+            length: pattern.length,
+            steps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{Accord, BaseNote::*};
+
+    fn keys(arpeggio: &str, length: usize) -> Vec<Vec<i32>> {
+        let notes = [Note::base(E), Note::base(C), Note::base(G)];
+        let arpeggio = Arpeggio::parse(arpeggio).unwrap();
+        let strikes = arpeggio.strikes(&notes, length);
+        strikes
+            .iter()
+            .map(|notes| notes.iter().map(|note| note.midi()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_orders() {
+        assert_eq!(keys("up", 4), [[60], [64], [67], [60]]);
+        assert_eq!(keys("down,2", 3), [[79], [76], [72]]);
+        assert_eq!(keys("converge,2", 6), [[60], [79], [64], [76], [67], [72]]);
+        assert_eq!(keys("diverge,2", 2), [[72], [67]]);
+        assert_eq!(keys("bass", 3), [vec![60], vec![64, 67], vec![60]]);
+        let walk = keys("random,2", 32);
+        assert_eq!(walk, keys("random,2", 32));
+        assert!(walk
+            .windows(2)
+            .all(|pair| pair[0] != pair[1] && (pair[0][0] - pair[1][0]).abs() <= 5));
+        assert!(Arpeggio::parse("sideways").is_err());
+        assert!(Arpeggio::parse("up,1,0").is_err());
+    }
+
+    #[test]
+    fn test_apply_fills_accord() {
+        let accord = Accord {
+            notes: vec![Note::base(C), Note::base(E)],
+        };
+        let pattern = Arpeggio::parse("up,1,0.5,0.5")
+            .unwrap()
+            .apply(&Pattern::accord(&accord, 2.0));
+        let steps: Vec<(f64, f64, i32)> = pattern
+            .steps
+            .iter()
+            .map(|step| (step.beat, step.duration, step.note.midi()))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (0.0, 0.25, 60),
+                (0.5, 0.25, 64),
+                (1.0, 0.25, 60),
+                (1.5, 0.25, 64)
+            ]
+        );
+        assert_eq!(pattern.length, 2.0);
+    }
+}
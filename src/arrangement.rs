@@ -0,0 +1,433 @@
+//! Songs built by chaining patterns on parallel tracks.
+
+use crate::arpeggio::Arpeggio;
+use crate::crossfade::Bank;
+use crate::groove::Template;
+use crate::instrument::Instrument;
+use crate::key::Key;
+use crate::meter::Meter;
+use crate::note::get_note_frequency;
+use crate::pattern::Pattern;
+use crate::schedule::{Action, Schedule};
+use crate::structure::Structure;
+use crate::strum::Strum;
+use crate::tuning::Tuning;
+use crate::velocity::{Accent, Curve};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    /// Index into `Arrangement::patterns`.
+    pub pattern: usize,
+    pub repeat: usize,
+    /// Level of the notes, from 0 to 1.
+    pub level: f64,
+}
+
+impl Section {
+    pub fn new(pattern: usize, repeat: usize) -> Self {
+        Self {
+            pattern,
+            repeat,
+            level: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+    pub instrument: Instrument,
+    /// Sections played one after another.
+    pub sections: Vec<Section>,
+    /// Sections of a second bank played along with `sections`, the first, and
+    /// crossfaded with them, if any.
+    pub b_sections: Option<Vec<Section>>,
+    /// Restarts the sections every this many beats for the whole song, so
+    /// that loops of different lengths phase against each other.
+    pub loop_length: Option<f64>,
+    /// Stereo position from -1 (left) to 1 (right).
+    pub pan: f64,
+    /// How notes starting together are staggered, if at all.
+    pub strum: Option<Strum>,
+    /// Arpeggiator playing the accords of the patterns, if any.
+    pub arpeggio: Option<Arpeggio>,
+    /// How the velocities of the notes are shaped.
+    pub velocity: Curve,
+    /// Steps of the patterns played louder than the others, if any.
+    pub accent: Option<Accent>,
+    /// Timing and velocity feel laid over the steps of the patterns, if any.
+    pub groove: Option<Template>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Arrangement {
+    pub patterns: Vec<Pattern>,
+    pub tracks: Vec<Track>,
+    /// Where the bars of the song fall.
+    pub meter: Meter,
+    /// Frequencies the notes of the patterns are played at.
+    pub tuning: Tuning,
+    /// Key signature that notes are spelled in.
+    pub key: Key,
+    /// Parts of the song that can be jumped to, if it is arranged in any.
+    pub structure: Structure,
+}
+
+impl Arrangement {
+    /// Adds a pattern and returns its index.
+    pub fn pattern(&mut self, pattern: Pattern) -> usize {
+        self.patterns.push(pattern);
+        self.patterns.len() - 1
+    }
+
+    /// Adds a track playing `sections`, given as (pattern, repeat count) pairs,
+    /// and returns its index.
+    pub fn track(&mut self, instrument: Instrument, sections: &[(usize, usize)]) -> usize {
+        self.tracks.push(Track {
+            instrument,
+            sections: sections
+                .iter()
+                .map(|&(pattern, repeat)| Section::new(pattern, repeat))
+                .collect(),
+            b_sections: None,
+            loop_length: None,
+            pan: 0.0,
+            strum: None,
+            arpeggio: None,
+            velocity: Curve::default(),
+            accent: None,
+            groove: None,
+        });
+        self.tracks.len() - 1
+    }
+
+    /// Adds a track cycling through `sections` every `length` beats while the song plays.
+    pub fn loop_track(
+        &mut self,
+        instrument: Instrument,
+        sections: &[(usize, usize)],
+        length: f64,
+    ) -> usize {
+        let track = self.track(instrument, sections);
+        self.tracks[track].loop_length = Some(length);
+        track
+    }
+
+    fn sections_length(&self, sections: &[Section]) -> f64 {
+        sections
+            .iter()
+            .map(|section| self.patterns[section.pattern].length * section.repeat as f64)
+            .sum()
+    }
+
+    fn track_length(&self, track: &Track) -> f64 {
+        let b = track.b_sections.as_deref().unwrap_or_default();
+        self.sections_length(&track.sections)
+            .max(self.sections_length(b))
+    }
+
+    /// Length of the longest track in beats. Looping tracks don't count,
+    /// they last as long as the others.
+    pub fn length(&self) -> f64 {
+        self.tracks
+            .iter()
+            .filter(|track| track.loop_length.is_none())
+            .map(|track| self.track_length(track))
+            .fold(0.0, f64::max)
+    }
+
+    /// The pattern that track `index` plays at song position `position`, and
+    /// the position within it.
+    pub fn pattern_at(&self, index: usize, position: f64) -> Option<(usize, f64)> {
+        let track = &self.tracks[index];
+        let mut position = match track.loop_length {
+            Some(length) => position.rem_euclid(length),
+            None => position,
+        };
+        for section in &track.sections {
+            let length = self.patterns[section.pattern].length;
+            let section_length = length * section.repeat as f64;
+            if position < section_length {
+                return Some((section.pattern, position.rem_euclid(length)));
+            }
+            position -= section_length;
+        }
+        None
+    }
+
+    /// Schedules the notes of every track that start in the song range
+    /// `from..to`, moved so that `from` falls on `offset` in the schedule.
+    pub fn schedule(&self, schedule: &mut Schedule, from: f64, to: f64, offset: f64) {
+        for (track, settings) in self.tracks.iter().enumerate() {
+            let banks = match settings.b_sections {
+                Some(_) => vec![Some(Bank::A), Some(Bank::B)],
+                None => vec![None],
+            };
+            for bank in banks {
+                let pass = |schedule: &mut Schedule, start, limit| {
+                    self.schedule_pass(schedule, track, bank, start, limit, from, to, offset)
+                };
+                match settings.loop_length {
+                    None => pass(schedule, 0.0, f64::INFINITY),
+                    Some(length) => {
+                        let mut start = (from / length).floor() * length;
+                        while start < to {
+                            pass(schedule, start, length);
+                            start += length;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Schedules one pass through the sections of track `index` in `bank`
+    /// starting at song position `start`, cut off after `limit` beats.
+    #[allow(clippy::too_many_arguments)]
+    fn schedule_pass(
+        &self,
+        schedule: &mut Schedule,
+        index: usize,
+        bank: Option<Bank>,
+        start: f64,
+        limit: f64,
+        from: f64,
+        to: f64,
+        offset: f64,
+    ) {
+        let track = &self.tracks[index];
+        let sections = match bank {
+            Some(Bank::B) => track.b_sections.as_deref().unwrap_or_default(),
+            _ => &track.sections,
+        };
+        let mut pattern_start = 0.0;
+        for section in sections {
+            let arpeggiated;
+            let pattern = match &track.arpeggio {
+                Some(arpeggio) => {
+                    arpeggiated = arpeggio.apply(&self.patterns[section.pattern]);
+                    &arpeggiated
+                }
+                None => &self.patterns[section.pattern],
+            };
+            let mut onsets: Vec<f64> = pattern.steps.iter().map(|step| step.beat).collect();
+            onsets.sort_by(f64::total_cmp);
+            onsets.dedup();
+            for _ in 0..section.repeat {
+                if start + pattern_start >= to || pattern_start >= limit {
+                    return;
+                }
+                for step in &pattern.steps {
+                    let frequency = match track.instrument {
+                        // Drum notes pick sounds of the kit rather than pitches.
+                        Instrument::Drums => Some(get_note_frequency(&step.note)),
+                        _ => self.tuning.note_frequency(&step.note),
+                    };
+                    // Notes the keyboard mapping of the tuning leaves out are silent.
+                    let Some(frequency) = frequency else {
+                        continue;
+                    };
+                    let (delay, velocity) = match track.strum {
+                        Some(strum) => {
+                            let together =
+                                pattern.steps.iter().filter(|other| other.beat == step.beat);
+                            let midi = step.note.midi();
+                            let lower = together.clone().filter(|other| other.note.midi() < midi);
+                            strum.note(lower.count(), together.count())
+                        }
+                        None => (0.0, 1.0),
+                    };
+                    let accent = match &track.accent {
+                        Some(accent) => {
+                            accent.velocity(onsets.partition_point(|&beat| beat < step.beat))
+                        }
+                        None => 1.0,
+                    };
+                    let (shift, feel) = match &track.groove {
+                        Some(groove) => groove.at(step.beat),
+                        None => (0.0, 1.0),
+                    };
+                    let velocity = track
+                        .velocity
+                        .apply(velocity * accent * feel * section.level);
+                    for (beat, duration) in step.hits() {
+                        let local = (pattern_start + beat + shift).max(0.0);
+                        let beat = start + local;
+                        if local < limit && beat >= from && beat < to {
+                            schedule.push(
+                                beat - from + offset,
+                                Action::Note {
+                                    instrument: track.instrument,
+                                    frequency,
+                                    duration,
+                                    track: Some(index),
+                                    velocity,
+                                    delay,
+                                    bank,
+                                },
+                            );
+                        }
+                    }
+                }
+                pattern_start += pattern.length;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{Accord, BaseNote::*, Note};
+
+    #[test]
+    fn test_sections_are_chained_and_repeated() {
+        let mut song = Arrangement::default();
+        let a = song.pattern(Pattern::melody(&[(Note::base(C), 2.0)]));
+        let b = song.pattern(Pattern::melody(&[
+            (Note::base(G), 1.0),
+            (Note::base(A), 1.0),
+        ]));
+        song.track(Instrument::Pluck, &[(a, 1), (b, 2)]);
+        assert_eq!(song.length(), 6.0);
+
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 0.0, f64::INFINITY, 0.0);
+        assert_eq!(beats(&mut schedule), [0.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_schedule_range_is_offset() {
+        let mut song = Arrangement::default();
+        let a = song.pattern(Pattern::melody(&[
+            (Note::base(C), 1.0),
+            (Note::base(D), 1.0),
+        ]));
+        song.track(Instrument::Pluck, &[(a, 4)]);
+
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 3.0, 5.0, 10.0);
+        assert_eq!(beats(&mut schedule), [10.0, 11.0]);
+    }
+
+    #[test]
+    fn test_loop_tracks_phase() {
+        let mut song = Arrangement::default();
+        let three = song.pattern(Pattern::melody(&[(Note::base(C), 1.0); 3]));
+        let four = song.pattern(Pattern::melody(&[(Note::base(C), 2.0); 2]));
+        song.loop_track(Instrument::Pluck, &[(three, 1)], 3.0);
+        song.loop_track(Instrument::Organ, &[(four, 1)], 2.0);
+        song.track(Instrument::Pluck, &[(four, 2)]);
+        assert_eq!(song.length(), 8.0);
+
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 4.0, 8.0, 0.0);
+        // The three beat loop, and the first note of the cut off two beat loop
+        // coinciding with the plain track.
+        assert_eq!(
+            beats(&mut schedule),
+            [0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0, 3.0]
+        );
+    }
+
+    #[test]
+    fn test_strummed_accord_is_staggered() {
+        let mut song = Arrangement::default();
+        let accord = Accord {
+            notes: vec![Note::base(G), Note::base(C), Note::base(E)],
+        };
+        let chord = song.pattern(Pattern::accord(&accord, 4.0));
+        let track = song.track(Instrument::Pluck, &[(chord, 1)]);
+        song.tracks[track].strum = Some(Strum::parse("20,down,12.7").unwrap());
+
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 0.0, 4.0, 0.0);
+        let mut notes = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            if let Action::Note {
+                frequency,
+                velocity,
+                delay,
+                ..
+            } = event.action
+            {
+                notes.push((frequency.round(), (delay * 1000.0).round(), velocity));
+            }
+        }
+        notes.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let velocities = (1.0, 1.0 - 0.1, 1.0 - 2.0 * 0.1);
+        assert_eq!(
+            notes,
+            [
+                (392.0, 0.0, velocities.0),
+                (330.0, 20.0, velocities.1),
+                (262.0, 40.0, velocities.2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accents_follow_steps() {
+        let mut song = Arrangement::default();
+        let run = Pattern::melody(&[(Note::base(C), 1.0); 4]);
+        let run = song.pattern(run);
+        let track = song.track(Instrument::Pluck, &[(run, 2)]);
+        song.tracks[track].accent = Some(Accent::parse("x..,63.5").unwrap());
+        song.tracks[track].velocity = Curve::Hard;
+
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 0.0, 8.0, 0.0);
+        let mut velocities = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            if let Action::Note { velocity, .. } = event.action {
+                velocities.push(velocity);
+            }
+        }
+        // The accents restart with every repetition of the pattern.
+        let soft = 0.25;
+        assert_eq!(velocities, [1.0, soft, soft, 1.0, 1.0, soft, soft, 1.0]);
+    }
+
+    #[test]
+    fn test_both_banks_are_scheduled() {
+        let mut song = Arrangement::default();
+        let a = song.pattern(Pattern::melody(&[(Note::base(C), 2.0)]));
+        let b = song.pattern(Pattern::melody(&[(Note::base(D), 1.0); 2]));
+        let track = song.track(Instrument::Pluck, &[(a, 1)]);
+        song.tracks[track].b_sections = Some(vec![Section::new(b, 2)]);
+        assert_eq!(song.length(), 4.0);
+
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 0.0, 4.0, 0.0);
+        let mut banks = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            if let Action::Note { bank, .. } = event.action {
+                banks.push((event.beat, bank));
+            }
+        }
+        banks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (a, b) = (Some(Bank::A), Some(Bank::B));
+        assert_eq!(banks, [(0.0, a), (0.0, b), (1.0, b), (2.0, b), (3.0, b)]);
+    }
+
+    #[test]
+    fn test_pattern_at_position() {
+        let mut song = Arrangement::default();
+        let a = song.pattern(Pattern::melody(&[(Note::base(C), 2.0)]));
+        let b = song.pattern(Pattern::melody(&[(Note::base(D), 3.0)]));
+        let track = song.track(Instrument::Pluck, &[(a, 2), (b, 1)]);
+        let bass = song.loop_track(Instrument::Pluck, &[(b, 1)], 3.0);
+        assert_eq!(song.pattern_at(track, 3.5), Some((a, 1.5)));
+        assert_eq!(song.pattern_at(track, 4.0), Some((b, 0.0)));
+        assert_eq!(song.pattern_at(track, 7.0), None);
+        assert_eq!(song.pattern_at(bass, 7.0), Some((b, 1.0)));
+    }
+
+    fn beats(schedule: &mut Schedule) -> Vec<f64> {
+        let mut beats = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            assert!(matches!(event.action, Action::Note { .. }));
+            beats.push(event.beat);
+        }
+        beats
+    }
+}
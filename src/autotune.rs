@@ -0,0 +1,245 @@
+//! Pitch correction of the live audio input, snapping the pitch detected in
+//! it to the nearest note of the key of the song, either at once for the hard
+//! stepped sound or gliding over to it for a natural one.
+
+use std::sync::Arc;
+
+use fundsp::hacker::*;
+
+use crate::key::Key;
+use crate::note::Note;
+use crate::param::{Param, ParamRegistry, Spec};
+use crate::pitch::{Yin, WINDOW};
+use crate::tuning::Tuning;
+
+/// Milliseconds the pitch takes to glide most of the way to the note it is
+/// corrected to, none snapping to it at once.
+pub const SPEED: Spec = Spec::linear(0.0, 500.0, 20.0);
+/// Level of the corrected input in the mix.
+pub const LEVEL: Spec = Spec {
+    random: (0.25, 0.75),
+    ..Spec::linear(0.0, 2.0, 1.0)
+};
+
+/// Samples between detections of the pitch.
+const HOP: usize = 256;
+/// Seconds of the grains the input is shifted in, long enough to hold a
+/// few periods of low voices.
+const GRAIN: f64 = 0.04;
+/// Octaves around middle C that notes are corrected to.
+const OCTAVES: std::ops::RangeInclusive<i32> = -5..=5;
+
+/// Pitch correction, set by name as `autotune_speed` and `autotune_level`.
+pub struct AutoTune {
+    speed: Param,
+    level: Param,
+    targets: Arc<Vec<f64>>,
+}
+
+impl AutoTune {
+    /// Corrects to the notes of `key` as `tuning` tunes them.
+    pub fn new(params: &mut ParamRegistry, key: Key, tuning: &Tuning) -> Self {
+        Self {
+            speed: params.register("autotune_speed", SPEED),
+            level: params.register("autotune_level", LEVEL),
+            targets: Arc::new(targets(key, tuning)),
+        }
+    }
+
+    /// A unit from the mono input to its corrected stereo output.
+    pub fn unit(&self) -> Net64 {
+        let corrector = An(Corrector::new(self.targets.clone(), DEFAULT_SR));
+        Net64::wrap(Box::new(
+            (pass() | self.speed.unit()) >> corrector * self.level.unit() >> split::<U2>(),
+        ))
+    }
+}
+
+/// Frequencies of the notes of `key` over `OCTAVES`, in ascending order.
+fn targets(key: Key, tuning: &Tuning) -> Vec<f64> {
+    let mut targets: Vec<f64> = OCTAVES
+        .flat_map(|octave| {
+            key.scale()
+                .into_iter()
+                .filter_map(move |base| tuning.note_frequency(&Note::new(base, octave)))
+        })
+        .collect();
+    targets.sort_by(f64::total_cmp);
+    targets.dedup();
+    targets
+}
+
+/// The one of `targets` nearest to `frequency` in pitch.
+fn snap(targets: &[f64], frequency: f64) -> f64 {
+    let above = targets.partition_point(|&target| target < frequency);
+    let candidates = [above.checked_sub(1), Some(above)];
+    candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|index| targets.get(index).copied())
+        .min_by(|a, b| {
+            (a / frequency)
+                .log2()
+                .abs()
+                .total_cmp(&(b / frequency).log2().abs())
+        })
+        .unwrap_or(frequency)
+}
+
+/// Detects the pitch of its first input and shifts it to the nearest target,
+/// the second input giving the speed in milliseconds. Shifted by two taps
+/// gliding through a delay line half a grain apart, each faded out as it
+/// wraps around.
+#[derive(Clone)]
+struct Corrector {
+    targets: Arc<Vec<f64>>,
+    sample_rate: f64,
+    yin: Yin,
+    /// The latest `WINDOW` samples, the last hop of them filled so far.
+    window: Vec<f64>,
+    fill: usize,
+    /// Ratio of the pitch corrected to and the one it is gliding to.
+    ratio: f64,
+    target: f64,
+    /// The delay line, its length a power of two, and where it is written next.
+    delay: Vec<f64>,
+    written: usize,
+    /// Position of the first tap in its grain, from 0 to 1.
+    phase: f64,
+}
+
+impl Corrector {
+    fn new(targets: Arc<Vec<f64>>, sample_rate: f64) -> Self {
+        Self {
+            targets,
+            sample_rate,
+            yin: Yin::default(),
+            window: vec![0.0; WINDOW],
+            fill: 0,
+            ratio: 1.0,
+            target: 1.0,
+            delay: vec![0.0; ((2.0 * GRAIN * sample_rate) as usize + 2).next_power_of_two()],
+            written: 0,
+            phase: 0.0,
+        }
+    }
+
+    fn grain(&self) -> f64 {
+        GRAIN * self.sample_rate
+    }
+
+    /// The delay line `delay` samples ago, interpolated.
+    fn tap(&self, delay: f64) -> f64 {
+        let mask = self.delay.len() - 1;
+        let position = self.written as f64 - delay;
+        let index = position.floor();
+        let t = position - index;
+        let index = index as isize as usize;
+        lerp(
+            self.delay[index & mask],
+            self.delay[index.wrapping_add(1) & mask],
+            t,
+        )
+    }
+}
+
+impl AudioNode for Corrector {
+    const ID: u64 = 0x4175_746f;
+    type Sample = f64;
+    type Inputs = U2;
+    type Outputs = U1;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.window.fill(0.0);
+        self.fill = 0;
+        self.ratio = 1.0;
+        self.target = 1.0;
+        self.delay.fill(0.0);
+        self.phase = 0.0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        // Set before playing starts, off the audio thread.
+        if sample_rate != self.sample_rate {
+            *self = Self::new(self.targets.clone(), sample_rate);
+        }
+    }
+
+    fn tick(&mut self, input: &Frame<f64, U2>) -> Frame<f64, U1> {
+        let x = input[0];
+        self.window[WINDOW - HOP + self.fill] = x;
+        self.fill += 1;
+        if self.fill == HOP {
+            self.fill = 0;
+            // Unpitched sounds, like most consonants, are left as they are.
+            self.target = match self.yin.detect(&self.window, self.sample_rate) {
+                Some(frequency) => snap(&self.targets, frequency) / frequency,
+                None => 1.0,
+            };
+            self.window.copy_within(HOP.., 0);
+        }
+        let speed = input[1] / 1000.0 * self.sample_rate;
+        let glide = if speed > 1.0 { speed.recip() } else { 1.0 };
+        self.ratio += (self.target - self.ratio) * glide;
+
+        let mask = self.delay.len() - 1;
+        self.delay[self.written & mask] = x;
+        self.written = self.written.wrapping_add(1);
+        let grain = self.grain();
+        self.phase = (self.phase + (1.0 - self.ratio) / grain).rem_euclid(1.0);
+        let other = (self.phase + 0.5) % 1.0;
+        let fade = |phase: f64| sin(PI * phase).powi(2);
+        let y = self.tap(self.phase * grain + 1.0) * fade(self.phase)
+            + self.tap(other * grain + 1.0) * fade(other);
+        [y].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.5 * self.grain()).propagate(input, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::BaseNote;
+
+    #[test]
+    fn test_snaps_to_key() {
+        let tuning = Tuning::default();
+        let c_major = targets(Key::default(), &tuning);
+        let a = tuning.note_frequency(&Note::new(BaseNote::A, 0)).unwrap();
+        assert!((snap(&c_major, 450.0) - a).abs() < 1e-9);
+        let c = tuning.note_frequency(&Note::new(BaseNote::C, 0)).unwrap();
+        assert!((snap(&c_major, 270.0) - c).abs() < 1e-9);
+        let d_major = targets(Key::parse("D").unwrap(), &tuning);
+        let cis = tuning.note_frequency(&Note::new(BaseNote::Cis, 0)).unwrap();
+        assert!((snap(&d_major, 270.0) - cis).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_corrects_pitch() {
+        let sample_rate = 48000.0;
+        let mut params = ParamRegistry::default();
+        let autotune = AutoTune::new(&mut params, Key::default(), &Tuning::default());
+        params.set("autotune_speed", 0.0).unwrap();
+        let mut unit = autotune.unit();
+        unit.set_sample_rate(sample_rate);
+        let mut output = [0.0; 2];
+        let corrected: Vec<f64> = (0..24000)
+            .map(|i| {
+                unit.tick(
+                    &[0.5 * sin(TAU * 450.0 * i as f64 / sample_rate)],
+                    &mut output,
+                );
+                output[0]
+            })
+            .collect();
+        let detected = Yin::default()
+            .detect(&corrected[24000 - WINDOW..], sample_rate)
+            .unwrap();
+        let cents = 1200.0 * (detected / 440.0).log2();
+        assert!(cents.abs() < 5.0, "{}", detected);
+    }
+}
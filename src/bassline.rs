@@ -0,0 +1,202 @@
+//! Basslines generated from the accords of a chord progression.
+
+use anyhow::bail;
+
+use crate::arrangement::Arrangement;
+use crate::chord::Chord;
+use crate::instrument::Instrument;
+use crate::note::{Accord, Note};
+use crate::pattern::{Pattern, Step};
+
+/// Octave the roots of basslines are played in.
+const OCTAVE: i32 = -1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    /// The root for the first half of a chord and its fifth for the second.
+    RootFifth,
+    /// A chord tone on every beat, leading into the next root from a semitone below.
+    Walking,
+    /// The root in eighths, alternating with the octave above.
+    OctavePump,
+}
+
+impl Style {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "root-fifth" => Ok(Style::RootFifth),
+            "walking" => Ok(Style::Walking),
+            "pump" => Ok(Style::OctavePump),
+            _ => bail!("unknown bassline style: {}", value),
+        }
+    }
+}
+
+/// The root of `accord` in the bass octave and the semitones of its chord
+/// tones above it, taking the lowest note as the root when it is no known chord.
+fn root_and_tones(accord: &Accord) -> Option<(Note, Vec<i32>)> {
+    let keys: Vec<u8> = accord
+        .notes
+        .iter()
+        .map(|note| note.midi().clamp(0, 127) as u8)
+        .collect();
+    if let Some(chord) = Chord::detect(&keys) {
+        let root = Note::new(chord.root, OCTAVE);
+        return Some((root, chord.quality.intervals().to_vec()));
+    }
+    let lowest = accord.notes.iter().min_by_key(|note| note.midi())?;
+    let root = Note::new(lowest.note, OCTAVE);
+    let mut tones: Vec<i32> = accord
+        .notes
+        .iter()
+        .map(|note| (note.midi() - lowest.midi()).rem_euclid(12))
+        .collect();
+    tones.sort_unstable();
+    tones.dedup();
+    Some((root, tones))
+}
+
+/// A bass pattern in `style` playing along with `progression`, given as
+/// accords and their durations in beats.
+pub fn bassline(progression: &[(Accord, f64)], style: Style) -> Pattern {
+    let roots: Vec<Option<(Note, Vec<i32>)>> = progression
+        .iter()
+        .map(|(accord, _)| root_and_tones(accord))
+        .collect();
+    let mut steps = vec![];
+    let mut beat = 0.0;
+    for (i, (_, duration)) in progression.iter().enumerate() {
+        let duration = *duration;
+        let Some((root, tones)) = &roots[i] else {
+            beat += duration;
+            continue;
+        };
+        let mut notes: Vec<(f64, f64, Note)> = vec![];
+        match style {
+            Style::RootFifth => {
+                let fifth = tones.iter().find(|&&tone| tone >= 6).copied().unwrap_or(7);
+                notes.push((0.0, duration / 2.0, *root));
+                notes.push((duration / 2.0, duration / 2.0, root.transpose(fifth)));
+            }
+            Style::Walking => {
+                let beats = (duration.floor() as usize).max(1);
+                let next = roots.get(i + 1).and_then(Option::as_ref);
+                for walk in 0..beats {
+                    let length = if walk + 1 == beats {
+                        duration - walk as f64
+                    } else {
+                        1.0
+                    };
+                    let note = match next {
+                        Some((next, _)) if walk + 1 == beats && walk > 0 => next.transpose(-1),
+                        _ => root.transpose(tones[walk % tones.len()]),
+                    };
+                    notes.push((walk as f64, length, note));
+                }
+            }
+            Style::OctavePump => {
+                let eighths = ((duration * 2.0).floor() as usize).max(1);
+                for eighth in 0..eighths {
+                    let note = root.transpose(12 * (eighth % 2) as i32);
+                    notes.push((eighth as f64 / 2.0, 0.5f64.min(duration), note));
+                }
+            }
+        }
+        steps.extend(notes.into_iter().map(|(start, length, note)| Step {
+            beat: beat + start,
+            duration: length,
+            note,
+            ratchet: 1,
+        }));
+        beat += duration;
+    }
+    Pattern {
+        length: beat,
+        steps,
+    }
+}
+
+impl Arrangement {
+    /// The accords that track `index` plays through its sections once, each
+    /// lasting until the next one starts.
+    pub fn progression(&self, index: usize) -> Vec<(Accord, f64)> {
+        let mut progression = vec![];
+        for section in &self.tracks[index].sections {
+            let pattern = &self.patterns[section.pattern];
+            let mut beats: Vec<f64> = pattern.steps.iter().map(|step| step.beat).collect();
+            beats.sort_by(f64::total_cmp);
+            beats.dedup();
+            for _ in 0..section.repeat {
+                for (i, &beat) in beats.iter().enumerate() {
+                    let end = beats.get(i + 1).copied().unwrap_or(pattern.length);
+                    let notes = pattern.steps.iter().filter(|step| step.beat == beat);
+                    let accord = Accord {
+                        notes: notes.map(|step| step.note).collect(),
+                    };
+                    progression.push((accord, end - beat));
+                }
+            }
+        }
+        progression
+    }
+
+    /// Adds a track playing a bassline in `style` along with the accords of
+    /// track `index`, and returns its index.
+    pub fn bass_track(&mut self, index: usize, style: Style, instrument: Instrument) -> usize {
+        let pattern = self.pattern(bassline(&self.progression(index), style));
+        self.track(instrument, &[(pattern, 1)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::BaseNote::{self, *};
+
+    fn accord(notes: &[BaseNote]) -> Accord {
+        Accord {
+            notes: notes.iter().map(|&note| Note::base(note)).collect(),
+        }
+    }
+
+    fn steps(pattern: &Pattern) -> Vec<(f64, i32)> {
+        pattern
+            .steps
+            .iter()
+            .map(|step| (step.beat, step.note.midi()))
+            .collect()
+    }
+
+    #[test]
+    fn test_styles() {
+        let progression = [(accord(&[C, E, G]), 4.0), (accord(&[A, C, F]), 4.0)];
+        let root_fifth = bassline(&progression, Style::RootFifth);
+        assert_eq!(
+            steps(&root_fifth),
+            [(0.0, 48), (2.0, 55), (4.0, 53), (6.0, 60)]
+        );
+        let walking = bassline(&progression, Style::Walking);
+        // C E G, and E leading a semitone up into the F.
+        assert_eq!(
+            steps(&walking)[..5],
+            [(0.0, 48), (1.0, 52), (2.0, 55), (3.0, 52), (4.0, 53)]
+        );
+        let pump = bassline(&progression[..1], Style::OctavePump);
+        assert_eq!(steps(&pump)[..3], [(0.0, 48), (0.5, 60), (1.0, 48)]);
+        assert_eq!(pump.length, 4.0);
+    }
+
+    #[test]
+    fn test_bass_track_follows_progression() {
+        let mut song = Arrangement::default();
+        let c = song.pattern(Pattern::accord(&accord(&[C, E, G]), 2.0));
+        let g = song.pattern(Pattern::accord(&accord(&[H, D, G]), 2.0));
+        let chords = song.track(Instrument::Organ, &[(c, 2), (g, 1)]);
+        assert_eq!(song.progression(chords).len(), 3);
+        let bass = song.bass_track(chords, Style::RootFifth, Instrument::Pluck);
+        let (pattern, _) = song.pattern_at(bass, 0.0).unwrap();
+        assert_eq!(song.patterns[pattern].length, 6.0);
+        assert_eq!(steps(&song.patterns[pattern])[4], (4.0, 55));
+        assert_eq!(song.length(), 6.0);
+    }
+}
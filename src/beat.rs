@@ -0,0 +1,264 @@
+//! The beat of audio, such as a live drummer or a track played into the
+//! input: onsets from how much the spectrum rises, the tempo from how they
+//! repeat, the beats from where they line up, and the bars from which of the
+//! beats are accented.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use fundsp::hacker::*;
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+
+/// Range of tempos found, in beats per minute.
+pub const MIN_BPM: f64 = 60.0;
+pub const MAX_BPM: f64 = 180.0;
+
+/// Samples between onset strengths, and samples of the spectrum each is
+/// taken over.
+const HOP: usize = 512;
+const FRAME: usize = 1024;
+/// Seconds of onsets that the tempo is found in, and that have to be heard
+/// before it is.
+const HISTORY_SECONDS: f64 = 8.0;
+const MIN_SECONDS: f64 = 4.0;
+/// Compression of the spectrum, so that quiet hits count too.
+const COMPRESSION: f64 = 100.0;
+/// Tempo most likely heard, the others less so the more octaves they are
+/// away, so that half or double the tempo is rarely taken instead.
+const LIKELY_BPM: f64 = 120.0;
+const LIKELY_OCTAVES: f64 = 1.0;
+/// How much of the accents of the beats of the bar carry over to the next.
+const ACCENT_DECAY: f64 = 0.9;
+
+/// A beat heard, at the tempo of those before it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beat {
+    pub bpm: f64,
+    /// Whether the beat is the first of a bar.
+    pub downbeat: bool,
+    /// Seconds before the end of the audio pushed that it fell.
+    pub ago: f64,
+}
+
+/// Follows the beats of the input.
+pub struct BeatTracker {
+    sample_rate: f64,
+    fft: Arc<dyn RealToComplex<f64>>,
+    /// The latest `FRAME` samples, the last hop of them filled so far.
+    frame: Vec<f64>,
+    fill: usize,
+    /// The frame windowed, and its spectrum.
+    windowed: Vec<f64>,
+    spectrum: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+    /// Compressed magnitudes of the spectrum of the previous frame.
+    previous: Vec<f64>,
+    /// Onset strengths of the history, one a hop and the latest last.
+    onsets: VecDeque<f64>,
+    /// Hops from the latest beat to the latest onset, at the latest hop.
+    since_beat: usize,
+    phase: usize,
+    /// Accents of the beats of the bar, and the beat of the bar counted last.
+    accents: Vec<f64>,
+    count: usize,
+}
+
+impl BeatTracker {
+    pub fn new(sample_rate: f64, beats_per_bar: usize) -> Self {
+        let fft = RealFftPlanner::new().plan_fft_forward(FRAME);
+        Self {
+            sample_rate,
+            frame: vec![0.0; FRAME],
+            fill: 0,
+            windowed: vec![0.0; FRAME],
+            spectrum: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            previous: vec![0.0; FRAME / 2 + 1],
+            onsets: VecDeque::new(),
+            since_beat: 0,
+            phase: 0,
+            accents: vec![0.0; max(beats_per_bar, 1)],
+            count: 0,
+        }
+    }
+
+    /// Follows the input on with `samples`, adding the beats heard in them
+    /// to `beats`.
+    pub fn push(&mut self, samples: &[f64], beats: &mut Vec<Beat>) {
+        for (i, &x) in samples.iter().enumerate() {
+            self.frame[FRAME - HOP + self.fill] = x;
+            self.fill += 1;
+            if self.fill == HOP {
+                self.fill = 0;
+                self.onset();
+                self.frame.copy_within(HOP.., 0);
+                if let Some(mut beat) = self.beat() {
+                    beat.ago += (samples.len() - 1 - i) as f64 / self.sample_rate;
+                    beats.push(beat);
+                }
+            }
+        }
+    }
+
+    /// Adds the onset strength of the latest frame, the sum of how much each
+    /// bin of its spectrum rose.
+    fn onset(&mut self) {
+        for (i, (windowed, &x)) in self.windowed.iter_mut().zip(&self.frame).enumerate() {
+            *windowed = x * (0.5 - 0.5 * cos(TAU * i as f64 / FRAME as f64));
+        }
+        self.fft
+            .process_with_scratch(&mut self.windowed, &mut self.spectrum, &mut self.scratch)
+            .unwrap();
+        let mut strength = 0.0;
+        for (bin, previous) in self.spectrum.iter().zip(self.previous.iter_mut()) {
+            let magnitude = (1.0 + COMPRESSION * bin.norm()).ln();
+            strength += (magnitude - *previous).max(0.0);
+            *previous = magnitude;
+        }
+        self.onsets.push_back(strength);
+        if self.onsets.len() as f64 > HISTORY_SECONDS * self.hops_per_second() {
+            self.onsets.pop_front();
+        }
+    }
+
+    fn hops_per_second(&self) -> f64 {
+        self.sample_rate / HOP as f64
+    }
+
+    /// Hops between beats, the lag the onsets repeat at most, if they do.
+    fn period(&self) -> Option<f64> {
+        let n = self.onsets.len();
+        let mean = self.onsets.iter().sum::<f64>() / n as f64;
+        let onsets: Vec<f64> = self.onsets.iter().map(|onset| onset - mean).collect();
+        let hops_per_minute = 60.0 * self.hops_per_second();
+        let lags = (hops_per_minute / MAX_BPM).floor() as usize
+            ..=(hops_per_minute / MIN_BPM).ceil() as usize;
+        let correlation = |lag: usize| {
+            let sum: f64 = (lag..n).map(|i| onsets[i] * onsets[i - lag]).sum();
+            let octaves = (hops_per_minute / lag as f64 / LIKELY_BPM).log2() / LIKELY_OCTAVES;
+            sum / (n - lag) as f64 * exp(-0.5 * octaves * octaves)
+        };
+        let correlations: Vec<(usize, f64)> = lags.map(|lag| (lag, correlation(lag))).collect();
+        let best = (1..correlations.len() - 1)
+            .max_by(|&a, &b| correlations[a].1.total_cmp(&correlations[b].1))?;
+        let (lag, b) = correlations[best];
+        if b <= 0.0 {
+            return None;
+        }
+        // Between lags, by a parabola through the peak and its neighbors.
+        let (a, c) = (correlations[best - 1].1, correlations[best + 1].1);
+        let curve = a - 2.0 * b + c;
+        let offset = if curve < 0.0 {
+            (a - c) / (2.0 * curve)
+        } else {
+            0.0
+        };
+        Some(lag as f64 + offset)
+    }
+
+    /// The beat that the latest hop passed, if it did.
+    fn beat(&mut self) -> Option<Beat> {
+        self.since_beat += 1;
+        if (self.onsets.len() as f64) < MIN_SECONDS * self.hops_per_second() {
+            return None;
+        }
+        let period = self.period()?;
+        // Hops back to the latest beat, where the onsets a period apart add
+        // up the most.
+        let n = self.onsets.len();
+        let score = |phase: usize| {
+            (0..)
+                .map(|k| phase + (k as f64 * period).round() as usize)
+                .take_while(|&back| back < n)
+                .map(|back| self.onsets[n - 1 - back])
+                .sum::<f64>()
+        };
+        let phase = (0..period.round() as usize)
+            .max_by(|&a, &b| score(a).total_cmp(&score(b)))
+            .unwrap_or(0);
+        // A beat has passed when the latest one is nearer than it was.
+        let passed = phase < self.phase && 2 * self.since_beat > period as usize;
+        self.phase = phase;
+        if !passed {
+            return None;
+        }
+        self.since_beat = phase;
+        // The beat before has been heard out by now, so its accent is
+        // counted to its beat of the bar.
+        let before = phase + period.round() as usize;
+        let strength = (before.saturating_sub(2)..=before + 2)
+            .filter(|&back| back < n)
+            .map(|back| self.onsets[n - 1 - back])
+            .fold(0.0, f64::max);
+        self.accents[self.count] = self.accents[self.count] * ACCENT_DECAY + strength;
+        self.count = (self.count + 1) % self.accents.len();
+        let accents = &self.accents;
+        let accented = (0..accents.len())
+            .max_by(|&a, &b| accents[a].total_cmp(&accents[b]))
+            .unwrap();
+        Some(Beat {
+            bpm: 60.0 * self.hops_per_second() / period,
+            downbeat: self.count == accented,
+            // Onsets are heard about the middle of their frame.
+            ago: (phase * HOP + FRAME / 2) as f64 / self.sample_rate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `seconds` of a drummer at `bpm`, hitting the first beat of a bar of
+    /// four harder.
+    fn drummer(bpm: f64, seconds: f64, sample_rate: f64) -> Vec<f64> {
+        let beat = 60.0 / bpm;
+        (0..(seconds * sample_rate) as usize)
+            .map(|i| {
+                let t = i as f64 / sample_rate;
+                let count = (t / beat).floor();
+                let since = t - count * beat;
+                let accent = if (count as usize).is_multiple_of(4) {
+                    1.0
+                } else {
+                    0.4
+                };
+                accent * exp(-since * 60.0) * sin(TAU * 180.0 * since)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_finds_tempo_and_downbeats() {
+        let sample_rate = 48000.0;
+        let mut tracker = BeatTracker::new(sample_rate, 4);
+        // Beats of the drummer that the beats heard fell on.
+        let (mut heard, mut pushed) = (vec![], 0);
+        for chunk in drummer(100.0, 16.0, sample_rate).chunks(1000) {
+            let mut beats = vec![];
+            tracker.push(chunk, &mut beats);
+            pushed += chunk.len();
+            for beat in beats {
+                assert!((beat.bpm - 100.0).abs() < 2.0, "{:?}", beat);
+                let count = (pushed as f64 / sample_rate - beat.ago) / 0.6;
+                assert!((count - count.round()).abs() < 0.05, "{}", count);
+                heard.push((count.round() as usize, beat.downbeat));
+            }
+        }
+        assert!(heard.len() > 10);
+        // Once a few bars are heard, the accented beats start them.
+        for &(count, downbeat) in heard.iter().rev().take(8) {
+            assert_eq!(downbeat, count.is_multiple_of(4), "{}", count);
+        }
+    }
+
+    #[test]
+    fn test_no_beats_in_silence() {
+        let mut tracker = BeatTracker::new(48000.0, 4);
+        let mut beats = vec![];
+        tracker.push(&[0.0; 48000 * 6], &mut beats);
+        assert!(beats.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Binaural placement for headphone listening. A spherical head model delays
+//! and shades the far ear, which is far simpler than measured HRTFs but
+//! places sources outside the head rather than between the ears.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+/// Radius of the model head in meters.
+const HEAD_RADIUS: f64 = 0.0875;
+const SPEED_OF_SOUND: f64 = 343.0;
+/// Delay line length, enough for the largest interaural delay up to 384 kHz.
+const DELAY_SAMPLES: usize = 256;
+/// Above this the head shadows the far ear.
+const SHADOW_HZ: f64 = 1500.0;
+/// Above this the outer ear colors sources by elevation.
+const PINNA_HZ: f64 = 7000.0;
+
+/// Direction of a source in degrees. Azimuth is 0 straight ahead and grows to
+/// the right, elevation is positive above the listener.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    pub azimuth: f64,
+    pub elevation: f64,
+}
+
+impl Position {
+    /// Parses `AZIMUTH[,ELEVATION]`, such as `-30` or `45,20`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (azimuth, elevation) = match value.split_once(',') {
+            Some((azimuth, elevation)) => (azimuth.trim().parse()?, elevation.trim().parse()?),
+            None => (value.trim().parse()?, 0.0),
+        };
+        if !(-180.0..=180.0).contains(&azimuth) || !(-90.0..=90.0).contains(&elevation) {
+            bail!("azimuth must be within -180...180 and elevation within -90...90");
+        }
+        Ok(Self { azimuth, elevation })
+    }
+
+    /// The frontal position matching a `pan()` position from -1 to 1.
+    pub fn from_pan(pan: f64) -> Self {
+        Self {
+            azimuth: pan.clamp(-1.0, 1.0).asin().to_degrees(),
+            elevation: 0.0,
+        }
+    }
+
+    /// How far the source is to the side, from -1 (left) to 1 (right). This is
+    /// also the closest `pan()` position.
+    pub fn lateral(self) -> f64 {
+        self.azimuth.to_radians().sin() * self.elevation.to_radians().cos()
+    }
+}
+
+/// A position shared with the voices placed at it, so that moving it moves
+/// the notes already sounding.
+#[derive(Clone)]
+pub struct Placement {
+    azimuth: Shared<f64>,
+    elevation: Shared<f64>,
+}
+
+impl Placement {
+    pub fn new(position: Position) -> Self {
+        Self {
+            azimuth: shared(position.azimuth),
+            elevation: shared(position.elevation),
+        }
+    }
+
+    pub fn set(&self, position: Position) {
+        self.azimuth.set_value(position.azimuth);
+        self.elevation.set_value(position.elevation);
+    }
+
+    pub fn position(&self) -> Position {
+        Position {
+            azimuth: self.azimuth.value(),
+            elevation: self.elevation.value(),
+        }
+    }
+
+    /// A mono in, stereo out unit placing its input at this position.
+    pub fn unit(&self) -> An<Binaural> {
+        An(Binaural {
+            placement: self.clone(),
+            delay: [0.0; DELAY_SAMPLES],
+            write: 0,
+            shadow: 0.0,
+            pinna: 0.0,
+            sample_rate: DEFAULT_SR,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Binaural {
+    placement: Placement,
+    /// Recent input, written at `write`.
+    delay: [f64; DELAY_SAMPLES],
+    write: usize,
+    /// One pole lowpass states of the far ear and the elevation cue.
+    shadow: f64,
+    pinna: f64,
+    sample_rate: f64,
+}
+
+impl Binaural {
+    /// Input `delay` samples ago, interpolated linearly.
+    fn delayed(&self, delay: f64) -> f64 {
+        let delay = delay.clamp(0.0, (DELAY_SAMPLES - 2) as f64);
+        let whole = delay as usize;
+        let at = |offset: usize| self.delay[(self.write + DELAY_SAMPLES - offset) % DELAY_SAMPLES];
+        lerp(at(whole), at(whole + 1), delay - whole as f64)
+    }
+
+    /// Coefficient of a one pole lowpass at `frequency`.
+    fn pole(&self, frequency: f64) -> f64 {
+        exp(-TAU * frequency / self.sample_rate)
+    }
+}
+
+impl AudioNode for Binaural {
+    const ID: u64 = 0x4272_6e6c;
+    type Sample = f64;
+    type Inputs = U1;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.delay = [0.0; DELAY_SAMPLES];
+        self.shadow = 0.0;
+        self.pinna = 0.0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn tick(&mut self, input: &Frame<f64, U1>) -> Frame<f64, U2> {
+        let position = self.placement.position();
+        let lateral = position.lateral();
+
+        // Sources above are brighter and those below darker.
+        let pole = self.pole(PINNA_HZ);
+        self.pinna = lerp(input[0], self.pinna, pole);
+        let brightness = 1.0 + 0.5 * position.elevation.to_radians().sin();
+        let x = self.pinna + brightness * (input[0] - self.pinna);
+
+        self.write = (self.write + 1) % DELAY_SAMPLES;
+        self.delay[self.write] = x;
+
+        // Woodworth's formula for the extra path around the head.
+        let angle = lateral.abs().asin();
+        let seconds = HEAD_RADIUS / SPEED_OF_SOUND * (angle + angle.sin());
+        let far = self.delayed(seconds * self.sample_rate);
+        let pole = self.pole(SHADOW_HZ);
+        self.shadow = lerp(far, self.shadow, pole);
+        let far = self.shadow + (1.0 - 0.9 * lateral.abs()) * (far - self.shadow);
+
+        if lateral >= 0.0 {
+            [far, x].into()
+        } else {
+            [x, far].into()
+        }
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_position() {
+        assert_eq!(
+            Position::parse("45,20").unwrap(),
+            Position {
+                azimuth: 45.0,
+                elevation: 20.0
+            }
+        );
+        assert_eq!(Position::parse("-30").unwrap().elevation, 0.0);
+        assert!(Position::parse("200").is_err());
+        assert!((Position::from_pan(0.5).lateral() - 0.5).abs() < 1e-9);
+    }
+
+    fn tick(unit: &mut An<Binaural>, x: f64) -> (f64, f64) {
+        let output = unit.tick(&Frame::from([x]));
+        (output[0], output[1])
+    }
+
+    #[test]
+    fn test_far_ear_is_late_and_quieter() {
+        let placement = Placement::new(Position::default());
+        let mut unit = placement.unit();
+        unit.set_sample_rate(48000.0);
+        let (left, right) = tick(&mut unit, 1.0);
+        assert_eq!(left, right);
+
+        unit.reset();
+        placement.set(Position::parse("90").unwrap());
+        assert_eq!(tick(&mut unit, 1.0), (0.0, 1.0));
+        let peak = (0..100)
+            .map(|_| tick(&mut unit, 0.0).0.abs())
+            .fold(0.0, f64::max);
+        assert!(peak > 0.0 && peak < 0.5);
+    }
+}
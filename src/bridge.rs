@@ -0,0 +1,188 @@
+//! Audio handed over from the stream of one output device to that of a
+//! second one, which calls back on a clock of its own.
+
+use fundsp::hacker::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Seconds buffered at least between the streams, so that the second one
+/// doesn't run dry between the callbacks of the first.
+pub const MARGIN_SECONDS: f64 = 0.01;
+/// Most seconds buffered, which the buffer is allocated for up front.
+const MAX_SECONDS: f64 = 1.0;
+
+/// Delays of the main and the second device that line up what they play,
+/// given the output latency of each in seconds. Audio bridged over to the
+/// second device is delayed by at least the margin it is buffered by.
+pub fn align(main_latency: f64, second_latency: f64, bridged: bool) -> (f64, f64) {
+    let margin = if bridged { MARGIN_SECONDS } else { 0.0 };
+    let second = (main_latency - second_latency).max(margin);
+    (second_latency + second - main_latency, second)
+}
+
+/// Stereo frames on their way from one stream to the other.
+#[derive(Clone)]
+pub struct Bridge {
+    frames: Arc<Mutex<VecDeque<(f64, f64)>>>,
+    capacity: usize,
+}
+
+impl Bridge {
+    /// A bridge between two streams running at `sample_rate`.
+    pub fn new(sample_rate: f64) -> Self {
+        let capacity = (MAX_SECONDS * sample_rate) as usize;
+        Self {
+            frames: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// A unit passing the stereo signal through and handing it over.
+    pub fn input(&self) -> An<BridgeIn> {
+        An(BridgeIn {
+            frames: self.frames.clone(),
+            capacity: self.capacity,
+        })
+    }
+
+    /// Moves the frames handed over so far to `frames`, for a thread that
+    /// isn't an audio stream.
+    pub fn take(&self, frames: &mut Vec<(f64, f64)>) {
+        frames.extend(self.frames.lock().unwrap().drain(..));
+    }
+
+    /// Hands over `frames` from a thread that isn't an audio stream.
+    pub fn put(&self, frames: &[(f64, f64)]) {
+        let mut handed = self.frames.lock().unwrap();
+        handed.extend(frames);
+        let over = handed.len().saturating_sub(self.capacity);
+        handed.drain(..over);
+    }
+
+    /// A unit playing the stereo signal handed over, `delay` seconds after
+    /// it was handed over at `sample_rate`.
+    pub fn output(&self, delay: f64, sample_rate: f64) -> An<BridgeOut> {
+        let target = (delay.min(MAX_SECONDS / 2.0) * sample_rate).max(1.0) as usize;
+        An(BridgeOut {
+            frames: self.frames.clone(),
+            target,
+            filling: true,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct BridgeIn {
+    frames: Arc<Mutex<VecDeque<(f64, f64)>>>,
+    capacity: usize,
+}
+
+impl AudioNode for BridgeIn {
+    const ID: u64 = 0x4272_6949;
+    type Sample = f64;
+    type Inputs = U2;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn tick(&mut self, input: &Frame<f64, U2>) -> Frame<f64, U2> {
+        // A frame is lost rather than waited for while the other side reads.
+        if let Ok(mut frames) = self.frames.try_lock() {
+            // Never grown past its capacity, which would allocate.
+            if frames.len() == self.capacity {
+                frames.pop_front();
+            }
+            frames.push_back((input[0], input[1]));
+        }
+        *input
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 2)
+    }
+}
+
+#[derive(Clone)]
+pub struct BridgeOut {
+    frames: Arc<Mutex<VecDeque<(f64, f64)>>>,
+    /// Frames kept buffered, which set the delay.
+    target: usize,
+    /// Whether it is silent until `target` frames are buffered, as it is at
+    /// the start and after running dry.
+    filling: bool,
+}
+
+impl AudioNode for BridgeOut {
+    const ID: u64 = 0x4272_694f;
+    type Sample = f64;
+    type Inputs = U0;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.filling = true;
+    }
+
+    fn tick(&mut self, _input: &Frame<f64, U0>) -> Frame<f64, U2> {
+        let Ok(mut frames) = self.frames.try_lock() else {
+            return [0.0, 0.0].into();
+        };
+        if self.filling {
+            self.filling = frames.len() < self.target;
+            if self.filling {
+                return [0.0, 0.0].into();
+            }
+        }
+        // The clocks drift apart, a stream running faster falls back in line.
+        while frames.len() > 2 * self.target {
+            frames.pop_front();
+        }
+        match frames.pop_front() {
+            Some((left, right)) => [left, right].into(),
+            None => {
+                self.filling = true;
+                [0.0, 0.0].into()
+            }
+        }
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align() {
+        // The slower device plays the faster one late by the difference.
+        let (main, second) = align(0.03, 0.01, false);
+        assert!((main - 0.0).abs() < 1e-12 && (second - 0.02).abs() < 1e-12);
+        let (main, second) = align(0.01, 0.03, false);
+        assert!((main - 0.02).abs() < 1e-12 && second == 0.0);
+        let (main, second) = align(0.01, 0.01, true);
+        assert!((main - MARGIN_SECONDS).abs() < 1e-12 && second == MARGIN_SECONDS);
+    }
+
+    #[test]
+    fn test_bridge_delays_by_target() {
+        let bridge = Bridge::new(1000.0);
+        let mut input = bridge.input();
+        let mut output = bridge.output(0.005, 1000.0);
+        let played: Vec<f64> = (0..10)
+            .map(|i| {
+                let passed = input.filter_stereo(i as f64, -(i as f64));
+                assert_eq!(passed, (i as f64, -(i as f64)));
+                output.get_stereo().0
+            })
+            .collect();
+        assert_eq!(played, [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        // Running dry, it fills up again before it plays on.
+        for _ in 0..10 {
+            output.get_stereo();
+        }
+        input.filter_stereo(10.0, 0.0);
+        assert_eq!(output.get_stereo(), (0.0, 0.0));
+    }
+}
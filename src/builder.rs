@@ -0,0 +1,161 @@
+//! Building the voices of notes off the thread that schedules them. The
+//! scheduler only hands over how to build each voice; a thread of its own
+//! builds it and starts it on the sequencer, which sizes it for the sample
+//! rate and allocates it there, so that scheduling never waits on building a
+//! voice and the audio thread only ever gets voices ready to play.
+
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::thread::JoinHandle;
+
+use fundsp::hacker::*;
+
+use crate::voice::VoicePool;
+
+/// Builds the voice of a note, given the pool that it plays in, with the
+/// seconds it keeps sounding after its note-off.
+pub type Build = Box<dyn FnOnce(&VoicePool) -> (Box<dyn AudioUnit64>, f64) + Send>;
+
+enum Job {
+    /// A note from `start` with its note-off at `end`, held until it is
+    /// released instead if it is on a key.
+    Note {
+        start: f64,
+        end: f64,
+        key: Option<u8>,
+        build: Build,
+    },
+    Release {
+        key: u8,
+        time: f64,
+        fade: f64,
+    },
+    /// Silences every voice from the time on.
+    Panic(f64),
+    Size(usize),
+    Bar(f64),
+}
+
+/// Plays the voices of notes through a pool, building them on a background
+/// thread that owns the pool and the sequencer they play on.
+pub struct Builder {
+    jobs: Sender<Job>,
+    thread: JoinHandle<()>,
+}
+
+impl Builder {
+    /// Starts the voices built on `sequencer` through `voices`.
+    pub fn spawn(mut sequencer: Sequencer64, mut voices: VoicePool) -> Self {
+        let (jobs, receiver) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            // Voices of the keys held down.
+            let mut held = HashMap::new();
+            for job in receiver {
+                match job {
+                    Job::Note {
+                        start,
+                        end,
+                        key,
+                        build,
+                    } => {
+                        let (unit, release) = build(&voices);
+                        let event = voices.note(&mut sequencer, start, end + release, unit);
+                        if let Some(key) = key {
+                            held.insert(key, event);
+                        }
+                    }
+                    Job::Release { key, time, fade } => {
+                        if let Some(event) = held.remove(&key) {
+                            voices.release(&mut sequencer, event, time, fade);
+                        }
+                    }
+                    Job::Panic(time) => {
+                        held.clear();
+                        voices.silence(&mut sequencer, time);
+                    }
+                    Job::Size(size) => voices.request_size(size),
+                    Job::Bar(time) => {
+                        if voices.apply_pending(&mut sequencer, time) {
+                            eprintln!("{} voices", voices.size());
+                        }
+                    }
+                }
+            }
+        });
+        Self { jobs, thread }
+    }
+
+    fn send(&self, job: Job) {
+        // The thread only ends once the builder is gone.
+        let _ = self.jobs.send(job);
+    }
+
+    /// Plays the voice that `build` builds from `start` with its note-off at
+    /// `end`, as `VoicePool::note` does.
+    pub fn note(&self, start: f64, end: f64, build: Build) {
+        self.send(Job::Note {
+            start,
+            end,
+            key: None,
+            build,
+        });
+    }
+
+    /// Plays the voice that `build` builds from `start` until `release` is
+    /// called with `key`.
+    pub fn hold(&self, key: u8, start: f64, build: Build) {
+        self.send(Job::Note {
+            start,
+            end: f64::INFINITY,
+            key: Some(key),
+            build,
+        });
+    }
+
+    /// Fades out the voice held on `key` over `fade` seconds from `time`.
+    pub fn release(&self, key: u8, time: f64, fade: f64) {
+        self.send(Job::Release { key, time, fade });
+    }
+
+    /// Silences every voice quickly from `time` on, those held down and
+    /// those handed over that haven't started yet too.
+    pub fn panic(&self, time: f64) {
+        self.send(Job::Panic(time));
+    }
+
+    /// Resizes the pool at the next bar, as `VoicePool::request_size` does.
+    pub fn request_size(&self, size: usize) {
+        self.send(Job::Size(size));
+    }
+
+    /// Applies a pending size of the pool at a bar at `time`.
+    pub fn bar(&self, time: f64) {
+        self.send(Job::Bar(time));
+    }
+
+    /// Waits for the voices handed over so far to be started.
+    pub fn finish(self) {
+        drop(self.jobs);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starts_built_voices() {
+        let mut sequencer = Sequencer64::new(false, 1);
+        let mut backend = sequencer.backend();
+        let builder = Builder::spawn(sequencer, VoicePool::new(4));
+        builder.note(0.0, 0.5, Box::new(|_| (Box::new(dc(0.5)), 0.5)));
+        builder.hold(60, 0.0, Box::new(|_| (Box::new(dc(0.25)), 0.0)));
+        builder.release(60, 0.5, 0.01);
+        builder.finish();
+        let mut played = (0..(0.7 * DEFAULT_SR) as usize).map(|_| backend.get_mono());
+        // Both until the held one is released.
+        assert!((played.nth((0.1 * DEFAULT_SR) as usize).unwrap() - 0.75).abs() < 1e-6);
+        assert!((played.last().unwrap() - 0.5).abs() < 1e-6);
+    }
+}
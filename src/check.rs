@@ -0,0 +1,82 @@
+//! Modes for checking devices and rooms, playing test signals through the
+//! same output path as songs.
+
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use cpal::{FromSample, SizedSample};
+use fundsp::hacker::*;
+
+use crate::output;
+use playground::dither::Dither;
+use playground::signal::{channel_at, channel_bursts, channel_hz, Signal};
+
+/// Time between checks for the next channel to announce.
+const POLL: Duration = Duration::from_millis(20);
+
+/// Plays `signal` on every channel of `device` until enter is pressed.
+pub fn test_tone<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    dither: Dither,
+    signal: Signal,
+) -> Result<(), anyhow::Error>
+where
+    T: SizedSample + FromSample<f64>,
+{
+    // The stereo main mix, and the click left silent.
+    let mut net = Net64::new(0, 3);
+    let id = net.push(signal.unit());
+    net.connect_output(id, 0, 0);
+    net.connect_output(id, 0, 1);
+    net.set_sample_rate(config.sample_rate.0 as f64);
+    let _stream = output::play::<T>(device, config, None, None, vec![], dither, net.backend())?;
+    eprintln!("playing {:?}, press enter to stop", signal);
+    std::io::stdin().read_line(&mut String::new())?;
+    Ok(())
+}
+
+/// Identifies every channel of `device` in turn, with bursts at a pitch of
+/// its own, announcing each as it plays until enter is pressed.
+pub fn channel_check<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    dither: Dither,
+) -> Result<(), anyhow::Error>
+where
+    T: SizedSample + FromSample<f64>,
+{
+    let channels = config.channels as usize;
+    if 3 + channels > output::MAX_OUTPUTS {
+        bail!(
+            "at most {} channels can be checked",
+            output::MAX_OUTPUTS - 3
+        );
+    }
+    // The main mix and the click left silent, and each channel on its own.
+    let mut net = Net64::new(0, 3 + channels);
+    for channel in 0..channels {
+        let id = net.push(channel_bursts(channel, channels));
+        net.connect_output(id, 0, 3 + channel);
+    }
+    net.set_sample_rate(config.sample_rate.0 as f64);
+    let cv = (0..channels).collect();
+    let _stream = output::play::<T>(device, config, None, None, cv, dither, net.backend())?;
+    let start = Instant::now();
+
+    let (sender, stop) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = std::io::stdin().read_line(&mut String::new());
+        let _ = sender.send(());
+    });
+    eprintln!("checking {} channels, press enter to stop", channels);
+    let mut announced = None;
+    while stop.recv_timeout(POLL).is_err() {
+        let channel = channel_at(start.elapsed().as_secs_f64(), channels);
+        if announced != Some(channel) {
+            eprintln!("channel {} at {:.0} Hz", channel + 1, channel_hz(channel));
+            announced = Some(channel);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! Names of chords, and recognizing them from the keys played.
+
+use crate::key::Key;
+use crate::note::{Accord, BaseNote, Note};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+    Major6,
+    Minor6,
+}
+
+impl Quality {
+    /// Qualities in the order they are preferred when notes fit several.
+    pub const ALL: [Quality; 13] = [
+        Quality::Major,
+        Quality::Minor,
+        Quality::Dominant7,
+        Quality::Major7,
+        Quality::Minor7,
+        Quality::HalfDiminished7,
+        Quality::Diminished7,
+        Quality::Major6,
+        Quality::Minor6,
+        Quality::Diminished,
+        Quality::Augmented,
+        Quality::Sus4,
+        Quality::Sus2,
+    ];
+
+    /// Semitones of the chord tones above the root, in ascending order.
+    pub fn intervals(self) -> &'static [i32] {
+        match self {
+            Quality::Major => &[0, 4, 7],
+            Quality::Minor => &[0, 3, 7],
+            Quality::Diminished => &[0, 3, 6],
+            Quality::Augmented => &[0, 4, 8],
+            Quality::Sus2 => &[0, 2, 7],
+            Quality::Sus4 => &[0, 5, 7],
+            Quality::Dominant7 => &[0, 4, 7, 10],
+            Quality::Major7 => &[0, 4, 7, 11],
+            Quality::Minor7 => &[0, 3, 7, 10],
+            Quality::HalfDiminished7 => &[0, 3, 6, 10],
+            Quality::Diminished7 => &[0, 3, 6, 9],
+            Quality::Major6 => &[0, 4, 7, 9],
+            Quality::Minor6 => &[0, 3, 7, 9],
+        }
+    }
+
+    /// Chord symbol following the root, such as `m7` for minor seventh.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Quality::Major => "",
+            Quality::Minor => "m",
+            Quality::Diminished => "dim",
+            Quality::Augmented => "aug",
+            Quality::Sus2 => "sus2",
+            Quality::Sus4 => "sus4",
+            Quality::Dominant7 => "7",
+            Quality::Major7 => "maj7",
+            Quality::Minor7 => "m7",
+            Quality::HalfDiminished7 => "m7b5",
+            Quality::Diminished7 => "dim7",
+            Quality::Major6 => "6",
+            Quality::Minor6 => "m6",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chord {
+    pub root: BaseNote,
+    pub quality: Quality,
+    /// Chord tone in the bass, 0 for the root, 1 for the first inversion and so on.
+    pub inversion: usize,
+}
+
+impl Chord {
+    /// The chord that MIDI `keys` form, ignoring doubled notes. A root in the
+    /// bass is preferred, so that C E G A is C6 rather than Am7/C.
+    pub fn detect(keys: &[u8]) -> Option<Self> {
+        let bass = *keys.iter().min()? as i32;
+        let mut classes: Vec<i32> = keys.iter().map(|&key| key as i32 % 12).collect();
+        classes.sort_unstable();
+        classes.dedup();
+        let roots = std::iter::once(bass % 12).chain(classes.iter().copied());
+        for root in roots {
+            for quality in Quality::ALL {
+                let mut tones: Vec<i32> = quality
+                    .intervals()
+                    .iter()
+                    .map(|interval| (root + interval) % 12)
+                    .collect();
+                tones.sort_unstable();
+                if tones == classes {
+                    let bass_interval = (bass - root).rem_euclid(12);
+                    return Some(Self {
+                        root: BaseNote::ALL[root as usize],
+                        quality,
+                        inversion: quality
+                            .intervals()
+                            .iter()
+                            .position(|&interval| interval == bass_interval)
+                            .unwrap(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The notes of the chord from its bass up, with the root in `octave`.
+    pub fn accord(&self, octave: i32) -> Accord {
+        let root = Note::new(self.root, octave);
+        let intervals = self.quality.intervals();
+        Accord {
+            notes: (self.inversion..self.inversion + intervals.len())
+                .map(|i| {
+                    // Tones below the bass move up an octave.
+                    let octave = (i / intervals.len()) as i32 * 12;
+                    root.transpose(intervals[i % intervals.len()] + octave)
+                })
+                .collect(),
+        }
+    }
+
+    /// Chord symbol spelled in `key`, with the bass after a slash when
+    /// inverted, such as `Es` or `Am/C`.
+    pub fn name(&self, key: &Key) -> String {
+        let mut name = format!("{}{}", key.spell(self.root), self.quality.symbol());
+        if self.inversion > 0 {
+            let bass = Note::base(self.root).transpose(self.quality.intervals()[self.inversion]);
+            name = format!("{}/{}", name, key.spell(bass.note));
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_chords() {
+        let chord = |keys: &[u8]| Chord::detect(keys).map(|chord| chord.name(&Key::default()));
+        assert_eq!(chord(&[60, 64, 67]).as_deref(), Some("C"));
+        assert_eq!(chord(&[64, 67, 72, 76]).as_deref(), Some("C/E"));
+        assert_eq!(chord(&[72, 57, 64]).as_deref(), Some("Am"));
+        assert_eq!(chord(&[55, 59, 62, 65]).as_deref(), Some("G7"));
+        assert_eq!(chord(&[65, 62, 59, 67]).as_deref(), Some("G7/H"));
+        assert_eq!(chord(&[60, 64, 67, 69]).as_deref(), Some("C6"));
+        assert_eq!(chord(&[57, 60, 64, 67]).as_deref(), Some("Am7"));
+        assert_eq!(chord(&[60, 62]), None);
+        assert_eq!(chord(&[]), None);
+        let flats = Key::parse("Es").unwrap();
+        let chord = Chord::detect(&[58, 62, 65, 68]).unwrap();
+        assert_eq!(chord.name(&flats), "B7");
+        assert_eq!(chord.quality, Quality::Dominant7);
+    }
+
+    #[test]
+    fn test_accord_of_inversion() {
+        let chord = Chord::detect(&[64, 67, 72]).unwrap();
+        assert_eq!(chord.inversion, 1);
+        let keys: Vec<i32> = chord
+            .accord(0)
+            .notes
+            .iter()
+            .map(|note| note.midi())
+            .collect();
+        assert_eq!(keys, [64, 67, 72]);
+    }
+}
@@ -0,0 +1,344 @@
+//! Runtime commands typed on stdin while the song is playing.
+
+use anyhow::bail;
+use std::io::BufRead;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use crate::keys::Piano;
+use crate::midi::Message;
+use crate::settings::{parse_bpm, parse_track, parse_voices, parse_width};
+use playground::binaural::Position;
+use playground::drums::Drum;
+use playground::evolve::parse_rating;
+use playground::note::Note;
+use playground::preset::Side;
+use playground::transport::LoopRegion;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Resize the voice pool at the next bar.
+    Voices(usize),
+    /// Loop a range of bars, or stop looping.
+    Loop(Option<LoopRegion>),
+    /// Continue at the next bar from the part of the song named, such as
+    /// `chorus` or `3`.
+    Jump(String),
+    /// Change the tempo right away.
+    Bpm(f64),
+    /// Tap tempo, timestamped when the line was read.
+    Tap(Instant),
+    /// A pulse of the clock on the trigger input, timestamped when it was read.
+    Clock(Instant),
+    /// A beat heard on the input at a tempo, and whether it starts a bar,
+    /// timestamped when it fell.
+    Beat(f64, bool, Instant),
+    /// A hit of a pad playing a drum at a velocity from 0 to 1, timestamped
+    /// when it was read.
+    Hit(Drum, f64, Instant),
+    /// Play and record a note, timestamped when the line was read.
+    Play(Note, Instant),
+    /// Start or stop recording played notes into the song.
+    Record(bool),
+    /// A message from the MIDI input, timestamped when it was read.
+    Midi(Message, Instant),
+    /// The jam partner starting a song bar at a tempo, timestamped when it
+    /// arrived.
+    Partner(usize, f64, Instant),
+    Looper(LooperCommand),
+    /// Set a parameter by name, such as `width`.
+    Set(String, f64),
+    /// Move the crossfader between the banks of tracks, from 0 to 1.
+    Crossfade(f64),
+    /// Move the crossfader over to the other bank at the next bar.
+    Switch,
+    /// Switch between the two versions of the parameters compared, the
+    /// first time keeping them as they are as A and tweaking a copy as B.
+    Compare,
+    /// Play one of the versions compared, ending the comparison.
+    Keep(Side),
+    /// Randomize the parameters that aren't locked, of those whose names
+    /// start with the prefix, such as `amp_`, or of all with none given.
+    Randomize(String),
+    /// Lock a parameter against randomizing or unlock it.
+    Lock(String),
+    /// Save the values of the parameters to a preset file.
+    Preset(String),
+    /// Morph between the parameters of two presets, or a saved project
+    /// each, from the first at 0.
+    MorphBetween(String, String),
+    /// Turn the morph knob, from 0 to 1.
+    Morph(f64),
+    /// Start evolving the parameters that aren't locked, by rating the
+    /// candidates played, or stop and keep the one playing.
+    Evolve(bool),
+    /// Rate the candidate playing while evolving, from 1 to 5.
+    Rate(u8),
+    /// Silence every note at once, those stuck included, as all notes off.
+    Panic,
+    /// List the named units of the network played, such as
+    /// `master.width`.
+    Nodes,
+    /// Write the wiring of the network played to a Graphviz DOT file.
+    Graph(String),
+    /// Bypass an insert effect by name, such as `track1.echo`, or bring it
+    /// back in.
+    Bypass(String),
+    /// Mute or unmute a track, counted from zero.
+    Mute(usize),
+    /// Solo or unsolo a track, counted from zero.
+    Solo(usize),
+    /// Stereo width of the master bus.
+    Width(f64),
+    /// Turn the DJ filter on the master bus, from -1 for the lowest lowpass
+    /// over 0 for none to 1 for the highest highpass.
+    Filter(f64),
+    /// Move a track, counted from zero, to a position around the listener.
+    Position(usize, Position),
+    /// Save the session to a project file.
+    Save(String),
+    /// End playback.
+    Stop,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LooperCommand {
+    /// Record a loop of this many bars from the next bar on.
+    Record(usize),
+    Overdub(bool),
+    Clear,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, anyhow::Error> {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("voices"), Some(value)) => Ok(Command::Voices(parse_voices(value)?)),
+            (Some("bpm"), Some(value)) => Ok(Command::Bpm(parse_bpm(value)?)),
+            (Some("width"), Some(value)) => Ok(Command::Width(parse_width(value)?)),
+            (Some("filter"), Some(value)) => Ok(Command::Filter(parse_filter(value)?)),
+            (Some("crossfade"), Some(value)) => Ok(Command::Crossfade(parse_crossfade(value)?)),
+            (Some("switch"), None) => Ok(Command::Switch),
+            (Some("compare"), None) => Ok(Command::Compare),
+            (Some("keep"), Some(side)) => Ok(Command::Keep(Side::parse(side)?)),
+            (Some("randomize"), prefix) => Ok(Command::Randomize(prefix.unwrap_or("").to_string())),
+            (Some("lock"), Some(name)) => Ok(Command::Lock(name.to_string())),
+            (Some("preset"), Some(path)) => Ok(Command::Preset(path.to_string())),
+            (Some("panic"), None) => Ok(Command::Panic),
+            (Some("evolve"), None) => Ok(Command::Evolve(true)),
+            (Some("evolve"), Some("off")) => Ok(Command::Evolve(false)),
+            (Some("rate"), Some(rating)) => Ok(Command::Rate(parse_rating(rating)?)),
+            (Some("morph"), Some(value)) => match words.next() {
+                Some(to) => Ok(Command::MorphBetween(value.to_string(), to.to_string())),
+                None => Ok(Command::Morph(parse_morph(value)?)),
+            },
+            (Some("nodes"), None) => Ok(Command::Nodes),
+            (Some("graph"), Some(path)) => Ok(Command::Graph(path.to_string())),
+            (Some("bypass"), Some(name)) => Ok(Command::Bypass(name.to_string())),
+            (Some("set"), Some(name)) => match words.next() {
+                Some(value) => Ok(Command::Set(name.to_string(), value.parse()?)),
+                None => bail!("set needs a parameter and a value"),
+            },
+            (Some("mute"), Some(track)) => Ok(Command::Mute(parse_track(track)?)),
+            (Some("solo"), Some(track)) => Ok(Command::Solo(parse_track(track)?)),
+            (Some("position"), Some(track)) => match words.next() {
+                Some(position) => Ok(Command::Position(
+                    parse_track(track)?,
+                    Position::parse(position)?,
+                )),
+                None => bail!("missing position"),
+            },
+            (Some("save"), Some(path)) => Ok(Command::Save(path.to_string())),
+            (Some("stop"), None) => Ok(Command::Stop),
+            (Some("tap"), None) => Ok(Command::Tap(Instant::now())),
+            (Some("play"), Some(note)) => Ok(Command::Play(Note::parse(note)?, Instant::now())),
+            (Some("record"), Some("on")) => Ok(Command::Record(true)),
+            (Some("record"), Some("off")) => Ok(Command::Record(false)),
+            (Some("looper"), Some(action)) => {
+                Ok(Command::Looper(LooperCommand::parse(action, words.next())?))
+            }
+            (Some("jump"), Some(part)) => Ok(Command::Jump(part.to_string())),
+            (Some("loop"), Some("off")) => Ok(Command::Loop(None)),
+            (Some("loop"), Some(value)) => Ok(Command::Loop(Some(LoopRegion::parse(value)?))),
+            _ => bail!("unknown command: {}", line.trim()),
+        }
+    }
+}
+
+fn parse_crossfade(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        position if (0.0..=1.0).contains(&position) => Ok(position),
+        _ => bail!("the crossfader goes from 0 to 1"),
+    }
+}
+
+fn parse_morph(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        position if (0.0..=1.0).contains(&position) => Ok(position),
+        _ => bail!("the morph knob goes from 0 to 1"),
+    }
+}
+
+fn parse_filter(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        position if (-1.0..=1.0).contains(&position) => Ok(position),
+        _ => bail!("the filter goes from -1 to 1"),
+    }
+}
+
+impl LooperCommand {
+    fn parse(action: &str, value: Option<&str>) -> Result<Self, anyhow::Error> {
+        match (action, value) {
+            ("record", None) => Ok(LooperCommand::Record(1)),
+            ("record", Some(bars)) => match bars.parse()? {
+                0 => bail!("a loop needs at least one bar"),
+                bars => Ok(LooperCommand::Record(bars)),
+            },
+            ("overdub", Some("on")) => Ok(LooperCommand::Overdub(true)),
+            ("overdub", Some("off")) => Ok(LooperCommand::Overdub(false)),
+            ("clear", None) => Ok(LooperCommand::Clear),
+            _ => bail!("unknown looper command: {}", action),
+        }
+    }
+}
+
+/// Reads commands from stdin on a background thread, or the keys of `piano`
+/// and the commands typed with their prefix, if given.
+pub fn spawn_stdin(sender: Sender<Command>, mut piano: Option<Piano>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let read = match &mut piano {
+                Some(piano) => {
+                    let octave = piano.octave();
+                    let read = piano.read(&line);
+                    if piano.octave() != octave {
+                        eprintln!("octave {:+}", piano.octave());
+                    }
+                    read
+                }
+                None => Command::parse(&line).map(|command| vec![command]),
+            };
+            let commands = match read {
+                Ok(commands) => commands,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    continue;
+                }
+            };
+            if commands
+                .into_iter()
+                .any(|command| sender.send(command).is_err())
+            {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("voices 4").unwrap(), Command::Voices(4));
+        assert!(Command::parse("voices 0").is_err());
+        assert!(Command::parse("louder").is_err());
+        assert_eq!(
+            Command::parse("loop 1-2").unwrap(),
+            Command::Loop(Some(LoopRegion { start: 0, end: 2 }))
+        );
+        assert_eq!(Command::parse("loop off").unwrap(), Command::Loop(None));
+        assert_eq!(Command::parse("solo 2").unwrap(), Command::Solo(1));
+        assert_eq!(
+            Command::parse("crossfade 0.25").unwrap(),
+            Command::Crossfade(0.25)
+        );
+        assert!(Command::parse("crossfade 2").is_err());
+        assert_eq!(
+            Command::parse("set width 1.5").unwrap(),
+            Command::Set("width".to_string(), 1.5)
+        );
+        assert!(Command::parse("set width").is_err());
+        assert!(Command::parse("mute 0").is_err());
+        assert_eq!(
+            Command::parse("jump chorus").unwrap(),
+            Command::Jump("chorus".to_string())
+        );
+        assert_eq!(Command::parse("record on").unwrap(), Command::Record(true));
+        assert_eq!(Command::parse("stop").unwrap(), Command::Stop);
+        assert_eq!(Command::parse("nodes").unwrap(), Command::Nodes);
+        assert_eq!(Command::parse("compare").unwrap(), Command::Compare);
+        assert_eq!(Command::parse("keep b").unwrap(), Command::Keep(Side::B));
+        assert!(Command::parse("keep c").is_err());
+        assert_eq!(
+            Command::parse("randomize").unwrap(),
+            Command::Randomize(String::new())
+        );
+        assert_eq!(
+            Command::parse("randomize amp_").unwrap(),
+            Command::Randomize("amp_".to_string())
+        );
+        assert_eq!(
+            Command::parse("lock width").unwrap(),
+            Command::Lock("width".to_string())
+        );
+        assert_eq!(
+            Command::parse("morph dry.json wet.json").unwrap(),
+            Command::MorphBetween("dry.json".to_string(), "wet.json".to_string())
+        );
+        assert_eq!(Command::parse("morph 0.25").unwrap(), Command::Morph(0.25));
+        assert!(Command::parse("morph 2").is_err());
+        assert_eq!(
+            Command::parse("evolve off").unwrap(),
+            Command::Evolve(false)
+        );
+        assert_eq!(Command::parse("rate 4").unwrap(), Command::Rate(4));
+        assert_eq!(Command::parse("panic").unwrap(), Command::Panic);
+        assert!(Command::parse("rate 9").is_err());
+        assert_eq!(
+            Command::parse("graph net.dot").unwrap(),
+            Command::Graph("net.dot".to_string())
+        );
+        assert_eq!(
+            Command::parse("bypass track1.echo").unwrap(),
+            Command::Bypass("track1.echo".to_string())
+        );
+        assert_eq!(
+            Command::parse("save song.json").unwrap(),
+            Command::Save("song.json".to_string())
+        );
+        assert_eq!(Command::parse("width 0.5").unwrap(), Command::Width(0.5));
+        assert_eq!(
+            Command::parse("filter -0.5").unwrap(),
+            Command::Filter(-0.5)
+        );
+        assert!(Command::parse("filter 1.5").is_err());
+        assert_eq!(
+            Command::parse("position 2 -30,10").unwrap(),
+            Command::Position(
+                1,
+                Position {
+                    azimuth: -30.0,
+                    elevation: 10.0
+                }
+            )
+        );
+        assert!(Command::parse("position 0 30").is_err());
+        assert_eq!(
+            Command::parse("looper record 2").unwrap(),
+            Command::Looper(LooperCommand::Record(2))
+        );
+        assert!(Command::parse("looper record 0").is_err());
+        assert!(matches!(Command::parse("tap").unwrap(), Command::Tap(_)));
+        assert_eq!(Command::parse("bpm 90").unwrap(), Command::Bpm(90.0));
+        assert!(matches!(
+            Command::parse("play Gis1").unwrap(),
+            Command::Play(Note { octave: 1, .. }, _)
+        ));
+    }
+}
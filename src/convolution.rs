@@ -0,0 +1,300 @@
+//! Convolution with an impulse response recorded in a real space, uniformly
+//! partitioned into blocks that are convolved by multiplying their spectra.
+//! Everything is allocated up front, so that convolving doesn't allocate on
+//! the audio thread.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use fundsp::hacker::*;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+/// Samples of a partition, which the convolution is delayed by.
+pub const PARTITION: usize = 256;
+/// Longest impulse response convolved with, beyond which it is cut off to
+/// keep the work of a block in bounds.
+const MAX_SECONDS: f64 = 5.0;
+
+/// The lower `PARTITION + 1` bins of a spectrum of `2 * PARTITION`, which
+/// the upper ones of real signals mirror.
+type Spectrum = Vec<Complex<f64>>;
+
+/// Convolution of one channel with an impulse response, by overlap and save.
+#[derive(Clone)]
+pub struct Convolver {
+    forward: Arc<dyn RealToComplex<f64>>,
+    inverse: Arc<dyn ComplexToReal<f64>>,
+    /// Spectra of the partitions of the impulse response, zero padded to
+    /// twice their length.
+    filters: Vec<Spectrum>,
+    /// Spectra of the latest blocks of input, as many as there are
+    /// partitions, in a ring.
+    inputs: Vec<Spectrum>,
+    /// Where in the ring the spectrum of the latest block is.
+    newest: usize,
+    /// The block of input before the one being filled, and that one.
+    window: Vec<f64>,
+    /// Output of the previous block, played while the next is filled.
+    output: Vec<f64>,
+    sum: Spectrum,
+    /// What is transformed, which the transforms overwrite, and their
+    /// scratch space.
+    frame: Vec<f64>,
+    scratch: Vec<Complex<f64>>,
+    /// Samples of the block filled so far.
+    position: usize,
+}
+
+impl Convolver {
+    pub fn new(response: &[f64]) -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(2 * PARTITION);
+        let inverse = planner.plan_fft_inverse(2 * PARTITION);
+        let mut frame = vec![0.0; 2 * PARTITION];
+        let filters: Vec<Spectrum> = response
+            .chunks(PARTITION)
+            .map(|partition| {
+                frame.fill(0.0);
+                frame[..partition.len()].copy_from_slice(partition);
+                let mut spectrum = forward.make_output_vec();
+                forward.process(&mut frame, &mut spectrum).unwrap();
+                spectrum
+            })
+            .collect();
+        let partitions = max(filters.len(), 1);
+        let scratch =
+            vec![Complex::default(); max(forward.get_scratch_len(), inverse.get_scratch_len())];
+        Self {
+            inputs: vec![forward.make_output_vec(); partitions],
+            sum: forward.make_output_vec(),
+            forward,
+            inverse,
+            filters,
+            newest: 0,
+            window: vec![0.0; 2 * PARTITION],
+            output: vec![0.0; PARTITION],
+            frame,
+            scratch,
+            position: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for input in &mut self.inputs {
+            input.fill(Complex::default());
+        }
+        self.window.fill(0.0);
+        self.output.fill(0.0);
+        self.position = 0;
+    }
+
+    /// Convolves the next sample, and returns the one `PARTITION` samples earlier.
+    pub fn tick(&mut self, x: f64) -> f64 {
+        let y = self.output[self.position];
+        self.window[PARTITION + self.position] = x;
+        self.position += 1;
+        if self.position == PARTITION {
+            self.position = 0;
+            self.convolve();
+        }
+        y
+    }
+
+    fn convolve(&mut self) {
+        self.newest = (self.newest + 1) % self.inputs.len();
+        let input = &mut self.inputs[self.newest];
+        self.frame.copy_from_slice(&self.window);
+        self.forward
+            .process_with_scratch(&mut self.frame, input, &mut self.scratch)
+            .unwrap();
+
+        let sum = &mut self.sum;
+        sum.fill(Complex::default());
+        for (age, filter) in self.filters.iter().enumerate() {
+            let input = &self.inputs[(self.newest + self.inputs.len() - age) % self.inputs.len()];
+            for ((sum, input), filter) in sum.iter_mut().zip(input).zip(filter) {
+                *sum += input * filter;
+            }
+        }
+        self.inverse
+            .process_with_scratch(sum, &mut self.frame, &mut self.scratch)
+            .unwrap();
+        // The first half wrapped around, the second is the output, scaled
+        // as the inverse transform leaves it by the length.
+        let scale = 1.0 / (2 * PARTITION) as f64;
+        for (y, x) in self.output.iter_mut().zip(&self.frame[PARTITION..]) {
+            *y = x * scale;
+        }
+        self.window.copy_within(PARTITION.., 0);
+    }
+}
+
+/// An impulse response of one or two channels.
+#[derive(Clone)]
+pub struct Response {
+    channels: Vec<Vec<f64>>,
+    sample_rate: f64,
+}
+
+impl Response {
+    /// Loads the impulse response in the WAV or FLAC file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        Ok(Self::from_wave(&Wave64::load(path)?))
+    }
+
+    /// The first two channels of `wave`, scaled to an energy of 1 for each,
+    /// so that responses of any length reverberate about as loud.
+    pub fn from_wave(wave: &Wave64) -> Self {
+        let channels: Vec<Vec<f64>> = (0..min(wave.channels(), 2))
+            .map(|channel| (0..wave.len()).map(|i| wave.at(channel, i)).collect())
+            .collect();
+        let energy = channels.iter().flatten().map(|x| x * x).sum::<f64>() / channels.len() as f64;
+        let scale = if energy > 0.0 {
+            energy.sqrt().recip()
+        } else {
+            0.0
+        };
+        let channels = channels
+            .into_iter()
+            .map(|channel| channel.into_iter().map(|x| x * scale).collect())
+            .collect();
+        Self::new(channels, wave.sample_rate())
+    }
+
+    /// The response of one or two `channels` at `sample_rate`, as it is.
+    pub fn new(mut channels: Vec<Vec<f64>>, sample_rate: f64) -> Self {
+        assert!((1..=2).contains(&channels.len()));
+        for channel in &mut channels {
+            channel.truncate((MAX_SECONDS * sample_rate) as usize);
+        }
+        Self {
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// The response of one of two stereo channels, mono responses the same
+    /// for both, resampled to `sample_rate` by linear interpolation.
+    fn channel(&self, channel: usize, sample_rate: f64) -> Vec<f64> {
+        let samples = &self.channels[min(channel, self.channels.len() - 1)];
+        if sample_rate == self.sample_rate || samples.is_empty() {
+            return samples.clone();
+        }
+        let step = self.sample_rate / sample_rate;
+        // Scaled by the step to keep the same gain, since more samples or
+        // fewer are summed over the same length of time.
+        (0..((samples.len() - 1) as f64 / step) as usize + 1)
+            .map(|i| {
+                let position = i as f64 * step;
+                let index = position as usize;
+                let next = samples.get(index + 1).copied().unwrap_or(0.0);
+                lerp(samples[index], next, position - index as f64) * step
+            })
+            .collect()
+    }
+
+    /// A unit convolving its two inputs with the two channels of the response.
+    pub fn unit(&self) -> An<Convolution> {
+        let response = Arc::new(self.clone());
+        An(Convolution {
+            convolvers: [0, 1]
+                .map(|channel| Convolver::new(&response.channel(channel, DEFAULT_SR))),
+            response,
+            sample_rate: DEFAULT_SR,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Convolution {
+    response: Arc<Response>,
+    sample_rate: f64,
+    convolvers: [Convolver; 2],
+}
+
+impl AudioNode for Convolution {
+    const ID: u64 = 0x436f_6e76;
+    type Sample = f64;
+    type Inputs = U2;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        for convolver in &mut self.convolvers {
+            convolver.reset();
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        // Set before playing starts, off the audio thread.
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            let response = &self.response;
+            self.convolvers =
+                [0, 1].map(|channel| Convolver::new(&response.channel(channel, sample_rate)));
+        }
+    }
+
+    fn tick(&mut self, input: &Frame<f64, U2>) -> Frame<f64, U2> {
+        let [left, right] = &mut self.convolvers;
+        [left.tick(input[0]), right.tick(input[1])].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(PARTITION as f64).propagate(input, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_direct_convolution() {
+        let response: Vec<f64> = (0..700)
+            .map(|i| ((i * 7919) % 13) as f64 / 13.0 - 0.5)
+            .collect();
+        let input: Vec<f64> = (0..2000)
+            .map(|i| ((i * 104729) % 17) as f64 / 17.0 - 0.5)
+            .collect();
+        let mut convolver = Convolver::new(&response);
+        let output: Vec<f64> = input
+            .iter()
+            .chain(&[0.0; PARTITION])
+            .map(|&x| convolver.tick(x))
+            .collect();
+        for (i, &y) in output[PARTITION..].iter().enumerate() {
+            let direct: f64 = (0..=i)
+                .filter(|&j| i - j < response.len())
+                .map(|j| input[j] * response[i - j])
+                .sum();
+            assert!(
+                (y - direct).abs() < 1e-9,
+                "{} at {}, expected {}",
+                y,
+                i,
+                direct
+            );
+        }
+    }
+
+    #[test]
+    fn test_mono_response_for_both_channels() {
+        let mut wave = Wave64::new(1, 1000.0);
+        for x in [0.0, 2.0, 0.0, 0.0] {
+            wave.push(x);
+        }
+        let mut unit = Response::from_wave(&wave).unit();
+        unit.set_sample_rate(1000.0);
+        let outputs: Vec<(f64, f64)> = (0..PARTITION + 4)
+            .map(|i| unit.filter_stereo(if i == 0 { 1.0 } else { 0.0 }, 0.0))
+            .collect();
+        // Scaled to an energy of 1, and delayed by a partition.
+        assert!((outputs[PARTITION + 1].0 - 1.0).abs() < 1e-9);
+        assert!(outputs.iter().all(|&(_, right)| right.abs() < 1e-9));
+        assert!(outputs[..PARTITION]
+            .iter()
+            .all(|&(left, _)| left.abs() < 1e-9));
+    }
+}
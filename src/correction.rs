@@ -0,0 +1,167 @@
+//! Correction of the speakers and the room that the main output is monitored
+//! on, by convolving it with a correction impulse response, or with one
+//! designed from a curve of the gains of an EQ.
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+
+use crate::convolution::{Convolution, Response, PARTITION};
+
+/// Taps of the filter designed from an EQ curve. Being linear phase, it
+/// delays by half of them.
+const TAPS: usize = 2048;
+
+/// The correction filter and how long it delays what it corrects, in seconds.
+pub struct Correction {
+    response: Response,
+    pub latency: f64,
+}
+
+impl Correction {
+    /// Loads the correction at `path` for `sample_rate`: an impulse response
+    /// in a WAV or FLAC file, or an EQ curve in a text file of a frequency
+    /// and a gain in decibels on each line.
+    pub fn load(path: &str, sample_rate: f64) -> Result<Self, anyhow::Error> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if extension == "wav" || extension == "flac" {
+            let wave = Wave64::load(path)?;
+            let channels = (0..min(wave.channels(), 2))
+                .map(|channel| (0..wave.len()).map(|i| wave.at(channel, i)).collect())
+                .collect();
+            // How late the response peaks is how long it delays.
+            let peak = (0..wave.len())
+                .max_by(|&a, &b| wave.at(0, a).abs().total_cmp(&wave.at(0, b).abs()))
+                .unwrap_or(0);
+            Ok(Self {
+                response: Response::new(channels, wave.sample_rate()),
+                latency: (peak + PARTITION) as f64 / wave.sample_rate(),
+            })
+        } else {
+            let curve = parse_curve(&std::fs::read_to_string(path)?)?;
+            Ok(Self {
+                response: Response::new(vec![design(&curve, sample_rate)], sample_rate),
+                latency: (TAPS / 2 + PARTITION) as f64 / sample_rate,
+            })
+        }
+    }
+
+    pub fn unit(&self) -> An<Convolution> {
+        self.response.unit()
+    }
+}
+
+/// Parses the points of an EQ curve, a frequency and a gain in decibels on
+/// each line, separated by spaces or a comma. Blank lines and lines starting
+/// with `#` are skipped.
+pub fn parse_curve(text: &str) -> Result<Vec<(f64, f64)>, anyhow::Error> {
+    let mut curve: Vec<(f64, f64)> = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut numbers = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(str::parse::<f64>);
+        let (Some(Ok(frequency)), Some(Ok(gain)), None) =
+            (numbers.next(), numbers.next(), numbers.next())
+        else {
+            bail!("expected FREQUENCY GAIN_DB, got {}", line);
+        };
+        if frequency <= 0.0 || curve.last().is_some_and(|&(last, _)| frequency <= last) {
+            bail!(
+                "the frequencies of an EQ curve go up from above 0, got {}",
+                line
+            );
+        }
+        curve.push((frequency, gain));
+    }
+    if curve.is_empty() {
+        return Err(anyhow!("the EQ curve has no points"));
+    }
+    Ok(curve)
+}
+
+/// Gain in decibels of `curve` at `frequency`, interpolated in octaves
+/// between its points and level beyond them.
+fn gain_at(curve: &[(f64, f64)], frequency: f64) -> f64 {
+    let above = curve.partition_point(|&(point, _)| point < frequency);
+    match (above.checked_sub(1), curve.get(above)) {
+        (None, _) => curve[0].1,
+        (Some(below), None) => curve[below].1,
+        (Some(below), Some(&(high, high_gain))) => {
+            let (low, low_gain) = curve[below];
+            let t = (frequency / low).log2() / (high / low).log2();
+            lerp(low_gain, high_gain, t)
+        }
+    }
+}
+
+/// A linear phase filter of `TAPS` taps following `curve`, by taking the
+/// inverse transform of its gains and windowing it.
+pub fn design(curve: &[(f64, f64)], sample_rate: f64) -> Vec<f64> {
+    let fft = RealFftPlanner::new().plan_fft_inverse(TAPS);
+    // The lower half of the bins, which the upper half mirrors.
+    let mut gains: Vec<Complex<f64>> = (0..=TAPS / 2)
+        .map(|bin| {
+            let gain = db_amp(gain_at(curve, bin as f64 * sample_rate / TAPS as f64));
+            Complex::new(gain, 0.0)
+        })
+        .collect();
+    let mut taps = fft.make_output_vec();
+    fft.process(&mut gains, &mut taps).unwrap();
+    // Zero phase wraps around the start, so it is turned around to peak in
+    // the middle, and the inverse transform leaves it scaled by its length.
+    (0..TAPS)
+        .map(|i| {
+            let hann = 0.5 - 0.5 * cos(TAU * i as f64 / TAPS as f64);
+            taps[(i + TAPS / 2) % TAPS] * hann / TAPS as f64
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_curve() {
+        let curve = parse_curve("# measured\n20 3.0\n\n1000, -2\n20000 0\n").unwrap();
+        assert_eq!(curve, [(20.0, 3.0), (1000.0, -2.0), (20000.0, 0.0)]);
+        assert_eq!(gain_at(&curve, 10.0), 3.0);
+        assert!((gain_at(&curve, 2000.0) - -2.0 * (1.0 - 1.0 / 20f64.log2())).abs() < 1e-9);
+        assert_eq!(gain_at(&curve, 30000.0), 0.0);
+        assert!(parse_curve("").is_err());
+        assert!(parse_curve("100 1\n50 2").is_err());
+        assert!(parse_curve("100").is_err());
+    }
+
+    #[test]
+    fn test_designed_filter_follows_curve() {
+        let sample_rate = 48000.0;
+        let curve = [(200.0, 0.0), (2000.0, -12.0)];
+        let mut unit = Response::new(vec![design(&curve, sample_rate)], sample_rate).unit();
+        unit.set_sample_rate(sample_rate);
+        let mut level = |frequency: f64| {
+            unit.reset();
+            let peak = (0..24000)
+                .map(|i| {
+                    let x = sin(TAU * frequency * i as f64 / sample_rate);
+                    unit.filter_stereo(x, x).0.abs()
+                })
+                .skip(12000)
+                .fold(0.0, f64::max);
+            amp_db(peak)
+        };
+        assert!(level(100.0).abs() < 0.5);
+        assert!((level(5000.0) + 12.0).abs() < 0.5);
+    }
+}
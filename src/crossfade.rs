@@ -0,0 +1,93 @@
+//! Crossfading between the two pattern banks of tracks, DJ style.
+
+use fundsp::hacker::*;
+
+use crate::param::{Param, Spec};
+
+/// Response time of the crossfader, which glides rather than clicks.
+const FADE_SECONDS: f64 = 0.01;
+
+/// One of the two sides of the crossfader.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bank {
+    A,
+    B,
+}
+
+impl Bank {
+    /// Gain of the bank with the crossfader at `position`, from 0 for only A to
+    /// 1 for only B, at constant power in between.
+    pub fn gain(self, position: f64) -> f64 {
+        let angle = position.clamp(0.0, 1.0) * std::f64::consts::FRAC_PI_2;
+        match self {
+            Bank::A => angle.cos(),
+            Bank::B => angle.sin(),
+        }
+    }
+}
+
+/// The crossfader position shared with the voices playing either bank.
+#[derive(Clone)]
+pub struct Crossfader {
+    position: Param,
+}
+
+impl Default for Crossfader {
+    fn default() -> Self {
+        Self::new(Param::new(Self::SPEC))
+    }
+}
+
+impl Crossfader {
+    /// The position, from 0 for only A to 1 for only B.
+    pub const SPEC: Spec = Spec {
+        smoothing: FADE_SECONDS,
+        ..Spec::linear(0.0, 1.0, 0.0)
+    };
+
+    /// A crossfader at `position`, a parameter of the `SPEC` spec.
+    pub fn new(position: Param) -> Self {
+        Self { position }
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position.value()
+    }
+
+    pub fn set(&self, position: f64) {
+        self.position.set(position);
+    }
+
+    /// Moves over to the bank that is heard less.
+    pub fn switch(&self) {
+        self.set(if self.position() < 0.5 { 1.0 } else { 0.0 });
+    }
+
+    /// Mono gain of a voice playing `bank`, following the crossfader.
+    pub fn unit(&self, bank: Bank) -> An<impl AudioNode<Sample = f64, Inputs = U1, Outputs = U1>> {
+        pass() * (self.position.unit() >> map(move |f| bank.gain(f[0])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_power() {
+        for position in [0.0, 0.3, 0.5, 1.0] {
+            let power = Bank::A.gain(position).powi(2) + Bank::B.gain(position).powi(2);
+            assert!((power - 1.0).abs() < 1e-12);
+        }
+        assert_eq!(Bank::A.gain(0.0), 1.0);
+        assert!(Bank::A.gain(1.0).abs() < 1e-12);
+        let crossfader = Crossfader::default();
+        crossfader.switch();
+        assert_eq!(crossfader.position(), 1.0);
+        crossfader.set(0.4);
+        crossfader.switch();
+        assert_eq!(crossfader.position(), 1.0);
+        crossfader.set(7.0);
+        assert_eq!(crossfader.position(), 1.0);
+    }
+}
@@ -0,0 +1,148 @@
+//! Control voltages for modular hardware: the pitch and gate of the notes of
+//! a track on two channels of a DC-coupled output.
+
+use fundsp::hacker::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Volts of a full scale sample, as on interfaces sending ±10 V.
+const FULL_SCALE_VOLTS: f64 = 10.0;
+/// The pitch heard at 0 V, middle C, going up 1 V per octave.
+const ZERO_VOLT_HZ: f64 = 261.63;
+const GATE_VOLTS: f64 = 5.0;
+/// The gate goes down this long before a note ends, so that the next note
+/// starting right then triggers anew.
+const RETRIGGER_SECONDS: f64 = 0.002;
+
+/// Output sample of the pitch CV of a note at `frequency`.
+pub fn pitch(frequency: f64) -> f64 {
+    (frequency / ZERO_VOLT_HZ).log2() / FULL_SCALE_VOLTS
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Change {
+    time: f64,
+    pitch: f64,
+    /// Whether a note starts or ends.
+    on: bool,
+}
+
+/// The notes of a track, shared with the unit sending their CV.
+#[derive(Clone, Default)]
+pub struct CvTrack {
+    /// Changes to come, in order of time.
+    changes: Arc<Mutex<VecDeque<Change>>>,
+}
+
+impl CvTrack {
+    /// Sends a note at `frequency` from `start` to `end` seconds of the audio
+    /// thread.
+    pub fn note(&self, start: f64, end: f64, frequency: f64) {
+        let pitch = pitch(frequency);
+        let end = (end - RETRIGGER_SECONDS).max(start);
+        let mut changes = self.changes.lock().unwrap();
+        for change in [
+            Change {
+                time: start,
+                pitch,
+                on: true,
+            },
+            Change {
+                time: end,
+                pitch,
+                on: false,
+            },
+        ] {
+            let index = changes.partition_point(|other| other.time <= change.time);
+            changes.insert(index, change);
+        }
+    }
+
+    /// A unit with the pitch on output 0 and the gate on output 1. The pitch
+    /// is that of the latest note, the gate is up while any is held.
+    pub fn unit(&self) -> An<CvOut> {
+        An(CvOut {
+            changes: self.changes.clone(),
+            time: 0.0,
+            pitch: 0.0,
+            held: 0,
+            sample_rate: DEFAULT_SR,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct CvOut {
+    changes: Arc<Mutex<VecDeque<Change>>>,
+    /// Seconds since the unit started, counted the same as the audio thread time.
+    time: f64,
+    pitch: f64,
+    held: usize,
+    sample_rate: f64,
+}
+
+impl AudioNode for CvOut {
+    const ID: u64 = 0x4376_4f75;
+    type Sample = f64;
+    type Inputs = U0;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.time = 0.0;
+        self.held = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn tick(&mut self, _input: &Frame<f64, U0>) -> Frame<f64, U2> {
+        // Changes that come while notes are being sent wait for the next sample.
+        if let Ok(mut changes) = self.changes.try_lock() {
+            while let Some(change) = changes.front().filter(|change| change.time <= self.time) {
+                if change.on {
+                    self.pitch = change.pitch;
+                    self.held += 1;
+                } else {
+                    self.held = self.held.saturating_sub(1);
+                }
+                changes.pop_front();
+            }
+        }
+        self.time += 1.0 / self.sample_rate;
+        let gate = if self.held > 0 { GATE_VOLTS } else { 0.0 };
+        [self.pitch, gate / FULL_SCALE_VOLTS].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pitch_is_volt_per_octave() {
+        assert!(pitch(ZERO_VOLT_HZ).abs() < 1e-12);
+        assert!((pitch(ZERO_VOLT_HZ * 4.0) - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_legato_notes_retrigger() {
+        let track = CvTrack::default();
+        let mut unit = track.unit();
+        unit.set_sample_rate(1000.0);
+        track.note(0.01, 0.02, ZERO_VOLT_HZ * 2.0);
+        track.note(0.02, 0.03, ZERO_VOLT_HZ);
+        let outputs: Vec<(f64, f64)> = (0..40).map(|_| unit.get_stereo()).collect();
+        assert_eq!(outputs[5], (0.0, 0.0));
+        assert_eq!(outputs[12], (0.1, 0.5));
+        // Down just before the second note, which sets its own pitch.
+        assert_eq!(outputs[18].1, 0.0);
+        assert_eq!(outputs[22], (0.0, 0.5));
+        assert_eq!(outputs[35].1, 0.0);
+    }
+}
@@ -0,0 +1,398 @@
+//! The tracks mixed the way the song plays them, the same live and in
+//! offline renders: a stereo bus for every track and one for live notes,
+//! each through the modulations and echoes inserted on it and the gain of
+//! the mixer, metered and sent to the reverb, and the voices of notes
+//! placed on their buses, humanized and panned.
+
+use fundsp::hacker::*;
+
+use crate::arrangement::Arrangement;
+use crate::binaural::{Placement, Position};
+use crate::builder::Build;
+use crate::convolution::Response;
+use crate::crossfade::{Bank, Crossfader};
+use crate::feedback::Echo;
+use crate::graph::{named, Patch, Transaction};
+use crate::humanize::{Humanize, HumanizeAmount};
+use crate::instrument::Instrument;
+use crate::mixer::Mixer;
+use crate::modulation::{self, Modulation};
+use crate::param::{Param, ParamRegistry};
+use crate::reverb::Reverb;
+use crate::sampler::Samplers;
+use crate::schedule::Action;
+use crate::stereo;
+use crate::voice::VoicePool;
+use crate::vu::VuMeter;
+
+/// Voices a pool has unless set up with more.
+pub const VOICES: usize = 8;
+
+/// What the desk is set up with, as the command line gives it.
+#[derive(Clone)]
+pub struct Setup {
+    pub voices: usize,
+    /// Modulations of tracks, each by the envelope of another.
+    pub modulations: Vec<(usize, Modulation)>,
+    /// Dub delays on tracks, after their modulations.
+    pub echoes: Vec<(usize, Echo)>,
+    /// Impulse response of the reverb that tracks send to.
+    pub reverb: Option<Response>,
+    /// Place tracks binaurally for headphones instead of panning them.
+    pub binaural: bool,
+    /// How far voices are moved to the side by their pitch, from 0 to 1.
+    pub pitch_pan: f64,
+    pub humanize: HumanizeAmount,
+    /// Seed of the humanize jitter, so that renders come out the same.
+    pub seed: u64,
+    pub samplers: Samplers,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Self {
+            voices: VOICES,
+            modulations: vec![],
+            echoes: vec![],
+            reverb: None,
+            binaural: false,
+            pitch_pan: 0.0,
+            humanize: HumanizeAmount::default(),
+            seed: 0,
+            samplers: Samplers::default(),
+        }
+    }
+}
+
+/// A note of the song as it is played, once humanized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Played {
+    pub instrument: Instrument,
+    pub frequency: f64,
+    /// Start and length until the note-off in seconds.
+    pub start: f64,
+    pub duration: f64,
+    pub track: Option<usize>,
+    pub velocity: f64,
+    pub bank: Option<Bank>,
+}
+
+/// Where the units of the buses are, once built.
+pub struct Buses {
+    /// The stereo mix of every bus, with the reverb returned into it.
+    pub mix: NodeId,
+    /// Each bus before the gain of the mixer, on the output given and the
+    /// one after it.
+    pub sources: Vec<(NodeId, usize)>,
+    /// The gain of each bus, which its meter and the reverb are fed from.
+    pub gains: Vec<NodeId>,
+}
+
+/// The mixer, meters and effects of the buses, and how notes play on them.
+pub struct Desk {
+    setup: Setup,
+    mixer: Mixer,
+    meters: Vec<VuMeter>,
+    crossfader: Crossfader,
+    width: Param,
+    reverb: Option<Reverb>,
+    /// Where each track is heard when placing binaurally.
+    placements: Option<Vec<Placement>>,
+    humanize: Humanize,
+}
+
+impl Desk {
+    /// The desk of the tracks of `song`, its parameters registered in `params`.
+    pub fn new(params: &mut ParamRegistry, song: &Arrangement, setup: Setup) -> Self {
+        let tracks = song.tracks.len();
+        let placements = setup.binaural.then(|| {
+            song.tracks
+                .iter()
+                .map(|track| Placement::new(Position::from_pan(track.pan)))
+                .collect()
+        });
+        Self {
+            mixer: Mixer::new(tracks),
+            meters: (0..tracks + 1).map(|_| VuMeter::default()).collect(),
+            crossfader: Crossfader::new(params.register("crossfade", Crossfader::SPEC)),
+            width: params.register("width", stereo::WIDTH),
+            reverb: setup
+                .reverb
+                .clone()
+                .map(|response| Reverb::new(params, tracks, response)),
+            placements,
+            humanize: Humanize::new(setup.humanize, setup.seed),
+            setup,
+        }
+    }
+
+    /// Buses there are, those of the tracks followed by the one of live notes.
+    pub fn buses(&self) -> usize {
+        self.meters.len()
+    }
+
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    pub fn mixer_mut(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+
+    /// The meter of each bus.
+    pub fn meters(&self) -> &[VuMeter] {
+        &self.meters
+    }
+
+    pub fn crossfader(&self) -> &Crossfader {
+        &self.crossfader
+    }
+
+    pub fn width(&self) -> &Param {
+        &self.width
+    }
+
+    /// Where `track` is heard, if tracks are placed binaurally.
+    pub fn placement(&self, track: usize) -> Option<&Placement> {
+        self.placements
+            .as_ref()
+            .map(|placements| &placements[track])
+    }
+
+    /// A pool of as many voices as set up, panning them by pitch as set up.
+    pub fn voice_pool(&self) -> VoicePool {
+        let mut voices = VoicePool::new(self.setup.voices);
+        voices.set_pitch_pan(self.setup.pitch_pan);
+        voices
+    }
+
+    /// Pushes the buses fed by the stereo pairs of `voices`, the inserts on
+    /// them registering their parameters in `params`.
+    pub fn build(
+        &mut self,
+        params: &mut ParamRegistry,
+        net: &mut Transaction,
+        voices: NodeId,
+    ) -> Buses {
+        let reverb = self
+            .reverb
+            .as_ref()
+            .map(|reverb| net.push_named("reverb", Box::new(reverb.unit())));
+        let mut mix = None;
+        let (mut sources, mut gains) = (vec![], vec![]);
+        for (bus, meter) in self.meters.iter().enumerate() {
+            let name = if bus < self.mixer.tracks() {
+                format!("track{}", bus + 1)
+            } else {
+                "live".to_string()
+            };
+            let meter_id = net.push_named(&format!("{}.meter", name), Box::new(meter.unit()));
+            // Tracks are muted and soloed before their meters, live notes are always heard.
+            let gain = if bus < self.mixer.tracks() {
+                net.push_named(&format!("{}.gain", name), Box::new(self.mixer.unit(bus)))
+            } else {
+                net.push_named(&format!("{}.gain", name), Box::new(multipass::<U2>()))
+            };
+            // Modulations of the track one after another, each following the
+            // envelope of its source as the sequencer plays it.
+            let mut input = (voices, 2 * bus);
+            for (_, modulation) in self
+                .setup
+                .modulations
+                .iter()
+                .filter(|(track, _)| *track == bus)
+            {
+                let envelope = net.push(Box::new(modulation::envelope()));
+                let insert = format!("{}.modulation", name);
+                let unit = self.mixer.insert(params, &insert, modulation.unit());
+                let unit = net.push_named(&insert, unit);
+                for channel in 0..2 {
+                    net.connect(voices, 2 * modulation.source + channel, envelope, channel);
+                    net.connect(input.0, input.1 + channel, unit, channel);
+                }
+                net.connect(envelope, 0, unit, 2);
+                input = (unit, 0);
+            }
+            for (_, echo) in self.setup.echoes.iter().filter(|(track, _)| *track == bus) {
+                let insert = format!("{}.echo", name);
+                let unit = self.mixer.insert(params, &insert, echo.unit());
+                let unit = net.push_named(&insert, unit);
+                for channel in 0..2 {
+                    net.connect(input.0, input.1 + channel, unit, channel);
+                }
+                input = (unit, 0);
+            }
+            for channel in 0..2 {
+                net.connect(input.0, input.1 + channel, gain, channel);
+                net.connect(gain, channel, meter_id, channel);
+                if let Some(reverb) = reverb {
+                    net.connect(gain, channel, reverb, 2 * bus + channel);
+                }
+                if let Some(mix) = mix {
+                    net.connect(mix, channel, meter_id, 2 + channel);
+                }
+            }
+            sources.push(input);
+            gains.push(gain);
+            mix = Some(meter_id);
+        }
+        let mut mix = mix.unwrap();
+        if let Some(reverb) = reverb {
+            let sum = net.push(Box::new((pass() | pass()) + (pass() | pass())));
+            for channel in 0..2 {
+                net.connect(mix, channel, sum, channel);
+                net.connect(reverb, channel, sum, 2 + channel);
+            }
+            mix = sum;
+        }
+        Buses {
+            mix,
+            sources,
+            gains,
+        }
+    }
+
+    /// The width control of the master bus, after the mix.
+    pub fn master(&self) -> Patch {
+        named("master.width", Box::new(stereo::width(&self.width)))
+    }
+
+    /// The note that `action` plays when it is due at `at` seconds, later by
+    /// its delay and jittered when humanizing, or none if it is a bar.
+    pub fn note(&mut self, action: &Action, at: f64, seconds_per_beat: f64) -> Option<Played> {
+        let Action::Note {
+            instrument,
+            frequency,
+            duration,
+            track,
+            velocity,
+            delay,
+            bank,
+        } = *action
+        else {
+            return None;
+        };
+        // The metronome keeps strict time.
+        let (start, velocity) = match instrument {
+            Instrument::Click => (at, velocity),
+            _ => self.humanize.apply(at + delay, velocity),
+        };
+        Some(Played {
+            instrument,
+            frequency,
+            start,
+            duration: duration * seconds_per_beat,
+            track,
+            velocity,
+            bank,
+        })
+    }
+
+    /// Builds the mono voice of `note`, from the sampler of its track if
+    /// it has one and on its side of the crossfader.
+    pub fn sound(&self, note: &Played, seconds_per_beat: f64) -> Build {
+        let samplers = self.setup.samplers.clone();
+        let crossfader = self.crossfader.clone();
+        let note = *note;
+        Box::new(move |_| {
+            let (mut unit, release) = samplers.voice(
+                note.track,
+                note.instrument,
+                note.frequency,
+                note.duration,
+                note.velocity,
+                seconds_per_beat,
+            );
+            if let Some(bank) = note.bank {
+                let gain = Box::new(crossfader.unit(bank));
+                unit = Box::new(Net64::wrap(unit) >> Net64::wrap(gain));
+            }
+            (unit, release)
+        })
+    }
+
+    /// Builds the voice of `note` of `song` placed on the bus of its track.
+    pub fn voice(&self, song: &Arrangement, note: &Played, seconds_per_beat: f64) -> Build {
+        let sound = self.sound(note, seconds_per_beat);
+        let place = self.place(song, note.track, note.frequency);
+        Box::new(move |voices| {
+            let (unit, release) = sound(voices);
+            (place(voices, unit), release)
+        })
+    }
+
+    /// Places a mono voice of `track` of `song` at `frequency` in stereo on
+    /// the bus of the track, given the pool it plays in, or in the center
+    /// of the bus of live notes when it is played live.
+    pub fn place(
+        &self,
+        song: &Arrangement,
+        track: Option<usize>,
+        frequency: f64,
+    ) -> impl FnOnce(&VoicePool, Box<dyn AudioUnit64>) -> Box<dyn AudioUnit64> + Send {
+        let placement = self.placements.as_ref().map(|placements| match track {
+            Some(track) => placements[track].clone(),
+            None => Placement::new(Position::default()),
+        });
+        let position = track.map_or(0.0, |track| song.tracks[track].pan);
+        let (bus, buses) = (track.unwrap_or(self.buses() - 1), self.buses());
+        move |voices, unit| {
+            let stage: Box<dyn AudioUnit64> = match placement {
+                Some(placement) => Box::new(placement.unit()),
+                None => Box::new(pan(voices.pan(position, frequency))),
+            };
+            let mut net = Net64::new(0, 2 * buses);
+            let voice = net.push(Box::new(Net64::wrap(unit) >> Net64::wrap(stage)));
+            net.connect_output(voice, 0, 2 * bus);
+            net.connect_output(voice, 1, 2 * bus + 1);
+            Box::new(net)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::song::song;
+
+    #[test]
+    fn test_plays_notes_on_their_buses() {
+        let song = song();
+        let mut params = ParamRegistry::default();
+        let mut desk = Desk::new(&mut params, &song, Setup::default());
+        let mut sequencer = Sequencer64::new(false, 2 * desk.buses());
+        let mut graph = Graph::new(0, 2);
+        let buses = {
+            let mut net = graph.edit();
+            let voices = net.push(Box::new(sequencer.backend()));
+            let buses = desk.build(&mut params, &mut net, voices);
+            net.connect_output(buses.mix, 0, 0);
+            net.connect_output(buses.mix, 1, 1);
+            buses
+        };
+        assert_eq!(buses.gains.len(), song.tracks.len() + 1);
+        let mut voices = desk.voice_pool();
+        let action = Action::Note {
+            instrument: Instrument::Organ,
+            frequency: 440.0,
+            duration: 1.0,
+            track: Some(1),
+            velocity: 1.0,
+            delay: 0.0,
+            bank: None,
+        };
+        let note = desk.note(&action, 0.0, 0.5).unwrap();
+        assert_eq!(note.duration, 0.5);
+        assert_eq!(desk.note(&Action::Bar, 0.0, 0.5), None);
+        let (unit, release) = desk.voice(&song, &note, 0.5)(&voices);
+        assert_eq!(unit.outputs(), 2 * desk.buses());
+        voices.note(&mut sequencer, 0.0, note.duration + release, unit);
+        let mut backend = graph.backend();
+        let peak = (0..4410).fold(0.0f64, |peak, _| peak.max(backend.get_stereo().0.abs()));
+        assert!(peak > 0.01);
+        // Metered on the bus of its track only.
+        assert!(desk.meters()[1].level().peak > 0.01);
+        assert_eq!(desk.meters()[0].level().peak, 0.0);
+    }
+}
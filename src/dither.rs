@@ -0,0 +1,132 @@
+//! Dither for where the output is cut down to 16 bit samples, which turns the
+//! distortion of quiet passages into a steady hiss far below them.
+
+use anyhow::bail;
+use fundsp::hacker::Wave64;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Steps of 16 bit samples either way from silence, as cpal converts to them
+/// by truncating and as fundsp writes WAV files by rounding.
+pub const DEVICE_STEPS: f64 = 32768.0;
+pub const WAV_STEPS: f64 = 32767.49;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Dither {
+    /// Samples are cut down as they are.
+    Off,
+    /// Triangular noise of a step either way is added before rounding.
+    #[default]
+    Tpdf,
+    /// Triangular noise, with the rounding error of each sample taken off the
+    /// next, which moves the hiss up to high frequencies where it is heard less.
+    Shaped,
+}
+
+impl Dither {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "off" => Ok(Dither::Off),
+            "tpdf" => Ok(Dither::Tpdf),
+            "shaped" => Ok(Dither::Shaped),
+            _ => bail!("unknown dither {}, use off, tpdf or shaped", value),
+        }
+    }
+}
+
+/// Rounds the samples of each channel to the steps of 16 bit ones.
+pub struct Quantizer {
+    dither: Dither,
+    steps: f64,
+    /// Rounding error of the last sample of each channel, in steps.
+    errors: Vec<f64>,
+    rng: StdRng,
+}
+
+impl Quantizer {
+    pub fn new(dither: Dither, steps: f64, channels: usize) -> Self {
+        Self {
+            dither,
+            steps,
+            errors: vec![0.0; channels],
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    /// The sample `value` on `channel` moved onto a step, or left as it is
+    /// without dither.
+    pub fn quantize(&mut self, channel: usize, value: f64) -> f64 {
+        if self.dither == Dither::Off {
+            return value;
+        }
+        let wanted = value * self.steps
+            - match self.dither {
+                Dither::Shaped => self.errors[channel],
+                _ => 0.0,
+            };
+        let noise = self.rng.gen::<f64>() - self.rng.gen::<f64>();
+        let step = (wanted + noise).round();
+        // Taken before clipping, which would feed back ever larger errors.
+        self.errors[channel] = step - wanted;
+        step.clamp(-self.steps.floor(), (self.steps - 1.0).ceil()) / self.steps
+    }
+}
+
+/// Dithers `wave` for writing it to a 16 bit WAV file.
+pub fn dither_wave(wave: &mut Wave64, dither: Dither) {
+    let mut quantizer = Quantizer::new(dither, WAV_STEPS, wave.channels());
+    let len = wave.len();
+    for i in 0..len {
+        for channel in 0..wave.channels() {
+            let value = quantizer.quantize(channel, wave.at(channel, i));
+            wave.set(channel, i, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Errors of `dither` on a constant a quarter step above silence, and
+    /// their low frequency part as a moving average over 16 samples.
+    fn errors(dither: Dither) -> (Vec<f64>, f64) {
+        let mut quantizer = Quantizer::new(dither, DEVICE_STEPS, 1);
+        let value = 0.25 / DEVICE_STEPS;
+        let errors: Vec<f64> = (0..100_000)
+            .map(|_| (quantizer.quantize(0, value) - value) * DEVICE_STEPS)
+            .collect();
+        let low = errors
+            .chunks(16)
+            .map(|chunk| (chunk.iter().sum::<f64>() / 16.0).powi(2))
+            .sum::<f64>();
+        (errors, low)
+    }
+
+    #[test]
+    fn test_dither_on_steps_without_bias() {
+        let (tpdf, tpdf_low) = errors(Dither::Tpdf);
+        // Samples land on steps, which truncate to the same ones.
+        assert!(tpdf.iter().all(|error| (error + 0.25).fract() == 0.0));
+        // Quieter than a step, the signal survives on average.
+        assert!((tpdf.iter().sum::<f64>() / tpdf.len() as f64).abs() < 0.01);
+        let (shaped, shaped_low) = errors(Dither::Shaped);
+        assert!((shaped.iter().sum::<f64>() / shaped.len() as f64).abs() < 0.01);
+        assert!(shaped_low < tpdf_low / 4.0);
+        assert_eq!(Dither::parse("shaped").unwrap(), Dither::Shaped);
+        assert!(Dither::parse("rectangular").is_err());
+    }
+
+    #[test]
+    fn test_dither_wave_clips_to_steps() {
+        let mut wave = Wave64::new(2, 44100.0);
+        wave.push((1.0, -1.0));
+        wave.push((0.5, 0.0));
+        dither_wave(&mut wave, Dither::Shaped);
+        assert_eq!((wave.at(0, 0) * WAV_STEPS).round(), 32767.0);
+        assert_eq!((wave.at(1, 0) * WAV_STEPS).round(), -32767.0);
+        let step = wave.at(0, 1) * WAV_STEPS;
+        assert!((step - step.round()).abs() < 1e-9);
+        assert!((step - 0.5 * WAV_STEPS).abs() <= 2.0);
+    }
+}
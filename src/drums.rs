@@ -0,0 +1,226 @@
+//! Drum kit sounds and a library of classic grooves to start jams from.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+use crate::arrangement::Arrangement;
+use crate::instrument::Instrument;
+use crate::note::Note;
+use crate::pattern::{Pattern, Step};
+
+/// Beats of a sixteenth note, the grid grooves are written on.
+const SIXTEENTH: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Drum {
+    Kick,
+    Rim,
+    Snare,
+    ClosedHat,
+    OpenHat,
+}
+
+impl Drum {
+    pub const ALL: [Drum; 5] = [
+        Drum::Kick,
+        Drum::Rim,
+        Drum::Snare,
+        Drum::ClosedHat,
+        Drum::OpenHat,
+    ];
+
+    /// Key of the drum in the General MIDI percussion map.
+    pub fn key(self) -> i32 {
+        match self {
+            Drum::Kick => 36,
+            Drum::Rim => 37,
+            Drum::Snare => 38,
+            Drum::ClosedHat => 42,
+            Drum::OpenHat => 46,
+        }
+    }
+
+    /// Parses the name of a drum, such as `kick` or `open-hat`.
+    pub fn parse(name: &str) -> Result<Self, anyhow::Error> {
+        match name {
+            "kick" => Ok(Drum::Kick),
+            "rim" => Ok(Drum::Rim),
+            "snare" => Ok(Drum::Snare),
+            "closed-hat" => Ok(Drum::ClosedHat),
+            "open-hat" => Ok(Drum::OpenHat),
+            _ => bail!(
+                "unknown drum {}, there are kick, rim, snare, closed-hat and open-hat",
+                name
+            ),
+        }
+    }
+
+    pub fn from_key(key: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|drum| drum.key() == key)
+    }
+
+    /// The note playing the drum on the `Drums` instrument.
+    pub fn note(self) -> Note {
+        Note::from_midi(self.key() as u8)
+    }
+
+    /// Builds a mono voice of the drum. `velocity` in 0...1 scales its level.
+    pub fn voice(self, velocity: f64) -> Box<dyn AudioUnit64> {
+        match self {
+            Drum::Kick => Box::new(
+                (envelope(|t| 50.0 + 110.0 * exp(-t * 30.0)) >> sine())
+                    * envelope(|t| exp(-t * 8.0))
+                    * velocity,
+            ),
+            Drum::Rim => Box::new(
+                (noise() >> bandpass_hz(2500.0, 4.0))
+                    * envelope(|t| exp(-t * 90.0))
+                    * (0.8 * velocity),
+            ),
+            Drum::Snare => Box::new(
+                ((noise() >> highpass_hz(1000.0, 0.7)) * envelope(|t| exp(-t * 18.0)) * 0.4
+                    + sine_hz(185.0) * envelope(|t| exp(-t * 25.0)) * 0.5)
+                    * velocity,
+            ),
+            Drum::ClosedHat => Box::new(
+                (noise() >> highpass_hz(7000.0, 0.7))
+                    * envelope(|t| exp(-t * 60.0))
+                    * (0.3 * velocity),
+            ),
+            Drum::OpenHat => Box::new(
+                (noise() >> highpass_hz(7000.0, 0.7))
+                    * envelope(|t| exp(-t * 10.0))
+                    * (0.3 * velocity),
+            ),
+        }
+    }
+}
+
+/// The drum that a note of `frequency` plays, picked by its MIDI key.
+pub fn drum_at(frequency: f64) -> Option<Drum> {
+    let key = 69.0 + 12.0 * (frequency / 440.0).log2();
+    Drum::from_key(key.round() as i32)
+}
+
+/// A bar of 4/4 on the sixteenth grid, with a row of `x` for hits and `.`
+/// for rests per drum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Groove {
+    pub name: &'static str,
+    rows: &'static [(Drum, &'static str)],
+}
+
+pub const GROOVES: [Groove; 4] = [
+    Groove {
+        name: "four-on-the-floor",
+        rows: &[
+            (Drum::Kick, "x...x...x...x..."),
+            (Drum::Snare, "....x.......x..."),
+            (Drum::OpenHat, "..x...x...x...x."),
+        ],
+    },
+    Groove {
+        name: "breakbeat",
+        rows: &[
+            (Drum::Kick, "x.x.......xx...."),
+            (Drum::Snare, "....x..x.x..x..x"),
+            (Drum::ClosedHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    Groove {
+        name: "bossa",
+        rows: &[
+            (Drum::Kick, "x..xx..xx..xx..x"),
+            (Drum::Rim, "x..x..x...x..x.."),
+            (Drum::ClosedHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    Groove {
+        name: "half-time",
+        rows: &[
+            (Drum::Kick, "x......x..x....."),
+            (Drum::Snare, "........x......."),
+            (Drum::ClosedHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+];
+
+impl Groove {
+    pub fn parse(name: &str) -> Result<Self, anyhow::Error> {
+        match GROOVES.into_iter().find(|groove| groove.name == name) {
+            Some(groove) => Ok(groove),
+            None => {
+                let names: Vec<&str> = GROOVES.iter().map(|groove| groove.name).collect();
+                bail!("unknown groove {}, try one of {}", name, names.join(", "))
+            }
+        }
+    }
+
+    /// The hits of the groove as a pattern for the `Drums` instrument.
+    pub fn pattern(&self) -> Pattern {
+        let mut steps = vec![];
+        for &(drum, row) in self.rows {
+            for (i, _) in row.char_indices().filter(|&(_, hit)| hit == 'x') {
+                steps.push(Step {
+                    beat: i as f64 * SIXTEENTH,
+                    duration: SIXTEENTH,
+                    note: drum.note(),
+                    ratchet: 1,
+                });
+            }
+        }
+        steps.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+        let sixteenths = self.rows.iter().map(|(_, row)| row.len()).max();
+        Pattern {
+            length: sixteenths.unwrap_or_default() as f64 * SIXTEENTH,
+            steps,
+        }
+    }
+}
+
+impl Arrangement {
+    /// Adds a drum track looping `groove` for as long as the song plays, and
+    /// returns its index.
+    pub fn drum_track(&mut self, groove: &Groove) -> usize {
+        let pattern = groove.pattern();
+        let length = pattern.length;
+        let pattern = self.pattern(pattern);
+        self.loop_track(Instrument::Drums, &[(pattern, 1)], length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::get_note_frequency;
+
+    #[test]
+    fn test_grooves_are_one_bar() {
+        for groove in GROOVES {
+            assert_eq!(groove.pattern().length, 4.0, "{}", groove.name);
+        }
+        let bossa = Groove::parse("bossa").unwrap().pattern();
+        let rims: Vec<f64> = bossa
+            .steps
+            .iter()
+            .filter(|step| step.note == Drum::Rim.note())
+            .map(|step| step.beat)
+            .collect();
+        assert_eq!(rims, [0.0, 0.75, 1.5, 2.5, 3.25]);
+        assert!(Groove::parse("polka").is_err());
+    }
+
+    #[test]
+    fn test_notes_pick_drums() {
+        for drum in Drum::ALL {
+            assert_eq!(drum_at(get_note_frequency(&drum.note())), Some(drum));
+        }
+        assert_eq!(drum_at(440.0), None);
+        assert_eq!(Drum::parse("open-hat").unwrap(), Drum::OpenHat);
+        assert!(Drum::parse("cowbell").is_err());
+        let mut kick = Drum::Kick.voice(1.0);
+        let wave = Wave64::render(44100.0, 0.5, &mut *kick);
+        assert!(wave.amplitude() > 0.5);
+        assert!(wave.at(0, 22000).abs() < 0.05);
+    }
+}
@@ -0,0 +1,240 @@
+//! The song rendered without an audio device, block by block, for the browser
+//! build and for offline renders.
+
+use fundsp::hacker::*;
+
+use crate::arrangement::Arrangement;
+use crate::crossfade::Crossfader;
+use crate::desk::{Desk, Setup};
+use crate::graph::{node, serial, Graph};
+use crate::mixer::Mixer;
+use crate::param::ParamRegistry;
+use crate::schedule::Schedule;
+use crate::song::song;
+use crate::transport::{Bar, Transport};
+use crate::voice::VoicePool;
+
+/// Frames rendered at a time by `render_wave`, like an audio callback would.
+const BLOCK: usize = 256;
+/// Time the last notes of the song need to ring out.
+const TAIL_SECONDS: f64 = 2.0;
+
+/// Plays the song without a separate scheduling thread: events are
+/// dispatched block by block right before the block that needs them,
+/// through the same desk as live playback.
+pub struct Engine {
+    sequencer: Sequencer64,
+    voices: VoicePool,
+    /// Kept for the network to be played by its backend.
+    _graph: Graph,
+    net: NetBackend64,
+    params: ParamRegistry,
+    desk: Desk,
+    transport: Transport,
+    schedule: Schedule,
+    song: Arrangement,
+    sample_rate: f64,
+    /// Seconds rendered so far.
+    time: f64,
+    /// The latest frame of the master bus followed by those of the tracks
+    /// after the mixer, left and right of each.
+    frame: Vec<f64>,
+    /// Interleaved stereo output of the latest block.
+    output: Vec<f32>,
+}
+
+impl Engine {
+    pub fn new(sample_rate: f64, bpm: f64) -> Self {
+        Self::with_song(song(), sample_rate, bpm)
+    }
+
+    /// Plays `song` instead of the built in one.
+    pub fn with_song(song: Arrangement, sample_rate: f64, bpm: f64) -> Self {
+        Self::with_setup(song, Setup::default(), sample_rate, bpm)
+    }
+
+    /// Plays `song` through a desk set up with `setup`.
+    pub fn with_setup(song: Arrangement, setup: Setup, sample_rate: f64, bpm: f64) -> Self {
+        let mut params = ParamRegistry::default();
+        let mut desk = Desk::new(&mut params, &song, setup);
+        let tracks = song.tracks.len();
+        let mut sequencer = Sequencer64::new(false, 2 * desk.buses());
+        sequencer.set_sample_rate(sample_rate);
+        let mut graph = Graph::new(0, 2 + 2 * tracks);
+        {
+            let mut net = graph.edit();
+            let voices = net.push_named("voices", Box::new(sequencer.backend()));
+            let buses = desk.build(&mut params, &mut net, voices);
+            let master = net
+                .add(serial([node(buses.mix), desk.master()]))
+                .expect("the master bus is stereo");
+            for (channel, &(master, output)) in master.outputs.iter().enumerate() {
+                net.connect_output(master, output, channel);
+            }
+            for (track, &gain) in buses.gains[..tracks].iter().enumerate() {
+                net.connect_output(gain, 0, 2 + 2 * track);
+                net.connect_output(gain, 1, 3 + 2 * track);
+            }
+        }
+        graph.set_sample_rate(sample_rate);
+        let net = graph.backend();
+        let mut schedule = Schedule::new();
+        schedule.bar(0.0);
+        Self {
+            sequencer,
+            voices: desk.voice_pool(),
+            _graph: graph,
+            net,
+            params,
+            desk,
+            transport: Transport::new(bpm, song.meter.clone()),
+            schedule,
+            song,
+            sample_rate,
+            time: 0.0,
+            frame: vec![0.0; 2 + 2 * tracks],
+            output: vec![],
+        }
+    }
+
+    /// The crossfader between the banks of tracks that have two, on the
+    /// first one to begin with.
+    pub fn crossfader(&self) -> &Crossfader {
+        self.desk.crossfader()
+    }
+
+    /// The parameters of the desk, which can be set by name, and its mixer.
+    pub fn controls(&mut self) -> (&ParamRegistry, &mut Mixer) {
+        (&self.params, self.desk.mixer_mut())
+    }
+
+    /// Length of the song in seconds, including the tail of its last notes.
+    pub fn duration(&self) -> f64 {
+        self.transport.time_of(self.song.length()) + TAIL_SECONDS
+    }
+
+    /// Renders the next `frames` frames, interleaved left and right.
+    pub fn render(&mut self, frames: usize) -> &[f32] {
+        let end = self.time + frames as f64 / self.sample_rate;
+        self.dispatch(end);
+        self.output.resize(frames * 2, 0.0);
+        for frame in self.output.chunks_mut(2) {
+            self.net.tick(&[], &mut self.frame);
+            frame[0] = self.frame[0] as f32;
+            frame[1] = self.frame[1] as f32;
+        }
+        self.time = end;
+        &self.output
+    }
+
+    /// Renders the next `seconds` into a stereo wave.
+    pub fn render_wave(&mut self, seconds: f64) -> Wave64 {
+        let frames = (seconds * self.sample_rate).round() as usize;
+        let mut wave = Wave64::with_capacity(2, self.sample_rate, frames);
+        let mut rendered = 0;
+        while rendered < frames {
+            let block = min(BLOCK, frames - rendered);
+            for frame in self.render(block).chunks(2) {
+                wave.push((frame[0] as f64, frame[1] as f64));
+            }
+            rendered += block;
+        }
+        wave
+    }
+
+    /// Renders the next `seconds` into a stereo wave of the mix and one of
+    /// each track after the mixer, which add up to the mix but for the
+    /// reverb returned into it and the width of the master bus.
+    pub fn render_stems(&mut self, seconds: f64) -> (Wave64, Vec<Wave64>) {
+        let frames = (seconds * self.sample_rate).round() as usize;
+        let wave = || Wave64::with_capacity(2, self.sample_rate, frames);
+        let mut mixed = wave();
+        let mut stems: Vec<Wave64> = self.song.tracks.iter().map(|_| wave()).collect();
+        let mut rendered = 0;
+        while rendered < frames {
+            let block = min(BLOCK, frames - rendered);
+            self.dispatch(self.time + block as f64 / self.sample_rate);
+            for _ in 0..block {
+                self.net.tick(&[], &mut self.frame);
+                mixed.push((self.frame[0], self.frame[1]));
+                for (stem, bus) in stems.iter_mut().zip(self.frame[2..].chunks(2)) {
+                    stem.push((bus[0], bus[1]));
+                }
+            }
+            self.time += block as f64 / self.sample_rate;
+            rendered += block;
+        }
+        (mixed, stems)
+    }
+
+    /// Pushes every event starting before `end` seconds to the sequencer.
+    fn dispatch(&mut self, end: f64) {
+        while let Some(beat) = self.schedule.next_beat() {
+            let at = self.transport.time_of(beat);
+            if at >= end {
+                break;
+            }
+            while let Some(event) = self.schedule.pop_due(beat) {
+                let seconds_per_beat = self.transport.seconds_per_beat();
+                match self.desk.note(&event.action, at, seconds_per_beat) {
+                    Some(note) => {
+                        let build = self.desk.voice(&self.song, &note, seconds_per_beat);
+                        let (unit, release) = build(&self.voices);
+                        let end = note.start + note.duration + release;
+                        self.voices.note(&mut self.sequencer, note.start, end, unit);
+                    }
+                    None => {
+                        if !self.transport.is_playing(self.song.length()) {
+                            continue;
+                        }
+                        let bar = self.transport.next_bar();
+                        if let Bar::Song { from, to, .. } = bar {
+                            self.song.schedule(&mut self.schedule, from, to, beat);
+                        }
+                        self.schedule.bar(beat + bar.signature().bar_beats());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_renders_song() {
+        let mut engine = Engine::new(8000.0, 240.0);
+        let peak = (0..100)
+            .flat_map(|_| engine.render(128).to_vec())
+            .fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!(peak > 0.01);
+        assert_eq!(engine.render(64).len(), 128);
+    }
+
+    #[test]
+    fn test_stems_add_up_to_mix() {
+        let mut engine = Engine::with_song(song(), 8000.0, 240.0);
+        let (mix, stems) = engine.render_stems(2.0);
+        assert_eq!(stems.len(), song().tracks.len());
+        for i in (0..mix.len()).step_by(7) {
+            let sum: f64 = stems.iter().map(|stem| stem.at(0, i)).sum();
+            assert!((sum - mix.at(0, i)).abs() < 1e-9);
+        }
+        // Every track is heard on its own stem.
+        assert!(stems.iter().all(|stem| stem.amplitude() > 0.001));
+    }
+
+    #[test]
+    fn test_renders_through_mixer() {
+        let mut engine = Engine::new(8000.0, 240.0);
+        let (_, mixer) = engine.controls();
+        for track in 0..mixer.tracks() {
+            mixer.toggle_mute(track);
+        }
+        let wave = engine.render_wave(2.0);
+        let peak = (4000..wave.len()).fold(0.0f64, |peak, i| peak.max(wave.at(0, i).abs()));
+        assert!(peak < 1e-6);
+    }
+}
@@ -0,0 +1,168 @@
+//! Evolving presets by ear: a generation of candidates is played one after
+//! another for the player to rate, and the next generation is bred from the
+//! ones rated highest, crossing two of them and mutating the result, with
+//! the best one kept as it was.
+
+use anyhow::bail;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::param::ParamRegistry;
+use crate::preset::Preset;
+
+/// Candidates in each generation.
+pub const POPULATION: usize = 8;
+/// Highest rating, the lowest being 1.
+pub const MAX_RATING: u8 = 5;
+/// Chance of each parameter of a candidate being mutated.
+const MUTATION_CHANCE: f64 = 0.3;
+/// Farthest a mutation moves a parameter on its knob.
+const MUTATION_REACH: f64 = 0.15;
+
+pub fn parse_rating(value: &str) -> Result<u8, anyhow::Error> {
+    match value.parse::<u8>()? {
+        rating @ 1..=MAX_RATING => Ok(rating),
+        _ => bail!("ratings go from 1 to {}", MAX_RATING),
+    }
+}
+
+pub struct Evolution {
+    rng: StdRng,
+    /// Parameters that are left as they are.
+    locked: Vec<String>,
+    /// Candidates of the generation, with their ratings so far.
+    candidates: Vec<(Preset, Option<u8>)>,
+    playing: usize,
+    generation: usize,
+}
+
+impl Evolution {
+    /// Starts from mutations of the parameters of `params` as they are, but
+    /// for those `locked`, and plays the first candidate, which is them
+    /// unchanged.
+    pub fn new(params: &ParamRegistry, locked: &[String], seed: u64) -> Self {
+        let mut evolution = Self {
+            rng: StdRng::seed_from_u64(seed),
+            locked: locked.to_vec(),
+            candidates: vec![],
+            playing: 0,
+            generation: 0,
+        };
+        let preset = Preset::capture(params);
+        let mut candidates = vec![(preset.clone(), None)];
+        while candidates.len() < POPULATION {
+            candidates.push((evolution.mutate(params, preset.clone()), None));
+        }
+        evolution.candidates = candidates;
+        evolution
+    }
+
+    /// The generation and the candidate in it playing, counted from zero.
+    pub fn playing(&self) -> (usize, usize) {
+        (self.generation, self.playing)
+    }
+
+    /// Rates the candidate playing and plays the next, the first of a new
+    /// generation once all of them are rated.
+    pub fn rate(&mut self, params: &ParamRegistry, rating: u8) {
+        self.candidates[self.playing].1 = Some(rating);
+        self.playing += 1;
+        if self.playing == self.candidates.len() {
+            self.breed(params);
+        }
+        self.candidates[self.playing].0.apply(params);
+    }
+
+    fn breed(&mut self, params: &ParamRegistry) {
+        let mut rated = std::mem::take(&mut self.candidates);
+        rated.sort_by_key(|&(_, rating)| std::cmp::Reverse(rating));
+        let total: u32 = rated
+            .iter()
+            .map(|&(_, rating)| rating.unwrap_or(0) as u32)
+            .sum();
+        // Parents picked by chance in proportion to their ratings.
+        let pick = |rng: &mut StdRng| {
+            let mut left = rng.gen_range(0..total);
+            for (preset, rating) in &rated {
+                let rating = rating.unwrap_or(0) as u32;
+                if left < rating {
+                    return preset.clone();
+                }
+                left -= rating;
+            }
+            unreachable!()
+        };
+        let mut candidates = vec![(rated[0].0.clone(), None)];
+        while candidates.len() < POPULATION {
+            let (a, b) = (pick(&mut self.rng), pick(&mut self.rng));
+            let child = self.cross(&a, &b);
+            candidates.push((self.mutate(params, child), None));
+        }
+        self.candidates = candidates;
+        self.playing = 0;
+        self.generation += 1;
+    }
+
+    /// Each value from either parent, as it happens.
+    fn cross(&mut self, a: &Preset, b: &Preset) -> Preset {
+        Preset {
+            params: a
+                .params
+                .iter()
+                .map(|(name, value)| match b.get(name) {
+                    Some(other) if self.rng.gen_bool(0.5) => (name.clone(), other),
+                    _ => (name.clone(), *value),
+                })
+                .collect(),
+        }
+    }
+
+    /// Moves some of the values a little along their knobs, within the part
+    /// of their range that randomizing keeps to.
+    fn mutate(&mut self, params: &ParamRegistry, mut preset: Preset) -> Preset {
+        for (name, value) in &mut preset.params {
+            let Some(param) = params.get(name) else {
+                continue;
+            };
+            if self.locked.contains(name) || !self.rng.gen_bool(MUTATION_CHANCE) {
+                continue;
+            }
+            let spec = param.spec();
+            let (low, high) = spec.random;
+            let reach = self.rng.gen_range(-MUTATION_REACH..=MUTATION_REACH);
+            let position = (spec.normalize(*value) + reach).clamp(low, high);
+            *value = spec.denormalize(position);
+        }
+        preset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::Spec;
+
+    #[test]
+    fn test_parse_rating() {
+        assert_eq!(parse_rating("5").unwrap(), 5);
+        assert!(parse_rating("0").is_err());
+        assert!(parse_rating("6").is_err());
+    }
+
+    #[test]
+    fn test_evolves_toward_rated_highest() {
+        let mut params = ParamRegistry::default();
+        let width = params.register("width", Spec::linear(0.0, 2.0, 1.0));
+        let filter = params.register("filter", Spec::linear(-1.0, 1.0, 0.0));
+        let locked = ["filter".to_string()];
+        let mut evolution = Evolution::new(&params, &locked, 3);
+        // Rated by how wide they are, over several generations.
+        for _ in 0..20 * POPULATION {
+            let rating = (1.0 + width.value() * 2.0).round() as u8;
+            evolution.rate(&params, rating.clamp(1, MAX_RATING));
+        }
+        assert_eq!(evolution.playing(), (20, 0));
+        assert!(width.value() > 1.5);
+        assert_eq!(filter.value(), 0.0);
+    }
+}
@@ -0,0 +1,253 @@
+//! Feedback loops around effects, each feeding what comes out of its effect
+//! back into it after a delay, for the repeats of a dub delay or a drone
+//! that sustains itself. A limiter in the loop holds what goes round under
+//! full scale, so that a loop fed back above 1 builds up to a steady drone
+//! instead of blowing up.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+/// Level that the limiter holds the loop under.
+const CEILING: f64 = 1.0;
+/// Seconds for the limiter to recover by 60 dB once the loop gets quieter.
+const RELEASE_SECONDS: f64 = 0.5;
+/// Highest level fed back, above 1 only held by the limiter.
+pub const MAX_LEVEL: f64 = 1.5;
+/// Longest echo.
+const MAX_ECHO_SECONDS: f64 = 4.0;
+/// Corner frequency of the lowpass that darkens each repeat of an echo.
+const ECHO_HZ: f64 = 2500.0;
+
+/// An effect of as many inputs as outputs in a feedback loop, passing on
+/// what comes out of it.
+#[derive(Clone)]
+pub struct Feedback {
+    effect: Box<dyn AudioUnit64>,
+    /// Seconds that the loop takes to come round, and the level fed back.
+    seconds: f64,
+    level: f64,
+    /// Share of what each channel feeds back that goes into the next one
+    /// instead, from 0 to 1.
+    cross: f64,
+    sample_rate: f64,
+    /// Delay line of each channel and where it is at.
+    lines: Vec<Vec<f64>>,
+    index: usize,
+    /// Gain of the limiter and the factor it recovers by each sample.
+    gain: f64,
+    recovery: f64,
+    /// Frame handed to the effect, and frames that blocks are ticked by.
+    fed: Vec<f64>,
+    frames: (Vec<f64>, Vec<f64>),
+}
+
+impl Feedback {
+    pub fn new(seconds: f64, level: f64, cross: f64, effect: Box<dyn AudioUnit64>) -> Self {
+        assert_eq!(effect.inputs(), effect.outputs());
+        let channels = effect.inputs();
+        let mut feedback = Self {
+            effect,
+            seconds,
+            level,
+            cross,
+            sample_rate: 0.0,
+            lines: vec![vec![]; channels],
+            index: 0,
+            gain: 1.0,
+            recovery: 1.0,
+            fed: vec![0.0; channels],
+            frames: (vec![0.0; channels], vec![0.0; channels]),
+        };
+        feedback.set_sample_rate(DEFAULT_SR);
+        feedback
+    }
+}
+
+impl AudioUnit64 for Feedback {
+    fn reset(&mut self) {
+        self.effect.reset();
+        for line in &mut self.lines {
+            line.fill(0.0);
+        }
+        self.index = 0;
+        self.gain = 1.0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.effect.set_sample_rate(sample_rate);
+        if self.sample_rate != sample_rate {
+            self.sample_rate = sample_rate;
+            // A sample at least, so that nothing feeds itself at once.
+            let samples = (self.seconds * sample_rate).round().max(1.0) as usize;
+            for line in &mut self.lines {
+                *line = vec![0.0; samples];
+            }
+            self.index = 0;
+            self.recovery = db_amp(60.0 / (RELEASE_SECONDS * sample_rate));
+        }
+    }
+
+    fn tick(&mut self, input: &[f64], output: &mut [f64]) {
+        let channels = self.fed.len();
+        for (channel, (fed, x)) in self.fed.iter_mut().zip(input).enumerate() {
+            let own = self.lines[channel][self.index];
+            let crossed = self.lines[(channel + channels - 1) % channels][self.index];
+            let back = (1.0 - self.cross) * own + self.cross * crossed;
+            *fed = x + self.level * back;
+        }
+        self.effect.tick(&self.fed, output);
+        // Down at once to what the peak needs, back up slowly.
+        let peak = output.iter().fold(0.0, |peak: f64, x| peak.max(x.abs()));
+        self.gain = (self.gain * self.recovery).min(1.0);
+        if peak * self.gain > CEILING {
+            self.gain = CEILING / peak;
+        }
+        for (line, x) in self.lines.iter_mut().zip(output.iter_mut()) {
+            *x *= self.gain;
+            line[self.index] = *x;
+        }
+        if let Some(line) = self.lines.first() {
+            self.index = (self.index + 1) % line.len();
+        }
+    }
+
+    fn process(&mut self, size: usize, input: &[&[f64]], output: &mut [&mut [f64]]) {
+        let (mut from, mut to) = std::mem::take(&mut self.frames);
+        for i in 0..size {
+            for (x, input) in from.iter_mut().zip(input) {
+                *x = input[i];
+            }
+            self.tick(&from, &mut to);
+            for (x, output) in to.iter().zip(output.iter_mut()) {
+                output[i] = *x;
+            }
+        }
+        self.frames = (from, to);
+    }
+
+    fn inputs(&self) -> usize {
+        self.fed.len()
+    }
+
+    fn outputs(&self) -> usize {
+        self.fed.len()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, self.outputs())
+    }
+
+    fn get_id(&self) -> u64 {
+        0x4665_6564
+    }
+
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+
+    fn allocate(&mut self) {
+        self.effect.allocate();
+    }
+}
+
+/// A dub delay on a stereo track, passing it on with repeats that get
+/// darker each time round.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Echo {
+    pub seconds: f64,
+    /// Level of each repeat to the one before, up to `MAX_LEVEL`.
+    pub level: f64,
+    /// Share of each repeat that crosses over to the other side, from 0 to
+    /// 1 for repeats that go back and forth.
+    pub cross: f64,
+}
+
+impl Echo {
+    /// Parses `SECONDS,FEEDBACK[,CROSS]`, such as `0.375,0.6,1`. The cross
+    /// defaults to 0.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+        let (seconds, level, cross) = match fields[..] {
+            [seconds, level] => (seconds.parse()?, level.parse()?, 0.0),
+            [seconds, level, cross] => (seconds.parse()?, level.parse()?, cross.parse()?),
+            _ => bail!("an echo is SECONDS,FEEDBACK[,CROSS]: {}", value),
+        };
+        if !(seconds > 0.0 && seconds <= MAX_ECHO_SECONDS) {
+            bail!("an echo takes up to {} seconds", MAX_ECHO_SECONDS);
+        }
+        if !(0.0..=MAX_LEVEL).contains(&level) {
+            bail!("the echo feedback must be between 0 and {}", MAX_LEVEL);
+        }
+        if !(0.0..=1.0).contains(&cross) {
+            bail!("the echo cross must be between 0 and 1");
+        }
+        Ok(Self {
+            seconds,
+            level,
+            cross,
+        })
+    }
+
+    pub fn unit(self) -> Box<dyn AudioUnit64> {
+        let darken = lowpass_hz(ECHO_HZ, 0.5) | lowpass_hz(ECHO_HZ, 0.5);
+        let repeats = Feedback::new(self.seconds, self.level, self.cross, Box::new(darken));
+        let wet = Net64::wrap(Box::new(delay(self.seconds) | delay(self.seconds)))
+            >> Net64::wrap(Box::new(repeats));
+        Box::new(Net64::wrap(Box::new(multipass::<U2>())) & wet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_echo() {
+        assert_eq!(
+            Echo::parse("0.375,0.6,1").unwrap(),
+            Echo {
+                seconds: 0.375,
+                level: 0.6,
+                cross: 1.0
+            }
+        );
+        assert_eq!(Echo::parse("0.5, 0.5").unwrap().cross, 0.0);
+        assert!(Echo::parse("0,0.5").is_err());
+        assert!(Echo::parse("0.5,2").is_err());
+        assert!(Echo::parse("0.5,0.5,1.5").is_err());
+        assert!(Echo::parse("0.5").is_err());
+    }
+
+    #[test]
+    fn test_loop_repeats_and_crosses() {
+        let mut feedback = Feedback::new(0.01, 0.5, 1.0, Box::new(multipass::<U2>()));
+        feedback.set_sample_rate(1000.0);
+        let mut output = [0.0; 2];
+        feedback.tick(&[1.0, 0.0], &mut output);
+        assert_eq!(output, [1.0, 0.0]);
+        let repeats: Vec<[f64; 2]> = (1..=20)
+            .map(|_| {
+                feedback.tick(&[0.0, 0.0], &mut output);
+                output
+            })
+            .collect();
+        // Over to the right after a round, back to the left after two.
+        assert_eq!(repeats[9], [0.0, 0.5]);
+        assert_eq!(repeats[19], [0.25, 0.0]);
+        assert_eq!(repeats[4], [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_limiter_holds_loop() {
+        let mut feedback = Feedback::new(0.001, MAX_LEVEL, 0.0, Box::new(pass()));
+        feedback.set_sample_rate(1000.0);
+        let mut output = [0.0];
+        let mut peak: f64 = 0.0;
+        for _ in 0..10000 {
+            feedback.tick(&[0.5], &mut output);
+            peak = peak.max(output[0].abs());
+        }
+        assert!(peak <= CEILING + 1e-9);
+        assert!(output[0] > 0.9);
+    }
+}
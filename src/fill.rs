@@ -0,0 +1,190 @@
+//! Drum fills, generated rolls that replace the last bar of a groove now and then.
+
+use anyhow::bail;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::arrangement::{Arrangement, Section};
+use crate::drums::Drum;
+use crate::pattern::{Pattern, Step};
+
+/// Beats of the sixteenth notes that fills are played on.
+const SIXTEENTH: f64 = 0.25;
+
+/// Different fills a looping track goes through before they repeat.
+const LOOP_FILLS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fill {
+    /// Every this many repetitions of a pattern may end in a fill.
+    pub every: usize,
+    /// Chance of a fill where one may be, from 0 to 1.
+    pub probability: f64,
+    /// How long and dense fills are, from 0 to 1.
+    pub intensity: f64,
+    /// Seed of the fills, which are the same every time the song is played.
+    pub seed: u64,
+}
+
+impl Fill {
+    /// Parses `EVERY[,PROBABILITY[,INTENSITY]]`, such as `4,0.75,0.5` for a
+    /// fill in three out of four times every fourth repetition. Both default to 1
+    /// and 0.5.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut fields = value.split(',').map(str::trim);
+        let every: usize = fields.next().unwrap_or_default().parse()?;
+        let probability = fields.next().map_or(Ok(1.0), str::parse)?;
+        let intensity = fields.next().map_or(Ok(0.5), str::parse)?;
+        if every == 0 {
+            bail!("fills come every 1 or more repetitions");
+        }
+        if !(0.0..=1.0).contains(&probability) {
+            bail!("the fill probability must be between 0 and 1");
+        }
+        if !(0.0..=1.0).contains(&intensity) {
+            bail!("the fill intensity must be between 0 and 1");
+        }
+        if fields.next().is_some() {
+            bail!("too many fill fields: {}", value);
+        }
+        Ok(Self {
+            every,
+            probability,
+            intensity,
+            seed: 0,
+        })
+    }
+
+    /// `pattern` with its last `bar` beats replaced by a fill. It starts with
+    /// a kick and ramps up from sparse snare hits to a roll, the more and the
+    /// longer the higher the intensity.
+    pub fn apply(&self, pattern: &Pattern, bar: f64, rng: &mut impl Rng) -> Pattern {
+        let bar = bar.min(pattern.length);
+        let sixteenths = (bar / SIXTEENTH).round() as usize;
+        // From the last beat at the lowest intensity to the whole bar at the highest.
+        let length = ((sixteenths as f64 * (0.25 + 0.75 * self.intensity)).round() as usize)
+            .clamp(1, sixteenths.max(1));
+        let start = pattern.length - length as f64 * SIXTEENTH;
+        let mut steps: Vec<Step> = pattern
+            .steps
+            .iter()
+            .filter(|step| step.beat < start)
+            .cloned()
+            .collect();
+        let hit = |beat, drum: Drum, ratchet| Step {
+            beat,
+            duration: SIXTEENTH,
+            note: drum.note(),
+            ratchet,
+        };
+        steps.push(hit(start, Drum::Kick, 1));
+        for i in 0..length {
+            let ramp = (i + 1) as f64 / length as f64;
+            let beat = start + i as f64 * SIXTEENTH;
+            if i > 0 && rng.gen::<f64>() > ramp.max(self.intensity) {
+                continue;
+            }
+            // Rolls of thirty-seconds get likelier towards the end.
+            let ratchet = if rng.gen::<f64>() < ramp * self.intensity {
+                2
+            } else {
+                1
+            };
+            steps.push(hit(beat, Drum::Snare, ratchet));
+        }
+        Pattern {
+            length: pattern.length,
+            steps,
+        }
+    }
+}
+
+impl Arrangement {
+    /// Ends every `fill.every`th repetition of each pattern of track `index` in
+    /// a fill, by chance. A looping track plays through several different fills
+    /// before it loops.
+    pub fn fill(&mut self, index: usize, fill: &Fill) {
+        let mut rng = StdRng::seed_from_u64(fill.seed);
+        let bar = self.meter.signature(0).bar_beats();
+        let mut sections = self.tracks[index].sections.clone();
+        if let Some(length) = self.tracks[index].loop_length {
+            let cycles = fill.every * LOOP_FILLS;
+            sections = vec![sections; cycles].concat();
+            self.tracks[index].loop_length = Some(length * cycles as f64);
+        }
+        let mut filled: Vec<Section> = vec![];
+        let mut played = vec![0usize; self.patterns.len()];
+        for section in sections {
+            for _ in 0..section.repeat {
+                played[section.pattern] += 1;
+                let pattern = if played[section.pattern].is_multiple_of(fill.every)
+                    && rng.gen::<f64>() < fill.probability
+                {
+                    let pattern = fill.apply(&self.patterns[section.pattern], bar, &mut rng);
+                    self.pattern(pattern)
+                } else {
+                    section.pattern
+                };
+                match filled.last_mut() {
+                    Some(last) if last.pattern == pattern && last.level == section.level => {
+                        last.repeat += 1
+                    }
+                    _ => filled.push(Section {
+                        level: section.level,
+                        ..Section::new(pattern, 1)
+                    }),
+                }
+            }
+        }
+        self.tracks[index].sections = filled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drums::Groove;
+
+    #[test]
+    fn test_fill_ends_bar() {
+        let groove = Groove::parse("four-on-the-floor").unwrap().pattern();
+        let mut rng = StdRng::seed_from_u64(1);
+        let fill = Fill::parse("1,1,0").unwrap().apply(&groove, 4.0, &mut rng);
+        assert_eq!(fill.length, 4.0);
+        // Only the last beat changes, starting with a kick and a snare.
+        let kept = groove.steps.iter().filter(|step| step.beat < 3.0);
+        assert!(kept.eq(fill.steps.iter().filter(|step| step.beat < 3.0)));
+        let last: Vec<_> = fill.steps.iter().filter(|step| step.beat >= 3.0).collect();
+        assert_eq!(last[0].note, Drum::Kick.note());
+        assert_eq!(last[1].note, Drum::Snare.note());
+        assert_eq!(last[1].beat, 3.0);
+        let full = Fill::parse("1,1,1").unwrap().apply(&groove, 4.0, &mut rng);
+        let snares = full
+            .steps
+            .iter()
+            .filter(|step| step.note == Drum::Snare.note());
+        assert_eq!(snares.count(), 16);
+        assert!(Fill::parse("0").is_err());
+        assert!(Fill::parse("4,2").is_err());
+    }
+
+    #[test]
+    fn test_every_nth_repetition_is_filled() {
+        let mut song = Arrangement::default();
+        let track = song.drum_track(&Groove::parse("bossa").unwrap());
+        song.fill(track, &Fill::parse("2").unwrap());
+        let sections = &song.tracks[track].sections;
+        assert_eq!(song.tracks[track].loop_length, Some(4.0 * 8.0));
+        assert_eq!(sections.len(), 8);
+        assert!(sections
+            .iter()
+            .step_by(2)
+            .all(|section| section.pattern == 0));
+        assert!(sections[1].pattern != sections[3].pattern);
+
+        let mut never = Arrangement::default();
+        let track = never.drum_track(&Groove::parse("bossa").unwrap());
+        never.fill(track, &Fill::parse("2,0").unwrap());
+        assert_eq!(never.tracks[track].sections.len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+//! Spectral fingerprints of renders: the level of every octave band over a
+//! few segments of time. Golden files of them catch unintended changes to
+//! the sound without storing whole buffers, and tolerate rounding noise.
+
+use fundsp::hacker::*;
+
+use crate::spectrogram::{spectrogram, WINDOW};
+
+/// Segments of time the render is split into.
+pub const SEGMENTS: usize = 8;
+/// Edges of the octave bands in Hz.
+const BANDS: [f64; 9] = [
+    0.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// Level of bands without energy.
+const FLOOR_DB: f64 = -90.0;
+
+/// Band levels in decibels, one row per segment.
+pub fn fingerprint(samples: &[f64], sample_rate: f64) -> Vec<Vec<f64>> {
+    let frames = spectrogram(samples, WINDOW / 2);
+    let band = |bin: usize| {
+        let frequency = bin as f64 * sample_rate / WINDOW as f64;
+        BANDS.windows(2).position(|edges| frequency < edges[1])
+    };
+    (0..SEGMENTS)
+        .map(|segment| {
+            let segment =
+                &frames[segment * frames.len() / SEGMENTS..][..max(frames.len() / SEGMENTS, 1)];
+            let mut power = [0.0; BANDS.len() - 1];
+            for frame in segment {
+                for (bin, &db) in frame.iter().enumerate() {
+                    if let Some(band) = band(bin).filter(|_| db > FLOOR_DB) {
+                        power[band] += squared(db_amp(db));
+                    }
+                }
+            }
+            power
+                .iter()
+                .map(|&power| amp_db((power / segment.len() as f64).sqrt()).max(FLOOR_DB))
+                .collect()
+        })
+        .collect()
+}
+
+/// One line of levels per segment, with one decimal.
+pub fn format(fingerprint: &[Vec<f64>]) -> String {
+    fingerprint
+        .iter()
+        .map(|row| {
+            let levels: Vec<String> = row.iter().map(|db| format!("{:.1}", db)).collect();
+            levels.join(" ") + "\n"
+        })
+        .collect()
+}
+
+pub fn parse(text: &str) -> Result<Vec<Vec<f64>>, anyhow::Error> {
+    text.lines()
+        .map(|line| line.split_whitespace().map(|db| Ok(db.parse()?)).collect())
+        .collect()
+}
+
+/// Describes the first band further than `tolerance` decibels from the golden one.
+pub fn compare(actual: &[Vec<f64>], golden: &[Vec<f64>], tolerance: f64) -> Result<(), String> {
+    if actual.len() != golden.len() {
+        return Err(format!(
+            "{} segments, expected {}",
+            actual.len(),
+            golden.len()
+        ));
+    }
+    for (segment, (actual, golden)) in actual.iter().zip(golden).enumerate() {
+        for (band, (&actual, &golden)) in actual.iter().zip(golden).enumerate() {
+            if (actual - golden).abs() > tolerance {
+                return Err(format!(
+                    "segment {} band {} is at {:.1} dB, expected {:.1} dB",
+                    segment, band, actual, golden
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::Instrument;
+    use crate::offline::{render_song, render_unit, SAMPLE_RATE};
+    use crate::song::song;
+
+    /// Band levels may drift this much before a render counts as changed.
+    const TOLERANCE_DB: f64 = 1.0;
+
+    /// Compares against `src/golden/NAME.txt`, or rewrites it when the
+    /// `UPDATE_GOLDEN` environment variable is set.
+    fn check_golden(name: &str, samples: &[f64]) {
+        let actual = fingerprint(samples, SAMPLE_RATE);
+        let path = format!("{}/src/golden/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, format(&actual)).unwrap();
+            return;
+        }
+        let golden = std::fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("missing {}, run with UPDATE_GOLDEN=1", path));
+        if let Err(err) = compare(&actual, &parse(&golden).unwrap(), TOLERANCE_DB) {
+            panic!("{} changed: {}", name, err);
+        }
+    }
+
+    #[test]
+    fn test_instruments_match_golden() {
+        for (name, instrument) in [
+            ("pluck", Instrument::Pluck),
+            ("organ", Instrument::Organ),
+            ("click", Instrument::Click),
+        ] {
+            let mut voice = instrument.voice(440.0, 0.25, 1.0);
+            let wave = render_unit(&mut *voice, 0.8);
+            check_golden(name, wave.channel(0));
+        }
+    }
+
+    #[test]
+    fn test_song_matches_golden() {
+        let wave = render_song(song(), 160.0, 3.0);
+        check_golden("song", wave.channel(0));
+    }
+
+    #[test]
+    fn test_compare_reports_band() {
+        let golden = vec![vec![-10.0, -20.0]];
+        assert_eq!(compare(&[vec![-10.5, -20.0]], &golden, 1.0), Ok(()));
+        assert_eq!(
+            compare(&[vec![-10.0, -25.0]], &golden, 1.0),
+            Err("segment 0 band 1 is at -25.0 dB, expected -20.0 dB".to_string())
+        );
+        assert_eq!(parse(&format(&golden)).unwrap(), golden);
+    }
+}
@@ -0,0 +1,284 @@
+//! Just enough of FLAC to write 16 bit files losslessly: every block is
+//! predicted by the best of the fixed polynomials and its residual Rice coded,
+//! which needs no linear prediction but still makes the files much smaller.
+
+use fundsp::hacker::Wave64;
+
+/// Frames of every block but the last.
+pub const BLOCK: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 16;
+/// Highest order of the fixed predictors.
+const MAX_ORDER: usize = 4;
+/// Highest Rice parameter, above which it would mean an escape code.
+const MAX_RICE: u32 = 14;
+
+/// Packs values of any number of bits, most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits not yet written out as a whole byte, in the low `bits` bits.
+    pending: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for bit in (0..bits).rev() {
+            self.pending = self.pending << 1 | (value >> bit & 1);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.bytes.push(self.pending as u8);
+                (self.pending, self.bits) = (0, 0);
+            }
+        }
+    }
+
+    /// Writes `value` in two's complement in `bits` bits.
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    /// Pads with zero bits up to the next byte.
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+}
+
+/// Encodes `wave` as a 16 bit FLAC file.
+pub fn encode(wave: &Wave64) -> Vec<u8> {
+    assert!((1..=8).contains(&wave.channels()));
+    let channels: Vec<Vec<i64>> = (0..wave.channels())
+        .map(|channel| wave.channel(channel).iter().map(|&x| sample(x)).collect())
+        .collect();
+
+    let mut flac = b"fLaC".to_vec();
+    flac.extend_from_slice(&block_header(true, 0, 34));
+    flac.extend_from_slice(&stream_info(
+        wave.sample_rate(),
+        wave.channels(),
+        wave.len() as u64,
+    ));
+    for (number, start) in (0..wave.len()).step_by(BLOCK).enumerate() {
+        let end = (start + BLOCK).min(wave.len());
+        let blocks: Vec<&[i64]> = channels
+            .iter()
+            .map(|samples| &samples[start..end])
+            .collect();
+        flac.extend_from_slice(&frame(number as u64, &blocks));
+    }
+    flac
+}
+
+/// The 16 bit sample of `x`, converted as it is for WAV files.
+pub fn sample(x: f64) -> i64 {
+    (x.clamp(-1.0, 1.0) * 32767.49).round() as i64
+}
+
+/// Header of a metadata block of `kind` that is `length` bytes long.
+pub fn block_header(last: bool, kind: u8, length: usize) -> [u8; 4] {
+    let [_, a, b, c] = (length as u32).to_be_bytes();
+    [(last as u8) << 7 | kind, a, b, c]
+}
+
+/// The stream info block of `frames` frames, or of an unknown number if 0.
+pub fn stream_info(sample_rate: f64, channels: usize, frames: u64) -> Vec<u8> {
+    let mut info = BitWriter::default();
+    info.write(BLOCK as u64, 16);
+    info.write(BLOCK as u64, 16);
+    // Smallest and largest frame sizes, left unknown.
+    info.write(0, 24);
+    info.write(0, 24);
+    info.write(sample_rate as u64, 20);
+    info.write(channels as u64 - 1, 3);
+    info.write(BITS_PER_SAMPLE as u64 - 1, 5);
+    info.write(frames, 36);
+    // No MD5 signature of the samples.
+    info.write(0, 64);
+    info.write(0, 64);
+    info.bytes
+}
+
+/// A frame of the blocks of each channel, given the number of the frame.
+/// Every block but the last of a stream is `BLOCK` samples long.
+pub fn frame(number: u64, blocks: &[&[i64]]) -> Vec<u8> {
+    let mut frame = BitWriter::default();
+    // Sync code with a fixed block size.
+    frame.write(0xfff8, 16);
+    // The block size follows the header in 16 bits, the sample rate is the
+    // one of the stream info.
+    frame.write(0b0111, 4);
+    frame.write(0b0000, 4);
+    // Channels coded independently.
+    frame.write(blocks.len() as u64 - 1, 4);
+    frame.write(0b100, 3);
+    frame.write(0, 1);
+    frame.bytes.extend_from_slice(&utf8(number));
+    frame.write(blocks[0].len() as u64 - 1, 16);
+    let crc = crc8(&frame.bytes);
+    frame.write(crc as u64, 8);
+    for block in blocks {
+        subframe(&mut frame, block);
+    }
+    frame.align();
+    let crc = crc16(&frame.bytes);
+    frame.write(crc as u64, 16);
+    frame.bytes
+}
+
+/// Writes `block` as a constant if it is one, or else predicted by the
+/// fixed polynomial that leaves the smallest residual.
+fn subframe(frame: &mut BitWriter, block: &[i64]) {
+    if block.iter().all(|&sample| sample == block[0]) {
+        frame.write(0, 8);
+        frame.write_signed(block[0], BITS_PER_SAMPLE);
+        return;
+    }
+    // Each order predicts from the differences of the one below it.
+    let mut differences = block.to_vec();
+    let mut best: Option<(usize, Vec<i64>, u32, u64)> = None;
+    for order in 0..=MAX_ORDER.min(block.len() - 1) {
+        if order > 0 {
+            for i in (order..block.len()).rev() {
+                differences[i] -= differences[i - 1];
+            }
+        }
+        let residual = differences[order..].to_vec();
+        let (rice, bits) = rice_parameter(&residual);
+        if best.as_ref().is_none_or(|best| bits < best.3) {
+            best = Some((order, residual, rice, bits));
+        }
+    }
+    let (order, residual, rice, bits) = best.unwrap();
+    if bits >= (block.len() as u64 - order as u64) * BITS_PER_SAMPLE as u64 {
+        // Noise that none of the predictors help with is stored as it is.
+        frame.write(0b10, 8);
+        for &sample in block {
+            frame.write_signed(sample, BITS_PER_SAMPLE);
+        }
+        return;
+    }
+    frame.write((0b1000 | order as u64) << 1, 8);
+    for &sample in &block[..order] {
+        frame.write_signed(sample, BITS_PER_SAMPLE);
+    }
+    // A single partition of Rice codes with a 4 bit parameter.
+    frame.write(0, 2);
+    frame.write(0, 4);
+    frame.write(rice as u64, 4);
+    for &value in &residual {
+        let folded = zigzag(value);
+        let quotient = folded >> rice;
+        for _ in 0..quotient {
+            frame.write(0, 1);
+        }
+        frame.write(1, 1);
+        frame.write(folded & ((1 << rice) - 1), rice);
+    }
+}
+
+/// Signed values folded onto unsigned ones, small either way staying small.
+fn zigzag(value: i64) -> u64 {
+    (value << 1 ^ value >> 63) as u64
+}
+
+/// The Rice parameter coding `residual` in the fewest bits, and those bits.
+fn rice_parameter(residual: &[i64]) -> (u32, u64) {
+    (0..=MAX_RICE)
+        .map(|rice| {
+            let bits = residual
+                .iter()
+                .map(|&value| (zigzag(value) >> rice) + 1 + rice as u64)
+                .sum::<u64>();
+            (rice, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+/// Frame numbers coded like characters in UTF-8, extended to 36 bits.
+fn utf8(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    // Bytes needed, of which the first holds 7 - n bits and the others 6 each.
+    let n = (2..=7)
+        .find(|&n| value < 1 << (7 - n + 6 * (n - 1)))
+        .unwrap();
+    let mut bytes = vec![(0xff00u16 >> n) as u8 | (value >> (6 * (n - 1))) as u8];
+    for i in (0..n - 1).rev() {
+        bytes.push(0x80 | (value >> (6 * i) & 0x3f) as u8);
+    }
+    bytes
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fundsp::hacker::*;
+
+    #[test]
+    fn test_headers() {
+        assert_eq!(utf8(0x7f), [0x7f]);
+        assert_eq!(utf8(0x80), [0xc2, 0x80]);
+        assert_eq!(utf8(0x20ac), [0xe2, 0x82, 0xac]);
+        // The check values of the CRCs FLAC uses.
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xfee8);
+    }
+
+    #[test]
+    fn test_decodes_losslessly() {
+        let mut wave = Wave64::new(2, 44100.0);
+        for i in 0..10_000 {
+            let t = i as f64 / 44100.0;
+            let noise = ((i * 7919 % 101) as f64 - 50.0) / 32767.49;
+            wave.push(((TAU * 440.0 * t).sin() * 0.5 + noise, 0.0));
+        }
+        let flac = encode(&wave);
+        // Smaller than the samples stored as they are.
+        assert!(flac.len() < wave.len() * 4 / 2);
+        let path = std::env::temp_dir().join("playground_test_decodes_losslessly.flac");
+        std::fs::write(&path, &flac).unwrap();
+        let decoded = Wave64::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.len(), wave.len());
+        assert_eq!(decoded.sample_rate(), 44100.0);
+        for i in 0..wave.len() {
+            let sample = (wave.at(0, i) * 32767.49).round();
+            assert_eq!(decoded.at(0, i) * 32768.0, sample);
+            assert_eq!(decoded.at(1, i), 0.0);
+        }
+    }
+}
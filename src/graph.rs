@@ -0,0 +1,634 @@
+//! A network of units that the audio thread plays while it is edited. Edits
+//! are made in transactions, which the audio thread doesn't hear until they
+//! end and are committed all at once, between two blocks, so that it never
+//! plays a network half rewired. Units can be given names, such as
+//! `master.width`, that commands find them by, and the wiring is kept track
+//! of so that it can be drawn with Graphviz, showing the units that aren't
+//! heard. Parts of a network can also be described as a whole, as units
+//! one after another or side by side with sends to buses, and added at once.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+use crate::feedback::Feedback;
+
+/// Where an input of a unit or an output of the network is fed from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    /// An input of the network.
+    Input(usize),
+    /// An output of a unit.
+    Unit(NodeId, usize),
+}
+
+/// A part of a network described as a whole, which `Transaction::add`
+/// pushes and wires up.
+pub enum Patch {
+    /// A unit, with its name if it has one.
+    Unit(Option<String>, Box<dyn AudioUnit64>),
+    /// A unit pushed before, which the part doesn't feed.
+    Node(NodeId),
+    /// Parts one after another, each feeding the next.
+    Serial(Vec<Patch>),
+    /// Parts side by side, with their inputs and outputs one after another.
+    Parallel(Vec<Patch>),
+    /// Passes on the channels of the part before it, sending them to the
+    /// bus too at a level.
+    Send(String, f64),
+    /// The sum of what is sent to a bus, with its channels.
+    Bus(String, usize),
+}
+
+pub fn unit(unit: Box<dyn AudioUnit64>) -> Patch {
+    Patch::Unit(None, unit)
+}
+
+pub fn named(name: &str, unit: Box<dyn AudioUnit64>) -> Patch {
+    Patch::Unit(Some(name.to_string()), unit)
+}
+
+pub fn node(node: NodeId) -> Patch {
+    Patch::Node(node)
+}
+
+pub fn serial(parts: impl IntoIterator<Item = Patch>) -> Patch {
+    Patch::Serial(parts.into_iter().collect())
+}
+
+pub fn parallel(parts: impl IntoIterator<Item = Patch>) -> Patch {
+    Patch::Parallel(parts.into_iter().collect())
+}
+
+pub fn send(bus: &str, level: f64) -> Patch {
+    Patch::Send(bus.to_string(), level)
+}
+
+pub fn bus(name: &str, channels: usize) -> Patch {
+    Patch::Bus(name.to_string(), channels)
+}
+
+/// `effect` in a feedback loop that comes round after `seconds`, at `level`
+/// through a limiter, `cross` of each channel into the next.
+pub fn feedback(seconds: f64, level: f64, cross: f64, effect: Box<dyn AudioUnit64>) -> Patch {
+    unit(Box::new(Feedback::new(seconds, level, cross, effect)))
+}
+
+impl Patch {
+    /// Adds up the sends to each bus.
+    fn count_sends(&self, sends: &mut HashMap<String, usize>) {
+        match self {
+            Patch::Serial(parts) | Patch::Parallel(parts) => {
+                for part in parts {
+                    part.count_sends(sends);
+                }
+            }
+            Patch::Send(bus, _) => *sends.entry(bus.clone()).or_default() += 1,
+            _ => (),
+        }
+    }
+
+    fn buses<'a>(&'a self, buses: &mut Vec<(&'a str, usize)>) {
+        match self {
+            Patch::Serial(parts) | Patch::Parallel(parts) => {
+                for part in parts {
+                    part.buses(buses);
+                }
+            }
+            Patch::Bus(name, channels) => buses.push((name, *channels)),
+            _ => (),
+        }
+    }
+}
+
+/// The unit inputs that the channels of an added part go in to, and the
+/// unit outputs that they come out of.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ports {
+    pub inputs: Vec<(NodeId, usize)>,
+    pub outputs: Vec<(NodeId, usize)>,
+}
+
+/// A network played on the audio thread and edited in transactions.
+pub struct Graph {
+    net: Net64,
+    /// The units in the order they were pushed, with what feeds each of
+    /// their inputs, and what feeds each output of the network.
+    units: Vec<(NodeId, Vec<Option<Source>>)>,
+    outputs: Vec<Option<Source>>,
+    /// Names of the units that have one.
+    names: Vec<(String, NodeId)>,
+    /// Transactions committed to the audio thread so far.
+    commits: usize,
+}
+
+impl Graph {
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        Self {
+            net: Net64::new(inputs, outputs),
+            units: vec![],
+            outputs: vec![None; outputs],
+            names: vec![],
+            commits: 0,
+        }
+    }
+
+    /// Starts editing the network, committing the edits once the
+    /// transaction ends.
+    pub fn edit(&mut self) -> Transaction<'_> {
+        Transaction { graph: self }
+    }
+
+    /// The unit that plays the network on the audio thread, as committed.
+    pub fn backend(&mut self) -> NetBackend64 {
+        self.net.backend()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.net.set_sample_rate(sample_rate);
+    }
+
+    pub fn commits(&self) -> usize {
+        self.commits
+    }
+
+    pub fn node(&self, name: &str) -> Option<NodeId> {
+        self.names
+            .iter()
+            .find(|(found, _)| found == name)
+            .map(|&(_, node)| node)
+    }
+
+    /// The unit named `name`, or an error listing the names there are.
+    pub fn find(&self, name: &str) -> Result<NodeId, anyhow::Error> {
+        match self.node(name) {
+            Some(node) => Ok(node),
+            None => bail!(
+                "unknown node {}, there are {}",
+                name,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.names.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// The named units with their channels, one line each, in the order
+    /// they were named.
+    pub fn list(&self) -> Vec<String> {
+        let heard = self.heard();
+        self.names
+            .iter()
+            .map(|(name, node)| {
+                let unit = self.net.node(*node);
+                let mut line = format!("{}: {} in, {} out", name, unit.inputs(), unit.outputs());
+                if !heard.contains(node) {
+                    line.push_str(", not heard");
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// What feeds input `input` of `node`, if anything does.
+    pub fn source(&self, node: NodeId, input: usize) -> Option<Source> {
+        let (_, sources) = self.units.iter().find(|(found, _)| *found == node)?;
+        sources.get(input).copied().flatten()
+    }
+
+    /// The units that the outputs of the network are fed from, however
+    /// indirectly.
+    pub fn heard(&self) -> HashSet<NodeId> {
+        let mut heard = HashSet::new();
+        let mut sources: Vec<Source> = self.outputs.iter().flatten().copied().collect();
+        while let Some(source) = sources.pop() {
+            if let Source::Unit(node, _) = source {
+                if heard.insert(node) {
+                    let (_, inputs) = self.units.iter().find(|(found, _)| *found == node).unwrap();
+                    sources.extend(inputs.iter().flatten());
+                }
+            }
+        }
+        heard
+    }
+
+    /// The wiring of the network in the DOT language of Graphviz, each unit
+    /// with its name and channels and each wire from an output to an input,
+    /// the units that aren't heard dashed.
+    pub fn dot(&self) -> String {
+        let heard = self.heard();
+        let index = |node: NodeId| self.units.iter().position(|(found, _)| *found == node);
+        let port = |source: Source| match source {
+            Source::Input(input) => (format!("input{}", input), input),
+            Source::Unit(node, output) => (format!("unit{}", index(node).unwrap()), output),
+        };
+        let mut lines = vec![
+            "digraph network {".to_string(),
+            "    rankdir=LR;".to_string(),
+            "    node [shape=box];".to_string(),
+        ];
+        for input in 0..self.net.inputs() {
+            lines.push(format!(
+                "    input{} [label=\"input {}\", shape=circle];",
+                input, input
+            ));
+        }
+        for (i, (node, sources)) in self.units.iter().enumerate() {
+            let unit = self.net.node(*node);
+            let name = match self.names.iter().find(|(_, found)| found == node) {
+                Some((name, _)) => name.clone(),
+                None => format!("unit {}", i),
+            };
+            let style = if heard.contains(node) {
+                ""
+            } else {
+                ", style=dashed"
+            };
+            lines.push(format!(
+                "    unit{} [label=\"{}\\n{} in, {} out\"{}];",
+                i,
+                name,
+                unit.inputs(),
+                unit.outputs(),
+                style
+            ));
+            for (input, source) in sources.iter().enumerate() {
+                if let Some((from, output)) = source.map(port) {
+                    lines.push(format!(
+                        "    {} -> unit{} [taillabel=\"{}\", headlabel=\"{}\"];",
+                        from, i, output, input
+                    ));
+                }
+            }
+        }
+        for (output, source) in self.outputs.iter().enumerate() {
+            lines.push(format!(
+                "    output{} [label=\"output {}\", shape=circle];",
+                output, output
+            ));
+            if let Some((from, channel)) = source.map(port) {
+                lines.push(format!(
+                    "    {} -> output{} [taillabel=\"{}\"];",
+                    from, output, channel
+                ));
+            }
+        }
+        lines.push("}\n".to_string());
+        lines.join("\n")
+    }
+}
+
+/// Edits of a graph that are committed together when it is dropped, the
+/// same ones as those of `Net64`.
+pub struct Transaction<'a> {
+    graph: &'a mut Graph,
+}
+
+impl Transaction<'_> {
+    pub fn push(&mut self, unit: Box<dyn AudioUnit64>) -> NodeId {
+        let inputs = unit.inputs();
+        let node = self.graph.net.push(unit);
+        self.graph.units.push((node, vec![None; inputs]));
+        node
+    }
+
+    /// Pushes `unit` named `name`, which then no longer names the unit it
+    /// did before.
+    pub fn push_named(&mut self, name: &str, unit: Box<dyn AudioUnit64>) -> NodeId {
+        let node = self.push(unit);
+        self.graph.names.retain(|(found, _)| found != name);
+        self.graph.names.push((name.to_string(), node));
+        node
+    }
+
+    pub fn node(&self, name: &str) -> Option<NodeId> {
+        self.graph.node(name)
+    }
+
+    /// Pushes `unit` fed by the latest unit pushed, or by the inputs of the
+    /// network if it is the first, and feeding its outputs if their
+    /// channels match.
+    pub fn chain(&mut self, unit: Box<dyn AudioUnit64>) -> NodeId {
+        let (inputs, outputs) = (unit.inputs(), unit.outputs());
+        let previous = self.graph.units.last().map(|&(node, _)| node);
+        let node = self.graph.net.chain(unit);
+        let feeds = |channel| match previous {
+            Some(previous) => Some(Source::Unit(previous, channel)),
+            None => Some(Source::Input(channel)),
+        };
+        self.graph
+            .units
+            .push((node, (0..inputs).map(feeds).collect()));
+        if self.graph.outputs.len() == outputs {
+            for (channel, source) in self.graph.outputs.iter_mut().enumerate() {
+                *source = Some(Source::Unit(node, channel));
+            }
+        }
+        node
+    }
+
+    /// Feeds input `input` of `node` from `source`, or nothing.
+    fn feed(&mut self, node: NodeId, input: usize, source: Option<Source>) {
+        if let Some((_, sources)) = self
+            .graph
+            .units
+            .iter_mut()
+            .find(|(found, _)| *found == node)
+        {
+            sources[input] = source;
+        }
+    }
+
+    /// Replaces the unit of `node` with `unit` of the same channels,
+    /// returning the one it had.
+    pub fn replace(&mut self, node: NodeId, unit: Box<dyn AudioUnit64>) -> Box<dyn AudioUnit64> {
+        self.graph.net.replace(node, unit)
+    }
+
+    /// Removes `node`, silencing the inputs it fed.
+    pub fn remove(&mut self, node: NodeId) -> Box<dyn AudioUnit64> {
+        let graph = &mut *self.graph;
+        graph.names.retain(|&(_, found)| found != node);
+        graph.units.retain(|&(found, _)| found != node);
+        let sources = graph.units.iter_mut().flat_map(|(_, sources)| sources);
+        for source in sources.chain(&mut graph.outputs) {
+            if matches!(source, Some(Source::Unit(found, _)) if *found == node) {
+                *source = None;
+            }
+        }
+        graph.net.remove(node)
+    }
+
+    pub fn connect(&mut self, source: NodeId, output: usize, target: NodeId, input: usize) {
+        self.graph.net.connect(source, output, target, input);
+        self.feed(target, input, Some(Source::Unit(source, output)));
+    }
+
+    pub fn connect_input(&mut self, input: usize, target: NodeId, target_input: usize) {
+        self.graph.net.connect_input(input, target, target_input);
+        self.feed(target, target_input, Some(Source::Input(input)));
+    }
+
+    pub fn connect_output(&mut self, source: NodeId, output: usize, global_output: usize) {
+        self.graph.net.connect_output(source, output, global_output);
+        self.graph.outputs[global_output] = Some(Source::Unit(source, output));
+    }
+
+    pub fn disconnect(&mut self, node: NodeId, input: usize) {
+        self.graph.net.disconnect(node, input);
+        self.feed(node, input, None);
+    }
+
+    /// Pushes the units of `patch` and wires them up, with a unit summing
+    /// the sends to each of its buses.
+    pub fn add(&mut self, patch: Patch) -> Result<Ports, anyhow::Error> {
+        let mut sends = HashMap::new();
+        patch.count_sends(&mut sends);
+        let mut buses = vec![];
+        patch.buses(&mut buses);
+        let mut sums = HashMap::new();
+        for &(name, channels) in &buses {
+            if sums.contains_key(name) {
+                bail!("the bus {} is there twice", name);
+            }
+            // Each send fed into channels of its own, which add up.
+            let count = sends.remove(name).unwrap_or(0);
+            let mut sum = Net64::new(0, channels);
+            for _ in 0..count {
+                let mut through = Net64::new(channels, channels);
+                for channel in 0..channels {
+                    through.pass_through(channel, channel);
+                }
+                sum = sum + through;
+            }
+            let node = self.push(Box::new(sum));
+            sums.insert(name.to_string(), (node, channels, 0));
+        }
+        if let Some(name) = sends.keys().next() {
+            bail!("there is no bus {} to send to", name);
+        }
+        self.build(patch, None, &mut sums)
+    }
+
+    /// Pushes the units of `patch`, fed by the outputs before it if
+    /// `before` has them.
+    fn build(
+        &mut self,
+        patch: Patch,
+        before: Option<&[(NodeId, usize)]>,
+        sums: &mut HashMap<String, (NodeId, usize, usize)>,
+    ) -> Result<Ports, anyhow::Error> {
+        let ports = |node, inputs, outputs| Ports {
+            inputs: (0..inputs).map(|input| (node, input)).collect(),
+            outputs: (0..outputs).map(|output| (node, output)).collect(),
+        };
+        Ok(match patch {
+            Patch::Unit(name, unit) => {
+                let (inputs, outputs) = (unit.inputs(), unit.outputs());
+                let node = match name {
+                    Some(name) => self.push_named(&name, unit),
+                    None => self.push(unit),
+                };
+                ports(node, inputs, outputs)
+            }
+            Patch::Node(node) => {
+                let unit = self.graph.net.node(node);
+                ports(node, unit.inputs(), unit.outputs())
+            }
+            Patch::Serial(parts) => {
+                let mut serial: Option<Ports> = None;
+                for part in parts {
+                    let before = serial.as_ref().map(|ports| ports.outputs.as_slice());
+                    let ports = self.build(part, before, sums)?;
+                    match &mut serial {
+                        Some(serial) => {
+                            if serial.outputs.len() != ports.inputs.len() {
+                                bail!(
+                                    "{} channels can't feed {}",
+                                    serial.outputs.len(),
+                                    ports.inputs.len()
+                                );
+                            }
+                            for (&(source, output), &(target, input)) in
+                                serial.outputs.iter().zip(&ports.inputs)
+                            {
+                                self.connect(source, output, target, input);
+                            }
+                            serial.outputs = ports.outputs;
+                        }
+                        None => serial = Some(ports),
+                    }
+                }
+                serial.unwrap_or_default()
+            }
+            Patch::Parallel(parts) => {
+                let mut parallel = Ports::default();
+                for part in parts {
+                    let ports = self.build(part, None, sums)?;
+                    parallel.inputs.extend(ports.inputs);
+                    parallel.outputs.extend(ports.outputs);
+                }
+                parallel
+            }
+            Patch::Send(bus, level) => {
+                let Some(before) = before else {
+                    bail!("a send to {} needs a part before it", bus);
+                };
+                let (sum, channels, sent) = sums.get_mut(&bus).unwrap();
+                let (sum, channels, first) = (*sum, *channels, *sent * *channels);
+                *sent += 1;
+                if before.len() != channels {
+                    bail!(
+                        "{} channels can't be sent to the bus {} of {}",
+                        before.len(),
+                        bus,
+                        channels
+                    );
+                }
+                // Passed through on the first channels, sent on the others.
+                let mut tap = Net64::new(channels, 2 * channels);
+                for channel in 0..channels {
+                    tap.pass_through(channel, channel);
+                    let gain = tap.push(Box::new(mul(level)));
+                    tap.connect_input(channel, gain, 0);
+                    tap.connect_output(gain, 0, channels + channel);
+                }
+                let node = self.push(Box::new(tap));
+                for channel in 0..channels {
+                    self.connect(node, channels + channel, sum, first + channel);
+                }
+                ports(node, channels, channels)
+            }
+            Patch::Bus(name, channels) => {
+                let (sum, _, _) = sums[&name];
+                ports(sum, 0, channels)
+            }
+        })
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // A network isn't committed until it is played.
+        if self.graph.net.has_backend() {
+            self.graph.net.commit();
+            self.graph.commits += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commits_edits_together() {
+        let mut graph = Graph::new(0, 1);
+        let mut edit = graph.edit();
+        let source = edit.push(Box::new(dc(1.0)));
+        let gain = edit.push(Box::new(pass()));
+        edit.connect(source, 0, gain, 0);
+        edit.connect_output(gain, 0, 0);
+        drop(edit);
+        let mut backend = graph.backend();
+        assert_eq!(backend.get_mono(), 1.0);
+        let mut edit = graph.edit();
+        edit.replace(source, Box::new(dc(3.0)));
+        edit.replace(gain, Box::new(mul(0.5)));
+        // Neither edit is heard before the other.
+        assert_eq!(backend.get_mono(), 1.0);
+        drop(edit);
+        assert_eq!(backend.get_mono(), 1.5);
+        assert_eq!(graph.commits(), 1);
+    }
+
+    #[test]
+    fn test_finds_nodes_by_name() {
+        let mut graph = Graph::new(0, 2);
+        let mut edit = graph.edit();
+        let first = edit.push_named("lead.osc", Box::new(sine_hz(440.0)));
+        let pan = edit.push_named("lead.pan", Box::new(pan(0.0)));
+        let second = edit.push_named("lead.osc", Box::new(saw_hz(220.0)));
+        assert_eq!(edit.node("lead.osc"), Some(second));
+        edit.remove(first);
+        edit.remove(pan);
+        drop(edit);
+        assert_eq!(graph.names(), ["lead.osc"]);
+        assert_eq!(graph.list(), ["lead.osc: 0 in, 1 out, not heard"]);
+        assert!(graph.find("lead.osc").is_ok());
+        let err = graph.find("lead.pan").unwrap_err().to_string();
+        assert_eq!(err, "unknown node lead.pan, there are lead.osc");
+    }
+
+    #[test]
+    fn test_draws_wiring() {
+        let mut graph = Graph::new(1, 1);
+        let mut edit = graph.edit();
+        let filter = edit.chain(Box::new(lowpass_hz(1000.0, 1.0)));
+        let gain = edit.push_named("gain", Box::new(mul(0.5)));
+        let lost = edit.push_named("lost", Box::new(sine()));
+        edit.connect(filter, 0, gain, 0);
+        edit.connect(filter, 0, lost, 0);
+        edit.connect_output(gain, 0, 0);
+        drop(edit);
+        assert_eq!(graph.source(gain, 0), Some(Source::Unit(filter, 0)));
+        assert_eq!(graph.source(filter, 0), Some(Source::Input(0)));
+        assert_eq!(graph.source(filter, 1), None);
+        assert_eq!(graph.heard(), HashSet::from([filter, gain]));
+        assert_eq!(
+            graph.list(),
+            ["gain: 1 in, 1 out", "lost: 1 in, 1 out, not heard"]
+        );
+        let dot = graph.dot();
+        assert!(dot.starts_with("digraph network {"));
+        assert!(dot.contains("    input0 -> unit0 [taillabel=\"0\", headlabel=\"0\"];"));
+        assert!(dot.contains("    unit1 [label=\"gain\\n1 in, 1 out\"];"));
+        assert!(dot.contains("    unit2 [label=\"lost\\n1 in, 1 out\", style=dashed];"));
+        assert!(dot.contains("    unit1 -> output0 [taillabel=\"0\"];"));
+        let mut edit = graph.edit();
+        edit.remove(gain);
+        drop(edit);
+        assert!(graph.heard().is_empty());
+    }
+
+    #[test]
+    fn test_adds_patches() {
+        let mut graph = Graph::new(0, 2);
+        let mut edit = graph.edit();
+        let ports = edit
+            .add(serial([
+                parallel([
+                    serial([unit(Box::new(dc(1.0))), send("echo", 0.5)]),
+                    serial([named("two", Box::new(dc(2.0))), send("echo", 0.25)]),
+                ]),
+                parallel([
+                    unit(Box::new(pass())),
+                    serial([
+                        named("gain", Box::new(mul(2.0))),
+                        parallel([unit(Box::new(pass())), bus("echo", 1)]),
+                        unit(Box::new(pass() + pass())),
+                    ]),
+                ]),
+            ]))
+            .unwrap();
+        assert_eq!(ports.inputs, []);
+        assert_eq!(ports.outputs.len(), 2);
+        for (channel, &(node, output)) in ports.outputs.iter().enumerate() {
+            edit.connect_output(node, output, channel);
+        }
+        drop(edit);
+        assert_eq!(graph.names(), ["two", "gain"]);
+        let mut backend = graph.backend();
+        // The echo bus sums both sends.
+        assert_eq!(backend.get_stereo(), (1.0, 4.0 + 0.5 + 0.5));
+        let mut edit = graph.edit();
+        let err = edit.add(send("echo", 1.0)).unwrap_err().to_string();
+        assert_eq!(err, "there is no bus echo to send to");
+        let one = || unit(Box::new(dc(1.0)));
+        let err = edit.add(serial([one(), one()])).unwrap_err().to_string();
+        assert_eq!(err, "1 channels can't feed 0");
+    }
+}
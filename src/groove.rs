@@ -0,0 +1,121 @@
+//! Groove templates, the timing and velocity feel of a reference performance
+//! that is laid over the steps of patterns.
+
+use anyhow::bail;
+
+use crate::humanize::VELOCITY_UNITS;
+use crate::smf::MidiFile;
+
+/// Beats of the sixteenth grid that the notes of a groove are measured against.
+const GRID: f64 = 0.25;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    /// Beats that the notes on each sixteenth of a bar are played late, or
+    /// early when negative.
+    pub offsets: Vec<f64>,
+    /// Velocities of the notes on each sixteenth of a bar, the loudest at 1.
+    pub velocities: Vec<f64>,
+}
+
+impl Template {
+    /// The groove of the notes of a MIDI file, averaged over its bars for
+    /// every sixteenth of a bar.
+    pub fn from_midi(midi: &MidiFile) -> Result<Self, anyhow::Error> {
+        let slots = (midi.signature.bar_beats() / GRID).round().max(1.0) as usize;
+        let mut offsets = vec![0.0; slots];
+        let mut velocities = vec![0.0; slots];
+        let mut counts = vec![0usize; slots];
+        for note in &midi.notes {
+            let nearest = (note.beat / GRID).round();
+            let slot = (nearest as usize) % slots;
+            offsets[slot] += note.beat - nearest * GRID;
+            velocities[slot] += note.velocity as f64 / VELOCITY_UNITS;
+            counts[slot] += 1;
+        }
+        if midi.notes.is_empty() {
+            bail!("the groove has no notes");
+        }
+        for ((offset, velocity), &count) in offsets.iter_mut().zip(&mut velocities).zip(&counts) {
+            // Sixteenths the groove leaves out are played as written.
+            match count {
+                0 => *velocity = f64::NAN,
+                count => {
+                    *offset /= count as f64;
+                    *velocity /= count as f64;
+                }
+            }
+        }
+        let loudest = velocities
+            .iter()
+            .copied()
+            .filter(|v| !v.is_nan())
+            .fold(0.0, f64::max);
+        for velocity in &mut velocities {
+            *velocity = match *velocity {
+                velocity if velocity.is_nan() || loudest == 0.0 => 1.0,
+                velocity => velocity / loudest,
+            };
+        }
+        Ok(Self {
+            offsets,
+            velocities,
+        })
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        Self::from_midi(&MidiFile::parse(bytes)?)
+    }
+
+    /// Beats that a note starting at `beat` into a pattern is moved by, and
+    /// the velocity it is played at. Notes off the sixteenth grid keep their
+    /// timing and velocity.
+    pub fn at(&self, beat: f64) -> (f64, f64) {
+        let nearest = (beat / GRID).round();
+        if (beat - nearest * GRID).abs() > 1e-9 {
+            return (0.0, 1.0);
+        }
+        let slot = (nearest as i64).rem_euclid(self.offsets.len() as i64) as usize;
+        (self.offsets[slot], self.velocities[slot])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meter::TimeSignature;
+    use crate::smf::NoteOn;
+
+    #[test]
+    fn test_template_averages_bars() {
+        let note = |beat, velocity| NoteOn {
+            beat,
+            key: 42,
+            velocity,
+        };
+        let midi = MidiFile {
+            // The second sixteenth played late in both bars of 2/4.
+            notes: vec![
+                note(0.0, 100),
+                note(0.3, 50),
+                note(2.0, 100),
+                note(2.3, 50),
+                note(1.0, 80),
+            ],
+            signature: TimeSignature::new(2, 4),
+        };
+        let template = Template::from_midi(&midi).unwrap();
+        assert_eq!(template.offsets.len(), 8);
+        let (offset, velocity) = template.at(4.25);
+        assert!((offset - 0.05).abs() < 1e-12);
+        assert!((velocity - 0.5).abs() < 1e-12);
+        assert!((template.at(1.0).1 - 0.8).abs() < 1e-12);
+        assert_eq!(template.at(0.5), (0.0, 1.0));
+        assert_eq!(template.at(0.1), (0.0, 1.0));
+        let empty = MidiFile {
+            notes: vec![],
+            signature: TimeSignature::COMMON,
+        };
+        assert!(Template::from_midi(&empty).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! Random timing and velocity jitter that makes sequenced notes sound less mechanical.
+
+use anyhow::bail;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Largest timing jitter, which must stay well inside the scheduling lookahead.
+const MAX_TIMING_MS: f64 = 50.0;
+/// Velocities are given in MIDI units, of which full velocity is this many.
+pub const VELOCITY_UNITS: f64 = 127.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HumanizeAmount {
+    /// Largest shift of a note start either way, in milliseconds.
+    pub timing_ms: f64,
+    /// Largest velocity change either way, in MIDI velocity units.
+    pub velocity: f64,
+}
+
+impl HumanizeAmount {
+    /// Parses `MS` or `MS,VELOCITY`, such as `10,8`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (timing, velocity) = value.split_once(',').unwrap_or((value, "0"));
+        let amount = Self {
+            timing_ms: timing.trim().parse()?,
+            velocity: velocity.trim().parse()?,
+        };
+        if !(0.0..=MAX_TIMING_MS).contains(&amount.timing_ms) {
+            bail!("timing jitter must be between 0 and {} ms", MAX_TIMING_MS);
+        }
+        if !(0.0..=VELOCITY_UNITS).contains(&amount.velocity) {
+            bail!("velocity jitter must be between 0 and {}", VELOCITY_UNITS);
+        }
+        Ok(amount)
+    }
+}
+
+pub struct Humanize {
+    amount: HumanizeAmount,
+    rng: StdRng,
+}
+
+impl Humanize {
+    pub fn new(amount: HumanizeAmount, seed: u64) -> Self {
+        Self {
+            amount,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Jitters the start `time` in seconds and the `velocity` in 0...1 of one note.
+    pub fn apply(&mut self, time: f64, velocity: f64) -> (f64, f64) {
+        let mut jitter = |range: f64| {
+            if range > 0.0 {
+                self.rng.gen_range(-range..=range)
+            } else {
+                0.0
+            }
+        };
+        let time = time + jitter(self.amount.timing_ms) / 1000.0;
+        let velocity = velocity + jitter(self.amount.velocity) / VELOCITY_UNITS;
+        (time, velocity.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_stays_in_range() {
+        let amount = HumanizeAmount::parse("10,8").unwrap();
+        let mut humanize = Humanize::new(amount, 1);
+        let notes: Vec<_> = (0..100).map(|_| humanize.apply(1.0, 1.0)).collect();
+        assert!(notes.iter().all(|&(time, _)| (time - 1.0).abs() <= 0.01));
+        assert!(notes
+            .iter()
+            .all(|&(_, velocity)| (1.0 - 8.0 / VELOCITY_UNITS..=1.0).contains(&velocity)));
+        assert!(notes.iter().any(|&(time, _)| time != 1.0));
+        assert_eq!(
+            Humanize::new(HumanizeAmount::default(), 1).apply(1.0, 0.5),
+            (1.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(
+            HumanizeAmount::parse("5").unwrap(),
+            HumanizeAmount {
+                timing_ms: 5.0,
+                velocity: 0.0
+            }
+        );
+        assert!(HumanizeAmount::parse("80").is_err());
+        assert!(HumanizeAmount::parse("5,x").is_err());
+    }
+}
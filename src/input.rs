@@ -0,0 +1,284 @@
+//! Input streams, read for trigger and clock pulses from modular hardware or
+//! for the hits of pads, played live or tracked for the notes played on them
+//! or their beat.
+
+use anyhow::{anyhow, bail};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::control::Command;
+use crate::midi::Message;
+use playground::beat::BeatTracker;
+use playground::bridge::Bridge;
+use playground::drums::Drum;
+use playground::pitch::{Event, Tracker};
+use playground::transient::Transients;
+use playground::trigger::{Detector, Trigger};
+use playground::tuning::Tuning;
+
+/// Frames of a callback allocated for up front, more than most devices hand
+/// over at once.
+const MAX_FRAMES: usize = 4096;
+/// Time between reads of the input tracked for notes or beats, a fraction
+/// of the time between detections.
+const TRACK_POLL: Duration = Duration::from_millis(2);
+
+/// The default input device of `host` and its config, which has to have the
+/// zero based `channel`.
+fn open(
+    host: &cpal::Host,
+    channel: usize,
+) -> Result<(cpal::Device, cpal::StreamConfig, cpal::SampleFormat), anyhow::Error> {
+    let device = host
+        .default_input_device()
+        .ok_or_else(|| anyhow!("no default input device on {}", host.id().name()))?;
+    let supported = device.default_input_config()?;
+    let format = supported.sample_format();
+    let config: cpal::StreamConfig = supported.into();
+    if channel >= config.channels as usize {
+        bail!(
+            "no input channel {}, the device has {}",
+            channel + 1,
+            config.channels
+        );
+    }
+    Ok((device, config, format))
+}
+
+/// Starts reading pulses off the zero based `channel` of the default input
+/// device of `host`, sending a command for every one of them.
+pub fn spawn(
+    host: &cpal::Host,
+    channel: usize,
+    trigger: Trigger,
+    sender: Sender<Command>,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let (device, config, format) = open(host, channel)?;
+    match format {
+        cpal::SampleFormat::F32 => listen::<f32>(&device, &config, channel, trigger, sender),
+        cpal::SampleFormat::I16 => listen::<i16>(&device, &config, channel, trigger, sender),
+        cpal::SampleFormat::U16 => listen::<u16>(&device, &config, channel, trigger, sender),
+        format => bail!("unsupported input sample format: {}", format),
+    }
+}
+
+/// Starts finding the hits of a pad on the zero based `channel` of the
+/// default input device of `host`, sending a command for every one of them
+/// to play `drum`.
+pub fn spawn_pad(
+    host: &cpal::Host,
+    channel: usize,
+    drum: Drum,
+    sender: Sender<Command>,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let (device, config, format) = open(host, channel)?;
+    match format {
+        cpal::SampleFormat::F32 => pad::<f32>(&device, &config, channel, drum, sender),
+        cpal::SampleFormat::I16 => pad::<i16>(&device, &config, channel, drum, sender),
+        cpal::SampleFormat::U16 => pad::<u16>(&device, &config, channel, drum, sender),
+        format => bail!("unsupported input sample format: {}", format),
+    }
+}
+
+/// The sample rate of the default input device of `host`.
+pub fn sample_rate(host: &cpal::Host) -> Result<f64, anyhow::Error> {
+    let (_, config, _) = open(host, 0)?;
+    Ok(config.sample_rate.0 as f64)
+}
+
+/// Starts handing over the audio on the zero based `channel` of the default
+/// input device of `host` to `bridge`, on both of its sides. The input has
+/// to run at the `sample_rate` that it is played at.
+pub fn record(
+    host: &cpal::Host,
+    channel: usize,
+    sample_rate: f64,
+    bridge: Bridge,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let (device, config, format) = open(host, channel)?;
+    if config.sample_rate.0 as f64 != sample_rate {
+        bail!(
+            "the input runs at {} Hz and the output at {} Hz, they have to be the same",
+            config.sample_rate.0,
+            sample_rate
+        );
+    }
+    match format {
+        cpal::SampleFormat::F32 => hand_over::<f32>(&device, &config, channel, bridge),
+        cpal::SampleFormat::I16 => hand_over::<i16>(&device, &config, channel, bridge),
+        cpal::SampleFormat::U16 => hand_over::<u16>(&device, &config, channel, bridge),
+        format => bail!("unsupported input sample format: {}", format),
+    }
+}
+
+/// Starts tracking the notes played one at a time on the zero based
+/// `channel` of the default input device of `host`, sending them as MIDI
+/// notes of the nearest keys of `tuning`.
+pub fn track_pitch(
+    host: &cpal::Host,
+    channel: usize,
+    tuning: &Tuning,
+    sender: Sender<Command>,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let sample_rate = sample_rate(host)?;
+    let bridge = Bridge::new(sample_rate);
+    let stream = record(host, channel, sample_rate, bridge.clone())?;
+    let mut tracker = Tracker::new(tuning.clone(), sample_rate);
+    std::thread::spawn(move || {
+        let (mut frames, mut samples, mut events) = (vec![], vec![], vec![]);
+        loop {
+            std::thread::sleep(TRACK_POLL);
+            bridge.take(&mut frames);
+            samples.clear();
+            samples.extend(frames.drain(..).map(|(x, _)| x));
+            tracker.push(&samples, &mut events);
+            for event in events.drain(..) {
+                let message = match event {
+                    Event::On { key, velocity } => Message::NoteOn { key, velocity },
+                    Event::Off { key } => Message::NoteOff { key },
+                };
+                if sender.send(Command::Midi(message, Instant::now())).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(stream)
+}
+
+/// Starts following the beat of the zero based `channel` of the default
+/// input device of `host`, in bars of `beats_per_bar`, sending a command for
+/// every beat heard.
+pub fn track_beats(
+    host: &cpal::Host,
+    channel: usize,
+    beats_per_bar: usize,
+    sender: Sender<Command>,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let sample_rate = sample_rate(host)?;
+    let bridge = Bridge::new(sample_rate);
+    let stream = record(host, channel, sample_rate, bridge.clone())?;
+    let mut tracker = BeatTracker::new(sample_rate, beats_per_bar);
+    std::thread::spawn(move || {
+        let (mut frames, mut samples, mut beats) = (vec![], vec![], vec![]);
+        loop {
+            std::thread::sleep(TRACK_POLL);
+            bridge.take(&mut frames);
+            samples.clear();
+            samples.extend(frames.drain(..).map(|(x, _)| x));
+            tracker.push(&samples, &mut beats);
+            let now = Instant::now();
+            for beat in beats.drain(..) {
+                let at = now - Duration::from_secs_f64(beat.ago);
+                if sender
+                    .send(Command::Beat(beat.bpm, beat.downbeat, at))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(stream)
+}
+
+fn hand_over<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channel: usize,
+    bridge: Bridge,
+) -> Result<cpal::Stream, anyhow::Error>
+where
+    T: SizedSample,
+    f64: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    let mut frames = Vec::with_capacity(MAX_FRAMES);
+    let err_fn = |err| eprintln!("an error occurred on the input stream: {}", err);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            frames.clear();
+            frames.extend(data.chunks(channels).map(|frame| {
+                let x = f64::from_sample_(frame[channel]);
+                (x, x)
+            }));
+            bridge.put(&frames);
+        },
+        err_fn,
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
+
+fn listen<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channel: usize,
+    trigger: Trigger,
+    sender: Sender<Command>,
+) -> Result<cpal::Stream, anyhow::Error>
+where
+    T: SizedSample,
+    f64: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    let mut detector = Detector::default();
+    let err_fn = |err| eprintln!("an error occurred on the input stream: {}", err);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            for frame in data.chunks(channels) {
+                if !detector.push(f64::from_sample_(frame[channel])) {
+                    continue;
+                }
+                let command = match trigger {
+                    Trigger::Clock(_) => Command::Clock(Instant::now()),
+                    Trigger::Note(note) => Command::Play(note, Instant::now()),
+                };
+                // The commands stop being read when playback ends.
+                let _ = sender.send(command);
+            }
+        },
+        err_fn,
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
+
+fn pad<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channel: usize,
+    drum: Drum,
+    sender: Sender<Command>,
+) -> Result<cpal::Stream, anyhow::Error>
+where
+    T: SizedSample,
+    f64: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    let sample_rate = config.sample_rate.0 as f64;
+    let mut transients = Transients::new(sample_rate);
+    let err_fn = |err| eprintln!("an error occurred on the input stream: {}", err);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            for frame in data.chunks(channels) {
+                if let Some(hit) = transients.push(f64::from_sample_(frame[channel])) {
+                    let at = Instant::now() - Duration::from_secs_f64(hit.ago as f64 / sample_rate);
+                    // The commands stop being read when playback ends.
+                    let _ = sender.send(Command::Hit(drum, hit.velocity, at));
+                }
+            }
+        },
+        err_fn,
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
@@ -0,0 +1,94 @@
+//! Insert effects on tracks, each mixed in with what it is inserted on by a
+//! wet/dry parameter and bypassed by fading it out, so that neither clicks.
+
+use fundsp::hacker::*;
+
+use crate::param::{Param, Spec};
+
+/// Share of the effect in what comes out, 0 for dry and 1 for all wet.
+pub const MIX: Spec = Spec::linear(0.0, 1.0, 1.0);
+/// Seconds that bypassing an effect fades it over.
+const BYPASS_SECONDS: f64 = 0.02;
+/// 1 while the effect is bypassed, faded rather than clicked.
+const BYPASS: Spec = Spec {
+    smoothing: BYPASS_SECONDS,
+    ..Spec::linear(0.0, 1.0, 0.0)
+};
+
+/// The wet/dry mix and bypass of an effect.
+#[derive(Clone)]
+pub struct Insert {
+    mix: Param,
+    bypass: Param,
+}
+
+impl Insert {
+    /// An insert mixing its effect in by `mix`, a parameter of the `MIX`
+    /// spec.
+    pub fn new(mix: Param) -> Self {
+        Self {
+            mix,
+            bypass: Param::new(BYPASS),
+        }
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypass.value() > 0.5
+    }
+
+    pub fn set_bypassed(&self, bypassed: bool) {
+        self.bypass.set(if bypassed { 1.0 } else { 0.0 });
+    }
+
+    /// Mixes `effect` in with its first inputs, as many as it has outputs.
+    /// Inputs after those, such as an envelope modulating it, only go to
+    /// the effect.
+    pub fn unit(&self, effect: Box<dyn AudioUnit64>) -> Box<dyn AudioUnit64> {
+        let (inputs, outputs) = (effect.inputs(), effect.outputs());
+        assert!(outputs <= inputs);
+        let mut net = Net64::new(inputs, outputs);
+        let effect = net.push(effect);
+        for input in 0..inputs {
+            net.connect_input(input, effect, input);
+        }
+        let wet = net.push(Box::new(self.mix.unit() * (1.0 - self.bypass.unit())));
+        for channel in 0..outputs {
+            let mix = net.push(Box::new(map(|f: &Frame<f64, U3>| lerp(f[0], f[1], f[2]))));
+            net.connect_input(channel, mix, 0);
+            net.connect(effect, channel, mix, 1);
+            net.connect(wet, 0, mix, 2);
+            net.connect_output(mix, 0, channel);
+        }
+        Box::new(net)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixes_and_bypasses() {
+        let insert = Insert::new(Param::new(MIX));
+        let mut unit = insert.unit(Box::new(mul(3.0) | mul(3.0) | sink()));
+        unit.set_sample_rate(1000.0);
+        let mut tick = || {
+            let mut output = [0.0; 2];
+            unit.tick(&[1.0, 0.5, 0.25], &mut output);
+            output
+        };
+        let wet = tick();
+        assert!((wet[0] - 3.0).abs() < 1e-9 && (wet[1] - 1.5).abs() < 1e-9);
+        insert.set_bypassed(true);
+        assert!(insert.is_bypassed());
+        // Faded over to the dry signal rather than switched.
+        let fading = tick();
+        assert!(fading[0] > 1.0 && fading[0] < 3.0);
+        let dry = (0..200).map(|_| tick()).last().unwrap();
+        assert!((dry[0] - 1.0).abs() < 1e-6 && (dry[1] - 0.5).abs() < 1e-6);
+        insert.set_bypassed(false);
+        insert.mix.set(0.5);
+        let half = (0..200).map(|_| tick()).last().unwrap();
+        assert!((half[0] - 2.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,93 @@
+//! Voice units for the available instruments.
+
+use fundsp::hacker::*;
+
+use crate::drums::drum_at;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instrument {
+    /// Plucked string, damped quickly on note-off. Softer notes sound darker.
+    Pluck,
+    /// Sustained organ tone that holds until note-off.
+    Organ,
+    /// Short metronome tick.
+    Click,
+    /// Drum kit playing the drum mapped to the MIDI key of a note in General MIDI.
+    Drums,
+}
+
+impl Instrument {
+    /// Time the voice keeps sounding after its note-off.
+    pub fn release(self) -> f64 {
+        match self {
+            Instrument::Pluck => 0.2,
+            Instrument::Organ => 0.4,
+            Instrument::Click => 0.0,
+            // Drums ring out on their own however short their notes are.
+            Instrument::Drums => 0.8,
+        }
+    }
+
+    /// Builds a mono voice whose note-off comes `duration` seconds after it starts.
+    /// It is silent once `release` has passed after that. `velocity` in 0...1 scales its level.
+    pub fn voice(self, frequency: f64, duration: f64, velocity: f64) -> Box<dyn AudioUnit64> {
+        let release = self.release();
+        match self {
+            Instrument::Pluck => Box::new(
+                (zero() >> pluck(frequency, 0.5, 0.9 + 0.09 * (1.0 - velocity)))
+                    * adsr(0.001, 0.0, 1.0, release, duration)
+                    * velocity,
+            ),
+            Instrument::Organ => Box::new(
+                organ_hz(frequency) * adsr(0.02, 0.2, 0.6, release, duration) * (0.2 * velocity),
+            ),
+            Instrument::Click => {
+                Box::new(sine_hz(frequency) * envelope(|t| exp(-t * 80.0)) * (0.5 * velocity))
+            }
+            Instrument::Drums => match drum_at(frequency) {
+                Some(drum) => drum.voice(velocity),
+                None => Box::new(zero()),
+            },
+        }
+    }
+}
+
+/// Attack-decay-sustain envelope that moves to its release at `duration` seconds.
+fn adsr(
+    attack: f64,
+    decay: f64,
+    sustain: f64,
+    release: f64,
+    duration: f64,
+) -> An<impl AudioNode<Sample = f64, Inputs = U0, Outputs = U1>> {
+    let level = move |t: f64| {
+        if t < attack {
+            t / attack
+        } else if t < attack + decay {
+            lerp(1.0, sustain, (t - attack) / decay)
+        } else {
+            sustain
+        }
+    };
+    envelope(move |t| {
+        if t < duration {
+            level(t)
+        } else {
+            level(duration) * clamp01(1.0 - (t - duration) / release)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adsr_releases_after_duration() {
+        let mut envelope = adsr(0.01, 0.01, 0.5, 0.1, 0.5);
+        let wave = Wave64::render(1000.0, 1.0, &mut envelope);
+        assert_eq!(wave.at(0, 300), 0.5);
+        assert!(wave.at(0, 550) < 0.5 && wave.at(0, 550) > 0.0);
+        assert_eq!(wave.at(0, 700), 0.0);
+    }
+}
@@ -0,0 +1,199 @@
+//! Audio and the transport sent between two instances over UDP, so that
+//! two machines can jam together, one of them following the song of the other.
+
+use std::time::{Duration, Instant};
+
+use crate::transport::BPM;
+
+/// Frames sent in one packet, few for a low latency and few enough that a
+/// packet isn't split up on the way.
+pub const FRAMES: usize = 128;
+/// Most packets lost in a row that are made up for with silence, beyond
+/// which the partner is taken to have been away.
+const MAX_LOST: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    /// Stereo frames following those of the packet numbered one before.
+    Audio {
+        sequence: u32,
+        frames: Vec<(f64, f64)>,
+    },
+    /// The partner starting `bar` of its song at `bpm`.
+    Sync { bar: u32, bpm: f64 },
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Packet::Audio { sequence, frames } => {
+                let mut bytes = Vec::with_capacity(5 + 8 * frames.len());
+                bytes.push(b'A');
+                bytes.extend_from_slice(&sequence.to_le_bytes());
+                for &(left, right) in frames {
+                    bytes.extend_from_slice(&(left as f32).to_le_bytes());
+                    bytes.extend_from_slice(&(right as f32).to_le_bytes());
+                }
+                bytes
+            }
+            Packet::Sync { bar, bpm } => {
+                let mut bytes = vec![b'S'];
+                bytes.extend_from_slice(&bar.to_le_bytes());
+                bytes.extend_from_slice(&bpm.to_le_bytes());
+                bytes
+            }
+        }
+    }
+
+    /// The packet in `bytes`, or `None` if they aren't one or hold a tempo
+    /// that can't be played. Samples that aren't finite are silenced.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&kind, rest) = bytes.split_first()?;
+        let u32_at = |i: usize| Some(u32::from_le_bytes(rest.get(i..i + 4)?.try_into().ok()?));
+        let f32_at = |i: usize| {
+            let x = f32::from_le_bytes(rest.get(i..i + 4)?.try_into().ok()?);
+            Some(if x.is_finite() { x } else { 0.0 })
+        };
+        match kind {
+            b'A' if rest.len() >= 4 && rest.len() % 8 == 4 => {
+                let frames = (4..rest.len())
+                    .step_by(8)
+                    .map(|i| Some((f32_at(i)? as f64, f32_at(i + 4)? as f64)))
+                    .collect::<Option<_>>()?;
+                Some(Packet::Audio {
+                    sequence: u32_at(0)?,
+                    frames,
+                })
+            }
+            b'S' if rest.len() == 12 => {
+                let bpm = f64::from_le_bytes(rest[4..].try_into().ok()?);
+                BPM.contains(&bpm).then_some(Packet::Sync {
+                    bar: u32_at(0)?,
+                    bpm,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Puts the audio packets that arrive back in line.
+#[derive(Default)]
+pub struct Sequence {
+    /// The number of the packet expected next.
+    next: Option<u32>,
+}
+
+impl Sequence {
+    /// Registers the packet numbered `sequence`, and returns how many were
+    /// lost before it, or `None` if it arrived too late to be played.
+    pub fn accept(&mut self, sequence: u32) -> Option<usize> {
+        let lost = match self.next {
+            None => 0,
+            Some(next) => match sequence.wrapping_sub(next) as usize {
+                // Earlier than expected, as wrapped around.
+                lost if lost > u32::MAX as usize / 2 => return None,
+                lost if lost > MAX_LOST => 0,
+                lost => lost,
+            },
+        };
+        self.next = Some(sequence.wrapping_add(1));
+        Some(lost)
+    }
+}
+
+/// The transport of the partner, while following it.
+#[derive(Default)]
+pub struct Partner {
+    /// When the latest bar of the partner arrived, while it plays.
+    last: Option<Instant>,
+}
+
+impl Partner {
+    /// Bars that stop arriving for longer than this have stopped.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Registers the partner starting `bar` `at` an instant, given the bar
+    /// played `next` here. Returns the bar to jump to and start playing, if
+    /// the song here isn't in step.
+    pub fn sync(&mut self, at: Instant, bar: usize, next: usize) -> Option<usize> {
+        let running = self.last.replace(at).is_some();
+        // In step, the same bar is played next here or has just been
+        // started, since bars are dispatched ahead of time.
+        (!running || !(bar..=bar + 1).contains(&next)).then_some(bar + 1)
+    }
+
+    /// Whether the partner was playing and has stopped by `now`.
+    pub fn stopped(&mut self, now: Instant) -> bool {
+        match self.last {
+            Some(then) if now.saturating_duration_since(then) > Self::TIMEOUT => {
+                self.last = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        let audio = Packet::Audio {
+            sequence: 7,
+            frames: vec![(0.5, -0.25), (1.0, 0.0)],
+        };
+        assert_eq!(Packet::decode(&audio.encode()), Some(audio));
+        let sync = Packet::Sync { bar: 12, bpm: 96.5 };
+        assert_eq!(Packet::decode(&sync.encode()), Some(sync));
+        assert_eq!(Packet::decode(b"A\x07\x00\x00\x00\x01"), None);
+        assert_eq!(Packet::decode(b""), None);
+        assert_eq!(Packet::decode(b"X1234"), None);
+    }
+
+    #[test]
+    fn test_sync_out_of_range() {
+        for bpm in [0.0, -120.0, 1000.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(Packet::decode(&Packet::Sync { bar: 1, bpm }.encode()), None);
+        }
+    }
+
+    #[test]
+    fn test_audio_not_finite() {
+        let audio = Packet::Audio {
+            sequence: 0,
+            frames: vec![(f64::NAN, 0.5), (f64::INFINITY, f64::NEG_INFINITY)],
+        };
+        let Some(Packet::Audio { frames, .. }) = Packet::decode(&audio.encode()) else {
+            panic!("audio with samples that aren't finite is still audio");
+        };
+        assert_eq!(frames, [(0.0, 0.5), (0.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut sequence = Sequence::default();
+        assert_eq!(sequence.accept(u32::MAX), Some(0));
+        assert_eq!(sequence.accept(0), Some(0));
+        assert_eq!(sequence.accept(3), Some(2));
+        assert_eq!(sequence.accept(2), None);
+        // After a long break it starts over.
+        assert_eq!(sequence.accept(1000), Some(0));
+    }
+
+    #[test]
+    fn test_partner() {
+        let mut partner = Partner::default();
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs_f64(seconds);
+        assert_eq!(partner.sync(at(0.0), 4, 0), Some(5));
+        assert_eq!(partner.sync(at(2.0), 5, 5), None);
+        assert_eq!(partner.sync(at(4.0), 6, 7), None);
+        assert_eq!(partner.sync(at(6.0), 12, 8), Some(13));
+        assert!(!partner.stopped(at(7.0)));
+        assert!(partner.stopped(at(9.0)));
+        assert_eq!(partner.sync(at(10.0), 0, 13), Some(1));
+    }
+}
@@ -0,0 +1,314 @@
+//! A small JSON reader and writer for the control protocols.
+
+use std::fmt;
+
+use anyhow::{anyhow, bail};
+
+/// Deepest nesting of arrays and objects accepted, well within the stack.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in the order they were written.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position < text.len() {
+            bail!("trailing characters after JSON value");
+        }
+        Ok(value)
+    }
+
+    /// Member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) if value.is_finite() => write!(f, "{}", value),
+            Value::Number(_) => write!(f, "null"),
+            Value::String(value) => write_string(f, value),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    /// Arrays and objects currently open.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.whitespace();
+        self.text.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), anyhow::Error> {
+        match self.peek() {
+            Some(found) if found == byte => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => bail!("expected '{}' at {}", byte as char, self.position),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, anyhow::Error> {
+        if self.text[self.position..].starts_with(word.as_bytes()) {
+            self.position += word.len();
+            Ok(value)
+        } else {
+            bail!("invalid JSON at {}", self.position)
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, anyhow::Error> {
+        if let Some(b'[' | b'{') = self.peek() {
+            if self.depth == MAX_DEPTH {
+                bail!("JSON nested too deeply at {}", self.position);
+            }
+            self.depth += 1;
+            let value = self.nested();
+            self.depth -= 1;
+            return value;
+        }
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Value::Null),
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => bail!("invalid JSON at {}", self.position),
+        }
+    }
+
+    fn nested(&mut self) -> Result<Value, anyhow::Error> {
+        match self.peek() {
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = vec![];
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b']')?;
+                Ok(Value::Array(values))
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = vec![];
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.peek();
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    members.push((name, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b'}')?;
+                Ok(Value::Object(members))
+            }
+            _ => bail!("invalid JSON at {}", self.position),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, anyhow::Error> {
+        let start = self.position;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+            self.text.get(self.position)
+        {
+            self.position += 1;
+        }
+        let text = std::str::from_utf8(&self.text[start..self.position])?;
+        let number: f64 = text.parse()?;
+        if !number.is_finite() {
+            bail!("number out of range at {}", start);
+        }
+        Ok(Value::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, anyhow::Error> {
+        if self.text.get(self.position) != Some(&b'"') {
+            bail!("expected a string at {}", self.position);
+        }
+        self.position += 1;
+        let mut bytes = vec![];
+        loop {
+            let byte = *self
+                .text
+                .get(self.position)
+                .ok_or_else(|| anyhow!("unterminated string"))?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self
+                        .text
+                        .get(self.position)
+                        .ok_or_else(|| anyhow!("unterminated string"))?;
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let hex = self
+                                .text
+                                .get(self.position..self.position + 4)
+                                .ok_or_else(|| anyhow!("invalid escape"))?;
+                            self.position += 4;
+                            let code = u32::from_str_radix(std::str::from_utf8(hex)?, 16)?;
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => bail!("invalid escape at {}", self.position),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_write_round_trip() {
+        let text = r#"{"command":"bpm","value":92.5,"tags":[true,null,"a\"b\u00e9"]}"#;
+        let value = Value::parse(text).unwrap();
+        assert_eq!(value.get("command").and_then(Value::as_str), Some("bpm"));
+        assert_eq!(value.get("value").and_then(Value::as_f64), Some(92.5));
+        assert_eq!(Value::parse(&value.to_string()).unwrap(), value);
+        assert_eq!(Value::String("a\"é".into()).to_string(), r#""a\"é""#);
+    }
+
+    #[test]
+    fn test_invalid_json_is_rejected() {
+        assert!(Value::parse("{\"a\":}").is_err());
+        assert!(Value::parse("[1,2").is_err());
+        assert!(Value::parse("1 2").is_err());
+        assert!(Value::parse("1e999").is_err());
+        assert!(Value::parse(&"[".repeat(100_000)).is_err());
+        let nested = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(Value::parse(&nested).is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+//! Key signatures, and note names spelled with their sharps or flats.
+
+use anyhow::bail;
+
+use crate::note::{BaseNote, Note};
+
+const SHARP_NAMES: [&str; 12] = [
+    "C", "Cis", "D", "Dis", "E", "F", "Fis", "G", "Gis", "A", "Ais", "H",
+];
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Des", "D", "Es", "E", "F", "Ges", "G", "As", "A", "B", "H",
+];
+
+/// A key signature, C major or A minor by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Key {
+    /// Sharps of the signature, negative for flats.
+    pub fifths: i32,
+}
+
+impl Key {
+    /// Parses a German key name, capitalized for major and lowercase for
+    /// minor keys, such as `G`, `Es` or `fis`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut chars = value.chars();
+        let Some(first) = chars.next() else {
+            bail!("missing key");
+        };
+        let minor = first.is_lowercase();
+        let (letter, accidental) = (first.to_ascii_uppercase(), chars.as_str());
+        // Fifths of the major keys of the natural notes, from F to H.
+        let natural = match letter {
+            'F' => -1,
+            'C' => 0,
+            'G' => 1,
+            'D' => 2,
+            'A' => 3,
+            'E' => 4,
+            'H' => 5,
+            // B is the German B flat.
+            'B' if accidental.is_empty() => -2,
+            _ => bail!("unknown key: {}", value),
+        };
+        let accidental = match (letter, accidental) {
+            (_, "") => 0,
+            (_, "is") => 7,
+            ('A' | 'E', "s") | (_, "es") => -7,
+            _ => bail!("unknown key: {}", value),
+        };
+        let fifths = natural + accidental - if minor { 3 } else { 0 };
+        if !(-7..=7).contains(&fifths) {
+            bail!(
+                "key signatures have at most seven sharps or flats: {}",
+                value
+            );
+        }
+        Ok(Self { fifths })
+    }
+
+    /// Notes of the signature in ascending order from C, those of its major
+    /// key and of its relative minor alike.
+    pub fn scale(&self) -> [BaseNote; 7] {
+        // The fifths from the fourth of the major key up to its seventh.
+        let mut semitones: Vec<usize> = (-1..=5)
+            .map(|fifth| (7 * (self.fifths + fifth)).rem_euclid(12) as usize)
+            .collect();
+        semitones.sort();
+        std::array::from_fn(|i| BaseNote::ALL[semitones[i]])
+    }
+
+    /// Name of `note` with sharps in sharp keys and flats in flat keys.
+    pub fn spell(&self, note: BaseNote) -> &'static str {
+        let index = BaseNote::ALL.iter().position(|&base| base == note).unwrap();
+        match self.fifths < 0 {
+            true => FLAT_NAMES[index],
+            false => SHARP_NAMES[index],
+        }
+    }
+
+    /// Spelled name of `note` with its octave and any cents, as `Note::parse`
+    /// reads it.
+    pub fn name(&self, note: &Note) -> String {
+        let mut name = format!("{}{}", self.spell(note.note), note.octave);
+        if note.cents != 0.0 {
+            name += &format!("{:+}c", note.cents);
+        }
+        name
+    }
+
+    /// Names of `notes` separated by spaces, such as for the notes of a chord.
+    pub fn names(&self, notes: &[Note]) -> String {
+        let names: Vec<String> = notes.iter().map(|note| self.name(note)).collect();
+        names.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(Key::parse("C").unwrap().fifths, 0);
+        assert_eq!(Key::parse("a").unwrap().fifths, 0);
+        assert_eq!(Key::parse("Fis").unwrap().fifths, 6);
+        assert_eq!(Key::parse("Es").unwrap().fifths, -3);
+        assert_eq!(Key::parse("as").unwrap().fifths, -7);
+        assert_eq!(Key::parse("B").unwrap().fifths, -2);
+        assert_eq!(Key::parse("h").unwrap().fifths, 2);
+        assert!(Key::parse("Eis").is_err());
+        assert!(Key::parse("X").is_err());
+        assert!(Key::parse("").is_err());
+    }
+
+    #[test]
+    fn test_scale() {
+        use BaseNote::*;
+        assert_eq!(Key::default().scale(), [C, D, E, F, G, A, H]);
+        assert_eq!(Key::parse("e").unwrap().scale(), [C, D, E, Fis, G, A, H]);
+        assert_eq!(
+            Key::parse("Es").unwrap().scale(),
+            [C, D, Dis, F, G, Gis, Ais]
+        );
+    }
+
+    #[test]
+    fn test_spelling_round_trips() {
+        let notes = [
+            Note::new(BaseNote::Fis, -1),
+            Note::base(BaseNote::Ais).with_cents(-30.0),
+        ];
+        assert_eq!(Key::parse("D").unwrap().names(&notes), "Fis-1 Ais0-30c");
+        assert_eq!(Key::parse("Ges").unwrap().names(&notes), "Ges-1 B0-30c");
+        for key in ["G", "f"] {
+            let key = Key::parse(key).unwrap();
+            for note in notes {
+                assert_eq!(Note::parse(&key.name(&note)).unwrap(), note);
+            }
+        }
+    }
+}
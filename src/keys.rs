@@ -0,0 +1,234 @@
+//! Keys of the computer keyboard played like a piano on the console, with
+//! keys that shift the octave and keys bound to commands such as those of
+//! the transport, as a bindings file sets them for layouts other than
+//! QWERTY. The console reads a line at a time, so a line of bound keys
+//! plays once Enter is pressed, its notes together. Commands typed while
+//! the keys are played start with `:`, as in `:record on`, so that no line
+//! of keys is taken for a command that it happens to spell.
+//!
+//! A bindings file holds a binding a line, with `#` starting a comment:
+//!
+//! ```text
+//! notes awsedftgyhujkolp
+//! octave z x
+//! panic !
+//! key , record on
+//! key . record off
+//! ```
+//!
+//! `notes` gives the keys of the notes from C up, a semitone each, `octave`
+//! the keys shifting the octave down and up, `panic` the key silencing
+//! every note, and `key` a key and the command it types.
+
+use std::ops::RangeInclusive;
+use std::time::Instant;
+
+use anyhow::bail;
+
+use crate::control::Command;
+use playground::note::{BaseNote, Note};
+
+/// The keys of the notes on a QWERTY keyboard: the white keys on the home
+/// row from `a` and the black ones above them.
+const NOTES: &str = "awsedftgyhujkolp";
+/// What lines typed as commands start with.
+const COMMAND_PREFIX: char = ':';
+/// Octaves that the keys may be shifted by.
+const OCTAVE_SHIFTS: RangeInclusive<i32> = -4..=4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    /// Keys of the notes from C up.
+    notes: Vec<char>,
+    octave_down: char,
+    octave_up: char,
+    panic: char,
+    /// Keys and the command lines they type.
+    commands: Vec<(char, String)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            notes: NOTES.chars().collect(),
+            octave_down: 'z',
+            octave_up: 'x',
+            panic: '!',
+            commands: vec![],
+        }
+    }
+}
+
+impl Bindings {
+    /// Parses a bindings file, keeping the QWERTY bindings of what it leaves
+    /// out but for the command keys.
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut bindings = Self::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((binding, value)) = line.split_once(char::is_whitespace) else {
+                if !line.is_empty() {
+                    bail!("a binding needs keys: {}", line);
+                }
+                continue;
+            };
+            let value = value.trim();
+            match binding {
+                "notes" => bindings.notes = value.chars().filter(|c| !c.is_whitespace()).collect(),
+                "octave" => match value.split_whitespace().collect::<Vec<_>>()[..] {
+                    [down, up] => {
+                        (bindings.octave_down, bindings.octave_up) = (key(down)?, key(up)?)
+                    }
+                    _ => bail!("octave takes a key down and a key up: {}", value),
+                },
+                "panic" => bindings.panic = key(value)?,
+                "key" => {
+                    let (pressed, command) =
+                        value.split_once(char::is_whitespace).ok_or_else(|| {
+                            anyhow::anyhow!("key takes a key and a command: {}", value)
+                        })?;
+                    let command = command.trim();
+                    Command::parse(command)?;
+                    bindings.commands.push((key(pressed)?, command.to_string()));
+                }
+                _ => bail!("unknown binding: {}", binding),
+            }
+        }
+        let mut keys = bindings.keys();
+        keys.sort_unstable();
+        if let Some(pair) = keys.windows(2).find(|pair| pair[0] == pair[1]) {
+            bail!("the key {} is bound twice", pair[0]);
+        }
+        Ok(bindings)
+    }
+
+    fn keys(&self) -> Vec<char> {
+        let mut keys = self.notes.clone();
+        keys.extend([self.octave_down, self.octave_up, self.panic]);
+        keys.extend(self.commands.iter().map(|&(key, _)| key));
+        keys
+    }
+}
+
+fn key(value: &str) -> Result<char, anyhow::Error> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(key), None) => Ok(key),
+        _ => bail!("a key is a single character: {}", value),
+    }
+}
+
+/// The keys as they are played, with the octave they are shifted to.
+pub struct Piano {
+    bindings: Bindings,
+    octave: i32,
+}
+
+impl Piano {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            octave: 0,
+        }
+    }
+
+    pub fn octave(&self) -> i32 {
+        self.octave
+    }
+
+    /// The commands of a line typed, the command it gives after the prefix
+    /// or else those of its keys.
+    pub fn read(&mut self, line: &str) -> Result<Vec<Command>, anyhow::Error> {
+        match line.trim().strip_prefix(COMMAND_PREFIX) {
+            Some(command) => Ok(vec![Command::parse(command)?]),
+            None => self.play(line),
+        }
+    }
+
+    /// The commands of the keys of `line` in turn, a shift of the octave
+    /// changing the notes after it, or an error if it holds a key not bound.
+    pub fn play(&mut self, line: &str) -> Result<Vec<Command>, anyhow::Error> {
+        let bindings = &self.bindings;
+        let mut octave = self.octave;
+        let mut commands = vec![];
+        for pressed in line.chars().filter(|c| !c.is_whitespace()) {
+            if pressed == bindings.octave_down || pressed == bindings.octave_up {
+                let shift = if pressed == bindings.octave_up { 1 } else { -1 };
+                octave = (octave + shift).clamp(*OCTAVE_SHIFTS.start(), *OCTAVE_SHIFTS.end());
+            } else if pressed == bindings.panic {
+                commands.push(Command::Panic);
+            } else if let Some(semitone) = bindings.notes.iter().position(|&key| key == pressed) {
+                let note = Note::new(BaseNote::C, octave).transpose(semitone as i32);
+                commands.push(Command::Play(note, Instant::now()));
+            } else {
+                let Some((_, command)) = bindings.commands.iter().find(|&&(key, _)| key == pressed)
+                else {
+                    bail!(
+                        "the key {} is not bound, and commands start with {}",
+                        pressed,
+                        COMMAND_PREFIX
+                    );
+                };
+                commands.push(Command::parse(command)?);
+            }
+        }
+        self.octave = octave;
+        Ok(commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bindings() {
+        let text = "# AZERTY\nnotes qzsedftgyhujk\noctave w x\nkey , record on\n";
+        let bindings = Bindings::parse(text).unwrap();
+        assert_eq!(bindings.notes[1], 'z');
+        assert_eq!((bindings.octave_down, bindings.octave_up), ('w', 'x'));
+        assert_eq!(bindings.commands, [(',', "record on".to_string())]);
+        assert_eq!(Bindings::parse("panic 0").unwrap().panic, '0');
+        assert_eq!(Bindings::parse("").unwrap(), Bindings::default());
+        let spaced = Bindings::parse("notes a w s").unwrap();
+        assert_eq!(spaced.notes, ['a', 'w', 's']);
+        assert!(Bindings::parse("notes asa").is_err());
+        assert!(Bindings::parse("octave z").is_err());
+        assert!(Bindings::parse("key , louder").is_err());
+        assert!(Bindings::parse("key z stop").is_err());
+        assert!(Bindings::parse("pedal q").is_err());
+    }
+
+    #[test]
+    fn test_plays_keys() {
+        let bindings = Bindings::parse("key . record off").unwrap();
+        let mut piano = Piano::new(bindings);
+        let notes = |commands: Vec<Command>| -> Vec<String> {
+            commands
+                .iter()
+                .map(|command| match command {
+                    Command::Play(note, _) => note.midi().to_string(),
+                    command => format!("{:?}", command),
+                })
+                .collect()
+        };
+        assert_eq!(notes(piano.play("aw").unwrap()), ["60", "61"]);
+        assert_eq!(
+            notes(piano.play("axk.").unwrap()),
+            ["60", "84", "Record(false)"]
+        );
+        assert_eq!(piano.play("!").unwrap(), [Command::Panic]);
+        assert_eq!(piano.octave(), 1);
+        // A line with a key not bound plays nothing and keeps the octave.
+        assert!(piano.play("zq").is_err());
+        assert_eq!(piano.octave(), 1);
+        // Keys that spell a command play, the command needs its prefix.
+        assert_eq!(piano.read("stop").unwrap().len(), 4);
+        assert_eq!(piano.read(" :stop").unwrap(), [Command::Stop]);
+        assert!(piano.read(":louder").is_err());
+        for _ in 0..10 {
+            piano.play("x").unwrap();
+        }
+        assert_eq!(piano.octave(), 4);
+    }
+}
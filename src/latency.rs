@@ -0,0 +1,128 @@
+//! Round trip latency measured with clicks played out of an output and
+//! recorded back on an input that it is looped back to with a cable.
+
+/// Clicks played, of which the median latency counts.
+pub const CLICKS: usize = 8;
+/// Seconds from one click to the next, longer than any round trip.
+pub const INTERVAL: f64 = 0.5;
+/// Seconds a click lasts, long enough to survive the coupling capacitors
+/// of the converters.
+const CLICK_SECONDS: f64 = 0.002;
+/// Level of the signal coming back that counts as a click, well above the
+/// noise of an input.
+const THRESHOLD: f64 = 0.1;
+
+/// Clicks every `INTERVAL`, one sample at a time.
+pub struct Clicks {
+    interval: u64,
+    length: u64,
+    frame: u64,
+}
+
+impl Clicks {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            interval: (INTERVAL * sample_rate) as u64,
+            length: (CLICK_SECONDS * sample_rate).ceil() as u64,
+            frame: 0,
+        }
+    }
+
+    /// The next sample, and whether a click starts with it.
+    pub fn sample(&mut self) -> (f64, bool) {
+        if self.frame >= CLICKS as u64 * self.interval {
+            return (0.0, false);
+        }
+        let offset = self.frame % self.interval;
+        self.frame += 1;
+        if offset < self.length {
+            (0.5, offset == 0)
+        } else {
+            (0.0, false)
+        }
+    }
+}
+
+/// Finds the clicks that come back on an input.
+#[derive(Default)]
+pub struct Detector {
+    /// Samples left before another click counts, so that one click ringing
+    /// counts once.
+    hold: u64,
+}
+
+impl Detector {
+    /// Whether a click comes back with `sample`, at `sample_rate`.
+    pub fn push(&mut self, sample: f64, sample_rate: f64) -> bool {
+        if self.hold > 0 {
+            self.hold -= 1;
+            return false;
+        }
+        let arrived = sample.abs() > THRESHOLD;
+        if arrived {
+            self.hold = (INTERVAL / 2.0 * sample_rate) as u64;
+        }
+        arrived
+    }
+}
+
+/// Seconds from each click `sent` to the click that `arrived` after it
+/// within the interval, in seconds on one clock.
+pub fn round_trips(sent: &[f64], arrived: &[f64]) -> Vec<f64> {
+    sent.iter()
+        .filter_map(|&sent| {
+            arrived
+                .iter()
+                .map(|&arrived| arrived - sent)
+                .find(|latency| (0.0..INTERVAL).contains(latency))
+        })
+        .collect()
+}
+
+/// The median of `values`, if there are any.
+pub fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+        _ => Some(sorted[middle]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clicks_come_back_once() {
+        let sample_rate = 1000.0;
+        let mut clicks = Clicks::new(sample_rate);
+        let mut detector = Detector::default();
+        // A loopback that delays by 30 samples and halves the level.
+        let mut line = std::collections::VecDeque::from(vec![0.0; 30]);
+        let (mut sent, mut arrived) = (vec![], vec![]);
+        for frame in 0..(CLICKS as f64 * INTERVAL * sample_rate) as usize + 100 {
+            let (sample, starts) = clicks.sample();
+            if starts {
+                sent.push(frame as f64 / sample_rate);
+            }
+            line.push_back(sample);
+            if detector.push(line.pop_front().unwrap() * 0.5, sample_rate) {
+                arrived.push(frame as f64 / sample_rate);
+            }
+        }
+        assert_eq!((sent.len(), arrived.len()), (CLICKS, CLICKS));
+        let latencies = round_trips(&sent, &arrived);
+        assert!((median(&latencies).unwrap() - 0.03).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_round_trips_and_median() {
+        assert_eq!(round_trips(&[0.0, 0.5, 1.0], &[0.25, 1.125]), [0.25, 0.125]);
+        assert_eq!(median(&[3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&[4.0, 1.0]), Some(2.5));
+        assert_eq!(median(&[]), None);
+    }
+}
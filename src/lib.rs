@@ -0,0 +1,84 @@
+//! Sequencing and synthesis, independent of the audio device.
+#![allow(clippy::precedence)]
+
+pub mod amp;
+pub mod arpeggio;
+pub mod arrangement;
+pub mod autotune;
+pub mod bassline;
+pub mod beat;
+pub mod binaural;
+pub mod bridge;
+pub mod builder;
+pub mod chord;
+pub mod convolution;
+pub mod correction;
+pub mod crossfade;
+pub mod cv;
+pub mod desk;
+pub mod dither;
+pub mod drums;
+pub mod engine;
+pub mod evolve;
+pub mod feedback;
+pub mod fill;
+pub mod fingerprint;
+pub mod flac;
+pub mod graph;
+pub mod groove;
+pub mod humanize;
+pub mod insert;
+pub mod instrument;
+pub mod jam;
+pub mod json;
+pub mod key;
+pub mod latency;
+pub mod looper;
+pub mod loudness;
+pub mod mapping;
+pub mod meter;
+pub mod metronome;
+pub mod mixer;
+pub mod modulation;
+pub mod monitor;
+pub mod note;
+pub mod offline;
+pub mod ogg;
+pub mod param;
+pub mod pattern;
+pub mod pitch;
+pub mod plot;
+pub mod preset;
+pub mod project;
+pub mod quantize;
+pub mod record;
+pub mod reverb;
+pub mod roll;
+pub mod sampler;
+pub mod scala;
+pub mod schedule;
+pub mod scope;
+pub mod sf2;
+pub mod sfz;
+pub mod signal;
+pub mod smf;
+pub mod song;
+pub mod spectrogram;
+pub mod stereo;
+pub mod stream;
+pub mod structure;
+pub mod strum;
+pub mod sweep;
+pub mod timecode;
+pub mod transient;
+pub mod transport;
+pub mod trigger;
+pub mod tuning;
+pub mod velocity;
+pub mod vocoder;
+pub mod voice;
+pub mod voicing;
+pub mod vorbis;
+pub mod vu;
+#[cfg(feature = "web")]
+pub mod web;
@@ -0,0 +1,138 @@
+//! Measuring the round trip latency of the audio interface, with the output
+//! looped back to an input by a cable, to calibrate the compensation for it.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+
+use playground::latency::{median, round_trips, Clicks, Detector, CLICKS, INTERVAL};
+
+/// Seconds on one clock, since the measurement started, that clicks were
+/// sent or arrived.
+type Times = Arc<Mutex<Vec<f64>>>;
+
+/// Plays clicks out of every channel of `device` and reports how long they
+/// take to come back on the zero based `channel` of the default input
+/// device of `host`.
+pub fn measure<T>(
+    host: &cpal::Host,
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channel: usize,
+) -> Result<(), anyhow::Error>
+where
+    T: SizedSample + FromSample<f64>,
+{
+    let start = Instant::now();
+    let sent: Times = Arc::default();
+    let arrived: Times = Arc::default();
+    let _input = listen(host, channel, start, arrived.clone())?;
+
+    let sample_rate = config.sample_rate.0 as f64;
+    let channels = config.channels as usize;
+    let mut clicks = Clicks::new(sample_rate);
+    let times = sent.clone();
+    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+    let output = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let now = start.elapsed().as_secs_f64();
+            for (i, frame) in data.chunks_mut(channels).enumerate() {
+                let (value, starts) = clicks.sample();
+                if starts {
+                    times.lock().unwrap().push(now + i as f64 / sample_rate);
+                }
+                frame.fill(T::from_sample(value));
+            }
+        },
+        err_fn,
+        None,
+    )?;
+    output.play()?;
+    eprintln!("measuring the round trip latency with {} clicks", CLICKS);
+    std::thread::sleep(Duration::from_secs_f64((CLICKS + 1) as f64 * INTERVAL));
+    drop(output);
+
+    let latencies = round_trips(&sent.lock().unwrap(), &arrived.lock().unwrap());
+    let Some(latency) = median(&latencies) else {
+        bail!(
+            "no clicks came back on input channel {}, is the output looped back to it?",
+            channel + 1
+        );
+    };
+    println!(
+        "round trip latency: {} samples ({:.1} ms) at {} Hz, from {} of {} clicks",
+        (latency * sample_rate).round(),
+        latency * 1000.0,
+        sample_rate,
+        latencies.len(),
+        CLICKS
+    );
+    Ok(())
+}
+
+/// Starts recording when clicks arrive on `channel` of the default input
+/// device of `host`.
+fn listen(
+    host: &cpal::Host,
+    channel: usize,
+    start: Instant,
+    arrived: Times,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let device = host
+        .default_input_device()
+        .ok_or_else(|| anyhow!("no default input device on {}", host.id().name()))?;
+    let supported = device.default_input_config()?;
+    let format = supported.sample_format();
+    let config: cpal::StreamConfig = supported.into();
+    if channel >= config.channels as usize {
+        bail!(
+            "no input channel {}, the device has {}",
+            channel + 1,
+            config.channels
+        );
+    }
+    match format {
+        cpal::SampleFormat::F32 => record::<f32>(&device, &config, channel, start, arrived),
+        cpal::SampleFormat::I16 => record::<i16>(&device, &config, channel, start, arrived),
+        cpal::SampleFormat::U16 => record::<u16>(&device, &config, channel, start, arrived),
+        format => bail!("unsupported input sample format: {}", format),
+    }
+}
+
+fn record<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channel: usize,
+    start: Instant,
+    arrived: Times,
+) -> Result<cpal::Stream, anyhow::Error>
+where
+    T: SizedSample,
+    f64: FromSample<T>,
+{
+    let sample_rate = config.sample_rate.0 as f64;
+    let channels = config.channels as usize;
+    let mut detector = Detector::default();
+    let err_fn = |err| eprintln!("an error occurred on the input stream: {}", err);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            // Frames count from the callback as they do on the output, so
+            // that the latency between the callbacks is the round trip.
+            let now = start.elapsed().as_secs_f64();
+            for (i, frame) in data.chunks(channels).enumerate() {
+                if detector.push(f64::from_sample_(frame[channel]), sample_rate) {
+                    arrived.lock().unwrap().push(now + i as f64 / sample_rate);
+                }
+            }
+        },
+        err_fn,
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
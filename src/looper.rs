@@ -0,0 +1,156 @@
+//! A stereo loop layer that records the engine output and plays it back in time with the bars.
+
+use fundsp::hacker::*;
+
+/// Longest loop that fits the preallocated buffer.
+pub const MAX_SECONDS: f64 = 30.0;
+
+/// Controls the looper from the main thread. Times are in seconds of the audio thread clock.
+#[derive(Clone)]
+pub struct LooperControl {
+    start: Shared<f64>,
+    length: Shared<f64>,
+    overdub: Shared<f64>,
+}
+
+impl Default for LooperControl {
+    fn default() -> Self {
+        Self {
+            start: shared(0.0),
+            length: shared(0.0),
+            overdub: shared(0.0),
+        }
+    }
+}
+
+impl LooperControl {
+    /// A stereo looper unit following these controls.
+    pub fn unit(&self) -> An<Looper> {
+        An(Looper {
+            control: self.clone(),
+            buffer: vec![],
+            sample_rate: DEFAULT_SR,
+            sample: 0,
+        })
+    }
+
+    /// Records a new loop of `length` seconds starting at `time`, replacing the
+    /// current one. It plays back from `time + length` on.
+    pub fn record(&self, time: f64, length: f64) {
+        self.start.set_value(time);
+        self.length.set_value(length.min(MAX_SECONDS));
+    }
+
+    /// While on, the input is mixed into the loop as it plays.
+    pub fn set_overdub(&self, overdub: bool) {
+        self.overdub.set_value(if overdub { 1.0 } else { 0.0 });
+    }
+
+    pub fn clear(&self) {
+        self.length.set_value(0.0);
+    }
+}
+
+#[derive(Clone)]
+pub struct Looper {
+    control: LooperControl,
+    /// Interleaved left and right samples.
+    buffer: Vec<f64>,
+    sample_rate: f64,
+    /// Samples processed so far, the looper's clock.
+    sample: u64,
+}
+
+impl AudioNode for Looper {
+    const ID: u64 = 0x4c6f_6f70;
+    type Sample = f64;
+    type Inputs = U2;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.sample = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.allocate();
+    }
+
+    fn allocate(&mut self) {
+        let size = (MAX_SECONDS * self.sample_rate) as usize * 2;
+        if self.buffer.len() != size {
+            self.buffer = vec![0.0; size];
+        }
+    }
+
+    fn tick(&mut self, input: &Frame<f64, U2>) -> Frame<f64, U2> {
+        let sample = self.sample;
+        self.sample += 1;
+        let start = (self.control.start.value() * self.sample_rate).round() as u64;
+        let length = min(
+            (self.control.length.value() * self.sample_rate).round() as usize,
+            self.buffer.len() / 2,
+        );
+        if length == 0 || sample < start {
+            return *input;
+        }
+        let offset = (sample - start) as usize;
+        let index = offset % length * 2;
+        let frame = &mut self.buffer[index..index + 2];
+        if offset < length {
+            // The first pass records.
+            frame.copy_from_slice(input);
+            return *input;
+        }
+        let played = [frame[0], frame[1]];
+        if self.control.overdub.value() > 0.0 {
+            frame[0] += input[0];
+            frame[1] += input[1];
+        }
+        [input[0] + played[0], input[1] + played[1]].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        let mut output = new_signal_frame(2);
+        output[0] = input[0].distort(0.0);
+        output[1] = input[1].distort(0.0);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `input` to the left channel and its negation to the right one,
+    /// returning the left output.
+    fn render(looper: &mut An<Looper>, input: &[f64]) -> Vec<f64> {
+        input
+            .iter()
+            .map(|&x| {
+                let (left, right) = looper.filter_stereo(x, -x);
+                assert_eq!(left, -right);
+                left
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_loop_records_then_plays_with_overdub() {
+        let control = LooperControl::default();
+        let mut looper = control.unit();
+        looper.set_sample_rate(10.0);
+        control.record(0.2, 0.3);
+        assert_eq!(
+            render(&mut looper, &[9.0, 9.0, 1.0, 2.0, 3.0, 0.0, 0.0, 0.0]),
+            [9.0, 9.0, 1.0, 2.0, 3.0, 1.0, 2.0, 3.0]
+        );
+        control.set_overdub(true);
+        assert_eq!(render(&mut looper, &[1.0, 1.0, 1.0]), [2.0, 3.0, 4.0]);
+        control.set_overdub(false);
+        assert_eq!(render(&mut looper, &[0.0, 0.0, 0.0]), [2.0, 3.0, 4.0]);
+        control.clear();
+        assert_eq!(render(&mut looper, &[0.0]), [0.0]);
+    }
+}
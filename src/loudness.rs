@@ -0,0 +1,228 @@
+//! Loudness of renders as in ITU-R BS.1770, and normalizing them to a target
+//! so that exports come out equally loud.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// Blocks that loudness is measured over, overlapping by three quarters.
+const BLOCK_SECONDS: f64 = 0.4;
+const BLOCK_STEP: f64 = 0.25;
+/// Blocks quieter than this, or this far below the loudness of the louder
+/// blocks, are left out as silence.
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+/// Peak level that normalizing to a loudness stops at, so that a loud target
+/// doesn't clip.
+pub const CEILING: f64 = -1.0;
+
+/// What a render is normalized to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// Integrated loudness in LUFS, such as -14 for streaming.
+    Loudness(f64),
+    /// Sample peak in dBFS.
+    Peak(f64),
+}
+
+impl Target {
+    /// Parses a loudness such as `-14` or a peak level such as `peak,-1`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let target = match value.split_once(',') {
+            Some(("peak", level)) => Target::Peak(level.trim().parse()?),
+            Some(_) => bail!("unknown normalization target: {}", value),
+            None => Target::Loudness(value.parse()?),
+        };
+        match target {
+            Target::Loudness(lufs) if !(-70.0..=0.0).contains(&lufs) => {
+                bail!("loudness targets go from -70 to 0 LUFS")
+            }
+            Target::Peak(level) if !(-70.0..=0.0).contains(&level) => {
+                bail!("peak targets go from -70 to 0 dBFS")
+            }
+            target => Ok(target),
+        }
+    }
+
+    /// Gain that brings `wave` to the target, or `None` if it is silent.
+    pub fn gain(self, wave: &Wave64) -> Option<f64> {
+        let peak = wave.amplitude();
+        if peak == 0.0 {
+            return None;
+        }
+        let gain = match self {
+            Target::Loudness(lufs) => {
+                let ceiling = db_amp(CEILING) / peak;
+                db_amp(lufs - integrated(wave)?).min(ceiling)
+            }
+            Target::Peak(level) => db_amp(level) / peak,
+        };
+        Some(gain)
+    }
+}
+
+/// Two pole filter coefficients, normalized so that `a0` is 1.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+
+    fn filter(&self, samples: &[f64]) -> Vec<f64> {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        samples
+            .iter()
+            .map(|&x| {
+                let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2
+                    - self.a[0] * y1
+                    - self.a[1] * y2;
+                (x2, x1, y2, y1) = (x1, x, y1, y);
+                y
+            })
+            .collect()
+    }
+}
+
+/// The K-weighting of BS.1770 at `sample_rate`: a high shelf for the head
+/// followed by a highpass, designed for any rate with the parameters that
+/// match its coefficients at 48 kHz.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let w0 = |frequency: f64| TAU * frequency / sample_rate;
+
+    let (gain, q, w) = (4.0, FRAC_1_SQRT_2, w0(1500.0));
+    let a = 10.0.pow(gain / 40.0);
+    let alpha = w.sin() / (2.0 * q);
+    let root = 2.0 * a.sqrt() * alpha;
+    let shelf = Biquad::new(
+        [
+            a * ((a + 1.0) + (a - 1.0) * w.cos() + root),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * w.cos()),
+            a * ((a + 1.0) + (a - 1.0) * w.cos() - root),
+        ],
+        [
+            (a + 1.0) - (a - 1.0) * w.cos() + root,
+            2.0 * ((a - 1.0) - (a + 1.0) * w.cos()),
+            (a + 1.0) - (a - 1.0) * w.cos() - root,
+        ],
+    );
+
+    let (q, w) = (0.5, w0(38.0));
+    let alpha = w.sin() / (2.0 * q);
+    let highpass = Biquad::new(
+        [
+            (1.0 + w.cos()) / 2.0,
+            -(1.0 + w.cos()),
+            (1.0 + w.cos()) / 2.0,
+        ],
+        [1.0 + alpha, -2.0 * w.cos(), 1.0 - alpha],
+    );
+    [shelf, highpass]
+}
+
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Integrated loudness of `wave` in LUFS, gated to leave out its silences,
+/// or `None` if it is all silence or shorter than a block.
+pub fn integrated(wave: &Wave64) -> Option<f64> {
+    let sample_rate = wave.sample_rate();
+    let weighted: Vec<Vec<f64>> = (0..wave.channels())
+        .map(|channel| {
+            let [shelf, highpass] = k_weighting(sample_rate);
+            highpass.filter(&shelf.filter(wave.channel(channel)))
+        })
+        .collect();
+    let block = (BLOCK_SECONDS * sample_rate) as usize;
+    let step = (BLOCK_SECONDS * BLOCK_STEP * sample_rate).max(1.0) as usize;
+    if block == 0 || wave.len() < block {
+        return None;
+    }
+    // Mean square of each block, summed over the channels, which all
+    // weigh the same in stereo.
+    let powers: Vec<f64> = (0..=(wave.len() - block) / step)
+        .map(|i| {
+            let range = i * step..i * step + block;
+            weighted
+                .iter()
+                .map(|samples| samples[range.clone()].iter().map(|x| x * x).sum::<f64>())
+                .sum::<f64>()
+                / block as f64
+        })
+        .collect();
+    let gated = |threshold: f64| {
+        let loud: Vec<f64> = powers
+            .iter()
+            .copied()
+            .filter(|&power| power > 0.0 && lufs(power) > threshold)
+            .collect();
+        (!loud.is_empty()).then(|| loud.iter().sum::<f64>() / loud.len() as f64)
+    };
+    let relative = lufs(gated(ABSOLUTE_GATE)?) + RELATIVE_GATE;
+    gated(relative).map(lufs)
+}
+
+/// Brings `wave` to `target` in a second pass over it, and returns the gain
+/// applied, if it isn't silent.
+pub fn normalize(wave: &mut Wave64, target: Target) -> Option<f64> {
+    let gain = target.gain(wave)?;
+    amplify(wave, gain);
+    Some(gain)
+}
+
+/// Multiplies every sample of `wave` by `gain`.
+pub fn amplify(wave: &mut Wave64, gain: f64) {
+    for channel in wave.channels_mut() {
+        for sample in channel.iter_mut() {
+            *sample *= gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f64, seconds: f64) -> Wave64 {
+        let sample_rate = 48000.0;
+        let mut wave = Wave64::new(2, sample_rate);
+        for i in 0..(seconds * sample_rate) as usize {
+            let x = amplitude * (TAU * 997.0 * i as f64 / sample_rate).sin();
+            wave.push((x, x));
+        }
+        wave
+    }
+
+    #[test]
+    fn test_full_scale_sine_is_zero_lufs() {
+        // A full scale sine in each of two channels, per BS.1770.
+        let loudness = integrated(&sine(1.0, 2.0)).unwrap();
+        assert!(loudness.abs() < 0.1, "{}", loudness);
+        let loudness = integrated(&sine(0.1, 2.0)).unwrap();
+        assert!((loudness + 20.0).abs() < 0.1, "{}", loudness);
+        assert_eq!(integrated(&sine(0.0, 2.0)), None);
+        assert_eq!(integrated(&sine(1.0, 0.1)), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut wave = sine(0.1, 2.0);
+        normalize(&mut wave, Target::Loudness(-14.0)).unwrap();
+        assert!((integrated(&wave).unwrap() + 14.0).abs() < 0.1);
+        // Too loud a target stops at the ceiling.
+        normalize(&mut wave, Target::Loudness(0.0)).unwrap();
+        assert!((amp_db(wave.amplitude()) - CEILING).abs() < 1e-9);
+        normalize(&mut wave, Target::Peak(-6.0)).unwrap();
+        assert!((amp_db(wave.amplitude()) + 6.0).abs() < 1e-9);
+        assert_eq!(Target::parse("peak, -1").unwrap(), Target::Peak(-1.0));
+        assert!(Target::parse("3").is_err());
+        assert!(Target::parse("rms,-3").is_err());
+    }
+}
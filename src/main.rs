@@ -2,215 +2,1105 @@
 #![allow(clippy::precedence)]
 
 use assert_no_alloc::*;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{FromSample, SizedSample};
 use fundsp::hacker::*;
 
+mod check;
+mod control;
+mod icecast;
+mod input;
+mod keys;
+mod loopback;
+mod midi;
+mod midi_out;
+mod output;
+mod peer;
+mod render;
+mod server;
+mod settings;
+mod tui;
+mod tuner;
+mod websocket;
+
+use control::{Command, LooperCommand};
+use keys::Piano;
+use midi::{Message, Sustain, SUSTAIN};
+use midi_out::MidiOut;
+use output::Cue;
+use playground::amp::Amp;
+use playground::autotune::AutoTune;
+use playground::bridge::{self, Bridge};
+use playground::builder::Builder;
+use playground::chord::Chord;
+use playground::convolution::Response;
+use playground::correction::Correction;
+use playground::cv::CvTrack;
+use playground::desk::{Desk, Played};
+use playground::evolve::{self, Evolution};
+use playground::graph::{named, node, serial, Graph};
+use playground::humanize;
+use playground::instrument::Instrument;
+use playground::jam::Partner;
+use playground::looper::{self, LooperControl};
+use playground::metronome;
+use playground::monitor::Monitor;
+use playground::note::get_note_frequency;
+use playground::param::ParamRegistry;
+use playground::preset::{Comparison, Morph, Preset, Randomizer};
+use playground::project::{Metadata, Project};
+use playground::record::{self, Recorder};
+use playground::roll::piano_roll;
+use playground::schedule::{Action, Schedule};
+use playground::scope::Scope;
+use playground::sweep;
+use playground::timecode::{Chase, Follow, QuarterFrames};
+use playground::transport::{Bar, ClockTempo, TapTempo, Transport};
+use playground::trigger::Trigger;
+use playground::vocoder::Vocoder;
+use playground::voice::VoicePool;
+use playground::vu::VuMeter;
+use settings::Settings;
+use websocket::State;
+
+/// Instrument of notes played live on the keyboard or over MIDI.
+const LIVE_INSTRUMENT: Instrument = Instrument::Pluck;
+/// Overlapping metronome clicks.
+const CLICK_VOICES: usize = 2;
+/// Height of the oscilloscope and the time it shows.
+const SCOPE_ROWS: usize = 12;
+const SCOPE_SECONDS: f64 = 2.0;
+/// Most keys shown by the piano roll.
+const ROLL_ROWS: usize = 12;
+/// Characters of a level meter bar.
+const METER_WIDTH: usize = 40;
+/// Beats per minute the beat heard on the input has to differ by for the
+/// tempo to follow it, and how much of the way into step with each beat
+/// heard the playback is pulled.
+const BEAT_BPM_TOLERANCE: f64 = 0.5;
+const BEAT_PULL: f64 = 0.5;
+
 #[cfg(debug_assertions)] // required when disable_release is set (default)
 #[global_allocator]
 static A: AllocDisabler = AllocDisabler;
 
-struct Note {
-    note: BaseNote,
-    octave: i32,
-}
-
-impl Note {
-    fn base(note: BaseNote) -> Self {
-        Self { note, octave: 0 }
-    }
+fn main() {
+    let settings = match Settings::from_args() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
 
-    fn new(note: BaseNote, octave: i32) -> Self {
-        Self { note: note, octave }
+    if let Err(err) = start(&settings) {
+        eprintln!("{}", err);
+        std::process::exit(1);
     }
 }
 
-struct Accord {
-    notes: Vec<Note>,
-}
-
-enum BaseNote {
-    C,
-    Cis,
-    D,
-    Dis,
-    E,
-    F,
-    Fis,
-    G,
-    Gis,
-    A,
-    Ais,
-    H,
-}
-
-fn main() {
-    let host = cpal::default_host();
-
-    let device = host
-        .default_output_device()
-        .expect("Failed to find a default output device");
-    let config = device.default_output_config().unwrap();
+fn start(settings: &Settings) -> Result<(), anyhow::Error> {
+    if let Some(path) = &settings.render {
+        return render::render(settings, path);
+    }
+    if let Some(path) = &settings.plot {
+        return render::plot(settings, path);
+    }
+    let host = output::host(settings.output.host.as_deref())?;
+    if settings.list_devices {
+        return output::list_devices(&host);
+    }
+    if let Some(channel) = settings.tuner {
+        return tuner::run(&host, channel, &settings.tuning);
+    }
+    let device = output::device(&host, settings.output.device.as_deref())?;
+    let (config, format) = output::config(&device, &settings.output)?;
 
-    match config.sample_format() {
-        cpal::SampleFormat::F32 => run::<f32>(&device, &config.into()).unwrap(),
-        cpal::SampleFormat::I16 => run::<i16>(&device, &config.into()).unwrap(),
-        cpal::SampleFormat::U16 => run::<u16>(&device, &config.into()).unwrap(),
-        _ => panic!("Unsupported format"),
+    if let Some(signal) = settings.test_tone {
+        let dither = settings.output.dither;
+        return match format {
+            cpal::SampleFormat::F32 => check::test_tone::<f32>(&device, &config, dither, signal),
+            cpal::SampleFormat::I16 => check::test_tone::<i16>(&device, &config, dither, signal),
+            cpal::SampleFormat::U16 => check::test_tone::<u16>(&device, &config, dither, signal),
+            format => anyhow::bail!("unsupported sample format: {}", format),
+        };
+    }
+    if settings.channel_check {
+        let dither = settings.output.dither;
+        return match format {
+            cpal::SampleFormat::F32 => check::channel_check::<f32>(&device, &config, dither),
+            cpal::SampleFormat::I16 => check::channel_check::<i16>(&device, &config, dither),
+            cpal::SampleFormat::U16 => check::channel_check::<u16>(&device, &config, dither),
+            format => anyhow::bail!("unsupported sample format: {}", format),
+        };
+    }
+    if let Some(channel) = settings.measure_latency {
+        return match format {
+            cpal::SampleFormat::F32 => loopback::measure::<f32>(&host, &device, &config, channel),
+            cpal::SampleFormat::I16 => loopback::measure::<i16>(&host, &device, &config, channel),
+            cpal::SampleFormat::U16 => loopback::measure::<u16>(&host, &device, &config, channel),
+            format => anyhow::bail!("unsupported sample format: {}", format),
+        };
+    }
+    match format {
+        cpal::SampleFormat::F32 => run::<f32>(&host, &device, &config, settings),
+        cpal::SampleFormat::I16 => run::<i16>(&host, &device, &config, settings),
+        cpal::SampleFormat::U16 => run::<u16>(&host, &device, &config, settings),
+        format => anyhow::bail!("unsupported sample format: {}", format),
     }
 }
 
-fn run<T>(device: &cpal::Device, config: &cpal::StreamConfig) -> Result<(), anyhow::Error>
+fn run<T>(
+    host: &cpal::Host,
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    settings: &Settings,
+) -> Result<(), anyhow::Error>
 where
     T: SizedSample + FromSample<f64>,
 {
     let sample_rate = config.sample_rate.0 as f64;
-    let channels = config.channels as usize;
 
-    let bpm = 160;
+    let mut song = settings.song()?;
+    if settings.record_track >= song.tracks.len() {
+        anyhow::bail!(
+            "no track {} to record into, there are {}",
+            settings.record_track + 1,
+            song.tracks.len()
+        );
+    }
+    let mut recorder = Recorder::new(settings.record_track, settings.quantize);
 
-    let mut net = Net64::new(0, 2);
+    if let Some(track) = settings.roll.filter(|&track| track >= song.tracks.len()) {
+        anyhow::bail!(
+            "no track {} to show, there are {}",
+            track + 1,
+            song.tracks.len()
+        );
+    }
+
+    if let Some(&(track, _)) = settings
+        .cv
+        .iter()
+        .find(|(track, _)| *track >= song.tracks.len())
+    {
+        anyhow::bail!(
+            "no track {} to send CV of, there are {}",
+            track + 1,
+            song.tracks.len()
+        );
+    }
+    if let Some(&(track, _)) = settings
+        .midi_tracks
+        .iter()
+        .find(|(track, _)| *track >= song.tracks.len())
+    {
+        anyhow::bail!(
+            "no track {} to send MIDI of, there are {}",
+            track + 1,
+            song.tracks.len()
+        );
+    }
+    let midi_out = match (&settings.midi_out, settings.midi_tracks.is_empty()) {
+        (Some(port), _) => Some(MidiOut::spawn(port)?),
+        (None, false) => anyhow::bail!("MIDI tracks need a --midi-out port"),
+        (None, true) => None,
+    };
+    for &(track, modulation) in &settings.modulations {
+        if track >= song.tracks.len() || modulation.source >= song.tracks.len() {
+            anyhow::bail!("there are {} tracks to modulate", song.tracks.len());
+        }
+    }
+    if let Some(&(track, _)) = settings
+        .echoes
+        .iter()
+        .find(|(track, _)| *track >= song.tracks.len())
+    {
+        anyhow::bail!("no track {} to echo", track + 1);
+    }
 
-    let id_noise = net.chain(Box::new(zero()));
-    net.chain(Box::new(pan(0.0)));
+    // The parameters that can be set by name while playing.
+    let mut params = ParamRegistry::default();
+    let mut setup = settings.setup(settings.live_samplers()?)?;
+    setup.seed = rand::random();
+    // A stereo bus for every track and one for live notes, each metered before the mix.
+    let mut desk = Desk::new(&mut params, &song, setup);
+    let buses = desk.buses();
+    // Whether to move the crossfader over to the other bank at the next bar.
+    let mut switch = false;
+    let master_meter = VuMeter::default();
+    let mut sequencer = Sequencer64::new(false, 2 * buses);
+    sequencer.set_sample_rate(sample_rate);
+    let voices = desk.voice_pool();
+    // The metronome plays on its own sequencer, so that it can be routed separately.
+    let mut click_sequencer = Sequencer64::new(false, 1);
+    click_sequencer.set_sample_rate(sample_rate);
+    let click_voices = VoicePool::new(CLICK_VOICES);
 
-    net.set_sample_rate(sample_rate);
+    // Audio thread time in seconds, which sequencer events are scheduled against.
+    let time = shared(0.0);
 
-    let mut backend = net.backend();
+    // Outputs the main stereo mix and the mono click, followed by the
+    // stereo monitor mix if there is one and the pitch and gate of every CV track.
+    let cv_start = if settings.monitor.is_some() { 5 } else { 3 };
+    let mut graph = Graph::new(0, cv_start + 2 * settings.cv.len());
+    // Wired up in one transaction, before it plays.
+    let mut net = graph.edit();
 
-    let mut next_value = move || assert_no_alloc(|| backend.get_stereo());
+    let looper = LooperControl::default();
+    let sweep = params.register("filter", sweep::KNOB);
+    let monitor = Monitor::new(&mut params, desk.mixer().tracks());
+    for &(track, level) in &settings.monitor_levels {
+        if let Err(err) = params.set(&format!("monitor{}", track + 1), level) {
+            anyhow::bail!("no track {} to monitor: {}", track + 1, err);
+        }
+    }
+    let main = net.push_named("voices", Box::new(sequencer.backend()));
+    let tracks = desk.build(&mut params, &mut net, main);
+    settings.restore(&params, desk.mixer_mut())?;
+    // The monitor mix is taken before the tracks are muted and soloed.
+    if settings.monitor.is_some() {
+        let monitor_id = net.push_named("monitor", Box::new(monitor.unit()));
+        for (bus, &(source, output)) in tracks.sources.iter().enumerate() {
+            for channel in 0..2 {
+                net.connect(source, output + channel, monitor_id, 2 * bus + channel);
+            }
+        }
+        net.connect_output(monitor_id, 0, 3);
+        net.connect_output(monitor_id, 1, 4);
+    }
+    let mut mix = tracks.mix;
+    // Played live through the amp, handed over from the input device.
+    let amp_input = Bridge::new(sample_rate);
+    if settings.amp.is_some() {
+        let cabinet = match &settings.cabinet {
+            Some(path) => {
+                Some(Response::load(path).map_err(|err| anyhow::anyhow!("{}: {}", path, err))?)
+            }
+            None => None,
+        };
+        let amp = Amp::new(&mut params, cabinet);
+        let played = Net64::wrap(Box::new(
+            amp_input.output(bridge::MARGIN_SECONDS, sample_rate) >> (pass() | sink()),
+        ));
+        let sum = net.push_named("amp", Box::new((pass() | pass()) + (played >> amp.unit())));
+        for channel in 0..2 {
+            net.connect(mix, channel, sum, channel);
+        }
+        mix = sum;
+    }
+    // Spoken live through the vocoder, handed over from the input device.
+    let vocoder_input = Bridge::new(sample_rate);
+    let vocoder = settings
+        .vocoder
+        .map(|_| Vocoder::new(&mut params, settings.vocoder_bands));
+    if let Some(vocoder) = &vocoder {
+        let spoken = Net64::wrap(Box::new(
+            vocoder_input.output(bridge::MARGIN_SECONDS, sample_rate) >> (pass() | sink()),
+        ));
+        let sum = net.push_named(
+            "vocoder",
+            Box::new((pass() | pass()) + (spoken >> vocoder.unit())),
+        );
+        for channel in 0..2 {
+            net.connect(mix, channel, sum, channel);
+        }
+        mix = sum;
+    }
+    // Corrected live to the key, handed over from the input device.
+    let autotune_input = Bridge::new(sample_rate);
+    if settings.autotune.is_some() {
+        let autotune = AutoTune::new(&mut params, song.key, &song.tuning);
+        params.set("autotune_speed", settings.autotune_speed)?;
+        let sung = Net64::wrap(Box::new(
+            autotune_input.output(bridge::MARGIN_SECONDS, sample_rate) >> (pass() | sink()),
+        ));
+        let sum = net.push_named(
+            "autotune",
+            Box::new((pass() | pass()) + (sung >> autotune.unit())),
+        );
+        for channel in 0..2 {
+            net.connect(mix, channel, sum, channel);
+        }
+        mix = sum;
+    }
+    let scope = Scope::new(SCOPE_SECONDS);
+    let master = net.add(serial([
+        node(mix),
+        named("master.looper", Box::new(looper.unit())),
+        desk.master(),
+        named("master.filter", Box::new(sweep::filter(&sweep))),
+        named("master.scope", Box::new(scope.unit())),
+        named("master.meter", Box::new(master_meter.unit())),
+    ]))?;
+    let (master_id, _) = master.outputs[0];
+    let correction = match &settings.correction {
+        Some(path) => Some(
+            Correction::load(path, sample_rate)
+                .map_err(|err| anyhow::anyhow!("{}: {}", path, err))?,
+        ),
+        None => None,
+    };
+    // The main and the second device, each delayed to line up with the other,
+    // the main one later by the correction.
+    let latency = settings.latency + correction.as_ref().map_or(0.0, |c| c.latency);
+    let (main_delay, cue_delay) = match &settings.cue {
+        Cue::Device(_) => bridge::align(latency, settings.cue_latency, false),
+        Cue::Mix(_) => bridge::align(latency, settings.cue_latency, true),
+        _ => (0.0, 0.0),
+    };
+    let bridge = Bridge::new(sample_rate);
+    let mut master_out = master_id;
+    // Sent to the jam partner without what it sent in turn.
+    let (jam_sent, jam_received) = (Bridge::new(sample_rate), Bridge::new(sample_rate));
+    if settings.jam.is_some() {
+        let sent_id = net.push(Box::new(jam_sent.input()));
+        let received = jam_received.output(settings.jam_buffer, sample_rate);
+        let mix_id = net.push(Box::new((pass() | pass()) + received));
+        for channel in 0..2 {
+            net.connect(master_out, channel, sent_id, channel);
+            net.connect(sent_id, channel, mix_id, channel);
+        }
+        master_out = mix_id;
+    }
+    if let Cue::Mix(_) = settings.cue {
+        let bridge_id = net.push(Box::new(bridge.input()));
+        for channel in 0..2 {
+            net.connect(master_out, channel, bridge_id, channel);
+        }
+        master_out = bridge_id;
+    }
+    // Streamed as it is played, before it is lined up with the cue device.
+    let icecast_bridge = Bridge::new(sample_rate);
+    if settings.icecast.is_some() {
+        let bridge_id = net.push(Box::new(icecast_bridge.input()));
+        for channel in 0..2 {
+            net.connect(master_out, channel, bridge_id, channel);
+        }
+        master_out = bridge_id;
+    }
+    // Corrected last, so that only the speakers play it.
+    if let Some(correction) = &correction {
+        let correction_id = net.push_named("master.correction", Box::new(correction.unit()));
+        for channel in 0..2 {
+            net.connect(master_out, channel, correction_id, channel);
+        }
+        master_out = correction_id;
+    }
+    if main_delay > 0.0 {
+        let delay_id = net.push(Box::new(delay(main_delay) | delay(main_delay)));
+        for channel in 0..2 {
+            net.connect(master_out, channel, delay_id, channel);
+        }
+        master_out = delay_id;
+    }
+    for channel in 0..2 {
+        net.connect_output(master_out, channel, channel);
+    }
+    let click = match settings.cue.device() {
+        Some(_) => net.push(Box::new(zero())),
+        None => net.push_named("click", Box::new(click_sequencer.backend())),
+    };
+    net.connect_output(click, 0, 2);
+    let cv_tracks: Vec<CvTrack> = settings.cv.iter().map(|_| CvTrack::default()).collect();
+    for (index, cv) in cv_tracks.iter().enumerate() {
+        let cv = net.push(Box::new(cv.unit()));
+        net.connect_output(cv, 0, cv_start + 2 * index);
+        net.connect_output(cv, 1, cv_start + 1 + 2 * index);
+    }
+    net.push(Box::new(timer(&time)));
+    drop(net);
 
-    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+    graph.set_sample_rate(sample_rate);
 
-    let stream = device.build_output_stream(
+    let cue_channel = match settings.cue {
+        Cue::Channels(channel) => Some(channel),
+        _ => None,
+    };
+    let cv_channels = settings
+        .cv
+        .iter()
+        .flat_map(|&(_, channel)| [channel, channel + 1])
+        .collect();
+    let _stream = output::play::<T>(
+        device,
         config,
-        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            write_data(data, channels, &mut next_value)
-        },
-        err_fn,
-        None,
+        cue_channel,
+        settings.monitor,
+        cv_channels,
+        settings.output.dither,
+        graph.backend(),
     )?;
-    stream.play()?;
-
-    use BaseNote::*;
-
-    let a = vec![
-        (Note { note: C, octave: 0 }, 1),
-        (Note { note: D, octave: 0 }, 1),
-        (Note { note: E, octave: 0 }, 1),
-        (Note { note: F, octave: 0 }, 1),
-        (Note { note: G, octave: 0 }, 2),
-        (Note { note: G, octave: 0 }, 2),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: G, octave: 0 }, 2),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: A, octave: 0 }, 1),
-        (Note { note: G, octave: 0 }, 2),
-        (Note { note: F, octave: 0 }, 1),
-        (Note { note: F, octave: 0 }, 1),
-        (Note { note: F, octave: 0 }, 1),
-        (Note { note: F, octave: 0 }, 1),
-        (Note { note: E, octave: 0 }, 2),
-        (Note { note: E, octave: 0 }, 2),
-        (Note { note: D, octave: 0 }, 1),
-        (Note { note: D, octave: 0 }, 1),
-        (Note { note: D, octave: 0 }, 1),
-        (Note { note: D, octave: 0 }, 1),
-        (Note { note: C, octave: 0 }, 3),
-    ];
-
-    let asdf = vec![Accord {
-        notes: vec![Note::new(C, 0), Note::new(E, 0), Note::new(C, 1)],
-    }];
-
-    for accord in asdf {
-        let length = 4 * 60000 / bpm;
-
-        let frequencies = accord.notes.iter().map(get_note_frequency);
-
-        for frequency in frequencies {
-            let c = zero() >> pluck(frequency, 0.5, 0.9);
-
-            net.replace(id_noise, Box::new(c));
-
-            net.commit();
-        }
-
-        std::thread::sleep(std::time::Duration::from_millis(length));
+    let _cue_stream = match settings.cue.device() {
+        Some(name) => {
+            let click = Net64::wrap(Box::new(click_sequencer.backend()))
+                >> delay(cue_delay)
+                >> split::<U2>();
+            // Bridged over at the sample rate it was played at.
+            let (unit, rate) = match settings.cue {
+                Cue::Mix(_) => (
+                    Net64::wrap(Box::new(bridge.output(cue_delay, sample_rate))) + click,
+                    Some(config.sample_rate.0),
+                ),
+                _ => (click, None),
+            };
+            Some(output::play_device(
+                host,
+                name,
+                &settings.output,
+                rate,
+                Box::new(unit),
+            )?)
+        }
+        None => None,
+    };
+
+    if let Some(mount) = &settings.icecast {
+        icecast::spawn(mount, icecast_bridge, sample_rate)?;
     }
-    Ok(())
-}
 
-fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> (f64, f64))
-where
-    T: SizedSample + FromSample<f64>,
-{
-    for frame in output.chunks_mut(channels) {
-        let sample = next_sample();
-        let left = T::from_sample(sample.0);
-        let right: T = T::from_sample(sample.1);
-
-        for (channel, sample) in frame.iter_mut().enumerate() {
-            if channel & 1 == 0 {
-                *sample = left;
-            } else {
-                *sample = right;
-            }
+    let (sender, commands) = std::sync::mpsc::channel();
+    let peer = match &settings.jam {
+        Some(partner) => {
+            let port = partner.rsplit_once(':').map_or("", |(_, port)| port);
+            let address = match &settings.jam_listen {
+                Some(address) => address.clone(),
+                None => format!("0.0.0.0:{}", port),
+            };
+            Some(peer::spawn(
+                &address,
+                partner,
+                jam_sent,
+                jam_received,
+                sender.clone(),
+            )?)
         }
+        None => None,
+    };
+    if let Some(path) = &settings.midi {
+        midi::spawn(path, settings.filter_controller, sender.clone())?;
+    }
+    let _trigger_stream = match settings.trigger {
+        Some((channel, trigger)) => Some(input::spawn(host, channel, trigger, sender.clone())?),
+        None => None,
+    };
+    let _pad_streams = settings
+        .drum_inputs
+        .iter()
+        .map(|&(channel, drum)| input::spawn_pad(host, channel, drum, sender.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let _pitch_stream = match settings.pitch_input {
+        Some(channel) => Some(input::track_pitch(
+            host,
+            channel,
+            &song.tuning,
+            sender.clone(),
+        )?),
+        None => None,
+    };
+    let _amp_stream = match settings.amp {
+        Some(channel) => Some(input::record(host, channel, sample_rate, amp_input)?),
+        None => None,
+    };
+    let _vocoder_stream = match settings.vocoder {
+        Some(channel) => Some(input::record(host, channel, sample_rate, vocoder_input)?),
+        None => None,
+    };
+    let _beat_stream = match settings.beat_input {
+        Some(channel) => {
+            let beats_per_bar = song.meter.signature(0).bar_beats().round() as usize;
+            Some(input::track_beats(
+                host,
+                channel,
+                beats_per_bar,
+                sender.clone(),
+            )?)
+        }
+        None => None,
+    };
+    let _autotune_stream = match settings.autotune {
+        Some(channel) => Some(input::record(host, channel, sample_rate, autotune_input)?),
+        None => None,
+    };
+    let broadcast = match &settings.websocket {
+        Some(address) => Some(websocket::spawn(address, sender.clone())?),
+        None => None,
+    };
+    match &settings.server {
+        Some(address) => server::spawn(address, sender)?,
+        None => control::spawn_stdin(sender, settings.keys.clone().map(Piano::new)),
     }
-}
 
-fn get_note_frequency(note: &Note) -> f64 {
-    let note_number = match note.note {
-        BaseNote::C => 0,
-        BaseNote::Cis => 1,
-        BaseNote::D => 2,
-        BaseNote::Dis => 3,
-        BaseNote::E => 4,
-        BaseNote::F => 5,
-        BaseNote::Fis => 6,
-        BaseNote::G => 7,
-        BaseNote::Gis => 8,
-        BaseNote::A => 9,
-        BaseNote::Ais => 10,
-        BaseNote::H => 11,
-    } as f64;
-
-    let note = note_number + 12.0 * note.octave as f64;
-
-    440.0 * 2.0.pow((note - 9.0) / 12.0) as f64
-}
+    // Voices are built and started off this thread from here on.
+    let voices = Builder::spawn(sequencer, voices);
+    let click_voices = Builder::spawn(click_sequencer, click_voices);
+    // MIDI keys held down, and the chord they form.
+    let mut held = std::collections::HashSet::new();
+    let mut sustain = Sustain::default();
+    let mut chord = None;
+    let mut transport = Transport::new(settings.bpm, song.meter.clone());
+    transport.set_stopped(settings.midi_clock || settings.mtc || settings.jam_follow);
+    transport.set_loop(settings.loop_region);
+    transport.count_in(settings.count_in);
+    let mut tap_tempo = TapTempo::default();
+    // Tempo of the clock of the MIDI input while following it, which the
+    // song waits for to start.
+    let mut midi_tempo = ClockTempo::default();
+    // Timecode of the MIDI input while chasing it.
+    let mut quarter_frames = QuarterFrames::default();
+    let mut chase = Chase::default();
+    // Song of the jam partner while following it.
+    let mut partner = Partner::default();
+    // Tempo of the clock pulses on the trigger input, as if tapped.
+    let mut clock_tempo = TapTempo::default();
+    // Bars of a loop to record from the next bar on.
+    let mut loop_bars = None;
+    // Playback beat and song position of the latest song bar.
+    let mut song_bar = None;
+    // Playback beat and length in beats of the latest bar of any kind.
+    let mut last_bar = (0.0, song.meter.signature(0).bar_beats());
+    // Versions of the parameters compared, while comparing them.
+    let mut comparison: Option<Comparison> = None;
+    let mut randomizer = Randomizer::new(rand::random());
+    // Presets that the morph knob glides between, once loaded.
+    let mut morph = None;
+    let mut evolution: Option<Evolution> = None;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use assert_approx_eq::assert_approx_eq;
-
-    #[test]
-    fn test_get_note_frequency_c() {
-        assert_approx_eq!(
-            get_note_frequency(&Note {
-                note: BaseNote::C,
-                octave: 0
-            }),
-            261.626,
-            0.01
-        );
+    let rows = settings.scope as usize * SCOPE_ROWS
+        + settings.meters as usize * (buses + 1)
+        + settings.roll.map_or(0, |_| ROLL_ROWS + 1);
+    let mut tui = (rows > 0).then(|| tui::Tui::new(rows));
+
+    let mut schedule = Schedule::new();
+    schedule.bar(0.0);
+
+    // Events are pushed slightly ahead of time, so they start sample accurately.
+    let lookahead = 0.1;
+    let start = time.value() + lookahead;
+    'playback: while let Some(beat) = schedule.next_beat() {
+        let wait = start + transport.time_of(beat) - lookahead - time.value();
+        if wait > 0.0 {
+            std::thread::sleep(std::time::Duration::from_secs_f64(wait));
+        }
+
+        for command in commands.try_iter() {
+            match command {
+                Command::Stop => break 'playback,
+                Command::Voices(size) => voices.request_size(size),
+                Command::Loop(region) => transport.set_loop(region),
+                Command::Jump(part) => match song.structure.start_bar(&part) {
+                    Ok(bar) => transport.jump(bar),
+                    Err(err) => eprintln!("{}", err),
+                },
+                Command::Bpm(bpm) => transport.set_bpm(beat, bpm),
+                Command::Width(amount) => desk.width().set(amount),
+                Command::Filter(position) => sweep.set(position),
+                Command::Crossfade(position) => desk.crossfader().set(position),
+                Command::Set(name, value) => {
+                    if let Err(error) = params.set(&name, value) {
+                        eprintln!("{}", error);
+                    }
+                }
+                Command::Switch => switch = true,
+                Command::Compare => {
+                    let playing = match &mut comparison {
+                        Some(comparison) => comparison.toggle(&params),
+                        None => comparison.insert(Comparison::new(&params)).playing(),
+                    };
+                    eprintln!("playing {:?}", playing);
+                }
+                Command::Randomize(prefix) => match randomizer.randomize(&params, &prefix) {
+                    0 => eprintln!("no unlocked parameters to randomize"),
+                    count => eprintln!("randomized {} parameters", count),
+                },
+                Command::Lock(name) => match params.find(&name) {
+                    Ok(_) if randomizer.toggle_lock(&name) => eprintln!("locked {}", name),
+                    Ok(_) => eprintln!("unlocked {}", name),
+                    Err(err) => eprintln!("{}", err),
+                },
+                Command::Preset(path) => match Preset::capture(&params).save(&path) {
+                    Ok(()) => eprintln!("saved {}", path),
+                    Err(err) => eprintln!("{}", err),
+                },
+                Command::MorphBetween(from, to) => {
+                    match Preset::load(&from).and_then(|from| Morph::new(from, Preset::load(&to)?))
+                    {
+                        Ok(loaded) => morph = Some(loaded),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+                Command::Morph(position) => match &morph {
+                    Some(morph) => morph.apply(&params, position),
+                    None => eprintln!("morph needs two presets first"),
+                },
+                Command::Evolve(true) => {
+                    let started = Evolution::new(&params, randomizer.locked(), rand::random());
+                    evolution = Some(started);
+                    eprintln!("rate candidate 1 of {}", evolve::POPULATION);
+                }
+                Command::Evolve(false) => evolution = None,
+                Command::Rate(rating) => match &mut evolution {
+                    Some(evolution) => {
+                        evolution.rate(&params, rating);
+                        let (generation, candidate) = evolution.playing();
+                        eprintln!(
+                            "generation {}, rate candidate {} of {}",
+                            generation + 1,
+                            candidate + 1,
+                            evolve::POPULATION
+                        );
+                    }
+                    None => eprintln!("rate needs evolve first"),
+                },
+                Command::Keep(side) => match comparison.take() {
+                    Some(comparison) => {
+                        comparison.keep(&params, side);
+                        eprintln!("kept {:?}", side);
+                    }
+                    None => eprintln!("nothing is compared"),
+                },
+                Command::Nodes => {
+                    for line in graph.list() {
+                        eprintln!("{}", line);
+                    }
+                }
+                Command::Graph(path) => match std::fs::write(&path, graph.dot()) {
+                    Ok(()) => eprintln!("wrote {}", path),
+                    Err(err) => eprintln!("cannot write {}: {}", path, err),
+                },
+                Command::Bypass(name) => match desk.mixer_mut().toggle_bypass(&name) {
+                    Ok(true) => eprintln!("{} bypassed", name),
+                    Ok(false) => eprintln!("{} back in", name),
+                    Err(err) => eprintln!("{}", err),
+                },
+                Command::Panic => {
+                    let now = time.value();
+                    let played_beat = transport.beat_at(now - start);
+                    for key in held.drain() {
+                        recorder.key_up(&mut song, key, played_beat);
+                    }
+                    sustain.reset();
+                    schedule.clear_notes();
+                    voices.panic(now);
+                    if let Some(midi_out) = &midi_out {
+                        for &(_, midi_track) in &settings.midi_tracks {
+                            midi_out.all_notes_off(midi_track.channel);
+                        }
+                    }
+                    eprintln!("all notes off");
+                }
+                Command::Mute(track) | Command::Solo(track) if track >= desk.mixer().tracks() => {
+                    eprintln!("there are {} tracks", desk.mixer().tracks());
+                }
+                Command::Mute(track) | Command::Solo(track) => {
+                    match command {
+                        Command::Mute(_) => desk.mixer_mut().toggle_mute(track),
+                        _ => desk.mixer_mut().toggle_solo(track),
+                    }
+                    // External synths follow the mixer by their channel volume.
+                    if let Some(midi_out) = &midi_out {
+                        for &(track, midi_track) in &settings.midi_tracks {
+                            let volume = midi_out::volume(
+                                midi_track.channel,
+                                desk.mixer().is_audible(track),
+                            );
+                            midi_out.send(std::time::Instant::now(), volume);
+                        }
+                    }
+                }
+                Command::Save(path) => {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |elapsed| elapsed.as_secs());
+                    let metadata = match &settings.project {
+                        Some(project) => project.metadata.saved(now),
+                        None => {
+                            let title = std::path::Path::new(&path).file_stem();
+                            Metadata::new(&title.unwrap_or_default().to_string_lossy(), now)
+                        }
+                    };
+                    let tracks = 0..desk.mixer().tracks();
+                    let project = Project {
+                        metadata,
+                        song: song.clone(),
+                        bpm: (60.0 / transport.seconds_per_beat()).clamp(1.0, 999.0),
+                        muted: tracks
+                            .clone()
+                            .map(|track| desk.mixer().is_muted(track))
+                            .collect(),
+                        soloed: tracks.map(|track| desk.mixer().is_soloed(track)).collect(),
+                        params: Preset::capture(&params).params,
+                        filter_controller: settings.filter_controller,
+                        midi_tracks: settings
+                            .midi_tracks
+                            .iter()
+                            .map(|&(track, midi)| (track, midi.channel, midi.latency))
+                            .collect(),
+                    };
+                    match project.save(&path) {
+                        Ok(()) => eprintln!("saved {}", path),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+                Command::Position(track, position) => {
+                    if track < song.tracks.len() {
+                        song.tracks[track].pan = position.lateral();
+                        if let Some(placement) = desk.placement(track) {
+                            placement.set(position);
+                        }
+                    } else {
+                        eprintln!("there are {} tracks", song.tracks.len());
+                    }
+                }
+                Command::Tap(at) => {
+                    if let Some(bpm) = tap_tempo.tap(at) {
+                        transport.glide_to(beat, bpm);
+                        eprintln!("{:.1} bpm", bpm);
+                    }
+                }
+                Command::Clock(at) => {
+                    if let (Some((_, Trigger::Clock(pulses))), Some(bpm)) =
+                        (settings.trigger, clock_tempo.tap(at))
+                    {
+                        transport.set_bpm(beat, bpm / pulses as f64);
+                    }
+                }
+                Command::Beat(bpm, downbeat, at) => {
+                    if (bpm - 60.0 / transport.seconds_per_beat()).abs() > BEAT_BPM_TOLERANCE {
+                        transport.glide_to(beat, bpm);
+                    }
+                    // How far the beat heard is off the nearest beat of the
+                    // playback, or of its bars for the first beat of a bar.
+                    let heard =
+                        transport.beat_at(time.value() - at.elapsed().as_secs_f64() - start);
+                    let (bar_beat, bar_beats) = last_bar;
+                    let length = if downbeat { bar_beats } else { 1.0 };
+                    let off = (heard - bar_beat + length / 2.0).rem_euclid(length) - length / 2.0;
+                    // Beats are pulled into step gradually, bars at once.
+                    let pull = if off.abs() > 0.5 { 1.0 } else { BEAT_PULL };
+                    transport.delay(beat, off * pull);
+                }
+                Command::Partner(bar, bpm, at) if settings.jam_follow => {
+                    if (bpm - 60.0 / transport.seconds_per_beat()).abs() > 0.01 {
+                        transport.glide_to(beat, bpm);
+                    }
+                    if let Some(bar) = partner.sync(at, bar, transport.bar()) {
+                        transport.jump(bar);
+                        transport.set_stopped(false);
+                    }
+                }
+                Command::Partner(..) => {}
+                Command::Play(note, at) => {
+                    let played = time.value() - at.elapsed().as_secs_f64();
+                    let played_beat = transport.beat_at(played - start);
+                    if recorder.armed
+                        && recorder
+                            .record(&mut song, played_beat, record::KEY_BEATS, note)
+                            .is_none()
+                    {
+                        eprintln!("not recording before the song starts");
+                    }
+                    let duration = record::KEY_BEATS * transport.seconds_per_beat();
+                    let now = time.value();
+                    let Some(frequency) = song.tuning.note_frequency(&note) else {
+                        eprintln!("{} is not mapped to the keyboard", song.key.name(&note));
+                        continue;
+                    };
+                    if let Some(vocoder) = &vocoder {
+                        vocoder.play(frequency);
+                    }
+                    let place = desk.place(&song, None, frequency);
+                    voices.note(
+                        now,
+                        now + duration,
+                        Box::new(move |voices| {
+                            let unit = LIVE_INSTRUMENT.voice(frequency, duration, 1.0);
+                            (place(voices, unit), LIVE_INSTRUMENT.release())
+                        }),
+                    );
+                }
+                Command::Hit(drum, velocity, _) => {
+                    let now = time.value();
+                    let frequency = get_note_frequency(&drum.note());
+                    let place = desk.place(&song, None, frequency);
+                    voices.note(
+                        now,
+                        now,
+                        Box::new(move |voices| {
+                            let unit = place(voices, drum.voice(velocity));
+                            (unit, Instrument::Drums.release())
+                        }),
+                    );
+                }
+                Command::Looper(LooperCommand::Record(bars)) => loop_bars = Some(bars),
+                Command::Looper(LooperCommand::Overdub(overdub)) => looper.set_overdub(overdub),
+                Command::Looper(LooperCommand::Clear) => looper.clear(),
+                // Recording begins after the count-in, if there is one.
+                Command::Record(true) if settings.count_in > 0 => {
+                    transport.count_in_recording(settings.count_in);
+                    eprintln!("recording after {} bars", settings.count_in);
+                }
+                Command::Record(armed) => {
+                    recorder.armed = armed;
+                    eprintln!("recording {}", if armed { "on" } else { "off" });
+                }
+                Command::Midi(message, at) => {
+                    let played = time.value() - at.elapsed().as_secs_f64();
+                    let played_beat = transport.beat_at(played - start);
+                    let now = time.value();
+                    match message {
+                        Message::NoteOn { key, velocity } => {
+                            recorder.key_down(key, played_beat);
+                            if sustain.press(key) {
+                                voices.release(key, now, LIVE_INSTRUMENT.release());
+                            }
+                            let Some(frequency) = song.tuning.key_frequency(key) else {
+                                continue;
+                            };
+                            if let Some(vocoder) = &vocoder {
+                                vocoder.play(frequency);
+                            }
+                            let velocity = velocity as f64 / humanize::VELOCITY_UNITS;
+                            let place = desk.place(&song, None, frequency);
+                            voices.hold(
+                                key,
+                                now,
+                                Box::new(move |voices| {
+                                    let unit =
+                                        LIVE_INSTRUMENT.voice(frequency, f64::INFINITY, velocity);
+                                    (place(voices, unit), 0.0)
+                                }),
+                            );
+                            held.insert(key);
+                        }
+                        Message::NoteOff { key } => {
+                            recorder.key_up(&mut song, key, played_beat);
+                            if held.remove(&key) && !sustain.release(key) {
+                                voices.release(key, now, LIVE_INSTRUMENT.release());
+                            }
+                        }
+                        Message::Control {
+                            controller: SUSTAIN,
+                            value,
+                        } => {
+                            for key in sustain.pedal(value) {
+                                voices.release(key, now, LIVE_INSTRUMENT.release());
+                            }
+                        }
+                        // Buttons arrive as commands of their own.
+                        Message::Control { .. } => {}
+                        Message::Clock if settings.midi_clock => {
+                            if let Some(bpm) = midi_tempo.tick(at) {
+                                transport.glide_to(beat, bpm);
+                            }
+                        }
+                        // The song starts and stops with the next bar.
+                        Message::Start if settings.midi_clock => {
+                            transport.jump(0);
+                            transport.set_stopped(false);
+                        }
+                        Message::Continue if settings.midi_clock => transport.set_stopped(false),
+                        Message::Stop if settings.midi_clock => transport.set_stopped(true),
+                        Message::SongPosition(sixteenths) if settings.midi_clock => {
+                            transport.jump(song.meter.bar_at(sixteenths as f64 / 4.0))
+                        }
+                        Message::QuarterFrame(data) if settings.mtc => {
+                            let Some(timecode) = quarter_frames.push(data) else {
+                                continue;
+                            };
+                            let bar_at = |seconds: f64| {
+                                song.meter.bar_at(seconds / transport.seconds_per_beat())
+                            };
+                            match chase.run(at, timecode.seconds()) {
+                                Follow::Start(seconds) => {
+                                    transport.jump(bar_at(seconds));
+                                    transport.set_stopped(false);
+                                }
+                                Follow::Locate(seconds) => transport.jump(bar_at(seconds)),
+                                Follow::Run => {}
+                            }
+                        }
+                        Message::FullFrame(timecode) if settings.mtc => {
+                            let beats = timecode.seconds() / transport.seconds_per_beat();
+                            transport.jump(song.meter.bar_at(beats));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if settings.mtc && chase.stopped(std::time::Instant::now()) {
+            transport.set_stopped(true);
+        }
+        if settings.jam_follow && partner.stopped(std::time::Instant::now()) {
+            transport.set_stopped(true);
+        }
+        transport.update(beat);
+        let at = start + transport.time_of(beat);
+
+        while let Some(event) = schedule.pop_due(beat) {
+            match event.action {
+                Action::Bar => {
+                    voices.bar(at);
+                    if std::mem::take(&mut switch) {
+                        desk.crossfader().switch();
+                    }
+                    if transport.is_playing(song.length()) {
+                        let bar = transport.next_bar();
+                        last_bar = (beat, bar.signature().bar_beats());
+                        let bar_seconds =
+                            bar.signature().bar_beats() * transport.seconds_per_beat();
+                        if let Some(bars) = loop_bars.take() {
+                            let length = bars as f64 * bar_seconds;
+                            if length > looper::MAX_SECONDS {
+                                eprintln!("loop cut to {} seconds", looper::MAX_SECONDS);
+                            }
+                            looper.record(at, length);
+                        }
+                        match bar {
+                            Bar::CountIn(signature) => {
+                                metronome::schedule_bar(&mut schedule, beat, signature)
+                            }
+                            Bar::Stopped(_) => {}
+                            Bar::Song { from, to, .. } => {
+                                if transport.take_armed() {
+                                    recorder.armed = true;
+                                    eprintln!("recording on");
+                                }
+                                if let Some(peer) = &peer {
+                                    peer.sync(
+                                        song.meter.bar_at(from),
+                                        60.0 / transport.seconds_per_beat(),
+                                    );
+                                }
+                                recorder.bar(beat, from);
+                                song_bar = Some((beat, from));
+                                song.schedule(&mut schedule, from, to, beat)
+                            }
+                        }
+                        schedule.bar(beat + bar.signature().bar_beats());
+                    }
+                }
+                Action::Note { .. } => {
+                    let seconds_per_beat = transport.seconds_per_beat();
+                    let Some(note) = desk.note(&event.action, at, seconds_per_beat) else {
+                        continue;
+                    };
+                    let Played {
+                        instrument,
+                        frequency,
+                        start: at,
+                        duration,
+                        track,
+                        velocity,
+                        ..
+                    } = note;
+                    // CV tracks play on the hardware they control instead.
+                    let cv =
+                        track.and_then(|track| settings.cv.iter().position(|cv| cv.0 == track));
+                    if let Some(cv) = cv {
+                        cv_tracks[cv].note(at, at + duration, frequency);
+                        continue;
+                    }
+                    let midi_track = track.and_then(|track| {
+                        let found = settings
+                            .midi_tracks
+                            .iter()
+                            .find(|(found, _)| *found == track);
+                        found.map(|&(_, midi_track)| midi_track)
+                    });
+                    if let (Some(midi_out), Some(midi_track)) = (&midi_out, midi_track) {
+                        let key = midi_out::key(instrument, frequency);
+                        let channel = midi_track.channel;
+                        // Audio thread time as an instant, ahead by the latency of the synth.
+                        let sent = |at: f64| {
+                            let ahead = (at - midi_track.latency - time.value()).max(0.0);
+                            std::time::Instant::now() + std::time::Duration::from_secs_f64(ahead)
+                        };
+                        midi_out.send(sent(at), midi_out::note_on(channel, key, velocity));
+                        midi_out.send(sent(at + duration), midi_out::note_off(channel, key));
+                        continue;
+                    }
+                    match instrument {
+                        Instrument::Click => click_voices.note(
+                            at,
+                            at + duration,
+                            desk.sound(&note, seconds_per_beat),
+                        ),
+                        _ => voices.note(
+                            at,
+                            at + duration,
+                            desk.voice(&song, &note, seconds_per_beat),
+                        ),
+                    };
+                }
+            }
+        }
+
+        let mut notes: Vec<u8> = held.iter().copied().collect();
+        notes.sort_unstable();
+        let held_chord = Chord::detect(&notes);
+        if held_chord != chord {
+            if let Some(held_chord) = held_chord {
+                eprintln!("{}", held_chord.name(&song.key));
+            }
+            chord = held_chord;
+        }
+        if let Some(broadcast) = &broadcast {
+            broadcast.send(&State {
+                beat,
+                bpm: 60.0 / transport.seconds_per_beat(),
+                level: master_meter.level().peak,
+                notes,
+                chord: chord.map(|chord| chord.name(&song.key)),
+            });
+        }
+        if let Some(tui) = &mut tui {
+            let mut lines = vec![];
+            if settings.scope {
+                lines.extend(scope.lines(SCOPE_ROWS));
+            }
+            if settings.meters {
+                for (bus, meter) in desk.meters().iter().enumerate() {
+                    let name = match song.tracks.get(bus) {
+                        Some(track) => {
+                            format!(
+                                "{} {:?} {:2}",
+                                bus + 1,
+                                track.instrument,
+                                desk.mixer().flags(bus)
+                            )
+                        }
+                        None => "live".to_string(),
+                    };
+                    lines.push(format!("{:>8} {}", name, meter.level().bar(METER_WIDTH)));
+                }
+                let level = master_meter.level();
+                lines.push(format!("{:>8} {}", "master", level.bar(METER_WIDTH)));
+            }
+            if let Some(track) = settings.roll {
+                let position = song_bar.map(|(bar_beat, from)| from + beat - bar_beat);
+                match position.and_then(|position| song.pattern_at(track, position)) {
+                    Some((pattern, at)) => {
+                        lines.push(format!("track {}, pattern {}", track + 1, pattern + 1));
+                        let roll =
+                            piano_roll(&song.patterns[pattern], &song.key, Some(at), ROLL_ROWS);
+                        lines.extend(roll);
+                    }
+                    None => lines.push(format!("track {} is silent", track + 1)),
+                }
+            }
+            tui.draw(&lines);
+        }
     }
 
-    #[test]
-    fn test_get_note_frequency_a() {
-        assert_eq!(
-            get_note_frequency(&Note {
-                note: BaseNote::A,
-                octave: 0
-            }),
-            440.0
-        );
+    if let Some(midi_out) = &midi_out {
+        for &(_, midi_track) in &settings.midi_tracks {
+            midi_out.all_notes_off(midi_track.channel);
+        }
     }
+    voices.finish();
+    click_voices.finish();
+    // Let the final notes and releases ring out.
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    Ok(())
 }
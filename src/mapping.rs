@@ -0,0 +1,198 @@
+//! Mapping of recordings onto the keys and velocities of a sampler, zone by
+//! zone: the layers whose ranges overlap crossfade over the overlap, so that
+//! a note between two velocity layers or two key zones plays a blend of them.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+
+use crate::sampler::{Region, Sampler};
+use crate::sfz::key;
+
+/// A recording and the keys and velocities that play it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub path: String,
+    pub keys: RangeInclusive<u8>,
+    pub velocities: RangeInclusive<u8>,
+    /// Key that plays the recording as it is, the lowest of the zone unless
+    /// given.
+    pub root: u8,
+}
+
+impl Zone {
+    /// Parses `FILE,KEYS,VELOCITIES` with an optional `,ROOT`, the keys and
+    /// velocities as ranges such as `c2-b2` and `1-80`, or a single one.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        // The path may hold commas, so the fields are split off its end,
+        // with the root if there is one.
+        let split = |count: usize| {
+            let mut fields: Vec<&str> = value.rsplitn(count + 1, ',').collect();
+            let path = (fields.len() == count + 1).then(|| fields.pop().unwrap())?;
+            fields.reverse();
+            Some((path, fields))
+        };
+        let with_root = split(3).filter(|(_, fields)| {
+            range(fields[0], key).is_ok()
+                && range(fields[1], velocity).is_ok()
+                && key(fields[2]).is_ok()
+        });
+        let (path, fields) = with_root
+            .or_else(|| split(2))
+            .ok_or_else(|| anyhow!("a zone is FILE,KEYS,VELOCITIES[,ROOT]: {}", value))?;
+        let keys = range(fields[0], key)?;
+        let root = match fields.get(2) {
+            Some(root) => key(root)?,
+            None => *keys.start(),
+        };
+        Ok(Self {
+            path: path.to_string(),
+            keys,
+            velocities: range(fields[1], velocity)?,
+            root,
+        })
+    }
+}
+
+fn velocity(value: &str) -> Result<u8, anyhow::Error> {
+    match value.parse::<u8>() {
+        Ok(velocity @ 1..=127) => Ok(velocity),
+        _ => bail!("invalid velocity: {}", value),
+    }
+}
+
+/// Parses a range as `LOW-HIGH` or a single value, each as `parse` does.
+/// Keys may hold a minus themselves, as in `c-1`.
+fn range(
+    value: &str,
+    parse: impl Fn(&str) -> Result<u8, anyhow::Error>,
+) -> Result<RangeInclusive<u8>, anyhow::Error> {
+    let split = value
+        .match_indices('-')
+        .find_map(|(i, _)| Some((parse(&value[..i]).ok()?, parse(&value[i + 1..]).ok()?)));
+    let (low, high) = match split {
+        Some(range) => range,
+        None => (parse(value)?, parse(value)?),
+    };
+    if low > high {
+        bail!("the range {} goes down", value);
+    }
+    Ok(low..=high)
+}
+
+/// Loads the recordings of `zones` into a sampler, crossfading the layers
+/// that overlap.
+pub fn load(zones: &[Zone]) -> Result<Sampler, anyhow::Error> {
+    let mut waves: HashMap<&str, Arc<Wave64>> = HashMap::new();
+    let mut regions = vec![];
+    for zone in zones {
+        let wave = match waves.get(zone.path.as_str()) {
+            Some(wave) => wave.clone(),
+            None => {
+                let wave =
+                    Wave64::load(&zone.path).map_err(|err| anyhow!("{}: {}", zone.path, err))?;
+                waves.entry(&zone.path).or_insert(Arc::new(wave)).clone()
+            }
+        };
+        regions.push(Region {
+            keys: zone.keys.clone(),
+            velocities: zone.velocities.clone(),
+            root: zone.root as f64,
+            ..Region::new(wave)
+        });
+    }
+    crossfade(&mut regions);
+    Ok(Sampler::with_regions(regions))
+}
+
+/// Fades the regions whose ranges of velocities overlap on the same keys,
+/// or whose ranges of keys overlap at the same velocities, out and in over
+/// the overlap. Regions on the same ranges take turns instead.
+pub fn crossfade(regions: &mut [Region]) {
+    let overlap = |a: &RangeInclusive<u8>, b: &RangeInclusive<u8>| {
+        (a.start() < b.start() && b.start() <= a.end() && a.end() < b.end())
+            .then_some((*b.start(), *a.end()))
+    };
+    for i in 0..regions.len() {
+        for j in 0..regions.len() {
+            let (a, b) = (&regions[i], &regions[j]);
+            if a.keys == b.keys {
+                if let Some(fade) = overlap(&a.velocities, &b.velocities) {
+                    regions[i].velocity_fade.fade_out = Some(fade);
+                    regions[j].velocity_fade.fade_in = Some(fade);
+                }
+            } else if a.velocities == b.velocities {
+                if let Some(fade) = overlap(&a.keys, &b.keys) {
+                    regions[i].key_fade.fade_out = Some(fade);
+                    regions[j].key_fade.fade_in = Some(fade);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{get_note_frequency, Note};
+
+    #[test]
+    fn test_parse_zone() {
+        let zone = Zone::parse("piano c3.wav,c3-d#3,1-80").unwrap();
+        assert_eq!(zone.path, "piano c3.wav");
+        assert_eq!(
+            (zone.keys, zone.velocities, zone.root),
+            (48..=51, 1..=80, 48)
+        );
+        let zone = Zone::parse("soft, far.wav,36-48,64,40").unwrap();
+        assert_eq!(zone.path, "soft, far.wav");
+        assert_eq!(
+            (zone.keys, zone.velocities, zone.root),
+            (36..=48, 64..=64, 40)
+        );
+        let zone = Zone::parse("low, wide.wav,c-1-c0,1-127").unwrap();
+        assert_eq!((zone.path.as_str(), zone.keys), ("low, wide.wav", 0..=12));
+        assert!(Zone::parse("a.wav,60-50,1-127").is_err());
+        assert!(Zone::parse("a.wav,60,0-127").is_err());
+        assert!(Zone::parse("a.wav,60").is_err());
+    }
+
+    #[test]
+    fn test_crossfades_overlapping_layers() {
+        let constant = |value: f64| {
+            let mut wave = Wave64::new(1, 8000.0);
+            for _ in 0..800 {
+                wave.push(value);
+            }
+            Arc::new(wave)
+        };
+        let mut regions = vec![
+            Region {
+                velocities: 1..=80,
+                ..Region::new(constant(0.25))
+            },
+            Region {
+                velocities: 60..=127,
+                ..Region::new(constant(0.5))
+            },
+        ];
+        crossfade(&mut regions);
+        assert_eq!(regions[0].velocity_fade.fade_out, Some((60, 80)));
+        assert_eq!(regions[1].velocity_fade.fade_in, Some((60, 80)));
+        assert_eq!(regions[0].key_fade, Default::default());
+        let sampler = Sampler::with_regions(regions);
+        let play = |velocity: u8| {
+            let frequency = get_note_frequency(&Note::from_midi(60));
+            let (mut voice, _) = sampler.voice(frequency, 1.0, velocity as f64 / 127.0, 0.5);
+            Wave64::render(8000.0, 0.1, &mut *voice).at(0, 100) * 127.0 / velocity as f64
+        };
+        // Only the soft layer below the overlap, only the loud one above,
+        // and both at equal power halfway.
+        assert!((play(40) - 0.25).abs() < 1e-9);
+        assert!((play(100) - 0.5).abs() < 1e-9);
+        assert!((play(70) - 0.75 * sin(0.25 * PI)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,157 @@
+//! Time signatures and where bars fall in a song.
+
+use anyhow::bail;
+
+/// Beats are counted in quarter notes throughout, so a bar of 7/8 is 3.5 beats long.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeSignature {
+    /// Counts per bar.
+    pub beats: u32,
+    /// Note value of one count: 4 for quarters, 8 for eighths.
+    pub unit: u32,
+}
+
+impl TimeSignature {
+    pub const COMMON: Self = Self { beats: 4, unit: 4 };
+
+    pub fn new(beats: u32, unit: u32) -> Self {
+        Self { beats, unit }
+    }
+
+    /// Parses a signature such as `7/8`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let Some((beats, unit)) = value.split_once('/') else {
+            bail!("invalid time signature: {}", value);
+        };
+        let signature = Self::new(beats.trim().parse()?, unit.trim().parse()?);
+        if signature.beats == 0 || !signature.unit.is_power_of_two() {
+            bail!("invalid time signature: {}", value);
+        }
+        Ok(signature)
+    }
+
+    /// Length of one count in beats.
+    pub fn count_beats(self) -> f64 {
+        4.0 / self.unit as f64
+    }
+
+    /// Length of a bar in beats.
+    pub fn bar_beats(self) -> f64 {
+        self.beats as f64 * self.count_beats()
+    }
+}
+
+/// Time signature changes over the bars of a song.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Meter {
+    /// First bar of each signature, in increasing order starting with bar zero.
+    changes: Vec<(usize, TimeSignature)>,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new(TimeSignature::COMMON)
+    }
+}
+
+impl Meter {
+    pub fn new(signature: TimeSignature) -> Self {
+        Self {
+            changes: vec![(0, signature)],
+        }
+    }
+
+    /// Parses comma separated signatures with the 1-based bar they start on,
+    /// such as `4/4,3/4@5,7/8@9`. The first one may omit its bar.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut meter = Self::default();
+        for (i, change) in value.split(',').enumerate() {
+            let (signature, bar) = match change.split_once('@') {
+                Some((signature, bar)) => (signature, bar.trim().parse::<usize>()?),
+                None if i == 0 => (change, 1),
+                None => bail!("missing bar for time signature: {}", change),
+            };
+            if bar == 0 || (i > 0 && bar <= meter.changes[meter.changes.len() - 1].0 + 1) {
+                bail!(
+                    "time signature changes must be in increasing bars: {}",
+                    value
+                );
+            }
+            meter.change(bar - 1, TimeSignature::parse(signature)?);
+        }
+        Ok(meter)
+    }
+
+    /// Switches to `signature` from `bar` on, replacing any later changes.
+    pub fn change(&mut self, bar: usize, signature: TimeSignature) {
+        self.changes.retain(|&(start, _)| start < bar);
+        self.changes.push((bar, signature));
+    }
+
+    /// The first bar of each signature and the signature.
+    pub fn changes(&self) -> &[(usize, TimeSignature)] {
+        &self.changes
+    }
+
+    pub fn signature(&self, bar: usize) -> TimeSignature {
+        self.changes
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start <= bar)
+            .map_or(TimeSignature::COMMON, |&(_, signature)| signature)
+    }
+
+    /// The bar that the song position `beat` falls in.
+    pub fn bar_at(&self, beat: f64) -> usize {
+        let mut bar = 0;
+        while self.bar_start(bar + 1) <= beat {
+            bar += 1;
+        }
+        bar
+    }
+
+    /// Song position in beats at which `bar` starts.
+    pub fn bar_start(&self, bar: usize) -> f64 {
+        let mut beat = 0.0;
+        for (i, &(start, signature)) in self.changes.iter().enumerate() {
+            let end = self.changes.get(i + 1).map_or(usize::MAX, |&(end, _)| end);
+            beat += (bar.min(end) - start) as f64 * signature.bar_beats();
+            if bar < end {
+                break;
+            }
+        }
+        beat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_signature() {
+        assert_eq!(
+            TimeSignature::parse("7/8").unwrap(),
+            TimeSignature::new(7, 8)
+        );
+        assert_eq!(TimeSignature::new(7, 8).bar_beats(), 3.5);
+        assert!(TimeSignature::parse("3/5").is_err());
+        assert!(TimeSignature::parse("3").is_err());
+    }
+
+    #[test]
+    fn test_bar_start_follows_changes() {
+        let mut meter = Meter::default();
+        meter.change(2, TimeSignature::new(3, 4));
+        meter.change(3, TimeSignature::new(7, 8));
+        assert_eq!(meter.signature(1), TimeSignature::COMMON);
+        assert_eq!(meter.signature(5), TimeSignature::new(7, 8));
+        let starts: Vec<_> = (0..6).map(|bar| meter.bar_start(bar)).collect();
+        assert_eq!(starts, [0.0, 4.0, 8.0, 11.0, 14.5, 18.0]);
+        assert_eq!(meter.bar_at(10.9), 2);
+        assert_eq!(meter.bar_at(11.0), 3);
+        assert_eq!(Meter::parse("4/4,3/4@3,7/8@4").unwrap(), meter);
+        assert!(Meter::parse("4/4,3/4@3,7/8@2").is_err());
+        assert!(Meter::parse("4/4,3/4").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! Metronome clicks for count-ins.
+
+use crate::instrument::Instrument;
+use crate::meter::TimeSignature;
+use crate::schedule::Schedule;
+
+const ACCENT_FREQUENCY: f64 = 1760.0;
+const BEAT_FREQUENCY: f64 = 880.0;
+
+/// Schedules one click per count of the bar starting at `beat`, accenting the downbeat.
+pub fn schedule_bar(schedule: &mut Schedule, beat: f64, signature: TimeSignature) {
+    let length = signature.count_beats();
+    for count in 0..signature.beats {
+        let frequency = if count == 0 {
+            ACCENT_FREQUENCY
+        } else {
+            BEAT_FREQUENCY
+        };
+        let offset = count as f64 * length;
+        schedule.note(
+            beat + offset,
+            length / 2.0,
+            Instrument::Click,
+            frequency,
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::Action;
+
+    #[test]
+    fn test_downbeat_is_accented() {
+        let mut schedule = Schedule::new();
+        schedule_bar(&mut schedule, 8.0, TimeSignature::new(3, 8));
+        let mut clicks = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            if let Action::Note { frequency, .. } = event.action {
+                clicks.push((event.beat, frequency));
+            }
+        }
+        assert_eq!(
+            clicks,
+            [
+                (8.0, ACCENT_FREQUENCY),
+                (8.5, BEAT_FREQUENCY),
+                (9.0, BEAT_FREQUENCY)
+            ]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! MIDI input read from a raw MIDI device such as `/dev/snd/midiC1D0`.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use crate::control::Command;
+use playground::timecode::Timecode;
+
+/// Controllers of the buttons that solo and mute tracks, from track 1 on, as
+/// on a Korg nanoKONTROL2.
+const SOLO_CONTROLLERS: std::ops::Range<u8> = 32..40;
+const MUTE_CONTROLLERS: std::ops::Range<u8> = 48..56;
+/// Controller of the all notes off message, which panics.
+const ALL_NOTES_OFF: u8 = 123;
+/// Controller of the sustain pedal, down from a value of 64.
+pub const SUSTAIN: u8 = 64;
+/// Controller of the knob turning the DJ filter unless set otherwise, the
+/// first knob of a nanoKONTROL2.
+pub const FILTER_CONTROLLER: u8 = 16;
+
+/// The channel voice messages that are acted on. Channels are not told apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    NoteOn {
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        key: u8,
+    },
+    /// A control change, such as a button going down with a value of 127.
+    Control {
+        controller: u8,
+        value: u8,
+    },
+    /// A tick of the MIDI clock, 24 to the beat.
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    /// The song position pointer, in sixteenths from the start.
+    SongPosition(u16),
+    /// The data byte of an MTC quarter frame, one piece of a running timecode.
+    QuarterFrame(u8),
+    /// An MTC full frame, sent to locate without running.
+    FullFrame(Timecode),
+}
+
+/// Turns a MIDI byte stream into messages, following running status.
+/// System messages other than clock, song position and timecode are skipped.
+#[derive(Default)]
+pub struct Parser {
+    status: Option<u8>,
+    /// Data bytes of the message so far, as many as a timecode full frame has.
+    data: [u8; 8],
+    len: usize,
+}
+
+impl Parser {
+    pub fn push(&mut self, byte: u8) -> Option<Message> {
+        match byte {
+            // Real time messages may come between any bytes.
+            0xf8 => Some(Message::Clock),
+            0xfa => Some(Message::Start),
+            0xfb => Some(Message::Continue),
+            0xfc => Some(Message::Stop),
+            0xf9..=0xff => None,
+            // System messages cancel running status.
+            0xf7 => {
+                let message = match (self.status, &self.data[..self.len.min(8)]) {
+                    (Some(0xf0), &[0x7f, _, 0x01, 0x01, hours, minutes, seconds, frames])
+                        if self.len == 8 =>
+                    {
+                        let timecode = [hours, minutes, seconds, frames];
+                        Some(Message::FullFrame(Timecode::from_full_frame(timecode)))
+                    }
+                    _ => None,
+                };
+                self.status = None;
+                self.len = 0;
+                message
+            }
+            0xf0..=0xf6 => {
+                self.status = matches!(byte, 0xf0..=0xf2).then_some(byte);
+                self.len = 0;
+                None
+            }
+            0x80..=0xef => {
+                self.status = Some(byte);
+                self.len = 0;
+                None
+            }
+            _ => {
+                let status = self.status?;
+                if status == 0xf0 {
+                    if let Some(data) = self.data.get_mut(self.len) {
+                        *data = byte;
+                    }
+                    self.len += 1;
+                    return None;
+                }
+                self.data[self.len] = byte;
+                self.len += 1;
+                let length = match status >> 4 {
+                    _ if status == 0xf1 => 1,
+                    0xc | 0xd => 1,
+                    _ => 2,
+                };
+                if self.len < length {
+                    return None;
+                }
+                self.len = 0;
+                let [key, velocity, ..] = self.data;
+                match status {
+                    0xf1 => {
+                        self.status = None;
+                        return Some(Message::QuarterFrame(key));
+                    }
+                    0xf2 => {
+                        self.status = None;
+                        return Some(Message::SongPosition(key as u16 | (velocity as u16) << 7));
+                    }
+                    _ => {}
+                }
+                match status >> 4 {
+                    0x9 if velocity > 0 => Some(Message::NoteOn { key, velocity }),
+                    0x8 | 0x9 => Some(Message::NoteOff { key }),
+                    0xb => Some(Message::Control {
+                        controller: key,
+                        value: velocity,
+                    }),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Keys let go of while the sustain pedal is down, whose notes sound on
+/// until it comes up.
+#[derive(Default)]
+pub struct Sustain {
+    down: bool,
+    keys: HashSet<u8>,
+}
+
+impl Sustain {
+    /// Whether the note of `key`, let go of, sounds on.
+    pub fn release(&mut self, key: u8) -> bool {
+        if self.down {
+            self.keys.insert(key);
+        }
+        self.down
+    }
+
+    /// Takes `key`, played again, out of those sounding on, and returns
+    /// whether it was, for its note to be let go of first.
+    pub fn press(&mut self, key: u8) -> bool {
+        self.keys.remove(&key)
+    }
+
+    /// The pedal going to `value`, and the keys whose notes to let go of
+    /// if it comes up.
+    pub fn pedal(&mut self, value: u8) -> Vec<u8> {
+        self.down = value >= 64;
+        match self.down {
+            true => vec![],
+            false => self.keys.drain().collect(),
+        }
+    }
+
+    /// Lifts the pedal and forgets the keys, whose notes a panic silences.
+    pub fn reset(&mut self) {
+        self.down = false;
+        self.keys.clear();
+    }
+}
+
+/// The command of a button going down or of all notes off, if `message` is
+/// one.
+fn button(message: Message) -> Option<Command> {
+    let Message::Control { controller, value } = message else {
+        return None;
+    };
+    if controller == ALL_NOTES_OFF {
+        return Some(Command::Panic);
+    }
+    if value < 64 {
+        return None;
+    }
+    if SOLO_CONTROLLERS.contains(&controller) {
+        Some(Command::Solo(
+            (controller - SOLO_CONTROLLERS.start) as usize,
+        ))
+    } else if MUTE_CONTROLLERS.contains(&controller) {
+        Some(Command::Mute(
+            (controller - MUTE_CONTROLLERS.start) as usize,
+        ))
+    } else {
+        None
+    }
+}
+
+/// The DJ filter position of the knob `filter` turning to `message`, if it
+/// is one. The values either side of 64 sweep either way.
+fn knob(message: Message, filter: u8) -> Option<Command> {
+    match message {
+        Message::Control { controller, value } if controller == filter => {
+            Some(Command::Filter(((value as f64 - 64.0) / 63.0).max(-1.0)))
+        }
+        _ => None,
+    }
+}
+
+/// Reads messages from the device at `path` on a background thread, with
+/// controller `filter` turning the DJ filter.
+pub fn spawn(path: &str, filter: u8, sender: Sender<Command>) -> Result<(), anyhow::Error> {
+    let mut device = File::open(path)?;
+    std::thread::spawn(move || {
+        let mut parser = Parser::default();
+        let mut buffer = [0; 64];
+        while let Ok(count @ 1..) = device.read(&mut buffer) {
+            for &byte in &buffer[..count] {
+                let Some(message) = parser.push(byte) else {
+                    continue;
+                };
+                let command = button(message)
+                    .or_else(|| knob(message, filter))
+                    .unwrap_or_else(|| Command::Midi(message, Instant::now()));
+                if sender.send(command).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_running_status() {
+        let mut parser = Parser::default();
+        let bytes = [
+            0x90, 60, 100, 0xf8, 64, 90, 60, 0, 0xc0, 5, 0x80, 64, 0, 0xb0, 48, 127, 0xf2, 0x10,
+            0xfa, 0x01, 0x10,
+        ];
+        let messages: Vec<_> = bytes.iter().filter_map(|&byte| parser.push(byte)).collect();
+        assert_eq!(
+            messages,
+            [
+                Message::NoteOn {
+                    key: 60,
+                    velocity: 100
+                },
+                Message::Clock,
+                Message::NoteOn {
+                    key: 64,
+                    velocity: 90
+                },
+                Message::NoteOff { key: 60 },
+                Message::NoteOff { key: 64 },
+                Message::Control {
+                    controller: 48,
+                    value: 127
+                },
+                Message::Start,
+                Message::SongPosition(0x90),
+            ]
+        );
+        // The song position takes no running status.
+        assert_eq!(parser.push(0x10), None);
+        let timecode = [
+            0xf1, 0x72, 0xf0, 0x7f, 0x7f, 0x01, 0x01, 0x21, 2, 3, 4, 0xf7,
+        ];
+        let timecodes: Vec<_> = timecode
+            .iter()
+            .filter_map(|&byte| parser.push(byte))
+            .collect();
+        assert_eq!(
+            timecodes,
+            [
+                Message::QuarterFrame(0x72),
+                Message::FullFrame(Timecode::from_full_frame([0x21, 2, 3, 4]))
+            ]
+        );
+        // Other system exclusive messages are skipped.
+        let identity = [0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7];
+        assert!(identity.iter().all(|&byte| parser.push(byte).is_none()));
+        assert_eq!(button(messages[5]), Some(Command::Mute(0)));
+        let release = Message::Control {
+            controller: 33,
+            value: 0,
+        };
+        assert_eq!(button(release), None);
+        let all_notes_off = Message::Control {
+            controller: ALL_NOTES_OFF,
+            value: 0,
+        };
+        assert_eq!(button(all_notes_off), Some(Command::Panic));
+        let turn = |value| Message::Control {
+            controller: FILTER_CONTROLLER,
+            value,
+        };
+        assert_eq!(
+            knob(turn(64), FILTER_CONTROLLER),
+            Some(Command::Filter(0.0))
+        );
+        assert_eq!(
+            knob(turn(0), FILTER_CONTROLLER),
+            Some(Command::Filter(-1.0))
+        );
+        assert_eq!(
+            knob(turn(127), FILTER_CONTROLLER),
+            Some(Command::Filter(1.0))
+        );
+        assert_eq!(knob(turn(127), 17), None);
+    }
+
+    #[test]
+    fn test_sustain() {
+        let mut sustain = Sustain::default();
+        assert!(!sustain.release(60));
+        assert!(sustain.pedal(127).is_empty());
+        assert!(sustain.release(60));
+        assert!(sustain.release(64));
+        assert!(sustain.press(64));
+        assert!(!sustain.press(62));
+        assert_eq!(sustain.pedal(0), [60]);
+        assert!(!sustain.release(64));
+        sustain.pedal(127);
+        sustain.release(67);
+        sustain.reset();
+        assert!(!sustain.release(67));
+        assert!(sustain.pedal(0).is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+//! MIDI output to external synths, hardware or software, on a port of the
+//! system's MIDI such as `FLUID Synth`, for tracks that play there instead
+//! of here.
+
+use anyhow::{anyhow, bail};
+use midir::MidiOutput;
+use std::collections::BinaryHeap;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use playground::drums::drum_at;
+use playground::instrument::Instrument;
+
+/// Controllers of the channel volume that follows the mixer, and of the
+/// message silencing every note.
+const VOLUME: u8 = 7;
+const ALL_NOTES_OFF: u8 = 123;
+/// Channel volume of an audible track, the usual default of synths.
+const AUDIBLE_VOLUME: u8 = 100;
+
+/// Where the notes of a track go.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiTrack {
+    /// Zero based MIDI channel.
+    pub channel: u8,
+    /// Seconds the synth takes to sound, which notes are sent earlier by.
+    pub latency: f64,
+}
+
+impl MidiTrack {
+    /// Parses `CHANNEL[,LATENCY]` with a channel from 1 to 16 and a latency in
+    /// milliseconds, such as `10,15`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (channel, latency) = match value.split_once(',') {
+            Some((channel, latency)) => (channel, latency.trim().parse::<f64>()?),
+            None => (value, 0.0),
+        };
+        let channel = match channel.trim().parse::<u8>()? {
+            channel @ 1..=16 => channel - 1,
+            _ => bail!("MIDI channels go from 1 to 16"),
+        };
+        if !(0.0..=1000.0).contains(&latency) {
+            bail!("the MIDI latency must be between 0 and 1000 ms");
+        }
+        Ok(Self {
+            channel,
+            latency: latency / 1000.0,
+        })
+    }
+}
+
+/// MIDI key closest to a note at `frequency` of `instrument`, the drum's own
+/// on a drum track.
+pub fn key(instrument: Instrument, frequency: f64) -> u8 {
+    if let (Instrument::Drums, Some(drum)) = (instrument, drum_at(frequency)) {
+        return drum.key() as u8;
+    }
+    (69.0 + 12.0 * (frequency / 440.0).log2())
+        .round()
+        .clamp(0.0, 127.0) as u8
+}
+
+pub fn note_on(channel: u8, key: u8, velocity: f64) -> [u8; 3] {
+    let velocity = (velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+    [0x90 | channel, key, velocity]
+}
+
+pub fn note_off(channel: u8, key: u8) -> [u8; 3] {
+    [0x80 | channel, key, 0]
+}
+
+pub fn control(channel: u8, controller: u8, value: u8) -> [u8; 3] {
+    [0xb0 | channel, controller, value]
+}
+
+/// The channel volume of a track that is heard or not.
+pub fn volume(channel: u8, audible: bool) -> [u8; 3] {
+    control(channel, VOLUME, if audible { AUDIBLE_VOLUME } else { 0 })
+}
+
+/// A message waiting to be sent, the earliest first out of a heap.
+#[derive(PartialEq, Eq)]
+struct Pending {
+    at: Instant,
+    /// Order of sending, so that messages due at once keep it.
+    order: u64,
+    message: [u8; 3],
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (other.at, other.order).cmp(&(self.at, self.order))
+    }
+}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Sends messages at the times they are due from a background thread.
+pub struct MidiOut {
+    sender: Sender<(Instant, [u8; 3])>,
+}
+
+impl MidiOut {
+    /// Connects to the first output port whose name holds `port`, ignoring
+    /// case.
+    pub fn spawn(port: &str) -> Result<Self, anyhow::Error> {
+        let output = MidiOutput::new("playground")?;
+        let ports = output.ports();
+        let names: Vec<String> = ports
+            .iter()
+            .map(|port| output.port_name(port).unwrap_or_default())
+            .collect();
+        let wanted = port.to_lowercase();
+        let Some(index) = names
+            .iter()
+            .position(|name| name.to_lowercase().contains(&wanted))
+        else {
+            if names.is_empty() {
+                bail!("there is no MIDI output port");
+            }
+            bail!(
+                "no MIDI output port is named {}: {}",
+                port,
+                names.join(", ")
+            );
+        };
+        let mut connection = output
+            .connect(&ports[index], "playground")
+            .map_err(|err| anyhow!("can't connect to {}: {}", names[index], err))?;
+        eprintln!("sending MIDI to {}", names[index]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            send_due(receiver, |message| connection.send(message).is_ok());
+        });
+        Ok(Self { sender })
+    }
+
+    /// Sends `message` at `at`, or right away if that has passed.
+    pub fn send(&self, at: Instant, message: [u8; 3]) {
+        // The thread only ends once the port fails, and then so do the notes.
+        let _ = self.sender.send((at, message));
+    }
+
+    /// Silences the notes of `channel` right away.
+    pub fn all_notes_off(&self, channel: u8) {
+        self.send(Instant::now(), control(channel, ALL_NOTES_OFF, 0));
+    }
+}
+
+/// Writes the messages received with `write` once they are due, until the
+/// sender is gone and every message is written or `write` fails.
+fn send_due(receiver: Receiver<(Instant, [u8; 3])>, mut write: impl FnMut(&[u8]) -> bool) {
+    let mut pending = BinaryHeap::new();
+    let mut order = 0;
+    let mut connected = true;
+    while connected || !pending.is_empty() {
+        let wait = match pending.peek() {
+            Some(Pending { at, .. }) => at.saturating_duration_since(Instant::now()),
+            None => Duration::from_secs(3600),
+        };
+        match receiver.recv_timeout(wait) {
+            Ok((at, message)) => {
+                pending.push(Pending { at, order, message });
+                order += 1;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => connected = false,
+        }
+        while let Some(next) = pending.peek() {
+            if !connected || next.at <= Instant::now() {
+                let next = pending.pop().unwrap();
+                if !write(&next.message) {
+                    return;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_midi_track() {
+        assert_eq!(
+            MidiTrack::parse("10,15").unwrap(),
+            MidiTrack {
+                channel: 9,
+                latency: 0.015
+            }
+        );
+        assert_eq!(MidiTrack::parse("1").unwrap().latency, 0.0);
+        assert!(MidiTrack::parse("17").is_err());
+        assert!(MidiTrack::parse("1,-5").is_err());
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(key(Instrument::Pluck, 440.0), 69);
+        assert_eq!(key(Instrument::Pluck, 261.63), 60);
+        assert_eq!(note_on(2, 60, 1.0), [0x92, 60, 127]);
+        assert_eq!(note_off(2, 60), [0x82, 60, 0]);
+        assert_eq!(volume(0, false), [0xb0, 7, 0]);
+    }
+
+    #[test]
+    fn test_sent_in_order_of_time() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let now = Instant::now();
+        sender
+            .send((now + Duration::from_millis(20), [3; 3]))
+            .unwrap();
+        sender.send((now, [1; 3])).unwrap();
+        sender.send((now, [2; 3])).unwrap();
+        drop(sender);
+        let mut written = vec![];
+        send_due(receiver, |message| {
+            written.push(message[0]);
+            true
+        });
+        assert_eq!(written, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Muting and soloing tracks while the song plays, and bypassing the
+//! effects inserted on them.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+use crate::insert::{self, Insert};
+use crate::param::{Param, ParamRegistry, Spec};
+
+/// Gain of a track, 1 while it is heard, faded rather than clicked.
+const GAIN: Spec = Spec::linear(0.0, 1.0, 1.0);
+
+/// Mute and solo flags of the tracks. Soloing is in place: while any track
+/// is soloed only the soloed ones are heard, each still where it is panned.
+pub struct Mixer {
+    muted: Vec<bool>,
+    soloed: Vec<bool>,
+    /// Gain of each track, set as the flags change.
+    gains: Vec<Param>,
+    /// Effects inserted on tracks by name, such as `track1.echo`.
+    inserts: Vec<(String, Insert)>,
+}
+
+impl Mixer {
+    pub fn new(tracks: usize) -> Self {
+        Self {
+            muted: vec![false; tracks],
+            soloed: vec![false; tracks],
+            gains: (0..tracks).map(|_| Param::new(GAIN)).collect(),
+            inserts: vec![],
+        }
+    }
+
+    pub fn tracks(&self) -> usize {
+        self.gains.len()
+    }
+
+    pub fn toggle_mute(&mut self, track: usize) {
+        self.muted[track] = !self.muted[track];
+        self.update();
+    }
+
+    pub fn toggle_solo(&mut self, track: usize) {
+        self.soloed[track] = !self.soloed[track];
+        self.update();
+    }
+
+    pub fn is_muted(&self, track: usize) -> bool {
+        self.muted[track]
+    }
+
+    pub fn is_soloed(&self, track: usize) -> bool {
+        self.soloed[track]
+    }
+
+    pub fn is_audible(&self, track: usize) -> bool {
+        let soloing = self.soloed.contains(&true);
+        !self.muted[track] && (!soloing || self.soloed[track])
+    }
+
+    /// `M` for a muted and `S` for a soloed track, shown next to its name.
+    pub fn flags(&self, track: usize) -> String {
+        let mut flags = String::new();
+        if self.muted[track] {
+            flags.push('M');
+        }
+        if self.soloed[track] {
+            flags.push('S');
+        }
+        flags
+    }
+
+    fn update(&self) {
+        for (track, gain) in self.gains.iter().enumerate() {
+            gain.set(if self.is_audible(track) { 1.0 } else { 0.0 });
+        }
+    }
+
+    /// Inserts `effect` as `name`, mixed in by the parameter `NAME.mix`.
+    /// Inserts of the same name share it and are bypassed together.
+    pub fn insert(
+        &mut self,
+        params: &mut ParamRegistry,
+        name: &str,
+        effect: Box<dyn AudioUnit64>,
+    ) -> Box<dyn AudioUnit64> {
+        let insert = Insert::new(params.register(&format!("{}.mix", name), insert::MIX));
+        let unit = insert.unit(effect);
+        self.inserts.push((name.to_string(), insert));
+        unit
+    }
+
+    /// Bypasses the inserts named `name` or brings them back in, returning
+    /// whether they are bypassed now.
+    pub fn toggle_bypass(&self, name: &str) -> Result<bool, anyhow::Error> {
+        let inserts: Vec<&Insert> = self
+            .inserts
+            .iter()
+            .filter(|(found, _)| found == name)
+            .map(|(_, insert)| insert)
+            .collect();
+        let Some(first) = inserts.first() else {
+            let mut names: Vec<&str> = self.inserts.iter().map(|(name, _)| name.as_str()).collect();
+            names.dedup();
+            bail!("unknown insert {}, there are {}", name, names.join(", "));
+        };
+        let bypassed = !first.is_bypassed();
+        for insert in inserts {
+            insert.set_bypassed(bypassed);
+        }
+        Ok(bypassed)
+    }
+
+    /// Stereo gain of `track`, fading in and out as it is muted and soloed.
+    pub fn unit(
+        &self,
+        track: usize,
+    ) -> An<impl AudioNode<Sample = f64, Inputs = U2, Outputs = U2>> {
+        (pass() | pass()) * (self.gains[track].unit() >> split::<U2>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solo_in_place() {
+        let mut mixer = Mixer::new(3);
+        mixer.toggle_mute(0);
+        assert!(!mixer.is_audible(0) && mixer.is_audible(1));
+        mixer.toggle_solo(1);
+        mixer.toggle_solo(2);
+        assert!(!mixer.is_audible(0) && mixer.is_audible(1) && mixer.is_audible(2));
+        // A muted track stays silent even when soloed.
+        mixer.toggle_solo(0);
+        assert!(!mixer.is_audible(0));
+        assert_eq!(mixer.flags(0), "MS");
+        mixer.toggle_solo(1);
+        mixer.toggle_solo(2);
+        mixer.toggle_solo(0);
+        mixer.toggle_mute(0);
+        assert!((0..3).all(|track| mixer.is_audible(track)));
+    }
+
+    #[test]
+    fn test_mute_fades() {
+        let mut mixer = Mixer::new(1);
+        let mut unit = mixer.unit(0);
+        unit.set_sample_rate(1000.0);
+        assert!((unit.filter_stereo(1.0, 1.0).0 - 1.0).abs() < 1e-6);
+        mixer.toggle_mute(0);
+        let first = unit.filter_stereo(1.0, 1.0).0;
+        assert!(first > 0.0 && first < 1.0);
+        for _ in 0..100 {
+            unit.filter_stereo(1.0, 1.0);
+        }
+        assert!(unit.filter_stereo(1.0, 1.0).1.abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_bypasses_inserts_by_name() {
+        let mut mixer = Mixer::new(2);
+        let mut params = ParamRegistry::default();
+        for name in ["track1.echo", "track1.echo", "track2.modulation"] {
+            mixer.insert(&mut params, name, Box::new(pass() | pass()));
+        }
+        assert_eq!(params.names(), ["track1.echo.mix", "track2.modulation.mix"]);
+        assert!(mixer.toggle_bypass("track1.echo").unwrap());
+        assert!(mixer.inserts[1].1.is_bypassed() && !mixer.inserts[2].1.is_bypassed());
+        assert!(!mixer.toggle_bypass("track1.echo").unwrap());
+        let err = mixer.toggle_bypass("track2.echo").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "unknown insert track2.echo, there are track1.echo, track2.modulation"
+        );
+    }
+}
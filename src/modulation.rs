@@ -0,0 +1,149 @@
+//! Envelope followers on the audio of tracks as modulation sources, ducking
+//! other tracks or opening a wah on them as they get loud.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+/// Attack and release in seconds of the envelope followers.
+const ATTACK_SECONDS: f64 = 0.005;
+const RELEASE_SECONDS: f64 = 0.15;
+/// Envelope level that modulates fully, those of tracks hardly go above.
+const FULL_LEVEL: f64 = 0.25;
+/// Range of the center of the wah from closed to open.
+const WAH_LOW_HZ: f64 = 300.0;
+const WAH_HIGH_HZ: f64 = 3000.0;
+const WAH_Q: f64 = 4.0;
+
+/// What the envelope of a track does to the track it modulates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Destination {
+    /// Turns it down, as a kick ducks a pad in sidechain compression.
+    Duck,
+    /// Sweeps a bandpass up over it, an auto-wah when it is its own source.
+    Wah,
+}
+
+impl Destination {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "duck" => Ok(Destination::Duck),
+            "wah" => Ok(Destination::Wah),
+            _ => bail!("unknown modulation destination: {}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Modulation {
+    pub destination: Destination,
+    /// Track whose envelope modulates, counted from zero.
+    pub source: usize,
+    /// How far the envelope modulates, from 0 to 1.
+    pub depth: f64,
+}
+
+impl Modulation {
+    /// Parses `DESTINATION,SOURCE[,DEPTH]` with a track counted from 1, such
+    /// as `duck,1,0.8`. The depth defaults to 1.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut fields = value.split(',').map(str::trim);
+        let destination = Destination::parse(fields.next().unwrap_or_default())?;
+        let source = match fields.next().unwrap_or_default().parse::<usize>()? {
+            0 => bail!("tracks are counted from 1"),
+            track => track - 1,
+        };
+        let depth = fields.next().map_or(Ok(1.0), str::parse)?;
+        if !(0.0..=1.0).contains(&depth) {
+            bail!("the modulation depth must be between 0 and 1");
+        }
+        if fields.next().is_some() {
+            bail!("too many modulation fields: {}", value);
+        }
+        Ok(Self {
+            destination,
+            source,
+            depth,
+        })
+    }
+
+    /// How far an envelope at `level` modulates, from 0 to `depth`.
+    pub fn amount(&self, level: f64) -> f64 {
+        self.depth * (level / FULL_LEVEL).min(1.0)
+    }
+
+    /// Modulates the stereo signal on inputs 0 and 1 by the envelope on input 2.
+    pub fn unit(self) -> Box<dyn AudioUnit64> {
+        match self.destination {
+            Destination::Duck => Box::new(map(move |f: &Frame<f64, U3>| {
+                let gain = 1.0 - self.amount(f[2]);
+                (f[0] * gain, f[1] * gain)
+            })),
+            Destination::Wah => {
+                let mut net = Net64::new(3, 2);
+                let center = net.push(Box::new(map(move |f: &Frame<f64, U1>| {
+                    WAH_LOW_HZ * (WAH_HIGH_HZ / WAH_LOW_HZ).powf(self.amount(f[0]))
+                })));
+                net.connect_input(2, center, 0);
+                // The dry signal is left in by as much as the depth is short of 1.
+                let (dry, wet) = (1.0 - self.depth, self.depth);
+                for channel in 0..2 {
+                    let wah = (pass() | sink()) * dry & bandpass_q(WAH_Q) * wet;
+                    let wah = net.push(Box::new(wah));
+                    net.connect_input(channel, wah, 0);
+                    net.connect(center, 0, wah, 1);
+                    net.connect_output(wah, 0, channel);
+                }
+                Box::new(net)
+            }
+        }
+    }
+}
+
+/// Level of the stereo signal on inputs 0 and 1, following its peaks.
+pub fn envelope() -> An<impl AudioNode<Sample = f64, Inputs = U2, Outputs = U1>> {
+    map(|f: &Frame<f64, U2>| f[0].abs().max(f[1].abs()))
+        >> follow((ATTACK_SECONDS, RELEASE_SECONDS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_modulation() {
+        assert_eq!(
+            Modulation::parse("duck,1,0.5").unwrap(),
+            Modulation {
+                destination: Destination::Duck,
+                source: 0,
+                depth: 0.5
+            }
+        );
+        assert_eq!(Modulation::parse("wah,3").unwrap().depth, 1.0);
+        assert!(Modulation::parse("duck,0").is_err());
+        assert!(Modulation::parse("duck,1,2").is_err());
+        assert!(Modulation::parse("pan,1").is_err());
+    }
+
+    #[test]
+    fn test_loud_source_ducks() {
+        let duck = Modulation::parse("duck,1,0.5").unwrap();
+        let mut envelope = envelope();
+        envelope.set_sample_rate(1000.0);
+        let mut level = 0.0;
+        for _ in 0..100 {
+            level = envelope.tick(&[0.0, -0.5].into())[0];
+        }
+        assert!((level - 0.5).abs() < 0.01);
+        let mut unit = duck.unit();
+        let mut output = [0.0; 2];
+        unit.tick(&[1.0, 1.0, level], &mut output);
+        assert!((output[0] - 0.5).abs() < 0.01);
+        unit.tick(&[1.0, 1.0, 0.0], &mut output);
+        assert_eq!(output, [1.0, 1.0]);
+        // Half dry, the wah lets some of the signal through.
+        let mut wah = Modulation::parse("wah,1,0.5").unwrap().unit();
+        wah.tick(&[1.0, 1.0, level], &mut output);
+        assert!(output[0] >= 0.5 && output[0] == output[1]);
+    }
+}
@@ -0,0 +1,83 @@
+//! A monitor mix for performers, with levels of its own apart from the main
+//! mix, so that what they hear can differ from what the house hears.
+
+use fundsp::hacker::*;
+
+use crate::param::{Param, ParamRegistry, Spec};
+
+/// Level of a track in the monitor mix, up to twice as loud as in the main mix.
+pub const LEVEL: Spec = Spec {
+    random: (0.25, 0.75),
+    ..Spec::linear(0.0, 2.0, 1.0)
+};
+
+/// The monitor levels of the tracks, set by name as `monitor1`, `monitor2`
+/// and so on. Live notes are always heard as they are played.
+pub struct Monitor {
+    levels: Vec<Param>,
+}
+
+impl Monitor {
+    pub fn new(params: &mut ParamRegistry, tracks: usize) -> Self {
+        Self {
+            levels: (0..tracks)
+                .map(|track| params.register(&format!("monitor{}", track + 1), LEVEL))
+                .collect(),
+        }
+    }
+
+    /// Mixes the stereo buses of the tracks followed by the one of live notes,
+    /// taken before they are muted or soloed, into the stereo monitor mix.
+    pub fn unit(&self) -> Net64 {
+        let buses = self.levels.len() + 1;
+        let mut net = Net64::new(2 * buses, 2);
+        let mut mix = None;
+        for bus in 0..buses {
+            let gain: Box<dyn AudioUnit64> = match self.levels.get(bus) {
+                Some(level) => Box::new((pass() | pass()) * (level.unit() >> split::<U2>())),
+                None => Box::new(multipass::<U2>()),
+            };
+            let gain = net.push(gain);
+            for channel in 0..2 {
+                net.connect_input(2 * bus + channel, gain, channel);
+            }
+            mix = Some(match mix {
+                None => gain,
+                Some(mix) => {
+                    let sum = net.push(Box::new((pass() | pass()) + (pass() | pass())));
+                    for channel in 0..2 {
+                        net.connect(mix, channel, sum, channel);
+                        net.connect(gain, channel, sum, 2 + channel);
+                    }
+                    sum
+                }
+            });
+        }
+        let mix = mix.unwrap();
+        for channel in 0..2 {
+            net.connect_output(mix, channel, channel);
+        }
+        net
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_of_its_own() {
+        let mut params = ParamRegistry::default();
+        let monitor = Monitor::new(&mut params, 2);
+        params.set("monitor2", 0.0).unwrap();
+        assert!(params.set("monitor3", 1.0).is_err());
+        let mut unit = monitor.unit();
+        unit.set_sample_rate(1000.0);
+        let mut output = [0.0; 2];
+        for _ in 0..200 {
+            unit.tick(&[1.0, 0.5, 1.0, 1.0, 0.25, 0.0], &mut output);
+        }
+        assert!((output[0] - 1.25).abs() < 1e-3);
+        assert!((output[1] - 0.5).abs() < 1e-3);
+    }
+}
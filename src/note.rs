@@ -0,0 +1,277 @@
+//! Note names and their frequencies.
+
+use fundsp::hacker::*;
+
+/// Octaves that note names may be given in, beyond the range of MIDI keys.
+pub const OCTAVES: std::ops::RangeInclusive<i32> = -10..=10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    pub note: BaseNote,
+    pub octave: i32,
+    /// Microtonal offset from the equal tempered pitch, in hundredths of a semitone.
+    pub cents: f64,
+}
+
+impl Note {
+    pub fn base(note: BaseNote) -> Self {
+        Self::new(note, 0)
+    }
+
+    pub fn new(note: BaseNote, octave: i32) -> Self {
+        Self {
+            note,
+            octave,
+            cents: 0.0,
+        }
+    }
+
+    /// The same note detuned by `cents`, such as -50 for a quarter tone flat.
+    pub fn with_cents(self, cents: f64) -> Self {
+        Self { cents, ..self }
+    }
+
+    /// The note of a MIDI key number, where key 60 is C in octave zero.
+    pub fn from_midi(key: u8) -> Self {
+        let offset = key as i32 - 60;
+        Self::new(
+            BaseNote::ALL[offset.rem_euclid(12) as usize],
+            offset.div_euclid(12),
+        )
+    }
+
+    /// The MIDI key number of the note, the inverse of `from_midi`. Cents are
+    /// left out.
+    pub fn midi(self) -> i32 {
+        let index = BaseNote::ALL.iter().position(|&note| note == self.note);
+        60 + index.unwrap() as i32 + 12 * self.octave
+    }
+
+    /// The note `semitones` higher, or lower when negative, keeping its cents.
+    pub fn transpose(self, semitones: i32) -> Self {
+        let index = BaseNote::ALL.iter().position(|&note| note == self.note);
+        let offset = index.unwrap() as i32 + semitones;
+        Self::new(
+            BaseNote::ALL[offset.rem_euclid(12) as usize],
+            self.octave + offset.div_euclid(12),
+        )
+        .with_cents(self.cents)
+    }
+
+    /// Parses a German note name, sharp or flat, followed by an optional octave
+    /// and an optional signed cents offset ending in `c`, such as `Fis`, `B`
+    /// for B flat, `C-1` or `E-1-30c`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        if let Some(note) = value.strip_suffix('c') {
+            let split = note
+                .rfind(['+', '-'])
+                .ok_or_else(|| anyhow::anyhow!("missing sign of cents: {}", value))?;
+            let cents: f64 = note[split..].parse()?;
+            if !(-100.0..=100.0).contains(&cents) {
+                anyhow::bail!("cents out of range: {}", value);
+            }
+            return Ok(Self::parse(&note[..split])?.with_cents(cents));
+        }
+        let split = value
+            .find(|c: char| c == '-' || c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (name, octave) = value.split_at(split);
+        let note = match name {
+            "C" => BaseNote::C,
+            "Cis" | "Des" => BaseNote::Cis,
+            "D" => BaseNote::D,
+            "Dis" | "Es" => BaseNote::Dis,
+            "E" => BaseNote::E,
+            "F" => BaseNote::F,
+            "Fis" | "Ges" => BaseNote::Fis,
+            "G" => BaseNote::G,
+            "Gis" | "As" => BaseNote::Gis,
+            "A" => BaseNote::A,
+            "Ais" | "B" => BaseNote::Ais,
+            "H" => BaseNote::H,
+            _ => anyhow::bail!("unknown note: {}", value),
+        };
+        let octave = if octave.is_empty() {
+            0
+        } else {
+            octave.parse()?
+        };
+        if !OCTAVES.contains(&octave) {
+            anyhow::bail!("octave out of range: {}", value);
+        }
+        Ok(Self::new(note, octave))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accord {
+    pub notes: Vec<Note>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BaseNote {
+    C,
+    Cis,
+    D,
+    Dis,
+    E,
+    F,
+    Fis,
+    G,
+    Gis,
+    A,
+    Ais,
+    H,
+}
+
+impl BaseNote {
+    /// All notes of an octave in ascending order.
+    pub const ALL: [BaseNote; 12] = [
+        BaseNote::C,
+        BaseNote::Cis,
+        BaseNote::D,
+        BaseNote::Dis,
+        BaseNote::E,
+        BaseNote::F,
+        BaseNote::Fis,
+        BaseNote::G,
+        BaseNote::Gis,
+        BaseNote::A,
+        BaseNote::Ais,
+        BaseNote::H,
+    ];
+}
+
+pub fn get_note_frequency(note: &Note) -> f64 {
+    let note_number = match note.note {
+        BaseNote::C => 0,
+        BaseNote::Cis => 1,
+        BaseNote::D => 2,
+        BaseNote::Dis => 3,
+        BaseNote::E => 4,
+        BaseNote::F => 5,
+        BaseNote::Fis => 6,
+        BaseNote::G => 7,
+        BaseNote::Gis => 8,
+        BaseNote::A => 9,
+        BaseNote::Ais => 10,
+        BaseNote::H => 11,
+    } as f64;
+
+    let note = note_number + 12.0 * note.octave as f64 + note.cents / 100.0;
+
+    440.0 * 2.0.pow((note - 9.0) / 12.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_get_note_frequency_c() {
+        assert_approx_eq!(
+            get_note_frequency(&Note {
+                note: BaseNote::C,
+                octave: 0,
+                cents: 0.0
+            }),
+            261.626,
+            0.01
+        );
+    }
+
+    #[test]
+    fn test_parse_note() {
+        assert_eq!(Note::parse("Fis").unwrap(), Note::base(BaseNote::Fis));
+        assert_eq!(Note::parse("C-1").unwrap(), Note::new(BaseNote::C, -1));
+        assert!(Note::parse("X").is_err());
+        assert_eq!(Note::parse("Es1").unwrap(), Note::new(BaseNote::Dis, 1));
+        assert_eq!(Note::parse("B").unwrap(), Note::base(BaseNote::Ais));
+        assert!(Note::parse("C2147483647").is_err());
+        assert_eq!(Note::from_midi(69), Note::base(BaseNote::A));
+        assert_eq!(Note::from_midi(59), Note::new(BaseNote::H, -1));
+        assert_eq!(Note::new(BaseNote::H, -1).midi(), 59);
+    }
+
+    #[test]
+    fn test_parse_cents() {
+        assert_eq!(
+            Note::parse("E-1-30c").unwrap(),
+            Note::new(BaseNote::E, -1).with_cents(-30.0)
+        );
+        assert_eq!(
+            Note::parse("A+50c").unwrap(),
+            Note::base(BaseNote::A).with_cents(50.0)
+        );
+        assert!(Note::parse("A50c").is_err());
+        assert!(Note::parse("A+150c").is_err());
+        assert_approx_eq!(
+            get_note_frequency(&Note::base(BaseNote::A).with_cents(-1200.0)),
+            220.0,
+            1e-9
+        );
+    }
+
+    #[test]
+    fn test_get_note_frequency_a() {
+        assert_eq!(
+            get_note_frequency(&Note {
+                note: BaseNote::A,
+                octave: 0,
+                cents: 0.0
+            }),
+            440.0
+        );
+    }
+
+    /// Every base note in the octaves around the audible range.
+    fn any_note() -> impl Strategy<Value = Note> {
+        (0..12usize, -5..=5i32).prop_map(|(note, octave)| Note::new(BaseNote::ALL[note], octave))
+    }
+
+    fn ratio(high: Note, low: Note) -> f64 {
+        get_note_frequency(&high) / get_note_frequency(&low)
+    }
+
+    proptest! {
+        #[test]
+        fn test_octaves_double(note in any_note()) {
+            let up = Note::new(note.note, note.octave + 1);
+            prop_assert!((ratio(up, note) - 2.0).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_semitones_are_equal(note in any_note()) {
+            let ratio = ratio(note.transpose(1), note);
+            prop_assert!((ratio - 2.0.pow(1.0 / 12.0)).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_transposition_scales_frequency(note in any_note(), semitones in -36..=36i32) {
+            let transposed = note.transpose(semitones);
+            prop_assert_eq!(transposed.midi(), note.midi() + semitones);
+            prop_assert_eq!(transposed.transpose(-semitones), note);
+            let expected = 2.0.pow(semitones as f64 / 12.0);
+            prop_assert!((ratio(transposed, note) / expected - 1.0).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_cents_scale_frequency(note in any_note(), cents in -100.0..=100.0f64) {
+            let detuned = note.with_cents(cents);
+            prop_assert_eq!(detuned.midi(), note.midi());
+            prop_assert_eq!(detuned.transpose(7).cents, cents);
+            let expected = 2.0.pow(cents / 1200.0);
+            prop_assert!((ratio(detuned, note) / expected - 1.0).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_midi_round_trips(key in 0..=127u8) {
+            let note = Note::from_midi(key);
+            prop_assert_eq!(note.midi(), key as i32);
+            prop_assert_eq!(Note::from_midi(note.midi() as u8), note);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Deterministic renders without an audio device, so that synthesis and
+//! sequencing can be checked in tests: everything runs at a fixed sample rate
+//! into a buffer of a fixed length.
+
+use fundsp::hacker::*;
+
+use crate::arrangement::Arrangement;
+use crate::engine::Engine;
+
+/// Sample rate of offline renders.
+pub const SAMPLE_RATE: f64 = 44100.0;
+
+/// Renders `seconds` of `unit` from its reset state.
+pub fn render_unit(unit: &mut dyn AudioUnit64, seconds: f64) -> Wave64 {
+    unit.set_sample_rate(SAMPLE_RATE);
+    unit.reset();
+    Wave64::render(SAMPLE_RATE, seconds, unit)
+}
+
+/// Renders the first `seconds` of `song` at `bpm` in stereo.
+pub fn render_song(song: Arrangement, bpm: f64, seconds: f64) -> Wave64 {
+    Engine::with_song(song, SAMPLE_RATE, bpm).render_wave(seconds)
+}
+
+/// Largest sample amplitude of `channel` between `from` and `to` seconds.
+pub fn peak(wave: &Wave64, channel: usize, from: f64, to: f64) -> f64 {
+    let index = |time: f64| min((time * wave.sample_rate()).round() as usize, wave.len());
+    wave.channel(channel)[index(from)..index(to)]
+        .iter()
+        .fold(0.0, |peak, x| max(peak, x.abs()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::Instrument;
+    use crate::note::{BaseNote::*, Note};
+    use crate::pattern::Pattern;
+
+    #[test]
+    fn test_song_renders_identically() {
+        let song = || {
+            let mut song = Arrangement::default();
+            let pattern = song.pattern(Pattern::melody(&[(Note::base(C), 1.0); 2]));
+            song.track(Instrument::Pluck, &[(pattern, 1)]);
+            song
+        };
+        let wave = render_song(song(), 120.0, 1.5);
+        assert_eq!(wave.len(), 66150);
+        assert_eq!(wave.channel(0), render_song(song(), 120.0, 1.5).channel(0));
+        // Notes at 0 and 0.5 seconds, each released after half a second.
+        assert!(peak(&wave, 0, 0.0, 0.1) > 0.1);
+        assert!(peak(&wave, 0, 0.5, 0.6) > 0.1);
+        assert!(peak(&wave, 0, 1.3, 1.5) < 1e-3);
+    }
+
+    #[test]
+    fn test_unit_render_starts_from_reset() {
+        let mut unit = Instrument::Organ.voice(440.0, 0.1, 1.0);
+        let first = render_unit(&mut *unit, 0.6);
+        assert_eq!(first.channel(0), render_unit(&mut *unit, 0.6).channel(0));
+        assert!(peak(&first, 0, 0.0, 0.1) > 0.05);
+        // Silent once the release after the note-off has passed.
+        assert_eq!(peak(&first, 0, 0.55, 0.6), 0.0);
+    }
+}
@@ -0,0 +1,310 @@
+//! FLAC and Vorbis in the Ogg container, as Icecast takes them: a page for
+//! each packet, after the header packets that tell the stream what it
+//! carries.
+
+use fundsp::hacker::Wave64;
+
+use crate::flac::{self, BLOCK};
+use crate::vorbis;
+
+/// Flags of the header type of a page.
+const FIRST_PAGE: u8 = 0x02;
+const LAST_PAGE: u8 = 0x04;
+/// Longest packet a single page can hold, in 255 segments of 255 bytes.
+const MAX_PACKET: usize = 255 * 255 - 1;
+
+/// Pages of one logical stream.
+struct Pages {
+    serial: u32,
+    sequence: u32,
+}
+
+impl Pages {
+    /// The page holding `packet`, after which `granule` samples have been
+    /// sent, with the header type `flags`.
+    fn page(&mut self, packet: &[u8], granule: u64, flags: u8) -> Vec<u8> {
+        assert!(packet.len() <= MAX_PACKET);
+        let mut page = b"OggS".to_vec();
+        page.push(0);
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        // The checksum, computed with itself zeroed.
+        page.extend_from_slice(&[0; 4]);
+        // Segments of 255 bytes and a shorter one ending the packet.
+        let full = packet.len() / 255;
+        page.push(full as u8 + 1);
+        page.extend(std::iter::repeat_n(255, full));
+        page.push((packet.len() % 255) as u8);
+        page.extend_from_slice(packet);
+        let crc = crc32(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        self.sequence += 1;
+        page
+    }
+}
+
+/// Encodes frames of samples into pages of 16 bit FLAC as they come.
+pub struct FlacStream {
+    pages: Pages,
+    sample_rate: f64,
+    /// Samples of each channel of the block being filled.
+    block: Vec<Vec<i64>>,
+    /// Frames sent so far, which the granule positions count.
+    frames: u64,
+    number: u64,
+}
+
+impl FlacStream {
+    /// A stream of `channels` channels at `sample_rate`, told apart from
+    /// others by `serial`.
+    pub fn new(sample_rate: f64, channels: usize, serial: u32) -> Self {
+        Self {
+            pages: Pages {
+                serial,
+                sequence: 0,
+            },
+            sample_rate,
+            block: vec![Vec::with_capacity(BLOCK); channels],
+            frames: 0,
+            number: 0,
+        }
+    }
+
+    /// The pages that start the stream: the stream info, and the comments
+    /// that are required to follow it.
+    pub fn headers(&mut self) -> Vec<u8> {
+        let mut first = vec![0x7f];
+        first.extend_from_slice(b"FLAC");
+        // Version 1.0 of the mapping, with one more header packet.
+        first.extend_from_slice(&[1, 0, 0, 1]);
+        first.extend_from_slice(b"fLaC");
+        first.extend_from_slice(&flac::block_header(false, 0, 34));
+        first.extend_from_slice(&flac::stream_info(self.sample_rate, self.block.len(), 0));
+
+        let vendor = concat!("playground ", env!("CARGO_PKG_VERSION"));
+        let mut comments = (vendor.len() as u32).to_le_bytes().to_vec();
+        comments.extend_from_slice(vendor.as_bytes());
+        comments.extend_from_slice(&0u32.to_le_bytes());
+        let mut second = flac::block_header(true, 4, comments.len()).to_vec();
+        second.extend_from_slice(&comments);
+
+        let mut pages = self.pages.page(&first, 0, FIRST_PAGE);
+        pages.extend_from_slice(&self.pages.page(&second, 0, 0));
+        pages
+    }
+
+    /// Adds a frame of a sample for each channel, and returns the page of
+    /// the block it fills, if it does.
+    pub fn push(&mut self, frame: &[f64]) -> Option<Vec<u8>> {
+        for (block, &x) in self.block.iter_mut().zip(frame) {
+            block.push(flac::sample(x));
+        }
+        (self.block[0].len() == BLOCK).then(|| self.flush(0))
+    }
+
+    /// The last page, with what is left of the block.
+    pub fn finish(&mut self) -> Vec<u8> {
+        if self.block[0].is_empty() {
+            // A block of silence, since the last page needs a packet.
+            for block in &mut self.block {
+                block.push(0);
+            }
+        }
+        self.flush(LAST_PAGE)
+    }
+
+    fn flush(&mut self, flags: u8) -> Vec<u8> {
+        let blocks: Vec<&[i64]> = self.block.iter().map(|block| &block[..]).collect();
+        let packet = flac::frame(self.number, &blocks);
+        self.frames += self.block[0].len() as u64;
+        self.number += 1;
+        for block in &mut self.block {
+            block.clear();
+        }
+        self.pages.page(&packet, self.frames, flags)
+    }
+}
+
+/// Encodes frames of samples into pages of Vorbis as they come.
+pub struct VorbisStream {
+    pages: Pages,
+    encoder: vorbis::Encoder,
+    /// Frames pushed so far, and packets sent, each of which but the first
+    /// decodes to half a block.
+    frames: u64,
+    packets: u64,
+}
+
+impl VorbisStream {
+    /// A stream of `channels` channels at `sample_rate`, told apart from
+    /// others by `serial`.
+    pub fn new(sample_rate: f64, channels: usize, serial: u32) -> Self {
+        Self {
+            pages: Pages {
+                serial,
+                sequence: 0,
+            },
+            encoder: vorbis::Encoder::new(sample_rate, channels),
+            frames: 0,
+            packets: 0,
+        }
+    }
+
+    /// The pages that start the stream: the identification, the comments
+    /// and the setup with the codebooks.
+    pub fn headers(&mut self) -> Vec<u8> {
+        let [identification, comments, setup] = self.encoder.headers();
+        let mut pages = self.pages.page(&identification, 0, FIRST_PAGE);
+        pages.extend_from_slice(&self.pages.page(&comments, 0, 0));
+        pages.extend_from_slice(&self.pages.page(&setup, 0, 0));
+        pages
+    }
+
+    /// Adds a frame of a sample for each channel, and returns the page of
+    /// the block it fills, if it does.
+    pub fn push(&mut self, frame: &[f64]) -> Option<Vec<u8>> {
+        self.frames += 1;
+        let packet = self.encoder.push(frame)?;
+        Some(self.send(&packet, 0))
+    }
+
+    /// The last pages, with what is left of the blocks, the granule of the
+    /// last of them cutting the silence that pads it.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let packets = self.encoder.finish();
+        let (last, rest) = packets.split_last().unwrap();
+        let mut pages: Vec<u8> = rest
+            .iter()
+            .flat_map(|packet| self.send(packet, 0))
+            .collect();
+        pages.extend(self.send(last, LAST_PAGE));
+        pages
+    }
+
+    fn send(&mut self, packet: &[u8], flags: u8) -> Vec<u8> {
+        let granule = if flags & LAST_PAGE != 0 {
+            self.frames
+        } else {
+            self.packets * (vorbis::BLOCK / 2) as u64
+        };
+        self.packets += 1;
+        self.pages.page(packet, granule, flags)
+    }
+}
+
+/// `wave` as an Ogg Vorbis file, of a single stream whose serial is zero
+/// so that the same wave is always the same file.
+pub fn vorbis(wave: &Wave64) -> Vec<u8> {
+    let mut stream = VorbisStream::new(wave.sample_rate(), wave.channels(), 0);
+    let mut ogg = stream.headers();
+    let mut frame = vec![0.0; wave.channels()];
+    for i in 0..wave.len() {
+        for (channel, x) in frame.iter_mut().enumerate() {
+            *x = wave.at(channel, i);
+        }
+        ogg.extend(stream.push(&frame).unwrap_or_default());
+    }
+    ogg.extend(stream.finish());
+    ogg
+}
+
+/// The CRC of Ogg pages, unreflected and starting from zero.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                crc << 1 ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fundsp::hacker::*;
+
+    #[test]
+    fn test_pages() {
+        assert_eq!(crc32(b"123456789"), 0x89a1_897f);
+        let mut pages = Pages {
+            serial: 7,
+            sequence: 0,
+        };
+        let page = pages.page(&[1; 300], 42, FIRST_PAGE);
+        assert_eq!(&page[..6], b"OggS\x00\x02");
+        assert_eq!(page[26..29], [2, 255, 45]);
+        assert_eq!(page.len(), 29 + 300);
+        assert_eq!(pages.page(&[], 42, 0)[18..22], 1u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_flac_stream_decodes() {
+        let mut stream = FlacStream::new(8000.0, 2, 1);
+        let mut ogg = stream.headers();
+        let frames = BLOCK + 100;
+        for i in 0..frames {
+            let x = (TAU * 440.0 * i as f64 / 8000.0).sin() * 0.5;
+            ogg.extend(stream.push(&[x, -x]).unwrap_or_default());
+        }
+        ogg.extend(stream.finish());
+        let path = std::env::temp_dir().join("playground_test_flac_stream_decodes.ogg");
+        std::fs::write(&path, &ogg).unwrap();
+        let decoded = Wave64::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.len(), frames);
+        assert_eq!(decoded.sample_rate(), 8000.0);
+        let x = flac::sample((TAU * 440.0 * 3.0 / 8000.0).sin() * 0.5);
+        assert_eq!(decoded.at(0, 3) * 32768.0, x as f64);
+        assert_eq!(decoded.at(1, 3) * 32768.0, -x as f64);
+    }
+
+    #[test]
+    fn test_vorbis_decodes() {
+        let sample_rate = 44100.0;
+        let frames = 20000;
+        // A tone on the left, and on the right a brighter one with partials
+        // up to the top of the spectrum.
+        let tone = |channel: usize, i: usize| {
+            let t = i as f64 / sample_rate;
+            match channel {
+                0 => (TAU * 440.0 * t).sin() * 0.5,
+                _ => {
+                    (1..40)
+                        .map(|k| (TAU * 523.0 * k as f64 * t).sin() / k as f64)
+                        .sum::<f64>()
+                        * 0.4
+                }
+            }
+        };
+        let mut wave = Wave64::new(0, sample_rate);
+        for channel in 0..2 {
+            wave.push_channel(&(0..frames).map(|i| tone(channel, i)).collect::<Vec<_>>());
+        }
+        let ogg = vorbis(&wave);
+        let path = std::env::temp_dir().join("playground_test_vorbis_decodes.ogg");
+        std::fs::write(&path, &ogg).unwrap();
+        let decoded = Wave64::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.sample_rate(), sample_rate);
+        // Decoders that do not cut at the last granule keep the silence
+        // padding the last block.
+        assert!((frames..frames + vorbis::BLOCK).contains(&decoded.len()));
+        for (channel, least) in [(0, 30.0), (1, 20.0)] {
+            let (mut signal, mut noise) = (0.0, 0.0);
+            for i in 0..frames {
+                signal += tone(channel, i) * tone(channel, i);
+                noise += (decoded.at(channel, i) - tone(channel, i)).powi(2);
+            }
+            assert!(amp_db(signal / noise) / 2.0 > least);
+        }
+        assert!(ogg.len() < flac::encode(&wave).len() / 2);
+    }
+}
@@ -0,0 +1,383 @@
+//! Output streams, and where the metronome click goes.
+
+use anyhow::{anyhow, bail};
+use assert_no_alloc::*;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use fundsp::hacker::*;
+use playground::dither::{Dither, Quantizer, DEVICE_STEPS};
+
+/// Most outputs of a backend played, the main mix, the click, the monitor mix and CV.
+pub const MAX_OUTPUTS: usize = 32;
+
+/// Choice of audio host, device and stream configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceOptions {
+    /// Audio host such as `alsa`, `jack` or `asio`, or the platform default.
+    pub host: Option<String>,
+    /// Output device name, or the host's default.
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// Frames per buffer, small for low latency, or the device's default.
+    pub buffer: Option<u32>,
+    /// Dither for devices playing 16 bit samples.
+    pub dither: Dither,
+}
+
+/// The host called `name`, or the default one.
+pub fn host(name: Option<&str>) -> Result<cpal::Host, anyhow::Error> {
+    let Some(name) = name else {
+        return Ok(cpal::default_host());
+    };
+    let hosts = cpal::available_hosts();
+    match hosts.iter().find(|id| id.name().eq_ignore_ascii_case(name)) {
+        Some(&id) => Ok(cpal::host_from_id(id)?),
+        None => {
+            let names: Vec<_> = hosts.iter().map(|id| id.name()).collect();
+            let hint = if name.eq_ignore_ascii_case("asio") {
+                " (ASIO needs a Windows build with --features asio)"
+            } else {
+                ""
+            };
+            bail!(
+                "no audio host {}{}, available: {}",
+                name,
+                hint,
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// The output device called `name`, or the default one of `host`.
+pub fn device(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device, anyhow::Error> {
+    match name {
+        None => host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("no default output device on {}", host.id().name())),
+        Some(name) => host
+            .output_devices()?
+            .find(|device| device.name().is_ok_and(|device| device == name))
+            .ok_or_else(|| anyhow!("no output device called {}", name)),
+    }
+}
+
+/// The stream configuration of `device` matching `options`, and its sample format.
+pub fn config(
+    device: &cpal::Device,
+    options: &DeviceOptions,
+) -> Result<(cpal::StreamConfig, cpal::SampleFormat), anyhow::Error> {
+    let supported = match options.sample_rate {
+        None => device.default_output_config()?,
+        Some(rate) => device
+            .supported_output_configs()?
+            .find(|config| {
+                (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&rate)
+            })
+            .ok_or_else(|| anyhow!("the device does not support {} Hz", rate))?
+            .with_sample_rate(cpal::SampleRate(rate)),
+    };
+    let format = supported.sample_format();
+    let mut config: cpal::StreamConfig = supported.into();
+    if let Some(frames) = options.buffer {
+        config.buffer_size = cpal::BufferSize::Fixed(frames);
+    }
+    Ok((config, format))
+}
+
+/// Prints the output devices of `host` with their default configurations.
+pub fn list_devices(host: &cpal::Host) -> Result<(), anyhow::Error> {
+    let default = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+    for device in host.output_devices()? {
+        let name = device.name()?;
+        let marker = if Some(&name) == default.as_ref() {
+            "*"
+        } else {
+            " "
+        };
+        match device.default_output_config() {
+            Ok(config) => println!(
+                "{} {} ({} channels, {} Hz, {})",
+                marker,
+                name,
+                config.channels(),
+                config.sample_rate().0,
+                config.sample_format()
+            ),
+            Err(_) => println!("{} {}", marker, name),
+        }
+    }
+    Ok(())
+}
+
+/// Where the click is heard.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cue {
+    /// Mixed into the main output.
+    Main,
+    /// A cue mix of the main output and the click on this zero based channel
+    /// and the one after it, while the other channels play the main output alone.
+    Channels(usize),
+    /// The click alone on a second output device.
+    Device(String),
+    /// A cue mix of the main output and the click on a second output
+    /// device, while the main device plays the main output alone.
+    Mix(String),
+}
+
+impl Cue {
+    /// The second output device, if there is one.
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            Cue::Device(name) | Cue::Mix(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Starts playing `backend` on `device`. Its outputs are the main stereo mix
+/// followed by the mono click, which is mixed into the `cue_channel` pair, or
+/// into every channel if there is none. With a `monitor` channel pair the
+/// stereo monitor mix follows, which plays there instead of the main mix and
+/// along with the click. Any further outputs go to the `cv` channels alone,
+/// one each.
+pub fn play<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    cue_channel: Option<usize>,
+    monitor: Option<usize>,
+    cv: Vec<usize>,
+    dither: Dither,
+    mut backend: NetBackend64,
+) -> Result<cpal::Stream, anyhow::Error>
+where
+    T: SizedSample + FromSample<f64>,
+{
+    let channels = config.channels as usize;
+    if let Some(channel) = cue_channel {
+        if channel + 1 >= channels {
+            bail!(
+                "cue channels {}-{} need a device with at least {} channels, it has {}",
+                channel + 1,
+                channel + 2,
+                channel + 2,
+                channels
+            );
+        }
+    }
+
+    if let Some(channel) = monitor {
+        if channel + 1 >= channels {
+            bail!(
+                "monitor channels {}-{} need a device with at least {} channels, it has {}",
+                channel + 1,
+                channel + 2,
+                channel + 2,
+                channels
+            );
+        }
+    }
+    if let Some(&channel) = cv.iter().find(|&&channel| channel >= channels) {
+        bail!(
+            "CV on channel {} needs a device with at least {} channels, it has {}",
+            channel + 1,
+            channel + 1,
+            channels
+        );
+    }
+    if 5 + cv.len() > MAX_OUTPUTS {
+        bail!("at most {} CV channels", MAX_OUTPUTS - 5);
+    }
+
+    let mut next_frame = move |frame: &mut [f64]| assert_no_alloc(|| backend.tick(&[], frame));
+    // The integer formats played are all 16 bit.
+    let dither = if T::FORMAT.is_float() {
+        Dither::Off
+    } else {
+        dither
+    };
+    let mut quantizer = Quantizer::new(dither, DEVICE_STEPS, channels);
+
+    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            write_data(
+                data,
+                channels,
+                cue_channel,
+                monitor,
+                &cv,
+                &mut quantizer,
+                &mut next_frame,
+            )
+        },
+        err_fn,
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
+
+/// Opens the output device called `name` on `host` and starts playing the stereo `unit` on it,
+/// at `sample_rate` or the device's own and with the buffer size of `options`.
+pub fn play_device(
+    host: &cpal::Host,
+    name: &str,
+    options: &DeviceOptions,
+    sample_rate: Option<u32>,
+    mut unit: Box<dyn AudioUnit64>,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let device = device(host, Some(name))?;
+    let options = DeviceOptions {
+        buffer: options.buffer,
+        sample_rate,
+        dither: options.dither,
+        ..DeviceOptions::default()
+    };
+    let (config, format) = config(&device, &options)?;
+    unit.set_sample_rate(config.sample_rate.0 as f64);
+
+    let mut net = Net64::new(0, 3);
+    let id = net.push(unit);
+    net.connect_output(id, 0, 0);
+    net.connect_output(id, 1, 1);
+    let backend = net.backend();
+
+    match format {
+        cpal::SampleFormat::F32 => play::<f32>(
+            &device,
+            &config,
+            None,
+            None,
+            vec![],
+            options.dither,
+            backend,
+        ),
+        cpal::SampleFormat::I16 => play::<i16>(
+            &device,
+            &config,
+            None,
+            None,
+            vec![],
+            options.dither,
+            backend,
+        ),
+        cpal::SampleFormat::U16 => play::<u16>(
+            &device,
+            &config,
+            None,
+            None,
+            vec![],
+            options.dither,
+            backend,
+        ),
+        format => bail!("unsupported sample format on {}: {}", name, format),
+    }
+}
+
+fn write_data<T>(
+    output: &mut [T],
+    channels: usize,
+    cue_channel: Option<usize>,
+    monitor: Option<usize>,
+    cv: &[usize],
+    quantizer: &mut Quantizer,
+    next_frame: &mut dyn FnMut(&mut [f64]),
+) where
+    T: SizedSample + FromSample<f64>,
+{
+    let cv_start = if monitor.is_some() { 5 } else { 3 };
+    let mut outputs = [0.0; MAX_OUTPUTS];
+    let outputs = &mut outputs[..cv_start + cv.len()];
+    for frame in output.chunks_mut(channels) {
+        next_frame(outputs);
+        let [left, right, click] = [outputs[0], outputs[1], outputs[2]];
+
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            // CV takes its channels from the monitor mix, which takes them from the main mix.
+            let value = if let Some(i) = cv.iter().position(|&cv| cv == channel) {
+                outputs[cv_start + i]
+            } else if let Some(side) = monitor
+                .map(|monitor| channel.wrapping_sub(monitor))
+                .filter(|&side| side < 2)
+            {
+                outputs[3 + side] + click
+            } else {
+                let (side, cue) = match cue_channel {
+                    None => (channel & 1, true),
+                    Some(cue) if channel == cue || channel == cue + 1 => (channel - cue, true),
+                    Some(_) => (channel & 1, false),
+                };
+                let main = if side == 0 { left } else { right };
+                if cue {
+                    main + click
+                } else {
+                    main
+                }
+            };
+            *sample = T::from_sample(quantizer.quantize(channel, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn off() -> Quantizer {
+        Quantizer::new(Dither::Off, DEVICE_STEPS, 5)
+    }
+
+    #[test]
+    fn test_click_goes_to_cue_channels_only() {
+        let mut output = [0.0f32; 5];
+        let mut frame = |frame: &mut [f64]| frame.copy_from_slice(&[1.0, 2.0, 0.5]);
+        write_data(&mut output, 5, Some(2), None, &[], &mut off(), &mut frame);
+        assert_eq!(output, [1.0, 2.0, 1.5, 2.5, 1.0]);
+        write_data(&mut output, 5, None, None, &[], &mut off(), &mut frame);
+        assert_eq!(output, [1.5, 2.5, 1.5, 2.5, 1.5]);
+    }
+
+    #[test]
+    fn test_cv_replaces_main_mix() {
+        let mut output = [0.0f32; 5];
+        let mut frame = |frame: &mut [f64]| frame.copy_from_slice(&[1.0, 2.0, 0.0, 0.1, 0.5]);
+        write_data(&mut output, 5, None, None, &[3, 4], &mut off(), &mut frame);
+        assert_eq!(output, [1.0, 2.0, 1.0, 0.1, 0.5]);
+    }
+
+    #[test]
+    fn test_monitor_mix_with_click() {
+        let mut output = [0.0f32; 5];
+        let mut frame = |frame: &mut [f64]| frame.copy_from_slice(&[1.0, 2.0, 0.5, 0.25, 0.0, 0.1]);
+        write_data(
+            &mut output,
+            5,
+            Some(0),
+            Some(2),
+            &[4],
+            &mut off(),
+            &mut frame,
+        );
+        assert_eq!(output, [1.5, 2.5, 0.75, 0.5, 0.1]);
+    }
+
+    #[test]
+    fn test_16_bit_output_is_dithered() {
+        let mut output = [0i16; 2];
+        let mut quantizer = Quantizer::new(Dither::Tpdf, DEVICE_STEPS, 2);
+        let mut frame = |frame: &mut [f64]| frame.copy_from_slice(&[0.5, 0.25 / DEVICE_STEPS, 0.0]);
+        let mut sum = 0.0;
+        for _ in 0..10_000 {
+            write_data(&mut output, 2, None, None, &[], &mut quantizer, &mut frame);
+            assert!((output[0] as f64 - 0.5 * DEVICE_STEPS).abs() <= 1.0);
+            sum += output[1] as f64;
+        }
+        // A quarter step survives the cut on average.
+        assert!((sum / 10_000.0 - 0.25).abs() < 0.05);
+    }
+}
@@ -0,0 +1,211 @@
+//! Parameters that can be changed while the song plays, each a shared value
+//! that the audio thread follows smoothly so that changing it doesn't click.
+
+use anyhow::bail;
+use fundsp::hacker::*;
+
+/// How a parameter is spread over a knob or fader going from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Taper {
+    Linear,
+    /// Evenly in ratios, such as octaves of a frequency. Both ends have to be
+    /// above 0.
+    Exponential,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spec {
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+    pub taper: Taper,
+    /// Response time in seconds of the smoothed value.
+    pub smoothing: f64,
+    /// Positions on the knob that randomizing picks from, only part of the
+    /// range for parameters that would silence the sound or blow it up
+    /// elsewhere.
+    pub random: (f64, f64),
+}
+
+impl Spec {
+    /// Response time unless given otherwise, short enough to feel immediate.
+    pub const SMOOTHING: f64 = 0.01;
+
+    pub const fn linear(min: f64, max: f64, default: f64) -> Self {
+        Self {
+            min,
+            max,
+            default,
+            taper: Taper::Linear,
+            smoothing: Self::SMOOTHING,
+            random: (0.0, 1.0),
+        }
+    }
+
+    /// Position of `value` on a knob going from 0 to 1.
+    pub fn normalize(&self, value: f64) -> f64 {
+        let Spec { min, max, .. } = *self;
+        match self.taper {
+            Taper::Linear => (value - min) / (max - min),
+            Taper::Exponential => (value / min).ln() / (max / min).ln(),
+        }
+    }
+
+    /// Value at `position` on a knob going from 0 to 1.
+    pub fn denormalize(&self, position: f64) -> f64 {
+        let Spec { min, max, .. } = *self;
+        let position = position.clamp(0.0, 1.0);
+        match self.taper {
+            Taper::Linear => lerp(min, max, position),
+            Taper::Exponential => min * (max / min).powf(position),
+        }
+    }
+}
+
+/// A parameter shared between the controls setting it and the units
+/// following it.
+#[derive(Clone)]
+pub struct Param {
+    spec: Spec,
+    value: Shared<f64>,
+}
+
+impl Param {
+    pub fn new(spec: Spec) -> Self {
+        Self {
+            spec,
+            value: shared(spec.default),
+        }
+    }
+
+    pub fn spec(&self) -> Spec {
+        self.spec
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value.value()
+    }
+
+    /// Sets the value, kept within the range of the parameter.
+    pub fn set(&self, value: f64) {
+        self.value
+            .set_value(value.clamp(self.spec.min, self.spec.max));
+    }
+
+    /// Position of the value on a knob going from 0 to 1.
+    pub fn normalized(&self) -> f64 {
+        self.spec.normalize(self.value())
+    }
+
+    /// Sets the value from the position of a knob going from 0 to 1.
+    pub fn set_normalized(&self, position: f64) {
+        self.set(self.spec.denormalize(position));
+    }
+
+    /// The smoothed value as a signal.
+    pub fn unit(&self) -> An<impl AudioNode<Sample = f64, Inputs = U0, Outputs = U1>> {
+        var(&self.value) >> follow(self.spec.smoothing)
+    }
+}
+
+/// The parameters of the song that can be set by name.
+#[derive(Default)]
+pub struct ParamRegistry {
+    params: Vec<(String, Param)>,
+}
+
+impl ParamRegistry {
+    /// Adds a parameter named `name`, or finds the one already added.
+    pub fn register(&mut self, name: &str, spec: Spec) -> Param {
+        if let Some(param) = self.get(name) {
+            return param.clone();
+        }
+        let param = Param::new(spec);
+        self.params.push((name.to_string(), param.clone()));
+        param
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Param> {
+        self.params
+            .iter()
+            .find(|(found, _)| found == name)
+            .map(|(_, param)| param)
+    }
+
+    /// The parameter named `name`, or an error listing the names there are.
+    pub fn find(&self, name: &str) -> Result<&Param, anyhow::Error> {
+        match self.get(name) {
+            Some(param) => Ok(param),
+            None => bail!(
+                "unknown parameter {}, there are {}",
+                name,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    /// Sets the parameter named `name`, which `value` has to be in range for.
+    pub fn set(&self, name: &str, value: f64) -> Result<(), anyhow::Error> {
+        let param = self.find(name)?;
+        let Spec { min, max, .. } = param.spec();
+        if !(min..=max).contains(&value) {
+            bail!("{} goes from {} to {}", name, min, max);
+        }
+        param.set(value);
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.params.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Param)> {
+        self.params
+            .iter()
+            .map(|(name, param)| (name.as_str(), param))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tapers() {
+        let param = Param::new(Spec::linear(-1.0, 1.0, 0.0));
+        assert_eq!(param.normalized(), 0.5);
+        param.set(3.0);
+        assert_eq!(param.value(), 1.0);
+        let cutoff = Param::new(Spec {
+            taper: Taper::Exponential,
+            ..Spec::linear(100.0, 1600.0, 400.0)
+        });
+        assert!((cutoff.normalized() - 0.5).abs() < 1e-12);
+        cutoff.set_normalized(0.75);
+        assert!((cutoff.value() - 800.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_registry_smooths_and_checks_range() {
+        let mut params = ParamRegistry::default();
+        let width = params.register("width", Spec::linear(0.0, 2.0, 1.0));
+        params.register("filter", Spec::linear(-1.0, 1.0, 0.0));
+        assert_eq!(
+            params
+                .register("width", Spec::linear(0.0, 9.0, 0.0))
+                .spec()
+                .max,
+            2.0
+        );
+        assert_eq!(params.names(), ["width", "filter"]);
+        assert!(params.set("width", 3.0).is_err());
+        assert!(params.set("depth", 1.0).is_err());
+
+        let mut unit = width.unit();
+        unit.set_sample_rate(1000.0);
+        assert!((unit.get_mono() - 1.0).abs() < 1e-9);
+        params.set("width", 0.0).unwrap();
+        let first = unit.get_mono();
+        assert!(first > 0.0 && first < 1.0);
+    }
+}
@@ -0,0 +1,137 @@
+//! Reusable phrases of notes.
+
+use crate::note::{Accord, Note};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    /// Start relative to the beginning of the pattern, in beats.
+    pub beat: f64,
+    pub duration: f64,
+    pub note: Note,
+    /// Number of times the note is retriggered within its duration.
+    pub ratchet: u32,
+}
+
+impl Step {
+    /// Start offsets and durations of the hits of a ratcheted step, in beats.
+    pub fn hits(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let duration = self.duration / self.ratchet as f64;
+        (0..self.ratchet).map(move |hit| (self.beat + hit as f64 * duration, duration))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    /// Length in beats. Steps may ring past it.
+    pub length: f64,
+    pub steps: Vec<Step>,
+}
+
+impl Pattern {
+    /// A pattern of notes played one after another, each with a duration in beats.
+    pub fn melody(notes: &[(Note, f64)]) -> Self {
+        let mut steps = Vec::with_capacity(notes.len());
+        let mut beat = 0.0;
+        for &(note, duration) in notes {
+            steps.push(Step {
+                beat,
+                duration,
+                note,
+                ratchet: 1,
+            });
+            beat += duration;
+        }
+        Self {
+            length: beat,
+            steps,
+        }
+    }
+
+    /// A pattern holding all notes of `accord` for `length` beats.
+    pub fn accord(accord: &Accord, length: f64) -> Self {
+        Self {
+            length,
+            steps: accord
+                .notes
+                .iter()
+                .map(|&note| Step {
+                    beat: 0.0,
+                    duration: length,
+                    note,
+                    ratchet: 1,
+                })
+                .collect(),
+        }
+    }
+
+    /// A pattern of accords played one after another, each with a duration in
+    /// beats. Every accord after the first is voiced to lead on from the one
+    /// before it.
+    pub fn progression(accords: &[(Accord, f64)]) -> Self {
+        let mut steps = vec![];
+        let mut beat = 0.0;
+        let mut previous: Option<Accord> = None;
+        for (accord, duration) in accords {
+            let voiced = match &previous {
+                Some(previous) => previous.lead_to(accord),
+                None => accord.clone(),
+            };
+            steps.extend(voiced.notes.iter().map(|&note| Step {
+                beat,
+                duration: *duration,
+                note,
+                ratchet: 1,
+            }));
+            beat += duration;
+            previous = Some(voiced);
+        }
+        Self {
+            length: beat,
+            steps,
+        }
+    }
+
+    /// Retriggers step `index` `count` times within its duration, for rolls and fills.
+    pub fn ratchet(mut self, index: usize, count: u32) -> Self {
+        self.steps[index].ratchet = count.max(1);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::BaseNote::{self, *};
+
+    #[test]
+    fn test_melody_places_notes_back_to_back() {
+        let pattern = Pattern::melody(&[(Note::base(C), 1.0), (Note::base(D), 2.0)]);
+        assert_eq!(pattern.length, 3.0);
+        assert_eq!(pattern.steps[1].beat, 1.0);
+        assert_eq!(pattern.steps[1].duration, 2.0);
+    }
+
+    #[test]
+    fn test_progression_leads_voices() {
+        let accord = |notes: &[BaseNote]| Accord {
+            notes: notes.iter().map(|&note| Note::base(note)).collect(),
+        };
+        let pattern = Pattern::progression(&[(accord(&[C, E, G]), 2.0), (accord(&[F, A, C]), 2.0)]);
+        assert_eq!(pattern.length, 4.0);
+        // C E G to C F A, holding the C.
+        let second: Vec<i32> = pattern.steps[3..]
+            .iter()
+            .map(|step| step.note.midi())
+            .collect();
+        assert_eq!(second, [60, 65, 69]);
+        assert_eq!(pattern.steps[3].beat, 2.0);
+    }
+
+    #[test]
+    fn test_ratchet_splits_step() {
+        let pattern = Pattern::melody(&[(Note::base(C), 1.0), (Note::base(D), 2.0)]).ratchet(1, 4);
+        let hits: Vec<_> = pattern.steps[1].hits().collect();
+        assert_eq!(hits, [(1.0, 0.5), (1.5, 0.5), (2.0, 0.5), (2.5, 0.5)]);
+        assert_eq!(pattern.steps[0].hits().count(), 1);
+    }
+}
@@ -0,0 +1,143 @@
+//! Jamming with another instance over UDP: the master output is sent to the
+//! partner as it is played, and what the partner sends is mixed into it.
+//! Both have to run at the same sample rate.
+
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+
+use crate::control::Command;
+use playground::bridge::Bridge;
+use playground::jam::{Packet, Sequence, FRAMES};
+
+/// Time between reads of the output handed over, a fraction of a packet.
+const POLL: Duration = Duration::from_millis(1);
+/// Largest packet received, more than the largest sent.
+const MAX_PACKET: usize = 2048;
+
+pub struct Peer {
+    socket: UdpSocket,
+    partner: SocketAddr,
+}
+
+impl Peer {
+    /// Tells the partner that `bar` of the song starts at `bpm`.
+    pub fn sync(&self, bar: usize, bpm: f64) {
+        let packet = Packet::Sync {
+            bar: bar as u32,
+            bpm,
+        };
+        // A sync lost on the way is made up for by the next one.
+        let _ = self.socket.send_to(&packet.encode(), self.partner);
+    }
+}
+
+/// Listens on `address` for the partner at `partner`, and on background
+/// threads sends it the output handed over by `sent`, hands over the output
+/// it sends to `received` and forwards the bars it starts.
+pub fn spawn(
+    address: &str,
+    partner: &str,
+    sent: Bridge,
+    received: Bridge,
+    commands: Sender<Command>,
+) -> Result<Peer, anyhow::Error> {
+    let socket = UdpSocket::bind(address)?;
+    let partner = std::net::ToSocketAddrs::to_socket_addrs(partner)?
+        .next()
+        .ok_or_else(|| anyhow!("no address for jam partner {}", partner))?;
+    eprintln!("jamming with {} from {}", partner, socket.local_addr()?);
+
+    let sender = socket.try_clone()?;
+    std::thread::spawn(move || {
+        let mut frames = vec![];
+        let mut sequence = 0u32;
+        loop {
+            std::thread::sleep(POLL);
+            sent.take(&mut frames);
+            let whole = frames.len() / FRAMES * FRAMES;
+            for chunk in frames.drain(..whole).as_slice().chunks(FRAMES) {
+                let packet = Packet::Audio {
+                    sequence,
+                    frames: chunk.to_vec(),
+                };
+                // Nobody may be listening yet, which is no reason to stop.
+                let _ = sender.send_to(&packet.encode(), partner);
+                sequence = sequence.wrapping_add(1);
+            }
+        }
+    });
+
+    let receiver = socket.try_clone()?;
+    std::thread::spawn(move || {
+        let mut sequence = Sequence::default();
+        let mut buffer = [0; MAX_PACKET];
+        let silence = [(0.0, 0.0); FRAMES];
+        while let Ok((count, from)) = receiver.recv_from(&mut buffer) {
+            if from.ip() != partner.ip() {
+                continue;
+            }
+            match Packet::decode(&buffer[..count]) {
+                Some(Packet::Audio {
+                    sequence: number,
+                    frames,
+                }) => {
+                    let Some(lost) = sequence.accept(number) else {
+                        continue;
+                    };
+                    // Lost packets are left silent, to stay in time.
+                    for _ in 0..lost {
+                        received.put(&silence);
+                    }
+                    received.put(&frames);
+                }
+                Some(Packet::Sync { bar, bpm }) => {
+                    let command = Command::Partner(bar as usize, bpm, Instant::now());
+                    if commands.send(command).is_err() {
+                        return;
+                    }
+                }
+                None => {}
+            }
+        }
+    });
+
+    Ok(Peer { socket, partner })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sends_output_and_forwards_bars() {
+        let partner = UdpSocket::bind("127.0.0.1:0").unwrap();
+        partner
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (sent, received) = (Bridge::new(1000.0), Bridge::new(1000.0));
+        let (commands, forwarded) = std::sync::mpsc::channel();
+        let address = partner.local_addr().unwrap().to_string();
+        let peer = spawn("127.0.0.1:0", &address, sent.clone(), received, commands).unwrap();
+
+        let mut input = sent.input();
+        for i in 0..FRAMES {
+            input.filter_stereo(i as f64, 0.0);
+        }
+        let mut buffer = [0; MAX_PACKET];
+        let (count, _) = partner.recv_from(&mut buffer).unwrap();
+        let Some(Packet::Audio { sequence, frames }) = Packet::decode(&buffer[..count]) else {
+            panic!("no audio packet");
+        };
+        assert_eq!((sequence, frames.len(), frames[5]), (0, FRAMES, (5.0, 0.0)));
+
+        let sync = Packet::Sync { bar: 3, bpm: 100.0 }.encode();
+        partner
+            .send_to(&sync, peer.socket.local_addr().unwrap())
+            .unwrap();
+        let command = forwarded.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(command, Command::Partner(3, 100.0, _)));
+    }
+}
@@ -0,0 +1,305 @@
+//! Pitch detection of monophonic audio with YIN, its difference function
+//! computed with FFTs so that it can keep up with the audio input, and the
+//! notes played in it tracked from their onsets and pitches.
+
+use std::sync::Arc;
+
+use fundsp::hacker::*;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+use crate::tuning::Tuning;
+
+/// Samples looked at for a pitch, in which periods up to half as long are
+/// found, down to 47 Hz at 48 kHz.
+pub const WINDOW: usize = 2048;
+/// Most of the aperiodic part of the signal for a pitch to be found.
+const THRESHOLD: f64 = 0.15;
+/// Quietest level in which pitches are found, as a mean square.
+const FLOOR: f64 = 1e-6;
+
+/// Samples between detections while tracking notes.
+const HOP: usize = 256;
+/// Level in decibels of the input above which notes start, and below which
+/// they end.
+const ON_DB: f64 = -40.0;
+const OFF_DB: f64 = -50.0;
+/// Level of the loudest velocity, and the range of levels it spans.
+const FULL_VELOCITY_DB: f64 = -6.0;
+const VELOCITY_RANGE_DB: f64 = 40.0;
+/// Detections in a row that must agree for a note to start or change, so
+/// that the attack of a note doesn't start a wrong one.
+const STABLE: usize = 2;
+/// Detections in a row without a pitch after which a note ends.
+const MISSES: usize = 4;
+
+/// A note starting or ending in the input, on the MIDI key of the tuning
+/// nearest to its pitch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    On { key: u8, velocity: u8 },
+    Off { key: u8 },
+}
+
+/// Tracks the notes played one at a time on the input.
+pub struct Tracker {
+    yin: Yin,
+    tuning: Tuning,
+    sample_rate: f64,
+    /// The latest `WINDOW` samples, the last hop of them filled so far.
+    window: Vec<f64>,
+    /// Samples of the last hop filled.
+    fill: usize,
+    /// The key held, if a note is playing.
+    held: Option<u8>,
+    /// The key detected lately and how many times in a row.
+    candidate: Option<(u8, usize)>,
+    misses: usize,
+}
+
+impl Tracker {
+    pub fn new(tuning: Tuning, sample_rate: f64) -> Self {
+        Self {
+            yin: Yin::default(),
+            tuning,
+            sample_rate,
+            window: vec![0.0; WINDOW],
+            fill: 0,
+            held: None,
+            candidate: None,
+            misses: 0,
+        }
+    }
+
+    /// Follows the input on with `samples`, adding the notes that start or
+    /// end to `events`.
+    pub fn push(&mut self, samples: &[f64], events: &mut Vec<Event>) {
+        for &x in samples {
+            self.window[WINDOW - HOP + self.fill] = x;
+            self.fill += 1;
+            if self.fill == HOP {
+                self.fill = 0;
+                self.detect(events);
+                self.window.copy_within(HOP.., 0);
+            }
+        }
+    }
+
+    fn detect(&mut self, events: &mut Vec<Event>) {
+        let hop = &self.window[WINDOW - HOP..];
+        let level = amp_db((hop.iter().map(|x| x * x).sum::<f64>() / HOP as f64).sqrt());
+        let threshold = if self.held.is_some() { OFF_DB } else { ON_DB };
+        let key = (level > threshold)
+            .then(|| self.yin.detect(&self.window, self.sample_rate))
+            .flatten()
+            .and_then(|frequency| self.tuning.nearest_key(frequency));
+        let Some(key) = key else {
+            self.candidate = None;
+            self.misses += 1;
+            if level <= OFF_DB || self.misses >= MISSES {
+                if let Some(key) = self.held.take() {
+                    events.push(Event::Off { key });
+                }
+            }
+            return;
+        };
+        self.misses = 0;
+        let count = match self.candidate {
+            Some((candidate, count)) if candidate == key => count + 1,
+            _ => 1,
+        };
+        self.candidate = Some((key, count));
+        if count < STABLE || self.held == Some(key) {
+            return;
+        }
+        if let Some(held) = self.held.take() {
+            events.push(Event::Off { key: held });
+        }
+        let velocity = (level - FULL_VELOCITY_DB) / VELOCITY_RANGE_DB + 1.0;
+        events.push(Event::On {
+            key,
+            velocity: (velocity.clamp(0.0, 1.0) * 126.0).round() as u8 + 1,
+        });
+        self.held = Some(key);
+    }
+}
+
+/// The buffers of the detection, allocated once.
+#[derive(Clone)]
+pub struct Yin {
+    forward: Arc<dyn RealToComplex<f64>>,
+    inverse: Arc<dyn ComplexToReal<f64>>,
+    /// What is transformed, zero padded to twice the window, and at last
+    /// the correlation.
+    frame: Vec<f64>,
+    spectrum: Vec<Complex<f64>>,
+    window_spectrum: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+    difference: Vec<f64>,
+}
+
+impl Default for Yin {
+    fn default() -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(2 * WINDOW);
+        let inverse = planner.plan_fft_inverse(2 * WINDOW);
+        let scratch = max(forward.get_scratch_len(), inverse.get_scratch_len());
+        Self {
+            frame: vec![0.0; 2 * WINDOW],
+            spectrum: forward.make_output_vec(),
+            window_spectrum: forward.make_output_vec(),
+            scratch: vec![Complex::default(); scratch],
+            forward,
+            inverse,
+            difference: vec![0.0; WINDOW / 2],
+        }
+    }
+}
+
+impl Yin {
+    /// The frequency of the `WINDOW` latest `samples` at `sample_rate`, if
+    /// they have one.
+    pub fn detect(&mut self, samples: &[f64], sample_rate: f64) -> Option<f64> {
+        assert_eq!(samples.len(), WINDOW);
+        let half = WINDOW / 2;
+        if samples[..half].iter().map(|x| x * x).sum::<f64>() / (half as f64) < FLOOR {
+            return None;
+        }
+
+        // The correlation of the first half with the whole window at every
+        // lag, from the product of their spectra.
+        let (forward, scratch) = (&self.forward, &mut self.scratch);
+        self.frame.fill(0.0);
+        self.frame[..half].copy_from_slice(&samples[..half]);
+        forward
+            .process_with_scratch(&mut self.frame, &mut self.spectrum, scratch)
+            .unwrap();
+        self.frame.fill(0.0);
+        self.frame[..WINDOW].copy_from_slice(samples);
+        forward
+            .process_with_scratch(&mut self.frame, &mut self.window_spectrum, scratch)
+            .unwrap();
+        for (bin, window) in self.spectrum.iter_mut().zip(&self.window_spectrum) {
+            *bin = bin.conj() * window;
+        }
+        self.inverse
+            .process_with_scratch(&mut self.spectrum, &mut self.frame, scratch)
+            .unwrap();
+        // Scaled as the inverse transform leaves it, by its length.
+        let correlation = |lag: usize| self.frame[lag] / (2 * WINDOW) as f64;
+
+        // The squared difference of the first half and the window shifted by
+        // each lag, from the energies of both and their correlation.
+        let energy = |from: usize| {
+            samples[from..from + half]
+                .iter()
+                .map(|x| x * x)
+                .sum::<f64>()
+        };
+        let first = energy(0);
+        let mut shifted = first;
+        self.difference[0] = 0.0;
+        for lag in 1..half {
+            shifted += samples[lag + half - 1].powi(2) - samples[lag - 1].powi(2);
+            self.difference[lag] = first + shifted - 2.0 * correlation(lag);
+        }
+
+        // Normalized by the running mean, the first dip below the threshold
+        // is the period.
+        let mut sum = 0.0;
+        for lag in 1..half {
+            sum += self.difference[lag];
+            self.difference[lag] *= lag as f64 / sum.max(f64::MIN_POSITIVE);
+        }
+        let difference = &self.difference;
+        let mut lag = (2..half - 1).find(|&lag| difference[lag] < THRESHOLD)?;
+        while lag + 2 < half && difference[lag + 1] < difference[lag] {
+            lag += 1;
+        }
+        // Between samples, by a parabola through the bottom and its neighbors.
+        let (a, b, c) = (
+            self.difference[lag - 1],
+            self.difference[lag],
+            self.difference[lag + 1],
+        );
+        let curve = a - 2.0 * b + c;
+        let offset = if curve > 0.0 {
+            (a - c) / (2.0 * curve)
+        } else {
+            0.0
+        };
+        Some(sample_rate / (lag as f64 + offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f64, sample_rate: f64) -> Vec<f64> {
+        (0..WINDOW)
+            .map(|i| {
+                let t = i as f64 / sample_rate;
+                // A few harmonics, like a plucked string.
+                0.5 * sin(TAU * frequency * t)
+                    + 0.3 * sin(TAU * 2.0 * frequency * t)
+                    + 0.2 * sin(TAU * 3.0 * frequency * t)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_pitches() {
+        let mut yin = Yin::default();
+        for frequency in [82.41, 196.0, 440.0, 1318.5] {
+            let detected = yin.detect(&tone(frequency, 48000.0), 48000.0).unwrap();
+            let cents = 1200.0 * (detected / frequency).log2();
+            assert!(cents.abs() < 2.0, "{} for {}", detected, frequency);
+        }
+    }
+
+    #[test]
+    fn test_no_pitch_in_silence_or_noise() {
+        let mut yin = Yin::default();
+        assert_eq!(yin.detect(&[0.0; WINDOW], 48000.0), None);
+        let mut noise = noise();
+        let samples: Vec<f64> = (0..WINDOW).map(|_| noise.get_mono()).collect();
+        assert_eq!(yin.detect(&samples, 48000.0), None);
+    }
+
+    #[test]
+    fn test_tracks_notes() {
+        let sample_rate = 48000.0;
+        let mut tracker = Tracker::new(Tuning::default(), sample_rate);
+        let mut events = vec![];
+        let mut play = |frequency: f64, amplitude: f64, seconds: f64| {
+            let samples: Vec<f64> = (0..(seconds * sample_rate) as usize)
+                .map(|i| amplitude * sin(TAU * frequency * i as f64 / sample_rate))
+                .collect();
+            tracker.push(&samples, &mut events);
+        };
+        play(440.0, 0.5, 0.3);
+        play(440.0, 0.0, 0.1);
+        play(220.0, 0.05, 0.3);
+        play(330.0, 0.05, 0.3);
+        assert_eq!(
+            events,
+            [
+                Event::On {
+                    key: 69,
+                    velocity: 118
+                },
+                Event::Off { key: 69 },
+                Event::On {
+                    key: 57,
+                    velocity: 56
+                },
+                Event::Off { key: 57 },
+                Event::On {
+                    key: 64,
+                    velocity: 56
+                },
+            ]
+        );
+    }
+}
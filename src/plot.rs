@@ -0,0 +1,127 @@
+//! Waveform plots of rendered audio, to look at envelopes and clicks without
+//! listening. Every channel gets its own lane, drawn as the range of the
+//! samples falling into each pixel column.
+
+use image::RgbImage;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+/// Size in pixels of the lane of one channel.
+pub const WIDTH: usize = 1200;
+pub const LANE_HEIGHT: usize = 200;
+
+const WAVE: RGBColor = RGBColor(0x22, 0x44, 0x66);
+const ZERO: RGBColor = RGBColor(0xcc, 0xcc, 0xcc);
+
+/// Lowest and highest sample of each of `WIDTH` columns.
+fn columns(samples: &[f64]) -> Vec<(f64, f64)> {
+    (0..WIDTH)
+        .map(|x| {
+            let start = x * samples.len() / WIDTH;
+            let end = ((x + 1) * samples.len() / WIDTH).max(start + 1);
+            samples[start.min(samples.len())..end.min(samples.len())]
+                .iter()
+                .fold((0.0f64, 0.0f64), |(low, high), &s| {
+                    (low.min(s), high.max(s))
+                })
+        })
+        .collect()
+}
+
+/// Vertical pixel of `value` in a lane, with 1 at its top.
+fn y(value: f64) -> i32 {
+    ((1.0 - value.clamp(-1.0, 1.0)) * 0.5 * (LANE_HEIGHT - 1) as f64).round() as i32
+}
+
+/// Draws `channels` lasting `seconds` on `root`, one lane each. Columns that
+/// reach full scale are drawn in red, and there is a tick every tenth of a second.
+fn draw<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    channels: &[Vec<f64>],
+    seconds: f64,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+    for (lane, samples) in root.split_evenly((channels.len(), 1)).iter().zip(channels) {
+        lane.draw(&PathElement::new(
+            [(0, y(0.0)), (WIDTH as i32, y(0.0))],
+            ZERO,
+        ))?;
+        let columns = columns(samples);
+        let outline: Vec<_> = (0..WIDTH as i32)
+            .map(|x| (x, y(columns[x as usize].1)))
+            .chain(
+                (0..WIDTH as i32)
+                    .rev()
+                    .map(|x| (x, y(columns[x as usize].0))),
+            )
+            .collect();
+        lane.draw(&Polygon::new(outline.clone(), WAVE.filled()))?;
+        lane.draw(&PathElement::new(outline, WAVE))?;
+        for (x, &(low, high)) in columns.iter().enumerate() {
+            if low <= -1.0 || high >= 1.0 {
+                let x = x as i32;
+                lane.draw(&PathElement::new([(x, 0), (x, LANE_HEIGHT as i32)], RED))?;
+            }
+        }
+    }
+    let ticks = (seconds * 10.0).floor() as usize;
+    for tick in 0..=ticks {
+        let x = (tick as f64 / 10.0 / seconds * WIDTH as f64).round() as i32;
+        let length = if tick % 10 == 0 { 12 } else { 5 };
+        root.draw(&PathElement::new([(x, 0), (x, length)], BLACK))?;
+    }
+    root.present()
+}
+
+/// Plots `channels` lasting `seconds` as an SVG image.
+pub fn svg(channels: &[Vec<f64>], seconds: f64) -> Result<String, anyhow::Error> {
+    let mut svg = String::new();
+    let size = (WIDTH as u32, (LANE_HEIGHT * channels.len()) as u32);
+    draw(
+        &SVGBackend::with_string(&mut svg, size).into_drawing_area(),
+        channels,
+        seconds,
+    )?;
+    Ok(svg)
+}
+
+/// Plots `channels` lasting `seconds` as a bitmap image, like the SVG plot.
+pub fn image(channels: &[Vec<f64>], seconds: f64) -> Result<RgbImage, anyhow::Error> {
+    let (width, height) = (WIDTH as u32, (LANE_HEIGHT * channels.len()) as u32);
+    let mut pixels = vec![0; WIDTH * LANE_HEIGHT * channels.len() * 3];
+    draw(
+        &BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area(),
+        channels,
+        seconds,
+    )?;
+    Ok(RgbImage::from_raw(width, height, pixels).expect("buffer fits the plot"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_cover_samples() {
+        let samples: Vec<f64> = (0..WIDTH * 2)
+            .map(|i| i as f64 / (WIDTH * 2) as f64)
+            .collect();
+        let columns = columns(&samples);
+        assert_eq!(columns.len(), WIDTH);
+        assert_eq!(columns[WIDTH - 1].1, samples[WIDTH * 2 - 1]);
+        // Fewer samples than columns repeat the last ones.
+        assert_eq!(super::columns(&[0.5])[WIDTH - 1], (0.0, 0.5));
+    }
+
+    #[test]
+    fn test_clipping_is_marked() {
+        let channels = vec![vec![0.0, 1.5, 0.0], vec![0.0; 3]];
+        let svg = svg(&channels, 0.25).unwrap();
+        assert_eq!(svg.matches("stroke=\"#FF0000\"").count(), WIDTH / 3);
+        assert!(svg.contains(r#"height="400""#));
+        let image = image(&channels, 0.25).unwrap();
+        assert_eq!(image.dimensions(), (WIDTH as u32, 400));
+        assert_eq!(image[(WIDTH as u32 / 2, 10)], image::Rgb([255, 0, 0]));
+        assert_eq!(image[(WIDTH as u32 / 2, 210)], image::Rgb([255, 255, 255]));
+    }
+}
@@ -0,0 +1,342 @@
+//! Presets, the values of the parameters set by name at one time, to go
+//! back to and to compare a version of a sound against another. Going from
+//! one preset to another, each parameter glides to its new value over its
+//! smoothing, so that the sound crossfades without a click. New presets can
+//! be found by randomizing the parameters that aren't locked, and two saved
+//! ones morphed between by a knob.
+
+use anyhow::{anyhow, bail};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::json::Value;
+use crate::param::ParamRegistry;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preset {
+    pub params: Vec<(String, f64)>,
+}
+
+impl Preset {
+    /// The values that the parameters of `params` have now.
+    pub fn capture(params: &ParamRegistry) -> Self {
+        Self {
+            params: params
+                .iter()
+                .map(|(name, param)| (name.to_string(), param.value()))
+                .collect(),
+        }
+    }
+
+    /// Reads a preset, which may also be a saved project of which it reads
+    /// the parameters.
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("cannot read {}: {}", path, err))?;
+        let value =
+            Value::parse(&text).map_err(|err| anyhow!("invalid preset {}: {}", path, err))?;
+        match value.get("params") {
+            Some(params) => Self::from_json(params),
+            None => bail!("invalid preset {}: missing params", path),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), anyhow::Error> {
+        let value = Value::Object(vec![("params".to_string(), self.to_json())]);
+        std::fs::write(path, value.to_string() + "\n")
+            .map_err(|err| anyhow!("cannot write {}: {}", path, err))
+    }
+
+    /// The values as an object of numbers by name.
+    pub fn to_json(&self) -> Value {
+        Value::Object(
+            self.params
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::Number(*value)))
+                .collect(),
+        )
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, anyhow::Error> {
+        let Value::Object(members) = value else {
+            bail!("params is not an object");
+        };
+        let params = members
+            .iter()
+            .map(|(name, value)| match value.as_f64() {
+                Some(value) => Ok((name.clone(), value)),
+                None => bail!("parameter {} is not a number", name),
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(Self { params })
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.params
+            .iter()
+            .find(|(found, _)| found == name)
+            .map(|&(_, value)| value)
+    }
+
+    /// Sets the parameters of `params` to their values, leaving those that
+    /// the preset has no value for.
+    pub fn apply(&self, params: &ParamRegistry) {
+        for (name, value) in &self.params {
+            if let Some(param) = params.get(name) {
+                param.set(*value);
+            }
+        }
+    }
+}
+
+/// One of the two versions compared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "a" | "A" => Ok(Side::A),
+            "b" | "B" => Ok(Side::B),
+            _ => bail!("compare a or b, not {}", value),
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+/// Two versions of the parameters, one of them playing and tweaked.
+pub struct Comparison {
+    a: Preset,
+    b: Preset,
+    playing: Side,
+}
+
+impl Comparison {
+    /// Keeps the parameters as they are as A and plays B, the same until it
+    /// is tweaked.
+    pub fn new(params: &ParamRegistry) -> Self {
+        let preset = Preset::capture(params);
+        Self {
+            a: preset.clone(),
+            b: preset,
+            playing: Side::B,
+        }
+    }
+
+    pub fn playing(&self) -> Side {
+        self.playing
+    }
+
+    /// Keeps the tweaks of the side playing and plays the other one,
+    /// returning which.
+    pub fn toggle(&mut self, params: &ParamRegistry) -> Side {
+        self.store(params);
+        self.playing = self.playing.other();
+        self.preset(self.playing).apply(params);
+        self.playing
+    }
+
+    /// Plays `side`, as tweaked so far, ending the comparison.
+    pub fn keep(mut self, params: &ParamRegistry, side: Side) {
+        self.store(params);
+        self.preset(side).apply(params);
+    }
+
+    fn store(&mut self, params: &ParamRegistry) {
+        let preset = Preset::capture(params);
+        match self.playing {
+            Side::A => self.a = preset,
+            Side::B => self.b = preset,
+        }
+    }
+
+    fn preset(&self, side: Side) -> &Preset {
+        match side {
+            Side::A => &self.a,
+            Side::B => &self.b,
+        }
+    }
+}
+
+/// Sets parameters to random values, each within the part of its range
+/// that its spec gives, leaving those locked as they are.
+pub struct Randomizer {
+    rng: StdRng,
+    locked: Vec<String>,
+}
+
+impl Randomizer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            locked: vec![],
+        }
+    }
+
+    pub fn locked(&self) -> &[String] {
+        &self.locked
+    }
+
+    pub fn is_locked(&self, name: &str) -> bool {
+        self.locked.iter().any(|locked| locked == name)
+    }
+
+    /// Locks the parameter named `name` or unlocks it, returning whether it
+    /// is locked now.
+    pub fn toggle_lock(&mut self, name: &str) -> bool {
+        if self.is_locked(name) {
+            self.locked.retain(|locked| locked != name);
+            false
+        } else {
+            self.locked.push(name.to_string());
+            true
+        }
+    }
+
+    /// Randomizes the parameters of `params` whose names start with
+    /// `prefix`, such as `amp_` for those of the amp, returning how many.
+    pub fn randomize(&mut self, params: &ParamRegistry, prefix: &str) -> usize {
+        let mut count = 0;
+        for (name, param) in params.iter() {
+            if name.starts_with(prefix) && !self.is_locked(name) {
+                let (low, high) = param.spec().random;
+                param.set_normalized(self.rng.gen_range(low..=high));
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// Two presets that a knob glides the parameters they share between.
+pub struct Morph {
+    from: Preset,
+    to: Preset,
+}
+
+impl Morph {
+    pub fn new(from: Preset, to: Preset) -> Result<Self, anyhow::Error> {
+        if !from.params.iter().any(|(name, _)| to.get(name).is_some()) {
+            bail!("the presets share no parameters");
+        }
+        Ok(Self { from, to })
+    }
+
+    /// Sets the shared parameters of `params` to where they are at
+    /// `position`, from 0 at the first preset to 1 at the second, evenly
+    /// along their knobs.
+    pub fn apply(&self, params: &ParamRegistry, position: f64) {
+        for (name, from) in &self.from.params {
+            if let (Some(to), Some(param)) = (self.to.get(name), params.get(name)) {
+                let spec = param.spec();
+                let (from, to) = (spec.normalize(*from), spec.normalize(to));
+                param.set_normalized(from + (to - from) * position);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::Spec;
+
+    #[test]
+    fn test_captures_and_applies() {
+        let mut params = ParamRegistry::default();
+        let width = params.register("width", Spec::linear(0.0, 2.0, 1.0));
+        params.register("filter", Spec::linear(-1.0, 1.0, 0.0));
+        params.set("filter", 0.5).unwrap();
+        let preset = Preset::capture(&params);
+        assert_eq!(preset.get("filter"), Some(0.5));
+        width.set(2.0);
+        Preset {
+            params: vec![("width".into(), 0.5), ("gone".into(), 1.0)],
+        }
+        .apply(&params);
+        assert_eq!(width.value(), 0.5);
+        preset.apply(&params);
+        assert_eq!(width.value(), 1.0);
+    }
+
+    #[test]
+    fn test_compares_tweaks() {
+        let mut params = ParamRegistry::default();
+        let width = params.register("width", Spec::linear(0.0, 2.0, 1.0));
+        let mut comparison = Comparison::new(&params);
+        width.set(1.5);
+        assert_eq!(comparison.toggle(&params), Side::A);
+        assert_eq!(width.value(), 1.0);
+        assert_eq!(comparison.toggle(&params), Side::B);
+        assert_eq!(width.value(), 1.5);
+        comparison.toggle(&params);
+        width.set(0.5);
+        comparison.keep(&params, Side::B);
+        assert_eq!(width.value(), 1.5);
+        assert_eq!(Side::parse("a").unwrap(), Side::A);
+        assert!(Side::parse("c").is_err());
+    }
+
+    #[test]
+    fn test_morphs_shared_parameters() {
+        let mut params = ParamRegistry::default();
+        let width = params.register("width", Spec::linear(0.0, 2.0, 1.0));
+        let cutoff = Spec {
+            taper: crate::param::Taper::Exponential,
+            ..Spec::linear(100.0, 1600.0, 400.0)
+        };
+        let cutoff = params.register("cutoff", cutoff);
+        let preset = |values: &[(&str, f64)]| Preset {
+            params: values
+                .iter()
+                .map(|&(name, value)| (name.into(), value))
+                .collect(),
+        };
+        let from = preset(&[("width", 0.0), ("cutoff", 100.0), ("level", 1.0)]);
+        let to = preset(&[("width", 2.0), ("cutoff", 1600.0)]);
+        let morph = Morph::new(from, to).unwrap();
+        morph.apply(&params, 0.5);
+        assert!((width.value() - 1.0).abs() < 1e-9);
+        // Halfway in octaves.
+        assert!((cutoff.value() - 400.0).abs() < 1e-9);
+        morph.apply(&params, 1.0);
+        assert!((width.value() - 2.0).abs() < 1e-9);
+        assert!(Morph::new(preset(&[("width", 0.0)]), preset(&[("level", 1.0)])).is_err());
+        let path = std::env::temp_dir().join("playground-preset.json");
+        let path = path.to_str().unwrap();
+        let saved = Preset::capture(&params);
+        saved.save(path).unwrap();
+        assert_eq!(Preset::load(path).unwrap(), saved);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_randomizes_unlocked_within_range() {
+        let mut params = ParamRegistry::default();
+        let gain = params.register("amp_gain", Spec::linear(0.0, 48.0, 20.0));
+        let level = Spec {
+            random: (0.25, 0.5),
+            ..Spec::linear(0.0, 2.0, 0.5)
+        };
+        let level = params.register("amp_level", level);
+        let width = params.register("width", Spec::linear(0.0, 2.0, 1.0));
+        let mut randomizer = Randomizer::new(7);
+        assert!(randomizer.toggle_lock("amp_gain"));
+        for _ in 0..100 {
+            assert_eq!(randomizer.randomize(&params, "amp_"), 1);
+            assert!((0.5..=1.0).contains(&level.value()));
+        }
+        assert_eq!((gain.value(), width.value()), (20.0, 1.0));
+        assert!(!randomizer.toggle_lock("amp_gain"));
+        assert_eq!(randomizer.randomize(&params, ""), 3);
+    }
+}
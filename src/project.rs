@@ -0,0 +1,729 @@
+//! Project files, bundling a song with the tempo, mixer, parameters and MIDI
+//! mappings it plays with, so that a session sounds the same when opened again.
+
+use std::fmt::Debug;
+
+use anyhow::{anyhow, bail};
+
+use crate::arpeggio::{Arpeggio, Order};
+use crate::arrangement::{Arrangement, Section, Track};
+use crate::groove::Template;
+use crate::instrument::Instrument;
+use crate::json::Value;
+use crate::key::Key;
+use crate::meter::{Meter, TimeSignature};
+use crate::note::{BaseNote, Note};
+use crate::pattern::{Pattern, Step};
+use crate::preset::Preset;
+use crate::structure::{Part, Role, Slot, Structure};
+use crate::strum::{Direction, Strum};
+use crate::tuning::{Keyboard, Tuning};
+use crate::velocity::{Accent, Curve};
+
+/// Version of the files written. Older files are read as they were written,
+/// newer ones are refused rather than read wrong.
+pub const VERSION: u32 = 1;
+
+const INSTRUMENTS: [Instrument; 4] = [
+    Instrument::Pluck,
+    Instrument::Organ,
+    Instrument::Click,
+    Instrument::Drums,
+];
+const ORDERS: [Order; 6] = [
+    Order::Up,
+    Order::Down,
+    Order::Converge,
+    Order::Diverge,
+    Order::ChordBass,
+    Order::RandomWalk,
+];
+const CURVES: [Curve; 4] = [Curve::Linear, Curve::Soft, Curve::Hard, Curve::Fixed];
+const DIRECTIONS: [Direction; 2] = [Direction::Up, Direction::Down];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub title: String,
+    /// Seconds since the Unix epoch that the project was first and last saved.
+    pub created: u64,
+    pub modified: u64,
+    /// Program and version that last saved the project.
+    pub generator: String,
+}
+
+impl Metadata {
+    /// Metadata of a project first saved at `now`.
+    pub fn new(title: &str, now: u64) -> Self {
+        Self {
+            title: title.to_string(),
+            created: now,
+            modified: now,
+            generator: concat!("sound ", env!("CARGO_PKG_VERSION")).to_string(),
+        }
+    }
+
+    /// The same metadata saved again at `now`.
+    pub fn saved(&self, now: u64) -> Self {
+        Self {
+            modified: now,
+            ..Self::new(&self.title, self.created)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Project {
+    pub metadata: Metadata,
+    /// The song with its tuning, key and the presets of its tracks.
+    pub song: Arrangement,
+    pub bpm: f64,
+    /// Mute and solo flags of the tracks.
+    pub muted: Vec<bool>,
+    pub soloed: Vec<bool>,
+    /// Values of the parameters set by name, such as `width`.
+    pub params: Vec<(String, f64)>,
+    /// MIDI controller turning the DJ filter.
+    pub filter_controller: u8,
+    /// Tracks played by external synths, with their zero based MIDI channel
+    /// and the latency in seconds that notes are sent earlier by.
+    pub midi_tracks: Vec<(usize, u8, f64)>,
+}
+
+impl Project {
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        Self::from_json(&Value::parse(text)?)
+    }
+
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("cannot read {}: {}", path, err))?;
+        Self::parse(&text).map_err(|err| anyhow!("invalid project {}: {}", path, err))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), anyhow::Error> {
+        std::fs::write(path, self.to_json().to_string() + "\n")
+            .map_err(|err| anyhow!("cannot write {}: {}", path, err))
+    }
+
+    pub fn to_json(&self) -> Value {
+        let metadata = &self.metadata;
+        object(vec![
+            ("version", Value::Number(VERSION as f64)),
+            (
+                "metadata",
+                object(vec![
+                    ("title", Value::String(metadata.title.clone())),
+                    ("created", Value::Number(metadata.created as f64)),
+                    ("modified", Value::Number(metadata.modified as f64)),
+                    ("generator", Value::String(metadata.generator.clone())),
+                ]),
+            ),
+            ("song", write_song(&self.song)),
+            ("bpm", Value::Number(self.bpm)),
+            ("muted", array(&self.muted, |&flag| Value::Bool(flag))),
+            ("soloed", array(&self.soloed, |&flag| Value::Bool(flag))),
+            (
+                "params",
+                Preset {
+                    params: self.params.clone(),
+                }
+                .to_json(),
+            ),
+            (
+                "midi",
+                object(vec![
+                    (
+                        "filter_controller",
+                        Value::Number(self.filter_controller as f64),
+                    ),
+                    (
+                        "tracks",
+                        array(&self.midi_tracks, |&(track, channel, latency)| {
+                            object(vec![
+                                ("track", Value::Number(track as f64)),
+                                ("channel", Value::Number(channel as f64)),
+                                ("latency", Value::Number(latency)),
+                            ])
+                        }),
+                    ),
+                ]),
+            ),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, anyhow::Error> {
+        match integer::<u32>(value, "version")? {
+            // Version 1 is the first, later ones are upgraded from it here.
+            1 => {}
+            version if version > VERSION => {
+                bail!("version {} is newer than this program reads", version)
+            }
+            version => bail!("unknown version {}", version),
+        }
+        let metadata = field(value, "metadata")?;
+        let metadata = Metadata {
+            title: string(metadata, "title")?.to_string(),
+            created: integer(metadata, "created")?,
+            modified: integer(metadata, "modified")?,
+            generator: string(metadata, "generator")?.to_string(),
+        };
+        let song = read_song(field(value, "song")?)?;
+        let bpm = number(value, "bpm")?;
+        if !(1.0..=999.0).contains(&bpm) {
+            bail!("tempo must be between 1 and 999 bpm");
+        }
+        let flags = |key| -> Result<Vec<bool>, anyhow::Error> {
+            let flags = list(value, key, |flag| {
+                flag.as_bool()
+                    .ok_or_else(|| anyhow!("{} are true or false", key))
+            })?;
+            if flags.len() != song.tracks.len() {
+                bail!("{} flags of {} tracks", flags.len(), song.tracks.len());
+            }
+            Ok(flags)
+        };
+        let (muted, soloed) = (flags("muted")?, flags("soloed")?);
+        let params = Preset::from_json(field(value, "params")?)?.params;
+        let midi = field(value, "midi")?;
+        let midi_tracks = list(midi, "tracks", |route| {
+            let track = integer(route, "track")?;
+            if track >= song.tracks.len() {
+                bail!("no track {} to send MIDI of", track + 1);
+            }
+            match integer::<u8>(route, "channel")? {
+                channel @ 0..=15 => Ok((track, channel, number(route, "latency")?)),
+                _ => bail!("MIDI channels go from 1 to 16"),
+            }
+        })?;
+        Ok(Self {
+            metadata,
+            song,
+            bpm,
+            muted,
+            soloed,
+            params,
+            filter_controller: integer(midi, "filter_controller")?,
+            midi_tracks,
+        })
+    }
+}
+
+fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+fn array<T>(values: &[T], write: impl Fn(&T) -> Value) -> Value {
+    Value::Array(values.iter().map(write).collect())
+}
+
+/// Variants by their names in Rust, which the files spell them in.
+fn name<T: Debug>(variant: T) -> Value {
+    Value::String(format!("{:?}", variant))
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, anyhow::Error> {
+    value.get(key).ok_or_else(|| anyhow!("missing {}", key))
+}
+
+/// Member `key` unless it is missing or null.
+fn optional<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.get(key).filter(|value| **value != Value::Null)
+}
+
+fn number(value: &Value, key: &str) -> Result<f64, anyhow::Error> {
+    field(value, key)?
+        .as_f64()
+        .ok_or_else(|| anyhow!("{} is not a number", key))
+}
+
+fn integer<T>(value: &Value, key: &str) -> Result<T, anyhow::Error>
+where
+    T: TryFrom<i64>,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    let number = number(value, key)?;
+    if number.fract() != 0.0 || number.abs() > i64::MAX as f64 {
+        bail!("{} is not a whole number", key);
+    }
+    Ok(T::try_from(number as i64)?)
+}
+
+fn string<'a>(value: &'a Value, key: &str) -> Result<&'a str, anyhow::Error> {
+    field(value, key)?
+        .as_str()
+        .ok_or_else(|| anyhow!("{} is not a string", key))
+}
+
+fn list<T>(
+    value: &Value,
+    key: &str,
+    mut read: impl FnMut(&Value) -> Result<T, anyhow::Error>,
+) -> Result<Vec<T>, anyhow::Error> {
+    field(value, key)?
+        .as_array()
+        .ok_or_else(|| anyhow!("{} is not an array", key))?
+        .iter()
+        .map(&mut read)
+        .collect()
+}
+
+fn variant<T: Copy + Debug>(all: &[T], value: &Value, key: &str) -> Result<T, anyhow::Error> {
+    let name = string(value, key)?;
+    all.iter()
+        .copied()
+        .find(|variant| format!("{:?}", variant) == name)
+        .ok_or_else(|| anyhow!("unknown {}: {}", key, name))
+}
+
+fn write_song(song: &Arrangement) -> Value {
+    object(vec![
+        ("patterns", array(&song.patterns, write_pattern)),
+        ("tracks", array(&song.tracks, write_track)),
+        (
+            "meter",
+            array(song.meter.changes(), |&(bar, signature)| {
+                object(vec![
+                    ("bar", Value::Number(bar as f64)),
+                    ("beats", Value::Number(signature.beats as f64)),
+                    ("unit", Value::Number(signature.unit as f64)),
+                ])
+            }),
+        ),
+        ("tuning", write_tuning(&song.tuning)),
+        ("key", Value::Number(song.key.fifths as f64)),
+        (
+            "structure",
+            array(&song.structure.parts, |part| {
+                object(vec![
+                    ("role", Value::String(part.role.name().to_string())),
+                    ("bars", Value::Number(part.bars as f64)),
+                    (
+                        "slots",
+                        array(&part.slots, |slot| match slot {
+                            Some(slot) => object(vec![
+                                ("pattern", Value::Number(slot.pattern as f64)),
+                                ("level", Value::Number(slot.level)),
+                            ]),
+                            None => Value::Null,
+                        }),
+                    ),
+                ])
+            }),
+        ),
+    ])
+}
+
+fn read_song(value: &Value) -> Result<Arrangement, anyhow::Error> {
+    let patterns = list(value, "patterns", read_pattern)?;
+    let pattern = |value: &Value| match integer::<usize>(value, "pattern")? {
+        pattern if pattern < patterns.len() => Ok(pattern),
+        pattern => bail!("no pattern {}, there are {}", pattern + 1, patterns.len()),
+    };
+    let tracks = list(value, "tracks", |track| read_track(track, &pattern))?;
+    let mut meter = None;
+    for change in field(value, "meter")?.as_array().into_iter().flatten() {
+        let bar: usize = integer(change, "bar")?;
+        let signature = TimeSignature::new(integer(change, "beats")?, integer(change, "unit")?);
+        if signature.beats == 0 || !signature.unit.is_power_of_two() {
+            bail!(
+                "invalid time signature {}/{}",
+                signature.beats,
+                signature.unit
+            );
+        }
+        match &mut meter {
+            None if bar == 0 => meter = Some(Meter::new(signature)),
+            None => bail!("the meter starts at bar 1"),
+            Some(meter) if bar > meter.changes().last().map_or(0, |&(start, _)| start) => {
+                meter.change(bar, signature)
+            }
+            Some(_) => bail!("time signature changes are in order of their bars"),
+        }
+    }
+    let parts = list(value, "structure", |part| {
+        let slots = list(part, "slots", |slot| match slot {
+            Value::Null => Ok(None),
+            slot => Ok(Some(Slot {
+                pattern: pattern(slot)?,
+                level: number(slot, "level")?,
+            })),
+        })?;
+        if slots.len() != tracks.len() {
+            bail!("{} slots of {} tracks", slots.len(), tracks.len());
+        }
+        Ok(Part {
+            role: Role::parse(string(part, "role")?)?,
+            bars: integer(part, "bars")?,
+            slots,
+        })
+    })?;
+    let fifths = integer(value, "key")?;
+    if !(-7..=7).contains(&fifths) {
+        bail!("key signatures have up to 7 sharps or flats");
+    }
+    Ok(Arrangement {
+        patterns,
+        tracks,
+        meter: meter.ok_or_else(|| anyhow!("the meter has no time signature"))?,
+        tuning: read_tuning(field(value, "tuning")?)?,
+        key: Key { fifths },
+        structure: Structure { parts },
+    })
+}
+
+fn write_pattern(pattern: &Pattern) -> Value {
+    object(vec![
+        ("length", Value::Number(pattern.length)),
+        (
+            "steps",
+            array(&pattern.steps, |step| {
+                object(vec![
+                    ("beat", Value::Number(step.beat)),
+                    ("duration", Value::Number(step.duration)),
+                    ("note", name(step.note.note)),
+                    ("octave", Value::Number(step.note.octave as f64)),
+                    ("cents", Value::Number(step.note.cents)),
+                    ("ratchet", Value::Number(step.ratchet as f64)),
+                ])
+            }),
+        ),
+    ])
+}
+
+fn read_pattern(value: &Value) -> Result<Pattern, anyhow::Error> {
+    let steps = list(value, "steps", |step| {
+        let note = Note::new(
+            variant(&BaseNote::ALL, step, "note")?,
+            integer(step, "octave")?,
+        );
+        Ok(Step {
+            beat: number(step, "beat")?,
+            duration: number(step, "duration")?,
+            note: note.with_cents(number(step, "cents")?),
+            ratchet: integer(step, "ratchet")?,
+        })
+    })?;
+    Ok(Pattern {
+        length: number(value, "length")?,
+        steps,
+    })
+}
+
+fn write_sections(sections: &[Section]) -> Value {
+    array(sections, |section| {
+        object(vec![
+            ("pattern", Value::Number(section.pattern as f64)),
+            ("repeat", Value::Number(section.repeat as f64)),
+            ("level", Value::Number(section.level)),
+        ])
+    })
+}
+
+fn write_track(track: &Track) -> Value {
+    let optional = |value: Option<Value>| value.unwrap_or(Value::Null);
+    object(vec![
+        ("instrument", name(track.instrument)),
+        ("sections", write_sections(&track.sections)),
+        (
+            "b_sections",
+            optional(track.b_sections.as_deref().map(write_sections)),
+        ),
+        (
+            "loop_length",
+            optional(track.loop_length.map(Value::Number)),
+        ),
+        ("pan", Value::Number(track.pan)),
+        (
+            "strum",
+            optional(track.strum.map(|strum| {
+                object(vec![
+                    ("seconds", Value::Number(strum.seconds)),
+                    ("direction", name(strum.direction)),
+                    ("ramp", Value::Number(strum.ramp)),
+                ])
+            })),
+        ),
+        (
+            "arpeggio",
+            optional(track.arpeggio.map(|arpeggio| {
+                object(vec![
+                    ("order", name(arpeggio.order)),
+                    ("octaves", Value::Number(arpeggio.octaves as f64)),
+                    ("rate", Value::Number(arpeggio.rate)),
+                    ("gate", Value::Number(arpeggio.gate)),
+                    // As a string, since numbers lose the low bits of large seeds.
+                    ("seed", Value::String(arpeggio.seed.to_string())),
+                ])
+            })),
+        ),
+        ("velocity", name(track.velocity)),
+        (
+            "accent",
+            optional(track.accent.as_ref().map(|accent| {
+                object(vec![
+                    ("steps", array(&accent.steps, |&step| Value::Bool(step))),
+                    ("amount", Value::Number(accent.amount)),
+                ])
+            })),
+        ),
+        (
+            "groove",
+            optional(track.groove.as_ref().map(|groove| {
+                object(vec![
+                    ("offsets", array(&groove.offsets, |&x| Value::Number(x))),
+                    (
+                        "velocities",
+                        array(&groove.velocities, |&x| Value::Number(x)),
+                    ),
+                ])
+            })),
+        ),
+    ])
+}
+
+fn read_track(
+    value: &Value,
+    pattern: &impl Fn(&Value) -> Result<usize, anyhow::Error>,
+) -> Result<Track, anyhow::Error> {
+    let sections = |sections: &Value| {
+        let sections = sections
+            .as_array()
+            .ok_or_else(|| anyhow!("sections are not an array"))?;
+        sections
+            .iter()
+            .map(|section| {
+                Ok(Section {
+                    pattern: pattern(section)?,
+                    repeat: integer(section, "repeat")?,
+                    level: number(section, "level")?,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()
+    };
+    let numbers = |value: &Value, key| {
+        list(value, key, |x| {
+            x.as_f64().ok_or_else(|| anyhow!("{} are not numbers", key))
+        })
+    };
+    let strum = optional(value, "strum")
+        .map(|strum| -> Result<_, anyhow::Error> {
+            Ok(Strum {
+                seconds: number(strum, "seconds")?,
+                direction: variant(&DIRECTIONS, strum, "direction")?,
+                ramp: number(strum, "ramp")?,
+            })
+        })
+        .transpose()?;
+    let arpeggio = optional(value, "arpeggio")
+        .map(|arpeggio| -> Result<_, anyhow::Error> {
+            Ok(Arpeggio {
+                order: variant(&ORDERS, arpeggio, "order")?,
+                octaves: integer(arpeggio, "octaves")?,
+                rate: number(arpeggio, "rate")?,
+                gate: number(arpeggio, "gate")?,
+                seed: string(arpeggio, "seed")?.parse()?,
+            })
+        })
+        .transpose()?;
+    let accent = optional(value, "accent")
+        .map(|accent| -> Result<_, anyhow::Error> {
+            let steps = list(accent, "steps", |step| {
+                step.as_bool()
+                    .ok_or_else(|| anyhow!("accent steps are true or false"))
+            })?;
+            if steps.is_empty() {
+                bail!("missing accent steps");
+            }
+            Ok(Accent {
+                steps,
+                amount: number(accent, "amount")?,
+            })
+        })
+        .transpose()?;
+    let groove = optional(value, "groove")
+        .map(|groove| -> Result<_, anyhow::Error> {
+            let (offsets, velocities) =
+                (numbers(groove, "offsets")?, numbers(groove, "velocities")?);
+            if offsets.is_empty() || offsets.len() != velocities.len() {
+                bail!("a groove needs as many velocities as offsets");
+            }
+            Ok(Template {
+                offsets,
+                velocities,
+            })
+        })
+        .transpose()?;
+    Ok(Track {
+        instrument: variant(&INSTRUMENTS, value, "instrument")?,
+        sections: sections(field(value, "sections")?)?,
+        b_sections: optional(value, "b_sections").map(sections).transpose()?,
+        loop_length: optional(value, "loop_length")
+            .map(|_| number(value, "loop_length"))
+            .transpose()?,
+        pan: number(value, "pan")?,
+        strum,
+        arpeggio,
+        velocity: variant(&CURVES, value, "velocity")?,
+        accent,
+        groove,
+    })
+}
+
+fn write_tuning(tuning: &Tuning) -> Value {
+    let keyboard = tuning.keyboard.as_ref().map(|keyboard| {
+        object(vec![
+            ("first", Value::Number(*keyboard.keys.start() as f64)),
+            ("last", Value::Number(*keyboard.keys.end() as f64)),
+            ("middle", Value::Number(keyboard.middle as f64)),
+            (
+                "map",
+                array(&keyboard.map, |degree| {
+                    degree.map_or(Value::Null, |degree| Value::Number(degree as f64))
+                }),
+            ),
+            ("octave", Value::Number(keyboard.octave as f64)),
+            ("reference", Value::Number(keyboard.reference as f64)),
+            ("frequency", Value::Number(keyboard.frequency)),
+        ])
+    });
+    object(vec![
+        (
+            "pitches",
+            array(&tuning.pitches, |&cents| Value::Number(cents)),
+        ),
+        ("period", Value::Number(tuning.period)),
+        ("root", Value::Number(tuning.root)),
+        ("keyboard", keyboard.unwrap_or(Value::Null)),
+    ])
+}
+
+fn read_tuning(value: &Value) -> Result<Tuning, anyhow::Error> {
+    let pitches = list(value, "pitches", |cents| {
+        cents
+            .as_f64()
+            .ok_or_else(|| anyhow!("pitches are not numbers"))
+    })?;
+    if pitches.first() != Some(&0.0) {
+        bail!("the pitches of a tuning start at 0 cents");
+    }
+    let (period, root) = (number(value, "period")?, number(value, "root")?);
+    if period <= 0.0 || root <= 0.0 {
+        bail!("the period and root of a tuning are positive");
+    }
+    let tuning = Tuning {
+        pitches,
+        period,
+        root,
+        keyboard: None,
+    };
+    let Some(keyboard) = optional(value, "keyboard") else {
+        return Ok(tuning);
+    };
+    let map = list(keyboard, "map", |degree| match degree {
+        Value::Null => Ok(None),
+        Value::Number(degree) if degree.fract() == 0.0 && degree.abs() <= i32::MAX as f64 => {
+            Ok(Some(*degree as i32))
+        }
+        _ => bail!("degrees are whole numbers or null"),
+    })?;
+    let keyboard = Keyboard {
+        keys: integer(keyboard, "first")?..=integer(keyboard, "last")?,
+        middle: integer(keyboard, "middle")?,
+        map,
+        octave: integer(keyboard, "octave")?,
+        reference: integer(keyboard, "reference")?,
+        frequency: number(keyboard, "frequency")?,
+    };
+    // The root follows from the reference key, as it did when saved.
+    tuning.with_keyboard(keyboard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scala;
+    use crate::song::song;
+
+    fn project(song: Arrangement) -> Project {
+        let tracks = song.tracks.len();
+        Project {
+            metadata: Metadata::new("Entchen", 1_700_000_000),
+            song,
+            bpm: 92.5,
+            muted: vec![false; tracks],
+            soloed: (0..tracks).map(|track| track == 1).collect(),
+            params: vec![("width".into(), 1.5), ("crossfade".into(), 0.0)],
+            filter_controller: 74,
+            midi_tracks: vec![(2, 9, 0.015)],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut song = song();
+        let instruments: Vec<_> = song.tracks.iter().map(|track| track.instrument).collect();
+        let slot = Some(Slot {
+            pattern: 0,
+            level: 0.5,
+        });
+        let parts = vec![Part {
+            role: Role::Chorus,
+            bars: 4,
+            slots: vec![slot, None, None],
+        }];
+        song.arrange(&instruments, Structure { parts });
+        song.meter = Meter::parse("4/4,3/4@5").unwrap();
+        song.key = Key::parse("es").unwrap();
+        song.tuning = scala::scale("! 5\n\n 2\n 700.0\n 2/1\n").unwrap();
+        song.tracks[0].strum = Some(Strum::parse("30,down,10").unwrap());
+        song.tracks[0].arpeggio = Some(Arpeggio {
+            seed: u64::MAX,
+            ..Arpeggio::parse("random,2").unwrap()
+        });
+        song.tracks[1].accent = Some(Accent::parse("x.,40").unwrap());
+        song.tracks[1].b_sections = Some(vec![Section::new(2, 3)]);
+        song.tracks[2].groove = Some(Template {
+            offsets: vec![0.0, 0.02],
+            velocities: vec![1.0, 0.5],
+        });
+        song.tracks[2].velocity = Curve::Soft;
+        song.tracks[2].loop_length = Some(3.0);
+        let project = project(song);
+        let text = project.to_json().to_string();
+        assert_eq!(Project::parse(&text).unwrap(), project);
+    }
+
+    #[test]
+    fn test_versions() {
+        let project = project(song());
+        let with_version = |version: f64| {
+            let mut json = project.to_json();
+            if let Value::Object(members) = &mut json {
+                members[0].1 = Value::Number(version);
+            }
+            Project::from_json(&json)
+        };
+        assert!(with_version(1.0).is_ok());
+        assert!(with_version(2.0).is_err());
+        assert!(with_version(0.5).is_err());
+        assert!(Project::parse(r#"{"metadata":{}}"#).is_err());
+    }
+
+    #[test]
+    fn test_invalid_projects_are_refused() {
+        let mut broken = project(song());
+        broken.song.tracks[0].sections[0].pattern = 99;
+        assert!(Project::parse(&broken.to_json().to_string()).is_err());
+        let mut broken = project(song());
+        broken.muted.pop();
+        assert!(Project::parse(&broken.to_json().to_string()).is_err());
+        let mut broken = project(song());
+        broken.midi_tracks[0].1 = 16;
+        assert!(Project::parse(&broken.to_json().to_string()).is_err());
+    }
+}
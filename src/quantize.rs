@@ -0,0 +1,58 @@
+//! Snapping recorded note starts to a rhythmic grid.
+
+use anyhow::bail;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quantize {
+    /// Grid spacing in beats, a quarter of a beat for 1/16.
+    pub grid: f64,
+    /// How far a note moves towards its grid line, from 0 (not at all) to 1.
+    pub strength: f64,
+}
+
+impl Quantize {
+    /// Parses a note value such as `1/16`, optionally followed by a strength
+    /// in percent such as `1/8,50%`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (grid, strength) = value.split_once(',').unwrap_or((value, "100%"));
+        let Some(("1", division)) = grid.trim().split_once('/') else {
+            bail!("invalid grid: {}", grid);
+        };
+        let division: u32 = division.parse()?;
+        if division == 0 {
+            bail!("invalid grid: {}", grid);
+        }
+        let strength: f64 = strength.trim().trim_end_matches('%').parse()?;
+        if !(0.0..=100.0).contains(&strength) {
+            bail!("quantize strength must be between 0 and 100%");
+        }
+        Ok(Self {
+            grid: 4.0 / division as f64,
+            strength: strength / 100.0,
+        })
+    }
+
+    /// Moves `beat` towards the nearest grid line.
+    pub fn apply(&self, beat: f64) -> f64 {
+        let target = (beat / self.grid).round() * self.grid;
+        beat + (target - beat) * self.strength
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize_to_grid() {
+        let sixteenths = Quantize::parse("1/16").unwrap();
+        assert_eq!(sixteenths.grid, 0.25);
+        assert_eq!(sixteenths.apply(1.1), 1.0);
+        assert_eq!(sixteenths.apply(1.2), 1.25);
+
+        let half = Quantize::parse("1/8,50%").unwrap();
+        assert_eq!(half.apply(0.75), 0.875);
+        assert!(Quantize::parse("3/8").is_err());
+        assert!(Quantize::parse("1/8,150%").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Recording played notes into a pattern of the running song.
+
+use std::collections::VecDeque;
+
+use crate::arrangement::Arrangement;
+use crate::note::Note;
+use crate::pattern::Step;
+use crate::quantize::Quantize;
+
+/// Length in beats of notes played on the computer keyboard, which has no note-off.
+pub const KEY_BEATS: f64 = 1.0;
+/// Song bars that may still be sounding, since bars are dispatched ahead of time.
+const RECENT_BARS: usize = 2;
+
+/// Records notes into the pattern that a track plays where they fall,
+/// merged with the notes it has. Every pass through a looping pattern
+/// overdubs onto the notes recorded so far, building it up layer by layer.
+pub struct Recorder {
+    /// Whether played notes are recorded, or only heard.
+    pub armed: bool,
+    quantize: Option<Quantize>,
+    /// Index of the song track recorded into.
+    track: usize,
+    /// Playback beat and song position of the latest song bars, oldest first.
+    bars: VecDeque<(f64, f64)>,
+    /// MIDI keys held down, with the playback beat they were pressed at.
+    held: Vec<(u8, f64)>,
+}
+
+impl Recorder {
+    /// Records into the patterns of the song track `track`.
+    pub fn new(track: usize, quantize: Option<Quantize>) -> Self {
+        Self {
+            armed: false,
+            quantize,
+            track,
+            bars: VecDeque::with_capacity(RECENT_BARS + 1),
+            held: vec![],
+        }
+    }
+
+    /// Notes that the song bar starting at `from` plays from the playback `beat` on.
+    pub fn bar(&mut self, beat: f64, from: f64) {
+        self.bars.push_back((beat, from));
+        if self.bars.len() > RECENT_BARS {
+            self.bars.pop_front();
+        }
+    }
+
+    /// Notes that MIDI `key` went down at the playback `beat`.
+    pub fn key_down(&mut self, key: u8, beat: f64) {
+        self.held.retain(|&(held, _)| held != key);
+        self.held.push((key, beat));
+    }
+
+    /// Records the note of MIDI `key` held until the playback `beat`.
+    pub fn key_up(&mut self, song: &mut Arrangement, key: u8, beat: f64) -> Option<f64> {
+        let index = self.held.iter().position(|&(held, _)| held == key)?;
+        let (_, start) = self.held.swap_remove(index);
+        self.record(song, start, beat - start, Note::from_midi(key))
+    }
+
+    /// Records `note` played at the playback `beat`, quantized if enabled.
+    /// Returns the position in the pattern it was stored at, or `None` when
+    /// not armed or outside the song.
+    pub fn record(
+        &mut self,
+        song: &mut Arrangement,
+        beat: f64,
+        duration: f64,
+        note: Note,
+    ) -> Option<f64> {
+        if !self.armed {
+            return None;
+        }
+        let &(bar_beat, from) = self.bars.iter().rev().find(|&&(start, _)| start <= beat)?;
+        let mut position = from + beat - bar_beat;
+        if let Some(quantize) = self.quantize {
+            position = quantize.apply(position);
+        }
+        let (pattern, position) = song.pattern_at(self.track, position)?;
+        song.patterns[pattern].steps.push(Step {
+            beat: position,
+            duration,
+            note,
+            ratchet: 1,
+        });
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::Instrument;
+    use crate::note::BaseNote::*;
+    use crate::pattern::Pattern;
+    use crate::schedule::Schedule;
+
+    #[test]
+    fn test_recorded_notes_are_quantized_into_looping_pattern() {
+        let mut song = Arrangement::default();
+        let bar = song.pattern(Pattern::melody(&[(Note::base(C), 4.0)]));
+        song.track(Instrument::Organ, &[(bar, 2)]);
+        let mut recorder = Recorder::new(0, Quantize::parse("1/4").ok());
+        recorder.armed = true;
+
+        // Looping the second bar, which plays from beat 12 on.
+        recorder.bar(8.0, 4.0);
+        recorder.bar(12.0, 4.0);
+        assert_eq!(recorder.record(&mut song, 7.0, 1.0, Note::base(E)), None);
+        assert_eq!(
+            recorder.record(&mut song, 13.1, 1.0, Note::base(E)),
+            Some(1.0)
+        );
+        // Overdubbed on the next pass.
+        recorder.bar(16.0, 4.0);
+        recorder.key_down(64, 18.0);
+        assert_eq!(recorder.key_up(&mut song, 64, 19.0), Some(2.0));
+
+        // Merged into the pattern, which every repeat plays, on no track of
+        // its own.
+        assert_eq!(song.tracks.len(), 1);
+        let mut schedule = Schedule::new();
+        song.schedule(&mut schedule, 0.0, 8.0, 0.0);
+        let mut beats = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            beats.push(event.beat);
+        }
+        assert_eq!(beats, [0.0, 1.0, 2.0, 4.0, 5.0, 6.0]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Offline renders of the song to files, without an audio device.
+
+use fundsp::hacker::*;
+use std::path::Path;
+
+use crate::settings::Settings;
+use playground::dither;
+use playground::engine::Engine;
+use playground::flac;
+use playground::loudness;
+use playground::ogg;
+use playground::plot;
+use playground::spectrogram::{self, WINDOW};
+
+/// Sample rate of renders unless `--sample-rate` is given.
+const SAMPLE_RATE: u32 = 44100;
+
+/// Renders the whole song to the WAV or FLAC file at `path`, and its stems and spectrogram if asked for.
+pub fn render(settings: &Settings, path: &str) -> Result<(), anyhow::Error> {
+    let (mut wave, mut stems) = match &settings.stems {
+        Some(_) => {
+            let mut engine = engine(settings)?;
+            engine.render_stems(engine.duration())
+        }
+        None => (render_wave(settings, None)?, vec![]),
+    };
+    if let Some(target) = settings.normalize {
+        // The stems by as much as the mix, so that they still add up to it.
+        match target.gain(&wave) {
+            Some(gain) => {
+                for wave in std::iter::once(&mut wave).chain(&mut stems) {
+                    loudness::amplify(wave, gain);
+                }
+                eprintln!("normalized by {:+.1} dB", amp_db(gain));
+            }
+            None => eprintln!("nothing to normalize in silence"),
+        }
+    }
+    dither::dither_wave(&mut wave, settings.output.dither);
+    save(&wave, path)?;
+    eprintln!("rendered {:.1} seconds to {}", wave.duration(), path);
+
+    if let Some(dir) = &settings.stems {
+        std::fs::create_dir_all(dir)?;
+        let song = settings.song()?;
+        for (index, (stem, track)) in stems.iter_mut().zip(&song.tracks).enumerate() {
+            // Such as 1-drums.wav, numbered as tracks may play the same
+            // instrument, in the format of the mix.
+            let name = format!("{}-{:?}", index + 1, track.instrument).to_lowercase();
+            let path = Path::new(dir).join(name).with_extension(format(path));
+            dither::dither_wave(stem, settings.output.dither);
+            save(stem, &path)?;
+            eprintln!("stem of track {} written to {}", index + 1, path.display());
+        }
+    }
+
+    if let Some(path) = &settings.spectrogram {
+        let mid: Vec<f64> = (0..wave.len())
+            .map(|i| (wave.at(0, i) + wave.at(1, i)) * 0.5)
+            .collect();
+        let frames = spectrogram::spectrogram(&mid, WINDOW / 4);
+        spectrogram::image(&frames).save(path)?;
+        eprintln!("spectrogram written to {}", path);
+    }
+    Ok(())
+}
+
+/// Writes `wave` to `path`, as FLAC if it ends in `.flac`, as Ogg Vorbis if
+/// it ends in `.ogg` and as WAV otherwise, with 16 bit samples but for Vorbis.
+fn save(wave: &Wave64, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+    match format(path.as_ref()) {
+        "flac" => std::fs::write(path, flac::encode(wave))?,
+        "ogg" => std::fs::write(path, ogg::vorbis(wave))?,
+        _ => wave.save_wav16(path)?,
+    }
+    Ok(())
+}
+
+/// The extension of the format that `path` is written in.
+fn format(path: impl AsRef<Path>) -> &'static str {
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str());
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("flac") => "flac",
+        Some("ogg" | "oga") => "ogg",
+        _ => "wav",
+    }
+}
+
+/// Plots the first `--plot-seconds` of the song to the SVG or PNG file at `path`.
+pub fn plot(settings: &Settings, path: &str) -> Result<(), anyhow::Error> {
+    let wave = render_wave(settings, Some(settings.plot_seconds))?;
+    let channels = [wave.channel(0).clone(), wave.channel(1).clone()];
+    if path.ends_with(".png") {
+        plot::image(&channels, wave.duration())?.save(path)?;
+    } else {
+        std::fs::write(path, plot::svg(&channels, wave.duration())?)?;
+    }
+    eprintln!("plotted {:.1} seconds to {}", wave.duration(), path);
+    Ok(())
+}
+
+/// Renders `seconds` of the song, or all of it.
+fn render_wave(settings: &Settings, seconds: Option<f64>) -> Result<Wave64, anyhow::Error> {
+    let mut engine = engine(settings)?;
+    let seconds = seconds.unwrap_or(engine.duration());
+    Ok(engine.render_wave(seconds))
+}
+
+/// The engine playing the song through the desk as the settings set it up,
+/// with the session of the project.
+fn engine(settings: &Settings) -> Result<Engine, anyhow::Error> {
+    let setup = settings.setup(settings.samplers()?)?;
+    let mut engine =
+        Engine::with_setup(settings.song()?, setup, sample_rate(settings), settings.bpm);
+    let (params, mixer) = engine.controls();
+    settings.restore(params, mixer)?;
+    Ok(engine)
+}
+
+fn sample_rate(settings: &Settings) -> f64 {
+    settings.output.sample_rate.unwrap_or(SAMPLE_RATE) as f64
+}
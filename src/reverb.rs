@@ -0,0 +1,93 @@
+//! A convolution reverb on a send, which every track sends to at a level of
+//! its own, returned into the mix.
+
+use fundsp::hacker::*;
+
+use crate::convolution::Response;
+use crate::param::{Param, ParamRegistry, Spec};
+
+/// Level a track sends at, also the level live notes always send at.
+pub const SEND: Spec = Spec {
+    random: (0.0, 0.5),
+    ..Spec::linear(0.0, 1.0, 0.2)
+};
+
+/// The send levels of the tracks, set by name as `send1`, `send2` and so on.
+pub struct Reverb {
+    response: Response,
+    sends: Vec<Param>,
+}
+
+impl Reverb {
+    pub fn new(params: &mut ParamRegistry, tracks: usize, response: Response) -> Self {
+        Self {
+            response,
+            sends: (0..tracks)
+                .map(|track| params.register(&format!("send{}", track + 1), SEND))
+                .collect(),
+        }
+    }
+
+    /// Sends the stereo buses of the tracks followed by the one of live
+    /// notes, taken after they are muted or soloed, to the reverb, and
+    /// outputs what it returns.
+    pub fn unit(&self) -> Net64 {
+        let buses = self.sends.len() + 1;
+        let mut net = Net64::new(2 * buses, 2);
+        let mut mix = None;
+        for bus in 0..buses {
+            let level: Box<dyn AudioUnit64> = match self.sends.get(bus) {
+                Some(level) => Box::new(level.unit() >> split::<U2>()),
+                None => Box::new(dc(SEND.default) >> split::<U2>()),
+            };
+            let level = net.push(level);
+            let gain = net.push(Box::new((pass() | pass()) * (pass() | pass())));
+            for channel in 0..2 {
+                net.connect_input(2 * bus + channel, gain, channel);
+                net.connect(level, channel, gain, 2 + channel);
+            }
+            mix = Some(match mix {
+                None => gain,
+                Some(mix) => {
+                    let sum = net.push(Box::new((pass() | pass()) + (pass() | pass())));
+                    for channel in 0..2 {
+                        net.connect(mix, channel, sum, channel);
+                        net.connect(gain, channel, sum, 2 + channel);
+                    }
+                    sum
+                }
+            });
+        }
+        let convolution = net.push(Box::new(self.response.unit()));
+        for channel in 0..2 {
+            net.connect(mix.unwrap(), channel, convolution, channel);
+            net.connect_output(convolution, channel, channel);
+        }
+        net
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convolution::PARTITION;
+
+    #[test]
+    fn test_tracks_send_at_their_levels() {
+        let mut impulse = Wave64::new(1, 1000.0);
+        impulse.push(1.0);
+        let mut params = ParamRegistry::default();
+        let reverb = Reverb::new(&mut params, 2, Response::from_wave(&impulse));
+        params.set("send1", 1.0).unwrap();
+        params.set("send2", 0.0).unwrap();
+        assert!(params.set("send3", 1.0).is_err());
+        let mut unit = reverb.unit();
+        unit.set_sample_rate(1000.0);
+        let mut output = [0.0; 2];
+        for _ in 0..200 + PARTITION {
+            unit.tick(&[0.5, 0.25, 1.0, 1.0, 1.0, 0.0], &mut output);
+        }
+        assert!((output[0] - (0.5 + SEND.default)).abs() < 1e-3);
+        assert!((output[1] - 0.25).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,68 @@
+//! Piano roll drawing of a pattern for the terminal.
+
+use fundsp::hacker::max;
+
+use crate::key::Key;
+use crate::note::Note;
+use crate::pattern::Pattern;
+
+/// Characters per beat.
+const COLUMNS_PER_BEAT: f64 = 4.0;
+
+/// Draws `pattern` with the highest note on top and one row per key between
+/// its lowest and highest note, at most `rows` of them. A step starts with `o`
+/// and holds with `=`, and the column of `playhead`, in beats into the
+/// pattern, is marked with `|` where no note sounds. Keys are named as
+/// spelled in `key`.
+pub fn piano_roll(pattern: &Pattern, key: &Key, playhead: Option<f64>, rows: usize) -> Vec<String> {
+    let keys = pattern.steps.iter().map(|step| step.note.midi());
+    let (Some(low), Some(high)) = (keys.clone().min(), keys.max()) else {
+        return vec![];
+    };
+    let columns = (pattern.length * COLUMNS_PER_BEAT).ceil() as usize;
+    let column = |beat: f64| (beat * COLUMNS_PER_BEAT).floor() as usize;
+    let playhead = playhead.map(column);
+    (low..=high)
+        .rev()
+        .take(rows)
+        .map(|midi| {
+            let mut cells = vec!['.'; columns];
+            if let Some(playhead) = playhead.filter(|&playhead| playhead < columns) {
+                cells[playhead] = '|';
+            }
+            for step in pattern.steps.iter().filter(|step| step.note.midi() == midi) {
+                for (beat, duration) in step.hits() {
+                    let start = column(beat);
+                    let end = max(column(beat + duration), start + 1);
+                    for (i, cell) in cells.iter_mut().enumerate().take(end).skip(start) {
+                        *cell = if i == start { 'o' } else { '=' };
+                    }
+                }
+            }
+            let note = Note::from_midi(midi.clamp(0, 127) as u8);
+            let name = key.name(&note);
+            format!("{:>5} {}", name, cells.into_iter().collect::<String>())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::BaseNote::*;
+
+    #[test]
+    fn test_roll_rows_and_playhead() {
+        let pattern = Pattern::melody(&[
+            (Note::base(C), 1.0),
+            (Note::base(D), 0.5),
+            (Note::base(C), 0.5),
+        ]);
+        assert_eq!(
+            piano_roll(&pattern, &Key::default(), Some(1.0), 8),
+            ["   D0 ....o=..", " Cis0 ....|...", "   C0 o===|.o=",].map(|line| line.to_string())
+        );
+        let flat = Key::parse("F").unwrap();
+        assert_eq!(piano_roll(&pattern, &flat, None, 8)[1], " Des0 ........");
+    }
+}
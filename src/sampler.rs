@@ -0,0 +1,921 @@
+//! A sampler playing the notes of a track from recordings: transposed from
+//! the key they were recorded at, from the regions of an instrument that the
+//! key and velocity of a note fall in, taking turns or picked at random when
+//! several do so that repeated notes don't all sound the same, or chopped into slices at transients
+//! that the notes pick one by one, so that a break can be rearranged in
+//! patterns.
+//! Loops recorded at a known tempo are stretched to follow the one of the
+//! song, in grains that keep their pitch, and the same grains shift the
+//! pitch of a recording without changing how long it plays.
+//! Long recordings can be streamed from disk instead of loaded, played
+//! straight through at the pitch of their notes.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use fundsp::hacker::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::instrument::Instrument;
+use crate::param::Spec;
+use crate::sf2;
+use crate::sfz;
+use crate::stream::{self, Header, Reader, Streamer};
+use crate::transient;
+
+/// Semitones that the pitch of a recording is shifted by, to tune it to the
+/// key of the song.
+pub const PITCH: Spec = Spec::linear(-24.0, 24.0, 0.0);
+
+/// MIDI key of middle C, which plays a recording as it is, and of the first
+/// slice, with the others on the keys above it like a drum kit.
+const ROOT_KEY: f64 = 60.0;
+const FIRST_SLICE_KEY: i32 = 36;
+/// Seconds a voice fades out in after its note-off, and at the end of a slice
+/// so that it doesn't click.
+const RELEASE: f64 = 0.01;
+const FADE_SECONDS: f64 = 0.002;
+/// Seconds of the grains a stretched recording is played in, two at a time
+/// half a grain apart.
+const GRAIN_SECONDS: f64 = 0.05;
+
+/// How a region plays on once the recording reaches its end, as the
+/// `loop_mode` of SFZ.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LoopMode {
+    /// Stops at its end or soon after its note-off, whichever comes first.
+    #[default]
+    NoLoop,
+    /// Plays to its end, however short its note.
+    OneShot,
+    /// Loops until soon after its note-off.
+    Continuous,
+    /// Loops until its note-off and plays out from there.
+    Sustain,
+}
+
+impl LoopMode {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "no_loop" => Ok(Self::NoLoop),
+            "one_shot" => Ok(Self::OneShot),
+            "loop_continuous" => Ok(Self::Continuous),
+            "loop_sustain" => Ok(Self::Sustain),
+            _ => Err(anyhow::anyhow!("unknown loop mode: {}", value)),
+        }
+    }
+}
+
+/// How a sampler picks one of the regions that a note falls in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Alternation {
+    /// Each in turn, in the order of the instrument.
+    #[default]
+    RoundRobin,
+    /// Any but the one picked last.
+    Random,
+}
+
+impl Alternation {
+    /// Parses `round-robin` or `random`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "round-robin" => Ok(Self::RoundRobin),
+            "random" => Ok(Self::Random),
+            _ => Err(anyhow::anyhow!("unknown alternation: {}", value)),
+        }
+    }
+}
+
+/// The region of those a note falls in that each key picked latest, for
+/// the layer starting at each region.
+struct Turns {
+    alternation: Alternation,
+    latest: HashMap<(u8, usize), usize>,
+    rng: StdRng,
+}
+
+/// Ranges of keys or velocities over which a region fades in and out, so
+/// that it crossfades with the regions next to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Crossfade {
+    pub fade_in: Option<(u8, u8)>,
+    pub fade_out: Option<(u8, u8)>,
+}
+
+impl Crossfade {
+    /// Gain of the region at `value`, fading at equal power so that two
+    /// regions crossfading are as loud as either.
+    pub fn gain(&self, value: u8) -> f64 {
+        let ramp = |(low, high): (u8, u8)| {
+            let span = max(high.saturating_sub(low), 1) as f64;
+            clamp01((value as f64 - low as f64) / span)
+        };
+        let fade_in = self
+            .fade_in
+            .map_or(1.0, |range| sin(0.5 * PI * ramp(range)));
+        let fade_out = self
+            .fade_out
+            .map_or(1.0, |range| cos(0.5 * PI * ramp(range)));
+        fade_in * fade_out
+    }
+}
+
+/// A recording and the notes that play it.
+#[derive(Clone)]
+pub struct Region {
+    pub wave: Arc<Wave64>,
+    /// MIDI keys and velocities that play the region.
+    pub keys: RangeInclusive<u8>,
+    pub velocities: RangeInclusive<u8>,
+    /// Key that plays the recording as it is, fractional when it is tuned.
+    pub root: f64,
+    /// Level in decibels.
+    pub volume: f64,
+    /// Frames played from and up to.
+    pub start: usize,
+    pub end: usize,
+    pub loop_mode: LoopMode,
+    /// Frames looped from and up to.
+    pub loop_start: usize,
+    pub loop_end: usize,
+    pub key_fade: Crossfade,
+    pub velocity_fade: Crossfade,
+}
+
+impl Region {
+    /// The whole of `wave` on every key, as it is on middle C.
+    pub fn new(wave: Arc<Wave64>) -> Self {
+        let end = wave.len();
+        Self {
+            wave,
+            keys: 0..=127,
+            velocities: 1..=127,
+            root: ROOT_KEY,
+            volume: 0.0,
+            start: 0,
+            end,
+            loop_mode: LoopMode::NoLoop,
+            loop_start: 0,
+            loop_end: end,
+            key_fade: Crossfade::default(),
+            velocity_fade: Crossfade::default(),
+        }
+    }
+
+    fn mono(&self, i: usize) -> f64 {
+        let channels = self.wave.channels();
+        (0..channels)
+            .map(|channel| self.wave.at(channel, i))
+            .sum::<f64>()
+            / channels as f64
+    }
+}
+
+/// Recordings and how its notes play them.
+pub struct Sampler {
+    regions: Vec<Region>,
+    /// Frames that the slices of the first recording start at, if it is
+    /// sliced.
+    slices: Option<Vec<usize>>,
+    /// Tempo in beats per minute that the recordings were played at, if they
+    /// follow the one of the song.
+    bpm: Option<f64>,
+    /// Semitones that their pitch is shifted by.
+    pitch: f64,
+    turns: Mutex<Turns>,
+    /// Where the recording is read from, if it is streamed.
+    stream: Option<(Arc<Header>, Streamer)>,
+}
+
+impl Sampler {
+    /// Loads the recording in the WAV or FLAC file at `path`, the SFZ
+    /// instrument if it ends in `.sfz`, or a preset of the SoundFont if it
+    /// ends in `.sf2`, given as `sf2::parse_preset` parses it.
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        if path.ends_with(".sfz") {
+            return sfz::load(path);
+        }
+        if path.ends_with(".sf2") || path.contains(".sf2@") {
+            let (path, bank, program) = sf2::parse_preset(path)?;
+            return sf2::load(path, bank, program);
+        }
+        Ok(Self::new(Wave64::load(path)?))
+    }
+
+    /// Streams the recording in the WAV file at `path` with `streamer`,
+    /// loading only its header and its head.
+    pub fn stream(path: &str, streamer: &Streamer) -> Result<Self, anyhow::Error> {
+        let header = Header::read(path)?;
+        let head = header.head(stream::HEAD_SECONDS)?;
+        let region = Region {
+            end: header.frames,
+            loop_end: header.frames,
+            ..Region::new(Arc::new(head))
+        };
+        let mut sampler = Self::with_regions(vec![region]);
+        sampler.stream = Some((Arc::new(header), streamer.clone()));
+        Ok(sampler)
+    }
+
+    pub fn new(wave: Wave64) -> Self {
+        Self::with_regions(vec![Region::new(Arc::new(wave))])
+    }
+
+    pub fn with_regions(regions: Vec<Region>) -> Self {
+        Self {
+            regions,
+            slices: None,
+            bpm: None,
+            pitch: PITCH.default,
+            turns: Mutex::new(Turns {
+                alternation: Alternation::default(),
+                latest: HashMap::new(),
+                rng: StdRng::seed_from_u64(0),
+            }),
+            stream: None,
+        }
+    }
+
+    /// Picks among the regions that a note falls in as `alternation` does.
+    pub fn alternate(&mut self, alternation: Alternation) {
+        self.turns.get_mut().unwrap().alternation = alternation;
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Stretches the recordings, played at `bpm`, to the tempo of the song.
+    pub fn stretch(&mut self, bpm: f64) {
+        self.bpm = Some(bpm);
+    }
+
+    /// Shifts the pitch of the recordings by `semitones`, playing them as
+    /// fast as before.
+    pub fn shift(&mut self, semitones: f64) {
+        self.pitch = semitones;
+    }
+
+    /// Chops the first recording into slices at its transients, the first
+    /// one starting at its start.
+    pub fn slice(&mut self) {
+        let Some(region) = self.regions.first() else {
+            return;
+        };
+        let mono: Vec<f64> = (0..region.wave.len()).map(|i| region.mono(i)).collect();
+        let mut slices = transient::onsets(&mono, region.wave.sample_rate());
+        if slices.first() != Some(&0) {
+            slices.insert(0, 0);
+        }
+        self.slices = Some(slices);
+    }
+
+    pub fn slices(&self) -> Option<&[usize]> {
+        self.slices.as_deref()
+    }
+
+    /// Builds a mono voice playing the slice that `frequency` picks, or the
+    /// recording of the region that it and `velocity` fall in transposed
+    /// to it, whose note-off comes `duration` seconds after it starts.
+    /// `velocity` in 0...1 scales its level, and stretched recordings keep
+    /// up with `seconds_per_beat`. Returns it with the time it keeps
+    /// sounding after its note-off.
+    pub fn voice(
+        &self,
+        frequency: f64,
+        duration: f64,
+        velocity: f64,
+        seconds_per_beat: f64,
+    ) -> (Box<dyn AudioUnit64>, f64) {
+        let key = 69.0 + 12.0 * (frequency / 440.0).log2();
+        let silent = || (Box::new(zero()) as Box<dyn AudioUnit64>, 0.0);
+        let plays: Vec<(&Region, usize, usize, f64, f64)> =
+            match (&self.slices, self.regions.first()) {
+                (Some(slices), Some(region)) => {
+                    let slice = key.round() as i32 - FIRST_SLICE_KEY;
+                    let Some(&start) = usize::try_from(slice)
+                        .ok()
+                        .and_then(|slice| slices.get(slice))
+                    else {
+                        return silent();
+                    };
+                    let end = slices
+                        .iter()
+                        .find(|&&next| next > start)
+                        .copied()
+                        .unwrap_or(region.wave.len());
+                    vec![(region, start, end, 1.0, 1.0)]
+                }
+                _ => {
+                    let midi_key = key.round().clamp(0.0, 127.0) as u8;
+                    let midi_velocity = (velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+                    self.layers(midi_key, midi_velocity)
+                        .into_iter()
+                        .map(|region| {
+                            let gain = region.key_fade.gain(midi_key)
+                                * region.velocity_fade.gain(midi_velocity);
+                            let ratio = exp2((key - region.root) / 12.0);
+                            (region, region.start, region.end, ratio, gain)
+                        })
+                        .collect()
+                }
+            };
+        plays
+            .into_iter()
+            .map(|(region, start, end, ratio, gain)| {
+                let (unit, release) =
+                    self.play(region, start..end, ratio, duration, seconds_per_beat);
+                (Net64::wrap(unit) * (velocity * gain), release)
+            })
+            .reduce(|(sum, longest), (unit, release)| (sum + unit, max(longest, release)))
+            .map_or_else(silent, |(sum, release)| {
+                (Box::new(sum) as Box<dyn AudioUnit64>, release)
+            })
+    }
+
+    /// The regions that a note on `key` at `velocity` plays, one of each
+    /// layer that it falls in, the regions mapped to the same keys and
+    /// velocities taking turns.
+    fn layers(&self, key: u8, velocity: u8) -> Vec<&Region> {
+        let found: Vec<(usize, &Region)> = self
+            .regions
+            .iter()
+            .enumerate()
+            .filter(|(_, region)| {
+                region.keys.contains(&key) && region.velocities.contains(&velocity)
+            })
+            .collect();
+        let mut layers = vec![];
+        for (i, &(first, region)) in found.iter().enumerate() {
+            let same =
+                |other: &Region| other.keys == region.keys && other.velocities == region.velocities;
+            if found[..i].iter().any(|(_, earlier)| same(earlier)) {
+                continue;
+            }
+            let alternatives: Vec<&Region> = found[i..]
+                .iter()
+                .map(|&(_, other)| other)
+                .filter(|other| same(other))
+                .collect();
+            layers.extend(self.pick((key, first), &alternatives));
+        }
+        layers
+    }
+
+    /// The one of `found` that a note plays, taking its turn among them on
+    /// its key in the layer starting at the region given by `turn`.
+    fn pick<'a>(&self, turn: (u8, usize), found: &[&'a Region]) -> Option<&'a Region> {
+        if found.len() < 2 {
+            return found.first().copied();
+        }
+        let mut turns = self.turns.lock().unwrap();
+        let Turns {
+            alternation,
+            latest,
+            rng,
+        } = &mut *turns;
+        let count = found.len();
+        let pick = match (*alternation, latest.get(&turn)) {
+            (Alternation::RoundRobin, None) => 0,
+            (Alternation::RoundRobin, Some(latest)) => (latest + 1) % count,
+            (Alternation::Random, None) => rng.gen_range(0..count),
+            (Alternation::Random, Some(latest)) => {
+                (latest + 1 + rng.gen_range(0..count - 1)) % count
+            }
+        };
+        latest.insert(turn, pick);
+        Some(found[pick])
+    }
+
+    /// A voice playing `frames` of the recording of `region` `ratio` times
+    /// as high as it is, and how long it sounds after its note-off.
+    fn play(
+        &self,
+        region: &Region,
+        frames: std::ops::Range<usize>,
+        ratio: f64,
+        duration: f64,
+        seconds_per_beat: f64,
+    ) -> (Box<dyn AudioUnit64>, f64) {
+        let released = |gate: f64| {
+            envelope(move |t| {
+                if t < gate {
+                    1.0
+                } else {
+                    clamp01(1.0 - (t - gate) / RELEASE)
+                }
+            })
+        };
+        let level = db_amp(region.volume);
+        if let Some((header, streamer)) = &self.stream {
+            // Read on from the end of the head.
+            let reader = streamer
+                .stream(header, region.wave.len())
+                .map_err(|err| eprintln!("cannot stream {}: {}", header.path.display(), err))
+                .ok();
+            let streamed = Streamed::new(region.wave.clone(), reader, header.frames, ratio);
+            return (Box::new(An(streamed) * released(duration) * level), RELEASE);
+        }
+        let (start, end) = (frames.start, frames.end);
+        let speed = match self.bpm {
+            Some(bpm) => 60.0 / bpm / seconds_per_beat,
+            None => ratio,
+        };
+        let pitch = ratio * exp2(self.pitch / 12.0);
+        let mut playback = Playback::new(region.wave.clone(), start, end, speed, pitch);
+        // Slices play to their end, which the loop of their region is past.
+        let loop_mode = match self.slices {
+            Some(_) => LoopMode::NoLoop,
+            None => region.loop_mode,
+        };
+        let (gate, release) = match loop_mode {
+            LoopMode::NoLoop => (duration, RELEASE),
+            LoopMode::OneShot => {
+                // Held until the recording is over.
+                let length = (end - start) as f64 / region.wave.sample_rate() / speed;
+                (length, max(length - duration, 0.0) + RELEASE)
+            }
+            LoopMode::Continuous => {
+                playback = playback.looped(region.loop_start, region.loop_end, f64::INFINITY);
+                (duration, RELEASE)
+            }
+            LoopMode::Sustain => {
+                playback = playback.looped(region.loop_start, region.loop_end, duration);
+                // Played out from the loop at most once more.
+                let tail = (region.end - region.loop_start) as f64 / region.wave.sample_rate();
+                (f64::INFINITY, tail / speed + RELEASE)
+            }
+        };
+        (Box::new(An(playback) * released(gate) * level), release)
+    }
+}
+
+/// The voices of the tracks that samplers play instead of their instrument.
+#[derive(Clone, Default)]
+pub struct Samplers {
+    tracks: Vec<(usize, Arc<Sampler>)>,
+}
+
+impl Samplers {
+    /// Plays the notes of `track` with `sampler`.
+    pub fn insert(&mut self, track: usize, sampler: Sampler) {
+        self.tracks.retain(|&(found, _)| found != track);
+        self.tracks.push((track, Arc::new(sampler)));
+    }
+
+    pub fn get(&self, track: usize) -> Option<&Sampler> {
+        let found = self.tracks.iter().find(|&&(found, _)| found == track);
+        found.map(|(_, sampler)| &**sampler)
+    }
+
+    /// The voice of a note of `track` as `Instrument::voice` builds it, from
+    /// the sampler of the track if it has one, and the time it keeps
+    /// sounding after its note-off.
+    pub fn voice(
+        &self,
+        track: Option<usize>,
+        instrument: Instrument,
+        frequency: f64,
+        duration: f64,
+        velocity: f64,
+        seconds_per_beat: f64,
+    ) -> (Box<dyn AudioUnit64>, f64) {
+        match track.and_then(|track| self.get(track)) {
+            Some(sampler) => sampler.voice(frequency, duration, velocity, seconds_per_beat),
+            None => (
+                instrument.voice(frequency, duration, velocity),
+                instrument.release(),
+            ),
+        }
+    }
+}
+
+/// Plays the frames of a recording from `start` to `end`, `speed` times as
+/// fast and `pitch` times as high, interpolated, fading out at the end. Two
+/// grains half a grain apart each read on at the pitch from where the
+/// recording had got to when they started, faded in and out so that they
+/// add up to one; at the same speed and pitch they read the same frames.
+#[derive(Clone)]
+struct Playback {
+    wave: Arc<Wave64>,
+    start: usize,
+    end: usize,
+    speed: f64,
+    pitch: f64,
+    sample_rate: f64,
+    /// Frame of the recording reached, and frames it and the grains advance
+    /// a sample.
+    position: f64,
+    speed_step: f64,
+    pitch_step: f64,
+    /// Position of the first grain, from 0 to 1, how far it advances a
+    /// sample, and the frames that the two grains started from.
+    phase: f64,
+    phase_step: f64,
+    origins: [f64; 2],
+    /// Frames looped from and up to, until how many seconds in, and the
+    /// seconds played so far.
+    looped: Option<(f64, f64, f64)>,
+    time: f64,
+}
+
+impl Playback {
+    fn new(wave: Arc<Wave64>, start: usize, end: usize, speed: f64, pitch: f64) -> Self {
+        let mut playback = Self {
+            wave,
+            start,
+            end,
+            speed,
+            pitch,
+            sample_rate: DEFAULT_SR,
+            position: 0.0,
+            speed_step: 0.0,
+            pitch_step: 0.0,
+            phase: 0.0,
+            phase_step: 0.0,
+            origins: [0.0; 2],
+            looped: None,
+            time: 0.0,
+        };
+        playback.set_sample_rate(DEFAULT_SR);
+        playback.reset();
+        playback
+    }
+
+    /// Loops from frame `start` up to frame `end` for the first `seconds`.
+    fn looped(mut self, start: usize, end: usize, seconds: f64) -> Self {
+        if start < end {
+            self.looped = Some((start as f64, end as f64, seconds));
+        }
+        self
+    }
+
+    /// Frames a grain reads over, and that the recording advances in one.
+    fn grain(&self) -> f64 {
+        self.pitch_step / self.phase_step
+    }
+
+    fn stride(&self) -> f64 {
+        self.speed_step / self.phase_step
+    }
+
+    /// Frames looped from and up to, while it still loops.
+    fn looping(&self) -> Option<(f64, f64)> {
+        let (start, end, seconds) = self.looped?;
+        (self.time < seconds).then_some((start, end))
+    }
+
+    fn mono(&self, i: usize) -> f64 {
+        // Frames past the loop are those at its start while it loops.
+        let i = match self.looping() {
+            Some((start, end)) if i as f64 >= end => {
+                let (start, end) = (start as usize, end as usize);
+                start + (i - start) % (end - start)
+            }
+            _ => i,
+        };
+        if i < self.start || i >= self.end {
+            return 0.0;
+        }
+        let channels = self.wave.channels();
+        (0..channels)
+            .map(|channel| self.wave.at(channel, i))
+            .sum::<f64>()
+            / channels as f64
+    }
+
+    /// The recording at `position`, interpolated.
+    fn at(&self, position: f64) -> f64 {
+        if position < 0.0 {
+            return 0.0;
+        }
+        let index = position as usize;
+        lerp(
+            self.mono(index),
+            self.mono(index + 1),
+            position - index as f64,
+        )
+    }
+}
+
+impl AudioNode for Playback {
+    const ID: u64 = 0x5361_6d70;
+    type Sample = f64;
+    type Inputs = U0;
+    type Outputs = U1;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.position = self.start as f64;
+        self.phase = 0.0;
+        self.time = 0.0;
+        // As if the second grain had started half a grain ago.
+        self.origins = [self.position, self.position - 0.5 * self.stride()];
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        let frames = self.wave.sample_rate() / sample_rate;
+        self.speed_step = self.speed * frames;
+        self.pitch_step = self.pitch * frames;
+        self.phase_step = 1.0 / (GRAIN_SECONDS * sample_rate);
+    }
+
+    fn tick(&mut self, _input: &Frame<f64, U0>) -> Frame<f64, U1> {
+        let looping = self.looping();
+        match looping {
+            // Back a loop, the grains too so that they read on seamlessly.
+            Some((start, end)) if self.position >= end => {
+                for frame in std::iter::once(&mut self.position).chain(&mut self.origins) {
+                    *frame -= end - start;
+                }
+            }
+            None if self.position + 1.0 >= self.end as f64 => return [0.0].into(),
+            _ => (),
+        }
+        let grain = self.grain();
+        let phases = [self.phase, (self.phase + 0.5) % 1.0];
+        let x: f64 = phases
+            .iter()
+            .zip(self.origins)
+            .map(|(&phase, origin)| self.at(origin + phase * grain) * sin(PI * phase).powi(2))
+            .sum();
+        let left = match looping {
+            Some(_) => f64::INFINITY,
+            None => (self.end as f64 - self.position) / self.speed_step / self.sample_rate,
+        };
+        self.position += self.speed_step;
+        for (grain, phase) in phases.into_iter().enumerate() {
+            let phase = phase + self.phase_step;
+            if phase >= 1.0 {
+                self.origins[grain] = self.position - (phase - 1.0) * self.stride();
+            }
+        }
+        self.phase = (self.phase + self.phase_step) % 1.0;
+        self.time += 1.0 / self.sample_rate;
+        [x * clamp01(left / FADE_SECONDS)].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 1)
+    }
+}
+
+/// Plays a streamed recording through once, `ratio` times as fast,
+/// interpolated, from its head and then from its reader, fading out at the
+/// end. The frames are read in order, so that resetting it plays the head
+/// again but goes on from where the reader had got to.
+#[derive(Clone)]
+struct Streamed {
+    head: Arc<Wave64>,
+    reader: Option<Reader>,
+    frames: usize,
+    ratio: f64,
+    sample_rate: f64,
+    /// Frame reached, and frames it advances a sample.
+    position: f64,
+    step: f64,
+    /// The latest frame taken, and it and the one before.
+    taken: usize,
+    previous: f64,
+    current: f64,
+}
+
+impl Streamed {
+    fn new(head: Arc<Wave64>, reader: Option<Reader>, frames: usize, ratio: f64) -> Self {
+        let mut streamed = Self {
+            head,
+            reader,
+            frames,
+            ratio,
+            sample_rate: DEFAULT_SR,
+            position: 0.0,
+            step: 0.0,
+            taken: 0,
+            previous: 0.0,
+            current: 0.0,
+        };
+        streamed.set_sample_rate(DEFAULT_SR);
+        streamed.reset();
+        streamed
+    }
+
+    /// Takes the next frame, from the head while it lasts.
+    fn take(&mut self) {
+        self.taken += 1;
+        self.previous = self.current;
+        self.current = match &mut self.reader {
+            _ if self.taken < self.head.len() => self.head.at(0, self.taken),
+            Some(reader) => reader.frame(),
+            None => 0.0,
+        };
+    }
+}
+
+impl AudioNode for Streamed {
+    const ID: u64 = 0x5374_726d;
+    type Sample = f64;
+    type Inputs = U0;
+    type Outputs = U1;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.position = 0.0;
+        self.taken = 0;
+        self.previous = 0.0;
+        self.current = self.head.at(0, 0);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.step = self.ratio * self.head.sample_rate() / sample_rate;
+    }
+
+    fn tick(&mut self, _input: &Frame<f64, U0>) -> Frame<f64, U1> {
+        let index = self.position as usize;
+        if index + 1 >= self.frames {
+            return [0.0].into();
+        }
+        while self.taken <= index {
+            self.take();
+        }
+        let x = lerp(self.previous, self.current, self.position - index as f64);
+        let left = (self.frames as f64 - self.position) / self.step / self.sample_rate;
+        self.position += self.step;
+        [x * clamp01(left / FADE_SECONDS)].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{get_note_frequency, Note};
+
+    /// A loop of `hits` hits a quarter of a second apart, each at a pitch of
+    /// its own.
+    fn break_loop(hits: usize) -> Wave64 {
+        let mut wave = Wave64::new(1, 8000.0);
+        for i in 0..hits * 2000 {
+            let t = (i % 2000) as f64 / 8000.0;
+            let frequency = 200.0 * (1 + i / 2000) as f64;
+            wave.push(exp(-t * 40.0) * sin(TAU * frequency * t + 0.5));
+        }
+        wave
+    }
+
+    fn render(sampler: &Sampler, key: u8) -> Wave64 {
+        let frequency = get_note_frequency(&Note::from_midi(key));
+        let (mut voice, _) = sampler.voice(frequency, 1.0, 1.0, 0.5);
+        Wave64::render(8000.0, 1.0, &mut *voice)
+    }
+
+    /// A recording of `value` for a tenth of a second.
+    fn constant(value: f64) -> Arc<Wave64> {
+        let mut wave = Wave64::new(1, 8000.0);
+        for _ in 0..800 {
+            wave.push(value);
+        }
+        Arc::new(wave)
+    }
+
+    /// Times the sign of `wave` changes over `frames`.
+    fn crossings(wave: &Wave64, frames: std::ops::Range<usize>) -> usize {
+        frames
+            .filter(|&i| (wave.at(0, i) < 0.0) != (wave.at(0, i + 1) < 0.0))
+            .count()
+    }
+
+    #[test]
+    fn test_slices_at_transients() {
+        let mut sampler = Sampler::new(break_loop(4));
+        sampler.slice();
+        assert_eq!(sampler.slices(), Some(&[0, 2000, 4000, 6000][..]));
+        // The third slice on the third key, and nothing past the last one.
+        let third = render(&sampler, 38);
+        assert!((third.at(0, 0) - sin(0.5)).abs() < 1e-9);
+        assert!(third.at(0, 1990).abs() < 1e-3 && third.at(0, 2100) == 0.0);
+        assert_eq!(render(&sampler, 40).amplitude(), 0.0);
+    }
+
+    #[test]
+    fn test_transposes_unsliced_recording() {
+        let sampler = Sampler::new(break_loop(1));
+        let wave = render(&sampler, 72);
+        // An octave up, every other frame of the recording.
+        assert!((wave.at(0, 10) - sampler.regions[0].wave.at(0, 20)).abs() < 1e-9);
+        assert_eq!(wave.at(0, 1000), 0.0);
+    }
+
+    #[test]
+    fn test_stretches_to_tempo() {
+        let mut sampler = Sampler::new(break_loop(1));
+        // Recorded at 60 bpm and played at 120, a quarter of a second at the
+        // same pitch takes an eighth.
+        sampler.stretch(60.0);
+        let wave = render(&sampler, 60);
+        assert!(wave.at(0, 990).abs() > 0.0 && wave.at(0, 1010) == 0.0);
+        let (stretched, recorded) = (
+            crossings(&wave, 0..990),
+            crossings(&sampler.regions[0].wave, 0..1980),
+        );
+        assert!(
+            stretched.abs_diff(recorded / 2) <= 2,
+            "{} {}",
+            stretched,
+            recorded
+        );
+    }
+
+    #[test]
+    fn test_shifts_pitch_at_same_speed() {
+        let mut sampler = Sampler::new(break_loop(1));
+        sampler.shift(12.0);
+        let wave = render(&sampler, 60);
+        // As long as the recording, an octave up.
+        assert!(wave.at(0, 1500).abs() > 0.0 && wave.at(0, 2010) == 0.0);
+        let (shifted, recorded) = (
+            crossings(&wave, 0..1500),
+            crossings(&sampler.regions[0].wave, 0..1500),
+        );
+        assert!(
+            shifted.abs_diff(2 * recorded) <= 4,
+            "{} {}",
+            shifted,
+            recorded
+        );
+    }
+
+    #[test]
+    fn test_plays_regions_and_loops() {
+        let soft = Region {
+            velocities: 1..=63,
+            ..Region::new(constant(0.25))
+        };
+        let loud = Region {
+            keys: 60..=72,
+            velocities: 64..=127,
+            loop_mode: LoopMode::Continuous,
+            ..Region::new(constant(0.5))
+        };
+        let sampler = Sampler::with_regions(vec![soft, loud]);
+        let play = |key: u8, velocity: f64| {
+            let frequency = get_note_frequency(&Note::from_midi(key));
+            let (mut voice, _) = sampler.voice(frequency, 1.0, velocity, 0.5);
+            Wave64::render(8000.0, 0.5, &mut *voice)
+        };
+        assert!((play(60, 0.2).at(0, 100) - 0.25 * 0.2).abs() < 1e-9);
+        // The loud layer loops on past its end, the soft one doesn't.
+        let loud = play(60, 1.0);
+        assert!((loud.at(0, 100) - 0.5).abs() < 1e-9 && (loud.at(0, 3000) - 0.5).abs() < 1e-9);
+        assert_eq!(play(60, 0.2).at(0, 3000), 0.0);
+        assert_eq!(play(80, 1.0).amplitude(), 0.0);
+    }
+
+    #[test]
+    fn test_alternates_regions_of_a_note() {
+        let regions = [0.25, 0.5, 0.75].map(|value| Region::new(constant(value)));
+        let mut sampler = Sampler::with_regions(regions.to_vec());
+        // The region played, counted from 1.
+        let play = |sampler: &Sampler| {
+            let frequency = get_note_frequency(&Note::from_midi(60));
+            let (mut voice, _) = sampler.voice(frequency, 1.0, 1.0, 0.5);
+            (Wave64::render(8000.0, 0.1, &mut *voice).at(0, 100) * 4.0).round() as usize
+        };
+        let turns: Vec<usize> = (0..4).map(|_| play(&sampler)).collect();
+        assert_eq!(turns, [1, 2, 3, 1]);
+        sampler.alternate(Alternation::Random);
+        let picks: Vec<usize> = (0..20).map(|_| play(&sampler)).collect();
+        assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!((1..=3).all(|region| picks.contains(&region)));
+    }
+
+    #[test]
+    fn test_streams_as_loaded() {
+        let mut wave = Wave64::new(1, 8000.0);
+        for i in 0..8000 {
+            wave.push(0.5 * sin(TAU * 220.0 * i as f64 / 8000.0));
+        }
+        let path = std::env::temp_dir().join("playground_test_streams_as_loaded.wav");
+        wave.save_wav16(&path).unwrap();
+        let loaded = Sampler::new(Wave64::load(&path).unwrap());
+        let streamed = Sampler::stream(path.to_str().unwrap(), &Streamer::spawn()).unwrap();
+        assert_eq!(streamed.regions()[0].wave.len(), 2000);
+        let frequency = get_note_frequency(&Note::from_midi(60));
+        let (mut voice, _) = streamed.voice(frequency, 1.0, 1.0, 0.5);
+        // As much as the ring holds beyond the head, once it has filled.
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let played = Wave64::render(8000.0, 0.7, &mut *voice);
+        std::fs::remove_file(&path).unwrap();
+        let expected = render(&loaded, 60);
+        for i in 0..played.len() {
+            assert!((played.at(0, i) - expected.at(0, i)).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Reading of Scala scale (.scl) and keyboard mapping (.kbm) files, see
+//! <https://www.huygens-fokker.org/scala/scl_format.html>.
+
+use anyhow::{anyhow, bail};
+
+use crate::tuning::{Keyboard, Tuning, MIDDLE_C};
+
+/// Longest keyboard map read, longer than the MIDI keys.
+const MAX_MAP: usize = 1024;
+
+/// Lines of a Scala file without its comments, which start with `!`.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| !line.starts_with('!'))
+}
+
+/// Cents of a pitch given in cents when it has a period, or as a ratio or
+/// whole number otherwise. Anything after the pitch is a comment.
+fn pitch(line: &str) -> Result<f64, anyhow::Error> {
+    let pitch = line
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("missing pitch"))?;
+    let cents = if pitch.contains('.') {
+        pitch.parse()?
+    } else {
+        let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+        let ratio = numerator.parse::<u64>()? as f64 / denominator.parse::<u64>()? as f64;
+        if !(ratio > 0.0 && ratio.is_finite()) {
+            bail!("invalid ratio: {}", pitch);
+        }
+        1200.0 * ratio.log2()
+    };
+    match cents {
+        cents if f64::is_finite(cents) => Ok(cents),
+        _ => bail!("invalid pitch: {}", pitch),
+    }
+}
+
+/// Reads a scale, with degree zero on middle C. The last pitch is the period.
+pub fn scale(text: &str) -> Result<Tuning, anyhow::Error> {
+    let mut lines = lines(text).skip(1);
+    let count: usize = lines
+        .next()
+        .ok_or_else(|| anyhow!("missing number of notes"))?
+        .trim()
+        .parse()?;
+    if count == 0 {
+        bail!("a scale needs at least one note");
+    }
+    let mut pitches = vec![0.0];
+    for _ in 0..count {
+        let line = lines.next().ok_or_else(|| anyhow!("missing pitches"))?;
+        pitches.push(pitch(line)?);
+    }
+    let period = pitches.pop().unwrap();
+    if period <= 0.0 {
+        bail!("the period of a scale must be above its first note");
+    }
+    Ok(Tuning {
+        pitches,
+        period,
+        root: MIDDLE_C,
+        keyboard: None,
+    })
+}
+
+/// Reads a keyboard mapping, where `x` marks silent keys.
+pub fn keyboard(text: &str) -> Result<Keyboard, anyhow::Error> {
+    let mut lines = lines(text).map(str::trim);
+    let mut field = |name: &str| lines.next().ok_or_else(|| anyhow!("missing {}", name));
+    let size: usize = field("map size")?.parse()?;
+    if size > MAX_MAP {
+        bail!("keyboard maps repeat after at most {} keys", MAX_MAP);
+    }
+    let mut key = |name: &str| -> Result<i32, anyhow::Error> {
+        match field(name)?.parse()? {
+            key @ 0..=127 => Ok(key),
+            key => bail!("{} {} is not a MIDI key", name, key),
+        }
+    };
+    let keys = key("first key")?..=key("last key")?;
+    let middle = key("middle key")?;
+    let reference = key("reference key")?;
+    let frequency: f64 = field("reference frequency")?.parse()?;
+    if !(frequency > 0.0 && frequency.is_finite()) {
+        bail!("invalid reference frequency: {}", frequency);
+    }
+    let octave = field("octave degree")?.parse()?;
+    let map = (0..size)
+        .map(|_| match lines.next() {
+            // Entries missing at the end are silent.
+            None | Some("x") => Ok(None),
+            Some(degree) => Ok(Some(degree.parse()?)),
+        })
+        .collect::<Result<_, anyhow::Error>>()?;
+    Ok(Keyboard {
+        keys,
+        middle,
+        map,
+        octave,
+        reference,
+        frequency,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEANTONE: &str = "! meanquar.scl
+!
+1/4-comma meantone scale, in cents and ratios
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1 the octave
+";
+
+    #[test]
+    fn test_scale() {
+        let tuning = scale(MEANTONE).unwrap();
+        assert_eq!(tuning.divisions(), 12);
+        assert!((tuning.period - 1200.0).abs() < 1e-9);
+        assert!((tuning.frequency(4) / MIDDLE_C - 1.25).abs() < 1e-12);
+        assert!((tuning.frequency(-8) / MIDDLE_C - 0.625).abs() < 1e-12);
+        assert!(scale("empty\n0\n").is_err());
+        assert!(scale("short\n2\n3/2\n").is_err());
+        assert!(scale("zero\n1\n0/1\n").is_err());
+    }
+
+    #[test]
+    fn test_keyboard() {
+        let text = "! seven keys an octave, A at 415 Hz
+7
+0
+127
+60
+69
+415.0
+12
+0
+x
+2
+4
+5
+7
+";
+        let keyboard = keyboard(text).unwrap();
+        assert_eq!(keyboard.degree(60), Some(0));
+        assert_eq!(keyboard.degree(61), None);
+        assert_eq!(keyboard.degree(67), Some(12));
+        // The seventh entry is missing, so the seventh key is silent.
+        assert_eq!(keyboard.degree(66), None);
+        let tuning = scale(MEANTONE).unwrap().with_keyboard(keyboard).unwrap();
+        assert!((tuning.key_frequency(69).unwrap() - 415.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,185 @@
+//! Beat-timed note events.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::crossfade::Bank;
+use crate::instrument::Instrument;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Start of a bar. Deferred changes are applied here.
+    Bar,
+    /// A note lasting `duration` beats until its note-off.
+    Note {
+        instrument: Instrument,
+        frequency: f64,
+        duration: f64,
+        /// Index of the song track playing it, if any.
+        track: Option<usize>,
+        /// From 0 to 1.
+        velocity: f64,
+        /// Seconds the note starts after its beat, such as when strummed.
+        delay: f64,
+        /// Side of the crossfader it is on, if its track has two banks.
+        bank: Option<Bank>,
+    },
+}
+
+impl Action {
+    /// Order of simultaneous events: bars come first, so that changes applied
+    /// at a bar already affect the notes starting on it.
+    fn priority(&self) -> u8 {
+        match self {
+            Action::Bar => 0,
+            Action::Note { .. } => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub beat: f64,
+    pub action: Action,
+    sequence: u64,
+}
+
+impl Event {
+    fn key(&self) -> (f64, u8, u64) {
+        (self.beat, self.action.priority(), self.sequence)
+    }
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    /// Reversed, so that the earliest event is at the top of the heap.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (beat, priority, sequence) = self.key();
+        let (other_beat, other_priority, other_sequence) = other.key();
+        other_beat
+            .total_cmp(&beat)
+            .then(other_priority.cmp(&priority))
+            .then(other_sequence.cmp(&sequence))
+    }
+}
+
+#[derive(Default)]
+pub struct Schedule {
+    events: BinaryHeap<Event>,
+    sequence: u64,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, beat: f64, action: Action) {
+        self.sequence += 1;
+        self.events.push(Event {
+            beat,
+            action,
+            sequence: self.sequence,
+        });
+    }
+
+    pub fn bar(&mut self, beat: f64) {
+        self.push(beat, Action::Bar);
+    }
+
+    /// Schedules a note at full velocity at `beat` with its note-off at
+    /// `beat + duration`.
+    pub fn note(
+        &mut self,
+        beat: f64,
+        duration: f64,
+        instrument: Instrument,
+        frequency: f64,
+        track: Option<usize>,
+    ) {
+        self.push(
+            beat,
+            Action::Note {
+                instrument,
+                frequency,
+                duration,
+                track,
+                velocity: 1.0,
+                delay: 0.0,
+                bank: None,
+            },
+        );
+    }
+
+    /// Drops the notes still pending, keeping the bars.
+    pub fn clear_notes(&mut self) {
+        self.events.retain(|event| event.action == Action::Bar);
+    }
+
+    /// Beat of the earliest pending event.
+    pub fn next_beat(&self) -> Option<f64> {
+        self.events.peek().map(|event| event.beat)
+    }
+
+    /// Removes and returns the earliest event if it is due at or before `beat`.
+    pub fn pop_due(&mut self, beat: f64) -> Option<Event> {
+        match self.events.peek() {
+            Some(event) if event.beat <= beat => self.events.pop(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_ordered_with_bars_first() {
+        let mut schedule = Schedule::new();
+        schedule.note(4.0, 2.0, Instrument::Pluck, 440.0, None);
+        schedule.note(1.0, 1.0, Instrument::Pluck, 220.0, None);
+        schedule.bar(4.0);
+        schedule.bar(0.0);
+
+        let mut actions = vec![];
+        while let Some(event) = schedule.pop_due(f64::INFINITY) {
+            actions.push((event.beat, event.action.priority()));
+        }
+        assert_eq!(actions, [(0.0, 0), (1.0, 1), (4.0, 0), (4.0, 1)]);
+    }
+
+    #[test]
+    fn test_pop_due_waits_for_beat() {
+        let mut schedule = Schedule::new();
+        schedule.note(1.0, 1.0, Instrument::Pluck, 440.0, None);
+        assert!(schedule.pop_due(0.5).is_none());
+        assert_eq!(schedule.next_beat(), Some(1.0));
+        assert!(schedule.pop_due(1.0).is_some());
+    }
+
+    #[test]
+    fn test_clear_notes_keeps_bars() {
+        let mut schedule = Schedule::new();
+        schedule.note(1.0, 1.0, Instrument::Pluck, 440.0, None);
+        schedule.bar(2.0);
+        schedule.note(3.0, 1.0, Instrument::Pluck, 220.0, None);
+        schedule.clear_notes();
+        assert_eq!(schedule.next_beat(), Some(2.0));
+        assert_eq!(schedule.pop_due(f64::INFINITY).unwrap().action, Action::Bar);
+        assert!(schedule.pop_due(f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! A scrolling oscilloscope of the master output. The audio thread keeps the
+//! lowest and highest sample of each column in a ring buffer that the display
+//! reads without locking.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use fundsp::hacker::*;
+
+/// Columns kept in the ring buffer, the width of the display.
+pub const COLUMNS: usize = 64;
+
+struct Columns {
+    /// Sample ranges as `f64` bits.
+    low: Vec<AtomicU64>,
+    high: Vec<AtomicU64>,
+    /// Index of the column written next, which is also the oldest one.
+    next: AtomicUsize,
+}
+
+/// Reads the columns of a scope unit from another thread.
+#[derive(Clone)]
+pub struct Scope {
+    columns: Arc<Columns>,
+    /// Time shown across the display.
+    seconds: f64,
+}
+
+impl Scope {
+    pub fn new(seconds: f64) -> Self {
+        let column = || (0..COLUMNS).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            columns: Arc::new(Columns {
+                low: column(),
+                high: column(),
+                next: AtomicUsize::new(0),
+            }),
+            seconds,
+        }
+    }
+
+    /// A stereo pass-through unit recording the mid signal into the display.
+    pub fn unit(&self) -> An<ScopeNode> {
+        let mut node = ScopeNode {
+            columns: self.columns.clone(),
+            seconds: self.seconds,
+            decimation: 1,
+            count: 0,
+            low: f64::INFINITY,
+            high: f64::NEG_INFINITY,
+        };
+        node.set_sample_rate(DEFAULT_SR);
+        An(node)
+    }
+
+    /// Sample ranges of the columns, oldest first.
+    pub fn columns(&self) -> Vec<(f64, f64)> {
+        let next = self.columns.next.load(Ordering::Acquire);
+        let load = |column: &AtomicU64| f64::from_bits(column.load(Ordering::Relaxed));
+        (0..COLUMNS)
+            .map(|i| (next + i) % COLUMNS)
+            .map(|i| (load(&self.columns.low[i]), load(&self.columns.high[i])))
+            .collect()
+    }
+
+    /// Draws the display `rows` high, with -1 at the bottom and 1 at the top.
+    /// Samples beyond either are drawn as `!` on the outer row.
+    pub fn lines(&self, rows: usize) -> Vec<String> {
+        let columns = self.columns();
+        let row = |value: f64| {
+            let row = ((1.0 - value) * 0.5 * rows as f64).floor();
+            row.clamp(0.0, (rows - 1) as f64) as usize
+        };
+        (0..rows)
+            .map(|line| {
+                columns
+                    .iter()
+                    .map(|&(low, high)| {
+                        let clipped =
+                            (line == 0 && high >= 1.0) || (line == rows - 1 && low <= -1.0);
+                        if clipped {
+                            '!'
+                        } else if (row(high)..=row(low)).contains(&line) {
+                            '#'
+                        } else if line == rows / 2 {
+                            '-'
+                        } else {
+                            ' '
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct ScopeNode {
+    columns: Arc<Columns>,
+    seconds: f64,
+    /// Samples per column.
+    decimation: usize,
+    count: usize,
+    low: f64,
+    high: f64,
+}
+
+impl AudioNode for ScopeNode {
+    const ID: u64 = 0x5363_6f70;
+    type Sample = f64;
+    type Inputs = U2;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.decimation = Ord::max((self.seconds * sample_rate) as usize / COLUMNS, 1);
+    }
+
+    fn tick(&mut self, input: &Frame<f64, U2>) -> Frame<f64, U2> {
+        let mid = (input[0] + input[1]) * 0.5;
+        self.low = self.low.min(mid);
+        self.high = self.high.max(mid);
+        self.count += 1;
+        if self.count == self.decimation {
+            let next = self.columns.next.load(Ordering::Relaxed);
+            self.columns.low[next].store(self.low.to_bits(), Ordering::Relaxed);
+            self.columns.high[next].store(self.high.to_bits(), Ordering::Relaxed);
+            self.columns
+                .next
+                .store((next + 1) % COLUMNS, Ordering::Release);
+            self.count = 0;
+            self.low = f64::INFINITY;
+            self.high = f64::NEG_INFINITY;
+        }
+        *input
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        let mut output = new_signal_frame(2);
+        output[0] = input[0];
+        output[1] = input[1];
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_scroll_and_show_clipping() {
+        let scope = Scope::new(1.0);
+        let mut unit = scope.unit();
+        unit.set_sample_rate(COLUMNS as f64 * 2.0);
+        for _ in 0..COLUMNS - 1 {
+            unit.filter_stereo(0.5, 0.5);
+            unit.filter_stereo(0.5, 0.5);
+        }
+        unit.filter_stereo(-2.0, -2.0);
+        unit.filter_stereo(1.5, 1.5);
+
+        let columns = scope.columns();
+        assert_eq!(columns[0], (0.5, 0.5));
+        assert_eq!(columns[COLUMNS - 1], (-2.0, 1.5));
+        let lines = scope.lines(4);
+        assert_eq!(lines[0].chars().last(), Some('!'));
+        assert_eq!(lines[3].chars().last(), Some('!'));
+        assert!(lines[1].starts_with('#') && lines[3].starts_with(' '));
+    }
+}
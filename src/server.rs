@@ -0,0 +1,211 @@
+//! Headless control over TCP with newline delimited JSON.
+//!
+//! Every request is one JSON object per line, answered by one line:
+//! `{"ok":true}` or `{"ok":false,"error":"..."}`. Requests name a `command`
+//! of the stdin console and its `value`, like `{"command":"bpm","value":120}`
+//! or `{"command":"loop","value":"3-4"}`. Notes are triggered with
+//! `{"command":"note_on","key":60,"velocity":100}` and `{"command":"note_off","key":60}`.
+//!
+//! Only the commands that are safe to take from the network are accepted,
+//! none of those reading or writing files, such as `save`, nor `stop`.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail};
+
+use crate::control::Command;
+use crate::midi::Message;
+use playground::json::Value;
+
+/// Longest request line taken, beyond which the client is hung up on.
+const MAX_LINE: u64 = 8 * 1024;
+
+/// Turns one request into a command.
+pub fn parse_request(line: &str) -> Result<Command, anyhow::Error> {
+    let request = Value::parse(line)?;
+    let command = request
+        .get("command")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("missing command"))?;
+    let key = || -> Result<u8, anyhow::Error> {
+        match request.get("key").and_then(Value::as_f64) {
+            Some(key) if (0.0..128.0).contains(&key) => Ok(key as u8),
+            _ => Err(anyhow!("missing or invalid key")),
+        }
+    };
+    match command {
+        "note_on" => {
+            let velocity = request
+                .get("velocity")
+                .and_then(Value::as_f64)
+                .unwrap_or(100.0);
+            let velocity = velocity.clamp(1.0, 127.0) as u8;
+            let message = Message::NoteOn {
+                key: key()?,
+                velocity,
+            };
+            Ok(Command::Midi(message, Instant::now()))
+        }
+        "note_off" => Ok(Command::Midi(
+            Message::NoteOff { key: key()? },
+            Instant::now(),
+        )),
+        _ => {
+            let parsed = match request.get("value") {
+                None => Command::parse(command)?,
+                Some(Value::String(value)) => Command::parse(&format!("{} {}", command, value))?,
+                Some(value) => Command::parse(&format!("{} {}", command, value))?,
+            };
+            if !is_allowed(&parsed) {
+                bail!("{} is not taken over the network", command);
+            }
+            Ok(parsed)
+        }
+    }
+}
+
+/// Whether `command` is safe to take from the network: it neither reads
+/// nor writes files, and doesn't end playback.
+fn is_allowed(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Voices(_)
+            | Command::Loop(_)
+            | Command::Jump(_)
+            | Command::Bpm(_)
+            | Command::Tap(_)
+            | Command::Hit(..)
+            | Command::Play(..)
+            | Command::Record(_)
+            | Command::Looper(_)
+            | Command::Set(..)
+            | Command::Crossfade(_)
+            | Command::Switch
+            | Command::Compare
+            | Command::Keep(_)
+            | Command::Randomize(_)
+            | Command::Lock(_)
+            | Command::Morph(_)
+            | Command::Evolve(_)
+            | Command::Rate(_)
+            | Command::Panic
+            | Command::Bypass(_)
+            | Command::Mute(_)
+            | Command::Solo(_)
+            | Command::Width(_)
+            | Command::Filter(_)
+            | Command::Position(..)
+    )
+}
+
+/// Listens on `address` and forwards the requests of every client on background threads.
+pub fn spawn(address: &str, sender: Sender<Command>) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            std::thread::spawn(move || serve(stream.try_clone()?, stream, sender));
+        }
+    });
+    Ok(())
+}
+
+/// Answers the requests read from `reader` on `writer`, a line each.
+fn serve(
+    reader: impl Read,
+    mut writer: impl Write,
+    sender: Sender<Command>,
+) -> Result<(), anyhow::Error> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader).take(MAX_LINE).read_line(&mut line)? as u64;
+        if read == 0 {
+            return Ok(());
+        }
+        if read == MAX_LINE && !line.ends_with('\n') {
+            writeln!(writer, "{}", response(Err(anyhow!("line too long"))))?;
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match parse_request(&line) {
+            Ok(command) => {
+                sender.send(command)?;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        };
+        writeln!(writer, "{}", response(result))?;
+    }
+}
+
+pub fn response(result: Result<(), anyhow::Error>) -> Value {
+    match result {
+        Ok(()) => Value::Object(vec![("ok".into(), Value::Bool(true))]),
+        Err(err) => Value::Object(vec![
+            ("ok".into(), Value::Bool(false)),
+            ("error".into(), Value::String(err.to_string())),
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            parse_request(r#"{"command":"bpm","value":120}"#).unwrap(),
+            Command::Bpm(120.0)
+        );
+        assert_eq!(
+            parse_request(r#"{"command":"voices","value":"4"}"#).unwrap(),
+            Command::Voices(4)
+        );
+        assert!(matches!(
+            parse_request(r#"{"command":"note_on","key":60}"#).unwrap(),
+            Command::Midi(
+                Message::NoteOn {
+                    key: 60,
+                    velocity: 100
+                },
+                _
+            )
+        ));
+        assert!(parse_request(r#"{"command":"note_off","key":300}"#).is_err());
+        assert!(parse_request(r#"{"value":1}"#).is_err());
+        // Nothing that touches files, nor ending playback.
+        assert!(parse_request(r#"{"command":"save","value":"/tmp/x.json"}"#).is_err());
+        assert!(parse_request(r#"{"command":"graph","value":"/tmp/x.dot"}"#).is_err());
+        assert!(parse_request(r#"{"command":"morph","value":"a.json b.json"}"#).is_err());
+        assert!(parse_request(r#"{"command":"stop"}"#).is_err());
+        assert_eq!(
+            parse_request(r#"{"command":"morph","value":0.5}"#).unwrap(),
+            Command::Morph(0.5)
+        );
+    }
+
+    #[test]
+    fn test_hangs_up_on_long_lines() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let requests = format!(
+            "{}\n{{\"command\":\"bpm\",\"value\":120}}\n",
+            "x".repeat(MAX_LINE as usize)
+        );
+        let mut responses = vec![];
+        serve(requests.as_bytes(), &mut responses, sender).unwrap();
+        assert_eq!(
+            String::from_utf8(responses).unwrap(),
+            "{\"ok\":false,\"error\":\"line too long\"}\n"
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,1357 @@
+//! Command line settings.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail};
+
+use crate::icecast::Mount;
+use crate::keys::Bindings;
+use crate::midi::FILTER_CONTROLLER;
+use crate::midi_out::MidiTrack;
+use crate::output::{Cue, DeviceOptions};
+use playground::arpeggio::Arpeggio;
+use playground::arrangement::{Arrangement, Section, Track};
+use playground::autotune;
+use playground::bassline::Style;
+use playground::convolution::Response;
+use playground::desk::Setup;
+use playground::dither::Dither;
+use playground::drums::{Drum, Groove};
+use playground::feedback::Echo;
+use playground::fill::Fill;
+use playground::groove::Template;
+use playground::humanize::HumanizeAmount;
+use playground::instrument::Instrument;
+use playground::key::Key;
+use playground::loudness::Target;
+use playground::mapping::{self, Zone};
+use playground::meter::Meter;
+use playground::mixer::Mixer;
+use playground::modulation::Modulation;
+use playground::monitor;
+use playground::param::{ParamRegistry, Spec};
+use playground::project::Project;
+use playground::quantize::Quantize;
+use playground::reverb;
+use playground::sampler::{self, Alternation, Sampler, Samplers};
+use playground::scala;
+use playground::signal::Signal;
+use playground::song::song;
+use playground::stereo::MAX_WIDTH;
+use playground::stream::Streamer;
+use playground::strum::Strum;
+use playground::transport::{LoopRegion, BPM};
+use playground::trigger::Trigger;
+use playground::tuning::Tuning;
+use playground::velocity::{Accent, Curve};
+use playground::vocoder;
+
+/// Seconds of the jam partner's output buffered unless `--jam-buffer` is given.
+const JAM_BUFFER: f64 = 0.02;
+
+pub struct Settings {
+    /// Project whose song and session are played instead of the default
+    /// song, if any.
+    pub project: Option<Project>,
+    /// Maximum number of simultaneously sounding voices.
+    pub voices: usize,
+    pub bpm: f64,
+    /// Bars to loop from the start, if any.
+    pub loop_region: Option<LoopRegion>,
+    /// Metronome bars played before the song starts and before recording
+    /// begins, none or 1 to 2.
+    pub count_in: usize,
+    /// Time signatures replacing those of the song, if any.
+    pub meter: Option<Meter>,
+    /// Tuning of the song and of played notes.
+    pub tuning: Tuning,
+    /// Key signature that notes are shown in.
+    pub key: Key,
+    /// Random jitter applied to the notes of the song.
+    pub humanize: HumanizeAmount,
+    /// Grid that played notes are recorded on, if any.
+    pub quantize: Option<Quantize>,
+    /// Raw MIDI device to read notes from, if any.
+    pub midi: Option<String>,
+    /// Zero based input channel to read trigger or clock pulses off, and
+    /// what they do, if any.
+    pub trigger: Option<(usize, Trigger)>,
+    /// Zero based input channels of pads, each playing a drum on every hit.
+    pub drum_inputs: Vec<(usize, Drum)>,
+    /// Tracks sending their pitch and gate as control voltages to a pair of
+    /// zero based output channels instead of playing.
+    pub cv: Vec<(usize, usize)>,
+    /// MIDI output port to send the notes of MIDI tracks to, by a part of
+    /// its name, if any.
+    pub midi_out: Option<String>,
+    /// Tracks played by external synths instead of here.
+    pub midi_tracks: Vec<(usize, MidiTrack)>,
+    /// Follow the clock, start, stop and song position of the MIDI input.
+    pub midi_clock: bool,
+    /// Chase the MIDI Time Code of the MIDI input.
+    pub mtc: bool,
+    /// MIDI controller turning the DJ filter.
+    pub filter_controller: u8,
+    /// Where the metronome is heard.
+    pub cue: Cue,
+    /// Zero based output channel pair playing the monitor mix, if any.
+    pub monitor: Option<usize>,
+    /// Levels of tracks in the monitor mix, set by name while playing.
+    pub monitor_levels: Vec<(usize, f64)>,
+    /// Impulse response of the reverb that tracks send to.
+    pub reverb: Option<String>,
+    /// Send levels of tracks to the reverb, zero based.
+    pub sends: Vec<(usize, f64)>,
+    /// Correction impulse response or EQ curve of the speakers and the room,
+    /// applied to the main output as it is played and nowhere else.
+    pub correction: Option<String>,
+    /// Zero based channel of the default input played live through the amp.
+    pub amp: Option<usize>,
+    /// Impulse response of the cabinet of the amp.
+    pub cabinet: Option<String>,
+    /// Zero based channel of the default input to tune, instead of playing.
+    pub tuner: Option<usize>,
+    /// Zero based channel of the default input tracked for notes, which
+    /// play the live instrument as MIDI notes do.
+    pub pitch_input: Option<usize>,
+    /// Zero based channel of the default input spoken through the vocoder,
+    /// and how many bands it is split into.
+    pub vocoder: Option<usize>,
+    pub vocoder_bands: usize,
+    /// Zero based channel of the default input corrected to the key, and the
+    /// milliseconds its pitch glides to the notes in.
+    pub autotune: Option<usize>,
+    pub autotune_speed: f64,
+    /// Zero based channel of the default input whose beat the tempo and the
+    /// bars follow.
+    pub beat_input: Option<usize>,
+    /// Output latency in seconds of the main and the second output device,
+    /// which the earlier one is delayed by the difference of.
+    pub latency: f64,
+    pub cue_latency: f64,
+    /// Address to accept JSON control connections on instead of reading stdin.
+    pub server: Option<String>,
+    /// Keys of the computer keyboard played like a piano on the console, if
+    /// it is.
+    pub keys: Option<Bindings>,
+    /// Address to serve the web UI on, with its WebSocket on the port after
+    /// it, if any.
+    pub websocket: Option<String>,
+    /// Icecast mount to broadcast the master output to, if any.
+    pub icecast: Option<Mount>,
+    /// Address of the instance to jam with, if any.
+    pub jam: Option<String>,
+    /// Address to receive the jam partner on, by default on the port of the
+    /// partner on every interface.
+    pub jam_listen: Option<String>,
+    /// Seconds of the partner's output buffered against the jitter of the network.
+    pub jam_buffer: f64,
+    /// Follow the song of the jam partner.
+    pub jam_follow: bool,
+    pub output: DeviceOptions,
+    /// Print the output devices and exit.
+    pub list_devices: bool,
+    /// Measure the round trip latency from the output back to this zero
+    /// based channel of the default input, and exit.
+    pub measure_latency: Option<usize>,
+    /// Play this test signal instead of a song.
+    pub test_tone: Option<Signal>,
+    /// Identify each output channel in turn instead of playing a song.
+    pub channel_check: bool,
+    /// Stereo width of the master bus, 1 leaves it unchanged.
+    pub width: f64,
+    /// Place tracks binaurally for headphones instead of panning them.
+    pub binaural: bool,
+    /// How far panned voices spread by pitch, from 0 to 1.
+    pub pitch_pan: f64,
+    /// Show an oscilloscope of the output at the top of the terminal.
+    pub scope: bool,
+    /// Show level meters of every track and the master.
+    pub meters: bool,
+    /// Strumming of the accords of tracks.
+    pub strums: Vec<(usize, Strum)>,
+    /// Arpeggiators of tracks.
+    pub arpeggios: Vec<(usize, Arpeggio)>,
+    /// Basslines added along with the accords of tracks.
+    pub basslines: Vec<(usize, Style)>,
+    /// Grooves added on drum tracks of their own.
+    pub drums: Vec<Groove>,
+    /// Fills ending repetitions of the patterns of tracks.
+    pub fills: Vec<(usize, Fill)>,
+    /// Velocity curves of tracks.
+    pub velocities: Vec<(usize, Curve)>,
+    /// Accent patterns of tracks.
+    pub accents: Vec<(usize, Accent)>,
+    /// Groove templates of tracks, read from MIDI files.
+    pub grooves: Vec<(usize, Template)>,
+    /// Paths of the recordings, SFZ instruments or SoundFont presets that
+    /// samplers play tracks from instead of their instruments.
+    pub samplers: Vec<(usize, String)>,
+    /// Recordings mapped onto the keys and velocities of the samplers of
+    /// tracks, zone by zone.
+    pub zones: Vec<(usize, Zone)>,
+    /// Tracks whose recording is chopped into slices at its transients.
+    pub slices: Vec<usize>,
+    /// Tempos that the recordings of tracks were played at, which they are
+    /// stretched from to the one of the song.
+    pub stretches: Vec<(usize, f64)>,
+    /// Semitones that the recordings of tracks are shifted by.
+    pub sample_pitches: Vec<(usize, f64)>,
+    /// How the samplers of tracks pick among the recordings that a note
+    /// plays.
+    pub alternations: Vec<(usize, Alternation)>,
+    /// Tracks whose recording is streamed from disk while playing live
+    /// instead of loaded.
+    pub streams: Vec<usize>,
+    /// Modulations of tracks by the envelopes of tracks.
+    pub modulations: Vec<(usize, Modulation)>,
+    /// Dub delays on tracks, after their modulations.
+    pub echoes: Vec<(usize, Echo)>,
+    /// Second banks of tracks, as (pattern, repeat count) pairs.
+    pub banks: Vec<(usize, Vec<(usize, usize)>)>,
+    /// Track to show the pattern of as a piano roll, if any.
+    pub roll: Option<usize>,
+    /// Track whose patterns played notes are recorded into.
+    pub record_track: usize,
+    /// WAV, FLAC or Ogg Vorbis file to render the song to instead of playing it, if any.
+    pub render: Option<String>,
+    /// Directory to write a file of each track of a render into, if any.
+    pub stems: Option<String>,
+    /// Level that renders are normalized to, if any.
+    pub normalize: Option<Target>,
+    /// PNG file to draw the spectrogram of a render into, if any.
+    pub spectrogram: Option<String>,
+    /// SVG or PNG file to plot the start of the output into instead of playing it, if any.
+    pub plot: Option<String>,
+    /// How much of the output to plot.
+    pub plot_seconds: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            project: None,
+            voices: 8,
+            bpm: 160.0,
+            loop_region: None,
+            count_in: 0,
+            meter: None,
+            tuning: Tuning::default(),
+            key: Key::default(),
+            humanize: HumanizeAmount::default(),
+            quantize: None,
+            midi: None,
+            trigger: None,
+            drum_inputs: vec![],
+            cv: vec![],
+            midi_out: None,
+            midi_clock: false,
+            mtc: false,
+            midi_tracks: vec![],
+            filter_controller: FILTER_CONTROLLER,
+            cue: Cue::Main,
+            monitor: None,
+            monitor_levels: vec![],
+            reverb: None,
+            sends: vec![],
+            correction: None,
+            amp: None,
+            cabinet: None,
+            tuner: None,
+            pitch_input: None,
+            vocoder: None,
+            vocoder_bands: vocoder::BANDS,
+            autotune: None,
+            autotune_speed: autotune::SPEED.default,
+            beat_input: None,
+            latency: 0.0,
+            cue_latency: 0.0,
+            server: None,
+            keys: None,
+            websocket: None,
+            icecast: None,
+            jam: None,
+            jam_listen: None,
+            jam_buffer: JAM_BUFFER,
+            jam_follow: false,
+            output: DeviceOptions::default(),
+            list_devices: false,
+            measure_latency: None,
+            test_tone: None,
+            channel_check: false,
+            width: 1.0,
+            binaural: false,
+            pitch_pan: 0.0,
+            scope: false,
+            meters: false,
+            strums: vec![],
+            arpeggios: vec![],
+            basslines: vec![],
+            drums: vec![],
+            fills: vec![],
+            velocities: vec![],
+            accents: vec![],
+            grooves: vec![],
+            samplers: vec![],
+            zones: vec![],
+            slices: vec![],
+            streams: vec![],
+            stretches: vec![],
+            sample_pitches: vec![],
+            alternations: vec![],
+            modulations: vec![],
+            echoes: vec![],
+            banks: vec![],
+            roll: None,
+            record_track: 0,
+            render: None,
+            stems: None,
+            normalize: None,
+            spectrogram: None,
+            plot: None,
+            plot_seconds: 2.0,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_args() -> Result<Self, anyhow::Error> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, anyhow::Error> {
+        let mut settings = Self::default();
+        let mut keyboard = None;
+        // Options given, for those that need others whatever their value.
+        let mut given = HashSet::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            given.insert(arg.clone());
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                // Before the settings that change it, which follow.
+                "--project" => {
+                    let project = Project::load(&value()?)?;
+                    settings.bpm = project.bpm;
+                    settings.tuning = project.song.tuning.clone();
+                    settings.key = project.song.key;
+                    settings.filter_controller = project.filter_controller;
+                    settings.midi_tracks = project
+                        .midi_tracks
+                        .iter()
+                        .map(|&(track, channel, latency)| (track, MidiTrack { channel, latency }))
+                        .collect();
+                    if let Some(&(_, width)) =
+                        project.params.iter().find(|(name, _)| name == "width")
+                    {
+                        settings.width = width;
+                    }
+                    settings.project = Some(project);
+                }
+                "--voices" => settings.voices = parse_voices(&value()?)?,
+                "--bpm" => settings.bpm = parse_bpm(&value()?)?,
+                "--loop" => settings.loop_region = Some(LoopRegion::parse(&value()?)?),
+                "--count-in" => settings.count_in = parse_count_in(&value()?)?,
+                "--humanize" => settings.humanize = HumanizeAmount::parse(&value()?)?,
+                "--quantize" => settings.quantize = Some(Quantize::parse(&value()?)?),
+                "--midi" => settings.midi = Some(value()?),
+                "--filter-controller" => settings.filter_controller = parse_controller(&value()?)?,
+                "--trigger" => {
+                    let value = value()?;
+                    let (channel, trigger) = value
+                        .split_once(':')
+                        .ok_or_else(|| anyhow!("expected CHANNEL:TRIGGER, got {}", value))?;
+                    settings.trigger = Some((parse_channel(channel)?, Trigger::parse(trigger)?));
+                }
+                "--drum-input" => {
+                    let value = value()?;
+                    let (channel, drum) = value
+                        .split_once(':')
+                        .ok_or_else(|| anyhow!("expected CHANNEL:DRUM, got {}", value))?;
+                    let pad = (parse_channel(channel)?, Drum::parse(drum)?);
+                    settings.drum_inputs.push(pad);
+                }
+                "--midi-clock" => settings.midi_clock = true,
+                "--mtc" => settings.mtc = true,
+                "--midi-out" => settings.midi_out = Some(value()?),
+                "--midi-track" => settings
+                    .midi_tracks
+                    .push(parse_per_track(&value()?, MidiTrack::parse)?),
+                "--cv" => settings.cv.push(parse_per_track(&value()?, parse_channel)?),
+                "--cue-channels" => settings.cue = Cue::Channels(parse_channel(&value()?)?),
+                "--cue-device" => settings.cue = Cue::Device(value()?),
+                "--cue-mix" => settings.cue = Cue::Mix(value()?),
+                "--monitor" => settings.monitor = Some(parse_channel(&value()?)?),
+                "--monitor-level" => settings
+                    .monitor_levels
+                    .push(parse_per_track(&value()?, parse_monitor_level)?),
+                "--reverb" => settings.reverb = Some(value()?),
+                "--send" => settings.sends.push(parse_per_track(&value()?, parse_send)?),
+                "--correction" => settings.correction = Some(value()?),
+                "--amp" => settings.amp = Some(parse_channel(&value()?)?),
+                "--cabinet" => settings.cabinet = Some(value()?),
+                "--tuner" => settings.tuner = Some(parse_channel(&value()?)?),
+                "--pitch-input" => settings.pitch_input = Some(parse_channel(&value()?)?),
+                "--vocoder" => settings.vocoder = Some(parse_channel(&value()?)?),
+                "--vocoder-bands" => settings.vocoder_bands = parse_vocoder_bands(&value()?)?,
+                "--autotune" => settings.autotune = Some(parse_channel(&value()?)?),
+                "--autotune-speed" => settings.autotune_speed = parse_autotune_speed(&value()?)?,
+                "--beat-input" => settings.beat_input = Some(parse_channel(&value()?)?),
+                "--latency" => settings.latency = parse_latency(&value()?)?,
+                "--cue-latency" => settings.cue_latency = parse_latency(&value()?)?,
+                "--server" => settings.server = Some(value()?),
+                "--piano" => settings.keys = Some(Bindings::default()),
+                "--keys" => settings.keys = Some(Bindings::parse(&read(&value()?)?)?),
+                "--websocket" => settings.websocket = Some(value()?),
+                "--icecast" => settings.icecast = Some(Mount::parse(&value()?)?),
+                "--jam" => settings.jam = Some(value()?),
+                "--jam-listen" => settings.jam_listen = Some(value()?),
+                "--jam-buffer" => settings.jam_buffer = parse_latency(&value()?)?,
+                "--jam-follow" => settings.jam_follow = true,
+                "--host" => settings.output.host = Some(value()?),
+                "--device" => settings.output.device = Some(value()?),
+                "--sample-rate" => settings.output.sample_rate = Some(value()?.parse()?),
+                "--buffer" => settings.output.buffer = Some(value()?.parse()?),
+                "--dither" => settings.output.dither = Dither::parse(&value()?)?,
+                "--list-devices" => settings.list_devices = true,
+                "--measure-latency" => settings.measure_latency = Some(parse_channel(&value()?)?),
+                "--test-tone" => settings.test_tone = Some(Signal::parse(&value()?)?),
+                "--channel-check" => settings.channel_check = true,
+                "--width" => settings.width = parse_width(&value()?)?,
+                "--binaural" => settings.binaural = true,
+                "--scope" => settings.scope = true,
+                "--meters" => settings.meters = true,
+                "--render" => settings.render = Some(value()?),
+                "--stems" => settings.stems = Some(value()?),
+                "--normalize" => settings.normalize = Some(Target::parse(&value()?)?),
+                "--spectrogram" => settings.spectrogram = Some(value()?),
+                "--plot" => settings.plot = Some(value()?),
+                "--plot-seconds" => settings.plot_seconds = parse_seconds(&value()?)?,
+                "--strum" => settings
+                    .strums
+                    .push(parse_per_track(&value()?, Strum::parse)?),
+                "--bass" => settings
+                    .basslines
+                    .push(parse_per_track(&value()?, Style::parse)?),
+                "--drums" => settings.drums.push(Groove::parse(&value()?)?),
+                "--fill" => settings
+                    .fills
+                    .push(parse_per_track(&value()?, Fill::parse)?),
+                "--velocity" => settings
+                    .velocities
+                    .push(parse_per_track(&value()?, Curve::parse)?),
+                "--accent" => settings
+                    .accents
+                    .push(parse_per_track(&value()?, Accent::parse)?),
+                "--groove" => {
+                    let (index, path) = parse_per_track(&value()?, |path| Ok(path.to_string()))?;
+                    let template = Template::parse(&read_bytes(&path)?)?;
+                    settings.grooves.push((index, template))
+                }
+                "--sampler" => settings
+                    .samplers
+                    .push(parse_per_track(&value()?, |path| Ok(path.to_string()))?),
+                "--zone" => settings
+                    .zones
+                    .push(parse_per_track(&value()?, Zone::parse)?),
+                "--slice" => settings.slices.push(parse_track(&value()?)?),
+                "--stream" => settings.streams.push(parse_track(&value()?)?),
+                "--stretch" => settings
+                    .stretches
+                    .push(parse_per_track(&value()?, parse_bpm)?),
+                "--sample-pitch" => settings
+                    .sample_pitches
+                    .push(parse_per_track(&value()?, parse_sample_pitch)?),
+                "--alternate" => settings
+                    .alternations
+                    .push(parse_per_track(&value()?, Alternation::parse)?),
+                "--mod" => settings
+                    .modulations
+                    .push(parse_per_track(&value()?, Modulation::parse)?),
+                "--echo" => settings
+                    .echoes
+                    .push(parse_per_track(&value()?, Echo::parse)?),
+                "--bank" => settings
+                    .banks
+                    .push(parse_per_track(&value()?, parse_sections)?),
+                "--arpeggio" => {
+                    let arpeggio = parse_per_track(&value()?, Arpeggio::parse)?;
+                    settings.arpeggios.push(arpeggio)
+                }
+                "--roll" => settings.roll = Some(parse_track(&value()?)?),
+                "--record-track" => settings.record_track = parse_track(&value()?)?,
+                "--pitch-pan" => settings.pitch_pan = parse_pitch_pan(&value()?)?,
+                "--meter" => settings.meter = Some(Meter::parse(&value()?)?),
+                "--edo" => settings.tuning = Tuning::edo(parse_edo(&value()?)?),
+                "--scl" => settings.tuning = scala::scale(&read(&value()?)?)?,
+                "--key" => settings.key = Key::parse(&value()?)?,
+                "--kbm" => keyboard = Some(scala::keyboard(&read(&value()?)?)?),
+                _ => bail!("unknown argument: {}", arg),
+            }
+        }
+        // After the scale, wherever it was given.
+        if let Some(keyboard) = keyboard {
+            settings.tuning = settings.tuning.with_keyboard(keyboard)?;
+        }
+        if settings.latency + settings.cue_latency > 0.0 && settings.cue.device().is_none() {
+            bail!("latencies line up a second device, given by --cue-device or --cue-mix");
+        }
+        if settings.spectrogram.is_some() && settings.render.is_none() {
+            bail!("--spectrogram needs --render");
+        }
+        if let Some(path) = &settings.render {
+            let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+            if let Some("opus") = extension {
+                bail!("there is no Opus encoder, render to .ogg for Vorbis instead");
+            }
+        }
+        if settings.jam.is_none()
+            && (settings.jam_follow
+                || settings.jam_listen.is_some()
+                || given.contains("--jam-buffer"))
+        {
+            bail!("--jam-follow, --jam-listen and --jam-buffer need --jam");
+        }
+        if settings.cabinet.is_some() && settings.amp.is_none() {
+            bail!("--cabinet needs --amp");
+        }
+        if given.contains("--vocoder-bands") && settings.vocoder.is_none() {
+            bail!("--vocoder-bands needs --vocoder");
+        }
+        if given.contains("--autotune-speed") && settings.autotune.is_none() {
+            bail!("--autotune-speed needs --autotune");
+        }
+        if !settings.sends.is_empty() && settings.reverb.is_none() {
+            bail!("--send needs a --reverb to send to");
+        }
+        if settings.stems.is_some() && settings.render.is_none() {
+            bail!("--stems needs --render");
+        }
+        if settings.normalize.is_some() && settings.render.is_none() {
+            bail!("--normalize needs --render");
+        }
+        let mapped = |track: &usize| settings.zones.iter().any(|(found, _)| found == track);
+        if let Some((track, _)) = settings.samplers.iter().find(|(track, _)| mapped(track)) {
+            bail!("track {} has both a --sampler and a --zone", track + 1);
+        }
+        let sampled = |track: &usize| {
+            mapped(track) || settings.samplers.iter().any(|(found, _)| found == track)
+        };
+        if let Some(track) = settings.slices.iter().find(|track| !sampled(track)) {
+            bail!("--slice needs a --sampler or --zone on track {}", track + 1);
+        }
+        if let Some((track, _)) = settings.stretches.iter().find(|(track, _)| !sampled(track)) {
+            bail!(
+                "--stretch needs a --sampler or --zone on track {}",
+                track + 1
+            );
+        }
+        let unsampled = settings
+            .sample_pitches
+            .iter()
+            .find(|(track, _)| !sampled(track));
+        if let Some((track, _)) = unsampled {
+            bail!(
+                "--sample-pitch needs a --sampler or --zone on track {}",
+                track + 1
+            );
+        }
+        let unsampled = settings
+            .alternations
+            .iter()
+            .find(|(track, _)| !sampled(track));
+        if let Some((track, _)) = unsampled {
+            bail!(
+                "--alternate needs a --sampler or --zone on track {}",
+                track + 1
+            );
+        }
+        for track in &settings.streams {
+            let Some((_, path)) = settings.samplers.iter().find(|(found, _)| found == track) else {
+                bail!("--stream needs a --sampler on track {}", track + 1);
+            };
+            if !path.ends_with(".wav") {
+                bail!("--stream plays WAV files, not {}", path);
+            }
+            let changed =
+                |changes: &[(usize, f64)]| changes.iter().any(|(found, _)| found == track);
+            if settings.slices.contains(track)
+                || changed(&settings.stretches)
+                || changed(&settings.sample_pitches)
+            {
+                bail!(
+                    "track {} is streamed, which plays it straight through without slicing, stretching or shifting it",
+                    track + 1
+                );
+            }
+        }
+        Ok(settings)
+    }
+
+    /// The song with the settings that change it applied.
+    pub fn song(&self) -> Result<Arrangement, anyhow::Error> {
+        let mut song = match &self.project {
+            Some(project) => project.song.clone(),
+            None => song(),
+        };
+        if let Some(meter) = &self.meter {
+            song.meter = meter.clone();
+        }
+        song.tuning = self.tuning.clone();
+        song.key = self.key;
+        fn track(song: &mut Arrangement, index: usize) -> Result<&mut Track, anyhow::Error> {
+            let tracks = song.tracks.len();
+            song.tracks
+                .get_mut(index)
+                .ok_or_else(|| anyhow!("no track {}, there are {}", index + 1, tracks))
+        }
+        for &(index, strum) in &self.strums {
+            track(&mut song, index)?.strum = Some(strum);
+        }
+        for &(index, arpeggio) in &self.arpeggios {
+            track(&mut song, index)?.arpeggio = Some(arpeggio);
+        }
+        for &(index, style) in &self.basslines {
+            track(&mut song, index)?;
+            song.bass_track(index, style, Instrument::Pluck);
+        }
+        for groove in &self.drums {
+            song.drum_track(groove);
+        }
+        for (index, sections) in &self.banks {
+            if let Some(&(pattern, _)) = sections.iter().find(|(p, _)| *p >= song.patterns.len()) {
+                bail!(
+                    "no pattern {}, there are {}",
+                    pattern + 1,
+                    song.patterns.len()
+                );
+            }
+            let sections = sections
+                .iter()
+                .map(|&(pattern, repeat)| Section::new(pattern, repeat))
+                .collect();
+            track(&mut song, *index)?.b_sections = Some(sections);
+        }
+        for &(index, curve) in &self.velocities {
+            track(&mut song, index)?.velocity = curve;
+        }
+        for (index, accent) in &self.accents {
+            track(&mut song, *index)?.accent = Some(accent.clone());
+        }
+        for (index, groove) in &self.grooves {
+            track(&mut song, *index)?.groove = Some(groove.clone());
+        }
+        // After the drum tracks, which are the ones usually filled.
+        for (index, fill) in &self.fills {
+            track(&mut song, *index)?;
+            song.fill(*index, fill);
+        }
+        Ok(song)
+    }
+
+    /// What the desk of the tracks is set up with, playing `samplers`.
+    pub fn setup(&self, samplers: Samplers) -> Result<Setup, anyhow::Error> {
+        let reverb = match &self.reverb {
+            Some(path) => Some(Response::load(path).map_err(|err| anyhow!("{}: {}", path, err))?),
+            None => None,
+        };
+        Ok(Setup {
+            voices: self.voices,
+            modulations: self.modulations.clone(),
+            echoes: self.echoes.clone(),
+            reverb,
+            binaural: self.binaural,
+            pitch_pan: self.pitch_pan,
+            humanize: self.humanize,
+            seed: 0,
+            samplers,
+        })
+    }
+
+    /// The session of the project as it was saved, of the tracks that
+    /// `mixer` has, with the width and the send levels given set over it.
+    pub fn restore(&self, params: &ParamRegistry, mixer: &mut Mixer) -> Result<(), anyhow::Error> {
+        if let Some(project) = &self.project {
+            for (name, value) in &project.params {
+                if let Err(err) = params.set(name, *value) {
+                    eprintln!("{}", err);
+                }
+            }
+            let flags = project.muted.iter().zip(&project.soloed);
+            for (track, (&muted, &soloed)) in flags.enumerate().take(mixer.tracks()) {
+                if muted {
+                    mixer.toggle_mute(track);
+                }
+                if soloed {
+                    mixer.toggle_solo(track);
+                }
+            }
+        }
+        params.set("width", self.width)?;
+        for &(track, level) in &self.sends {
+            if let Err(err) = params.set(&format!("send{}", track + 1), level) {
+                bail!("no track {} to send: {}", track + 1, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// The samplers of the tracks, their recordings loaded and sliced.
+    pub fn samplers(&self) -> Result<Samplers, anyhow::Error> {
+        self.load_samplers(None)
+    }
+
+    /// The samplers of the tracks for playing live, streaming the
+    /// recordings of the tracks given by `--stream`. Rendering loads them
+    /// all, since it goes faster than they would be read.
+    pub fn live_samplers(&self) -> Result<Samplers, anyhow::Error> {
+        let streamer = (!self.streams.is_empty()).then(Streamer::spawn);
+        self.load_samplers(streamer.as_ref())
+    }
+
+    fn load_samplers(&self, streamer: Option<&Streamer>) -> Result<Samplers, anyhow::Error> {
+        let mut samplers = Samplers::default();
+        let mut loaded = vec![];
+        for (index, path) in &self.samplers {
+            let sampler = match streamer.filter(|_| self.streams.contains(index)) {
+                Some(streamer) => Sampler::stream(path, streamer),
+                None => Sampler::load(path),
+            };
+            let sampler = sampler.map_err(|err| anyhow!("{}: {}", path, err))?;
+            loaded.push((*index, sampler));
+        }
+        let mut mapped: Vec<usize> = self.zones.iter().map(|&(index, _)| index).collect();
+        mapped.sort_unstable();
+        mapped.dedup();
+        for index in mapped {
+            let zones: Vec<Zone> = self
+                .zones
+                .iter()
+                .filter(|(found, _)| *found == index)
+                .map(|(_, zone)| zone.clone())
+                .collect();
+            loaded.push((index, mapping::load(&zones)?));
+        }
+        for (index, mut sampler) in loaded {
+            let index = &index;
+            if self.slices.contains(index) {
+                sampler.slice();
+            }
+            if let Some(&(_, bpm)) = self.stretches.iter().find(|(found, _)| found == index) {
+                sampler.stretch(bpm);
+            }
+            let pitch = self.sample_pitches.iter().find(|(found, _)| found == index);
+            if let Some(&(_, semitones)) = pitch {
+                sampler.shift(semitones);
+            }
+            let alternation = self.alternations.iter().find(|(found, _)| found == index);
+            if let Some(&(_, alternation)) = alternation {
+                sampler.alternate(alternation);
+            }
+            samplers.insert(*index, sampler);
+        }
+        Ok(samplers)
+    }
+}
+
+pub fn parse_voices(value: &str) -> Result<usize, anyhow::Error> {
+    match value.parse::<usize>()? {
+        0 => bail!("at least one voice is required"),
+        voices => Ok(voices),
+    }
+}
+
+fn parse_count_in(value: &str) -> Result<usize, anyhow::Error> {
+    match value.parse::<usize>()? {
+        bars @ 1..=2 => Ok(bars),
+        _ => bail!("the count-in is 1 or 2 bars"),
+    }
+}
+
+pub fn parse_bpm(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        bpm if BPM.contains(&bpm) => Ok(bpm),
+        _ => bail!(
+            "tempo must be between {} and {} bpm",
+            BPM.start(),
+            BPM.end()
+        ),
+    }
+}
+
+pub fn parse_width(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        width if (0.0..=MAX_WIDTH).contains(&width) => Ok(width),
+        _ => bail!("width must be between 0 and {}", MAX_WIDTH),
+    }
+}
+
+fn parse_pitch_pan(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        amount if (0.0..=1.0).contains(&amount) => Ok(amount),
+        _ => bail!("pitch pan must be between 0 and 1"),
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        seconds if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => bail!("a duration must be positive"),
+    }
+}
+
+fn parse_monitor_level(value: &str) -> Result<f64, anyhow::Error> {
+    let Spec { min, max, .. } = monitor::LEVEL;
+    match value.parse::<f64>()? {
+        level if (min..=max).contains(&level) => Ok(level),
+        _ => bail!("monitor levels go from {} to {}", min, max),
+    }
+}
+
+fn parse_send(value: &str) -> Result<f64, anyhow::Error> {
+    let Spec { min, max, .. } = reverb::SEND;
+    match value.parse::<f64>()? {
+        level if (min..=max).contains(&level) => Ok(level),
+        _ => bail!("send levels go from {} to {}", min, max),
+    }
+}
+
+fn parse_sample_pitch(value: &str) -> Result<f64, anyhow::Error> {
+    let Spec { min, max, .. } = sampler::PITCH;
+    match value.parse::<f64>()? {
+        semitones if (min..=max).contains(&semitones) => Ok(semitones),
+        _ => bail!("samples are shifted by {} to {} semitones", min, max),
+    }
+}
+
+fn parse_vocoder_bands(value: &str) -> Result<usize, anyhow::Error> {
+    match value.parse::<usize>()? {
+        bands if (vocoder::MIN_BANDS..=vocoder::MAX_BANDS).contains(&bands) => Ok(bands),
+        _ => bail!(
+            "the vocoder has from {} to {} bands",
+            vocoder::MIN_BANDS,
+            vocoder::MAX_BANDS
+        ),
+    }
+}
+
+fn parse_autotune_speed(value: &str) -> Result<f64, anyhow::Error> {
+    let Spec { min, max, .. } = autotune::SPEED;
+    match value.parse::<f64>()? {
+        speed if (min..=max).contains(&speed) => Ok(speed),
+        _ => bail!("the correction speed goes from {} to {} ms", min, max),
+    }
+}
+
+/// Parses a latency in milliseconds into seconds.
+fn parse_latency(value: &str) -> Result<f64, anyhow::Error> {
+    match value.parse::<f64>()? {
+        ms if (0.0..=1000.0).contains(&ms) => Ok(ms / 1000.0),
+        _ => bail!("a latency must be between 0 and 1000 ms"),
+    }
+}
+
+fn read(path: &str) -> Result<String, anyhow::Error> {
+    std::fs::read_to_string(path).map_err(|err| anyhow!("cannot read {}: {}", path, err))
+}
+
+fn read_bytes(path: &str) -> Result<Vec<u8>, anyhow::Error> {
+    std::fs::read(path).map_err(|err| anyhow!("cannot read {}: {}", path, err))
+}
+
+/// Parses the number of equal steps to divide the octave into.
+fn parse_edo(value: &str) -> Result<usize, anyhow::Error> {
+    match value.parse::<usize>()? {
+        divisions if (1..=1200).contains(&divisions) => Ok(divisions),
+        _ => bail!("the octave can be divided into 1 to 1200 steps"),
+    }
+}
+
+/// Parses a one based track number into a zero based one.
+pub fn parse_track(value: &str) -> Result<usize, anyhow::Error> {
+    match value.parse::<usize>()? {
+        0 => bail!("tracks are counted from 1"),
+        track => Ok(track - 1),
+    }
+}
+
+/// Parses a one based track and its setting as `TRACK:SETTING`, such as
+/// `2:30,down`.
+fn parse_per_track<T>(
+    value: &str,
+    parse: fn(&str) -> Result<T, anyhow::Error>,
+) -> Result<(usize, T), anyhow::Error> {
+    let (track, setting) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow!("missing track before the setting: {}", value))?;
+    Ok((parse_track(track)?, parse(setting)?))
+}
+
+/// Parses one based patterns with optional repeat counts, such as `2x4,3`,
+/// into zero based (pattern, repeat count) pairs.
+fn parse_sections(value: &str) -> Result<Vec<(usize, usize)>, anyhow::Error> {
+    value
+        .split(',')
+        .map(|section| {
+            let (pattern, repeat) = section.split_once('x').unwrap_or((section, "1"));
+            let pattern = match pattern.trim().parse::<usize>()? {
+                0 => bail!("patterns are counted from 1"),
+                pattern => pattern - 1,
+            };
+            Ok((pattern, repeat.trim().parse()?))
+        })
+        .collect()
+}
+
+fn parse_controller(value: &str) -> Result<u8, anyhow::Error> {
+    match value.parse::<u8>()? {
+        controller @ 0..=119 => Ok(controller),
+        _ => bail!("MIDI controllers go up to 119"),
+    }
+}
+
+/// Parses a one based channel number into a zero based one.
+fn parse_channel(value: &str) -> Result<usize, anyhow::Error> {
+    match value.parse::<usize>()? {
+        0 => bail!("channels are counted from 1"),
+        channel => Ok(channel - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_voices() {
+        assert_eq!(Settings::parse(args(&[])).unwrap().voices, 8);
+        assert_eq!(Settings::parse(args(&["--voices", "3"])).unwrap().voices, 3);
+        assert!(Settings::parse(args(&["--voices", "0"])).is_err());
+        assert!(Settings::parse(args(&["--voices"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_bpm() {
+        assert_eq!(Settings::parse(args(&["--bpm", "92.5"])).unwrap().bpm, 92.5);
+        assert!(Settings::parse(args(&["--bpm", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_count_in() {
+        assert_eq!(Settings::parse(args(&[])).unwrap().count_in, 0);
+        let settings = Settings::parse(args(&["--count-in", "2"])).unwrap();
+        assert_eq!(settings.count_in, 2);
+        assert!(Settings::parse(args(&["--count-in", "0"])).is_err());
+        assert!(Settings::parse(args(&["--count-in", "3"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_edo() {
+        assert_eq!(Settings::parse(args(&[])).unwrap().tuning.divisions(), 12);
+        let settings = Settings::parse(args(&["--edo", "19"])).unwrap();
+        assert_eq!(settings.tuning, Tuning::edo(19));
+        assert!(Settings::parse(args(&["--edo", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let settings = Settings::parse(args(&["--key", "es"])).unwrap();
+        assert_eq!(settings.key.fifths, -6);
+    }
+
+    #[test]
+    fn test_parse_strum() {
+        let settings = Settings::parse(args(&["--strum", "2:30,down", "--strum", "1:10"])).unwrap();
+        assert_eq!(settings.strums[0], (1, Strum::parse("30,down").unwrap()));
+        assert_eq!(settings.strums.len(), 2);
+        assert!(Settings::parse(args(&["--strum", "30"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_arpeggio() {
+        let settings = Settings::parse(args(&["--arpeggio", "1:diverge,2"])).unwrap();
+        assert_eq!(settings.arpeggios[0].1.octaves, 2);
+        assert!(settings.song().unwrap().tracks[0].arpeggio.is_some());
+        let settings = Settings::parse(args(&["--arpeggio", "9:up"])).unwrap();
+        assert!(settings.song().is_err());
+    }
+
+    #[test]
+    fn test_parse_bass() {
+        let settings = Settings::parse(args(&["--bass", "2:walking"])).unwrap();
+        assert_eq!(
+            settings.song().unwrap().tracks.len(),
+            song().tracks.len() + 1
+        );
+    }
+
+    #[test]
+    fn test_parse_drums() {
+        let settings =
+            Settings::parse(args(&["--drums", "bossa", "--drums", "half-time"])).unwrap();
+        let drums = &settings.song().unwrap().tracks[song().tracks.len() + 1];
+        assert_eq!(drums.instrument, Instrument::Drums);
+        assert!(Settings::parse(args(&["--drums", "polka"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_fill() {
+        let fill = format!("{}:4,0.5", song().tracks.len() + 1);
+        let settings = Settings::parse(args(&["--drums", "bossa", "--fill", &fill])).unwrap();
+        let drums = &settings.song().unwrap().tracks[song().tracks.len()];
+        assert_eq!(drums.loop_length, Some(64.0));
+    }
+
+    #[test]
+    fn test_parse_velocity() {
+        let settings =
+            Settings::parse(args(&["--velocity", "1:soft", "--accent", "1:x."])).unwrap();
+        let melody = &settings.song().unwrap().tracks[0];
+        assert_eq!(melody.velocity, Curve::Soft);
+        assert_eq!(melody.accent.as_ref().unwrap().steps, [true, false]);
+    }
+
+    #[test]
+    fn test_parse_bank() {
+        let settings = Settings::parse(args(&["--bank", "1:2x2,4"])).unwrap();
+        let sections = settings.song().unwrap().tracks[0]
+            .b_sections
+            .clone()
+            .unwrap();
+        assert_eq!(sections, [Section::new(1, 2), Section::new(3, 1)]);
+        let settings = Settings::parse(args(&["--bank", "1:99"])).unwrap();
+        assert!(settings.song().is_err());
+        assert!(Settings::parse(args(&["--bank", "1:0x2"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_cue() {
+        assert_eq!(Settings::parse(args(&[])).unwrap().cue, Cue::Main);
+        assert_eq!(
+            Settings::parse(args(&["--cue-channels", "3"])).unwrap().cue,
+            Cue::Channels(2)
+        );
+        assert!(Settings::parse(args(&["--cue-channels", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_filter_controller() {
+        let settings = Settings::parse(args(&["--filter-controller", "74"])).unwrap();
+        assert_eq!(settings.filter_controller, 74);
+        assert!(Settings::parse(args(&["--filter-controller", "120"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_trigger() {
+        let settings = Settings::parse(args(&["--trigger", "2:clock,24"])).unwrap();
+        assert_eq!(settings.trigger, Some((1, Trigger::Clock(24))));
+        assert!(Settings::parse(args(&["--trigger", "clock"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_cv() {
+        let settings = Settings::parse(args(&["--cv", "1:3"])).unwrap();
+        assert_eq!(settings.cv, [(0, 2)]);
+    }
+
+    #[test]
+    fn test_parse_cue_mix() {
+        let settings =
+            Settings::parse(args(&["--cue-mix", "Speakers", "--cue-latency", "25"])).unwrap();
+        assert_eq!(settings.cue.device(), Some("Speakers"));
+        assert_eq!(settings.cue_latency, 0.025);
+        assert!(Settings::parse(args(&["--latency", "25"])).is_err());
+        assert!(Settings::parse(args(&["--latency", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_monitor() {
+        let settings =
+            Settings::parse(args(&["--monitor", "3", "--monitor-level", "2:0.5"])).unwrap();
+        assert_eq!(settings.monitor, Some(2));
+        assert_eq!(settings.monitor_levels, [(1, 0.5)]);
+        assert!(Settings::parse(args(&["--monitor-level", "2:3"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_normalize() {
+        let settings =
+            Settings::parse(args(&["--render", "out.wav", "--normalize", "-14"])).unwrap();
+        assert_eq!(settings.normalize, Some(Target::Loudness(-14.0)));
+        assert!(Settings::parse(args(&["--normalize", "-14"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_dither() {
+        assert_eq!(
+            Settings::parse(args(&[])).unwrap().output.dither,
+            Dither::Tpdf
+        );
+        let settings = Settings::parse(args(&["--dither", "shaped"])).unwrap();
+        assert_eq!(settings.output.dither, Dither::Shaped);
+    }
+
+    #[test]
+    fn test_parse_stems() {
+        assert!(Settings::parse(args(&["--stems", "stems"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_render() {
+        assert!(Settings::parse(args(&["--render", "out.flac"])).is_ok());
+        assert!(Settings::parse(args(&["--render", "out.ogg"])).is_ok());
+        assert!(Settings::parse(args(&["--render", "out.opus"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_icecast() {
+        let settings = Settings::parse(args(&["--icecast", "http://pw@localhost/jam"])).unwrap();
+        assert_eq!(settings.icecast.unwrap().path, "/jam");
+    }
+
+    #[test]
+    fn test_parse_jam() {
+        let settings = Settings::parse(args(&["--jam", "10.0.0.2:9000", "--jam-follow"])).unwrap();
+        assert_eq!(settings.jam.as_deref(), Some("10.0.0.2:9000"));
+        assert!(settings.jam_follow);
+        assert!(Settings::parse(args(&["--jam-buffer", "40"])).is_err());
+        assert!(Settings::parse(args(&["--jam-buffer", "20"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_measure_latency() {
+        let settings = Settings::parse(args(&["--measure-latency", "2"])).unwrap();
+        assert_eq!(settings.measure_latency, Some(1));
+    }
+
+    #[test]
+    fn test_parse_test_tone() {
+        let settings = Settings::parse(args(&["--test-tone", "sine,440"])).unwrap();
+        assert_eq!(settings.test_tone, Some(Signal::Sine(440.0)));
+    }
+
+    #[test]
+    fn test_parse_channel_check() {
+        assert!(
+            Settings::parse(args(&["--channel-check"]))
+                .unwrap()
+                .channel_check
+        );
+    }
+
+    #[test]
+    fn test_parse_reverb() {
+        let settings = Settings::parse(args(&["--reverb", "hall.wav", "--send", "1:0.5"])).unwrap();
+        assert_eq!(settings.reverb.as_deref(), Some("hall.wav"));
+        assert_eq!(settings.sends, [(0, 0.5)]);
+        assert!(Settings::parse(args(&["--send", "1:0.5"])).is_err());
+        assert!(Settings::parse(args(&["--reverb", "hall.wav", "--send", "1:2"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_correction() {
+        let settings = Settings::parse(args(&["--correction", "room.txt"])).unwrap();
+        assert_eq!(settings.correction.as_deref(), Some("room.txt"));
+    }
+
+    #[test]
+    fn test_parse_amp() {
+        let settings = Settings::parse(args(&["--amp", "1", "--cabinet", "4x12.wav"])).unwrap();
+        assert_eq!(settings.amp, Some(0));
+        assert_eq!(settings.cabinet.as_deref(), Some("4x12.wav"));
+        assert!(Settings::parse(args(&["--cabinet", "4x12.wav"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_tuner() {
+        let settings = Settings::parse(args(&["--tuner", "2", "--edo", "19"])).unwrap();
+        assert_eq!((settings.tuner, settings.tuning.divisions()), (Some(1), 19));
+    }
+
+    #[test]
+    fn test_parse_pitch_input() {
+        let settings = Settings::parse(args(&["--pitch-input", "1"])).unwrap();
+        assert_eq!(settings.pitch_input, Some(0));
+    }
+
+    #[test]
+    fn test_parse_vocoder() {
+        let settings = Settings::parse(args(&["--vocoder", "1", "--vocoder-bands", "24"])).unwrap();
+        assert_eq!((settings.vocoder, settings.vocoder_bands), (Some(0), 24));
+        assert!(Settings::parse(args(&["--vocoder", "1", "--vocoder-bands", "2"])).is_err());
+        assert!(Settings::parse(args(&["--vocoder-bands", "24"])).is_err());
+        assert!(Settings::parse(args(&["--vocoder-bands", "16"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_autotune() {
+        let settings =
+            Settings::parse(args(&["--autotune", "1", "--autotune-speed", "0"])).unwrap();
+        assert_eq!((settings.autotune, settings.autotune_speed), (Some(0), 0.0));
+        assert!(Settings::parse(args(&["--autotune", "1", "--autotune-speed", "-5"])).is_err());
+        assert!(Settings::parse(args(&["--autotune-speed", "0"])).is_err());
+        let speed = autotune::SPEED.default.to_string();
+        assert!(Settings::parse(args(&["--autotune-speed", &speed])).is_err());
+    }
+
+    #[test]
+    fn test_parse_beat_input() {
+        let settings = Settings::parse(args(&["--beat-input", "2"])).unwrap();
+        assert_eq!(settings.beat_input, Some(1));
+    }
+
+    #[test]
+    fn test_parse_drum_input() {
+        let settings =
+            Settings::parse(args(&["--drum-input", "1:kick", "--drum-input", "2:snare"])).unwrap();
+        assert_eq!(settings.drum_inputs, [(0, Drum::Kick), (1, Drum::Snare)]);
+        assert!(Settings::parse(args(&["--drum-input", "1:cowbell"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_sampler() {
+        let settings =
+            Settings::parse(args(&["--sampler", "2:break.wav", "--slice", "2"])).unwrap();
+        assert_eq!(settings.samplers, [(1, "break.wav".to_string())]);
+        assert_eq!(settings.slices, [1]);
+        assert!(settings.samplers().is_err());
+        assert!(Settings::parse(args(&["--sampler", "2:break.wav", "--slice", "1"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_stretch() {
+        let settings =
+            Settings::parse(args(&["--sampler", "1:loop.wav", "--stretch", "1:96"])).unwrap();
+        assert_eq!(settings.stretches, [(0, 96.0)]);
+        assert!(Settings::parse(args(&["--stretch", "1:96"])).is_err());
+        assert!(Settings::parse(args(&["--sampler", "1:loop.wav", "--stretch", "1:0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_sample_pitch() {
+        let settings = Settings::parse(args(&[
+            "--sampler",
+            "1:hit.wav",
+            "--sample-pitch",
+            "1:-3.5",
+        ]))
+        .unwrap();
+        assert_eq!(settings.sample_pitches, [(0, -3.5)]);
+        assert!(
+            Settings::parse(args(&["--sampler", "1:hit.wav", "--sample-pitch", "1:30"])).is_err()
+        );
+        assert!(Settings::parse(args(&["--sample-pitch", "1:2"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_alternate() {
+        let settings =
+            Settings::parse(args(&["--sampler", "3:kit.sfz", "--alternate", "3:random"])).unwrap();
+        assert_eq!(settings.alternations, [(2, Alternation::Random)]);
+        assert!(Settings::parse(args(&[
+            "--sampler",
+            "3:kit.sfz",
+            "--alternate",
+            "3:shuffle"
+        ]))
+        .is_err());
+        assert!(Settings::parse(args(&["--alternate", "3:round-robin"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        let settings = Settings::parse(args(&[
+            "--zone",
+            "1:soft.wav,c2-c4,1-80",
+            "--zone",
+            "1:loud.wav,c2-c4,60-127",
+            "--alternate",
+            "1:random",
+        ]))
+        .unwrap();
+        assert_eq!(settings.zones.len(), 2);
+        assert_eq!(settings.zones[1].1.velocities, 60..=127);
+        assert!(settings.samplers().is_err());
+        assert!(Settings::parse(args(&["--zone", "1:a.wav,60", "--sampler", "1:b.wav"])).is_err());
+        assert!(Settings::parse(args(&[
+            "--zone",
+            "1:a.wav,60,1-127",
+            "--sampler",
+            "1:b.wav"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        let settings =
+            Settings::parse(args(&["--sampler", "3:strings.wav", "--stream", "3"])).unwrap();
+        assert_eq!(settings.streams, [2]);
+        assert!(settings.live_samplers().is_err());
+        assert!(Settings::parse(args(&["--stream", "3"])).is_err());
+        assert!(Settings::parse(args(&["--sampler", "3:a.sfz", "--stream", "3"])).is_err());
+        assert!(Settings::parse(args(&[
+            "--sampler",
+            "3:a.wav",
+            "--stream",
+            "3",
+            "--stretch",
+            "3:120"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_echo() {
+        let settings = Settings::parse(args(&["--echo", "2:0.375,0.6,1"])).unwrap();
+        assert_eq!(settings.echoes, [(1, Echo::parse("0.375,0.6,1").unwrap())]);
+        assert!(Settings::parse(args(&["--echo", "2:0.375"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_project() {
+        let mut song = song();
+        song.key = Key::parse("G").unwrap();
+        let tracks = song.tracks.len();
+        let project = Project {
+            metadata: Default::default(),
+            song,
+            bpm: 92.0,
+            muted: vec![false; tracks],
+            soloed: vec![false; tracks],
+            params: vec![("width".into(), 1.5)],
+            filter_controller: 74,
+            midi_tracks: vec![(1, 9, 0.0)],
+        };
+        let path = std::env::temp_dir().join("sound-settings-project.json");
+        let path = path.to_str().unwrap();
+        project.save(path).unwrap();
+        let settings = Settings::parse(args(&["--project", path, "--bpm", "100"])).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(settings.bpm, 100.0);
+        assert_eq!(settings.width, 1.5);
+        assert_eq!(settings.filter_controller, 74);
+        assert_eq!(settings.midi_tracks[0].1.channel, 9);
+        assert_eq!(settings.song().unwrap().key.fifths, 1);
+        assert!(Settings::parse(args(&["--project", "missing.json"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_device() {
+        let settings = Settings::parse(args(&["--host", "asio", "--buffer", "64"])).unwrap();
+        assert_eq!(settings.output.host.as_deref(), Some("asio"));
+        assert_eq!(settings.output.buffer, Some(64));
+        assert!(
+            Settings::parse(args(&["--list-devices"]))
+                .unwrap()
+                .list_devices
+        );
+    }
+}
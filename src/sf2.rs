@@ -0,0 +1,423 @@
+//! Reading of SoundFont 2 banks, such as the General MIDI ones: the zones of
+//! the instruments of a preset, each a range of keys and velocities playing
+//! one of the samples of the bank, turned into the regions of a sampler.
+//! Only the left half of stereo samples is read, so that they play mono.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+
+use crate::sampler::{LoopMode, Region, Sampler};
+
+/// Generators read, by their number in the file.
+const START_OFFSET: u16 = 0;
+const END_OFFSET: u16 = 1;
+const LOOP_START_OFFSET: u16 = 2;
+const LOOP_END_OFFSET: u16 = 3;
+const START_COARSE_OFFSET: u16 = 4;
+const END_COARSE_OFFSET: u16 = 12;
+const INSTRUMENT: u16 = 41;
+const KEY_RANGE: u16 = 43;
+const VELOCITY_RANGE: u16 = 44;
+const LOOP_START_COARSE_OFFSET: u16 = 45;
+const ATTENUATION: u16 = 48;
+const LOOP_END_COARSE_OFFSET: u16 = 50;
+const COARSE_TUNE: u16 = 51;
+const FINE_TUNE: u16 = 52;
+const SAMPLE: u16 = 53;
+const SAMPLE_MODES: u16 = 54;
+const ROOT_KEY: u16 = 58;
+/// Sample types that are right halves of stereo samples, or in ROM.
+const RIGHT_SAMPLE: u16 = 4;
+const ROM_SAMPLE: u16 = 0x8000;
+
+/// Generators of a zone, by their number, with the amounts they are set to.
+type Generators = HashMap<u16, [u8; 2]>;
+
+/// Loads `program` of `bank` of the SoundFont at `path` into a sampler.
+pub fn load(path: &str, bank: u16, program: u16) -> Result<Sampler, anyhow::Error> {
+    let bytes = std::fs::read(path).map_err(|err| anyhow!("cannot read {}: {}", path, err))?;
+    Ok(Sampler::with_regions(regions(&bytes, bank, program)?))
+}
+
+/// Parses a path with an optional preset as `PATH@PROGRAM` or
+/// `PATH@BANK:PROGRAM`, the first program of the first bank by default.
+pub fn parse_preset(value: &str) -> Result<(&str, u16, u16), anyhow::Error> {
+    let Some((path, preset)) = value.rsplit_once('@') else {
+        return Ok((value, 0, 0));
+    };
+    let (bank, program) = preset.split_once(':').unwrap_or(("0", preset));
+    let number = |value: &str, most: u16| match value.parse::<u16>() {
+        Ok(number) if number <= most => Ok(number),
+        _ => Err(anyhow!("invalid preset: {}", preset)),
+    };
+    Ok((path, number(bank, 16383)?, number(program, 127)?))
+}
+
+/// A chunk of a RIFF file.
+struct Chunk<'a> {
+    id: &'a [u8],
+    data: &'a [u8],
+}
+
+/// The chunks that `bytes` hold one after the other.
+fn chunks(mut bytes: &[u8]) -> Result<Vec<Chunk<'_>>, anyhow::Error> {
+    let mut chunks = vec![];
+    while bytes.len() >= 8 {
+        let size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        if bytes.len() < 8 + size {
+            bail!("SoundFont ends early");
+        }
+        chunks.push(Chunk {
+            id: &bytes[..4],
+            data: &bytes[8..8 + size],
+        });
+        // Chunks start at even offsets.
+        bytes = &bytes[min(8 + size + size % 2, bytes.len())..];
+    }
+    Ok(chunks)
+}
+
+/// The subchunks of the `LIST` chunk of `kind` among `within`.
+fn list<'a>(within: &[Chunk<'a>], kind: &[u8]) -> Result<Vec<Chunk<'a>>, anyhow::Error> {
+    let list = within
+        .iter()
+        .find(|chunk| chunk.id == b"LIST" && chunk.data.starts_with(kind))
+        .ok_or_else(|| anyhow!("SoundFont has no {} list", String::from_utf8_lossy(kind)))?;
+    chunks(&list.data[4..])
+}
+
+/// The records of `size` bytes of the chunk `id` among `within`.
+fn records<'a>(
+    within: &[Chunk<'a>],
+    id: &[u8],
+    size: usize,
+) -> Result<Vec<&'a [u8]>, anyhow::Error> {
+    let chunk = within
+        .iter()
+        .find(|chunk| chunk.id == id)
+        .ok_or_else(|| anyhow!("SoundFont has no {} chunk", String::from_utf8_lossy(id)))?;
+    Ok(chunk.data.chunks_exact(size).collect())
+}
+
+fn u16_at(record: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([record[offset], record[offset + 1]])
+}
+
+fn u32_at(record: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(record[offset..offset + 4].try_into().unwrap())
+}
+
+/// The generators of each zone of each preset or instrument, given their
+/// headers, the index of their first zone at `bag_offset` in them, and
+/// their zones and generators.
+fn zones(
+    headers: &[&[u8]],
+    bag_offset: usize,
+    bags: &[&[u8]],
+    generators: &[&[u8]],
+) -> Result<Vec<Vec<Generators>>, anyhow::Error> {
+    let bag = |header: &[u8]| u16_at(header, bag_offset) as usize;
+    headers
+        .windows(2)
+        .map(|pair| {
+            let (first, last) = (bag(pair[0]), bag(pair[1]));
+            if first > last || last >= bags.len() {
+                bail!("SoundFont zones are out of order");
+            }
+            (first..last)
+                .map(|zone| {
+                    let (start, end) = (
+                        u16_at(bags[zone], 0) as usize,
+                        u16_at(bags[zone + 1], 0) as usize,
+                    );
+                    let generators = generators
+                        .get(start..end)
+                        .ok_or_else(|| anyhow!("SoundFont generators are out of order"))?;
+                    Ok(generators
+                        .iter()
+                        .map(|generator| (u16_at(generator, 0), [generator[2], generator[3]]))
+                        .collect())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The zones that play, leaving out a first global one, each with the
+/// generators of the global one that it doesn't set itself.
+fn with_global(zones: &[Generators], terminal: u16) -> Vec<Generators> {
+    let (global, zones) = match zones.first() {
+        Some(first) if !first.contains_key(&terminal) => (first.clone(), &zones[1..]),
+        _ => (Generators::new(), zones),
+    };
+    zones
+        .iter()
+        .filter(|zone| zone.contains_key(&terminal))
+        .map(|zone| {
+            let mut merged = global.clone();
+            merged.extend(zone);
+            merged
+        })
+        .collect()
+}
+
+fn amount(generators: &Generators, generator: u16) -> i32 {
+    generators
+        .get(&generator)
+        .map_or(0, |&amount| i16::from_le_bytes(amount) as i32)
+}
+
+/// The range of keys or velocities that `generators` set, all of them if
+/// they don't.
+fn range(generators: &Generators, generator: u16) -> RangeInclusive<u8> {
+    generators
+        .get(&generator)
+        .map_or(0..=127, |&[low, high]| low..=high)
+}
+
+fn intersect(a: RangeInclusive<u8>, b: RangeInclusive<u8>) -> RangeInclusive<u8> {
+    max(*a.start(), *b.start())..=min(*a.end(), *b.end())
+}
+
+/// The regions of `program` of `bank` in the SoundFont `bytes`.
+fn regions(bytes: &[u8], bank: u16, program: u16) -> Result<Vec<Region>, anyhow::Error> {
+    let riff = chunks(bytes)?;
+    match riff.first() {
+        Some(chunk) if chunk.id == b"RIFF" && chunk.data.starts_with(b"sfbk") => (),
+        _ => bail!("not a SoundFont"),
+    }
+    let file = chunks(&riff[0].data[4..])?;
+    let sdta = list(&file, b"sdta")?;
+    let samples: Vec<i16> = records(&sdta, b"smpl", 2)?
+        .iter()
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect();
+    let pdta = list(&file, b"pdta")?;
+    let presets = records(&pdta, b"phdr", 38)?;
+    let instruments = records(&pdta, b"inst", 22)?;
+    let headers = records(&pdta, b"shdr", 46)?;
+    let preset_zones = zones(
+        &presets,
+        24,
+        &records(&pdta, b"pbag", 4)?,
+        &records(&pdta, b"pgen", 4)?,
+    )?;
+    let instrument_zones = zones(
+        &instruments,
+        20,
+        &records(&pdta, b"ibag", 4)?,
+        &records(&pdta, b"igen", 4)?,
+    )?;
+
+    let preset = presets
+        .iter()
+        .position(|preset| u16_at(preset, 20) == program && u16_at(preset, 22) == bank)
+        .filter(|&preset| preset < preset_zones.len())
+        .ok_or_else(|| anyhow!("SoundFont has no program {} in bank {}", program, bank))?;
+    let mut waves: HashMap<(usize, usize), Arc<Wave64>> = HashMap::new();
+    let mut regions = vec![];
+    for preset_zone in with_global(&preset_zones[preset], INSTRUMENT) {
+        let instrument = amount(&preset_zone, INSTRUMENT) as usize;
+        let zones = instrument_zones
+            .get(instrument)
+            .ok_or_else(|| anyhow!("SoundFont has no instrument {}", instrument))?;
+        for zone in with_global(zones, SAMPLE) {
+            let sample = amount(&zone, SAMPLE) as u16 as usize;
+            let header = headers
+                .get(sample)
+                .filter(|_| sample + 1 < headers.len())
+                .ok_or_else(|| anyhow!("SoundFont has no sample {}", sample))?;
+            let kind = u16_at(header, 44);
+            if kind & (RIGHT_SAMPLE | ROM_SAMPLE) != 0 {
+                continue;
+            }
+            let keys = intersect(range(&preset_zone, KEY_RANGE), range(&zone, KEY_RANGE));
+            let velocities = intersect(
+                range(&preset_zone, VELOCITY_RANGE),
+                range(&zone, VELOCITY_RANGE),
+            );
+            if keys.is_empty() || velocities.is_empty() {
+                continue;
+            }
+            // Offsets into the sample, in their fine and coarse parts.
+            let offset =
+                |fine: u16, coarse: u16| amount(&zone, fine) + 32768 * amount(&zone, coarse);
+            let (start, end) = (u32_at(header, 20) as i64, u32_at(header, 24) as i64);
+            let start = start + offset(START_OFFSET, START_COARSE_OFFSET) as i64;
+            let end = end + offset(END_OFFSET, END_COARSE_OFFSET) as i64;
+            if start < 0 || end as usize > samples.len() || start >= end {
+                bail!("sample {} of the SoundFont is out of its data", sample);
+            }
+            let wave = waves
+                .entry((start as usize, end as usize))
+                .or_insert_with(|| {
+                    let mut wave = Wave64::new(1, u32_at(header, 36) as f64);
+                    for &sample in &samples[start as usize..end as usize] {
+                        wave.push(sample as f64 / 32768.0);
+                    }
+                    Arc::new(wave)
+                })
+                .clone();
+            let length = (end - start) as usize;
+            let frame = |frame: i64| frame.clamp(0, length as i64) as usize;
+            let loop_start = u32_at(header, 28) as i64 - start
+                + offset(LOOP_START_OFFSET, LOOP_START_COARSE_OFFSET) as i64;
+            let loop_end = u32_at(header, 32) as i64 - start
+                + offset(LOOP_END_OFFSET, LOOP_END_COARSE_OFFSET) as i64;
+            let root = match amount(&zone, ROOT_KEY) {
+                key @ 0..=127 if zone.contains_key(&ROOT_KEY) => key as f64,
+                _ => header[40] as f64,
+            };
+            // Tuning up plays the sample on lower keys, the tuning of the
+            // preset adding to that of the instrument.
+            let tune = |generator: u16| amount(&zone, generator) + amount(&preset_zone, generator);
+            let correction = header[41] as i8 as f64;
+            let cents = 100.0 * tune(COARSE_TUNE) as f64 + tune(FINE_TUNE) as f64 + correction;
+            let attenuation = max(tune(ATTENUATION), 0) as f64 / 10.0;
+            regions.push(Region {
+                keys,
+                velocities,
+                root: root - cents / 100.0,
+                volume: -attenuation,
+                start: 0,
+                end: length,
+                loop_mode: match amount(&zone, SAMPLE_MODES) & 3 {
+                    1 => LoopMode::Continuous,
+                    3 => LoopMode::Sustain,
+                    _ => LoopMode::NoLoop,
+                },
+                loop_start: frame(loop_start),
+                loop_end: frame(loop_end),
+                ..Region::new(wave)
+            });
+        }
+    }
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn list(kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[kind.to_vec(), chunks.concat()].concat())
+    }
+
+    /// A record of a name and the fields after it.
+    fn named(name: &str, size: usize, fields: &[u8]) -> Vec<u8> {
+        let mut record = name.as_bytes().to_vec();
+        record.resize(20, 0);
+        record.extend(fields);
+        record.resize(size, 0);
+        record
+    }
+
+    fn generator(generator: u16, amount: [u8; 2]) -> Vec<u8> {
+        [generator.to_le_bytes(), amount].concat()
+    }
+
+    fn bag(generator: u16) -> Vec<u8> {
+        [generator.to_le_bytes(), [0, 0]].concat()
+    }
+
+    /// A bank with a sample of 1000 frames, looped from 100 to 900, played
+    /// on keys 40 to 80 by program 5 of bank 0, 6 dB down.
+    fn bank() -> Vec<u8> {
+        let samples: Vec<u8> = (0..1000i16).flat_map(|i| (i * 16).to_le_bytes()).collect();
+        let mut header = vec![];
+        for field in [0u32, 1000, 100, 900, 22050] {
+            header.extend(field.to_le_bytes());
+        }
+        header.extend([60, -20i8 as u8, 0, 0, 1, 0]);
+        let pdta = list(
+            b"pdta",
+            &[
+                chunk(
+                    b"phdr",
+                    &[
+                        named("Piano", 38, &[5, 0, 0, 0, 0, 0]),
+                        named("EOP", 38, &[0, 0, 0, 0, 1, 0]),
+                    ]
+                    .concat(),
+                ),
+                chunk(b"pbag", &[bag(0), bag(1)].concat()),
+                chunk(b"pmod", &[0; 10]),
+                chunk(
+                    b"pgen",
+                    &[generator(INSTRUMENT, [0, 0]), generator(0, [0, 0])].concat(),
+                ),
+                chunk(
+                    b"inst",
+                    &[named("Piano", 22, &[0, 0]), named("EOI", 22, &[2, 0])].concat(),
+                ),
+                chunk(b"ibag", &[bag(0), bag(1), bag(5)].concat()),
+                chunk(b"imod", &[0; 10]),
+                chunk(
+                    b"igen",
+                    &[
+                        generator(ATTENUATION, 60i16.to_le_bytes()),
+                        generator(KEY_RANGE, [40, 80]),
+                        generator(SAMPLE_MODES, [1, 0]),
+                        generator(ROOT_KEY, [64, 0]),
+                        generator(SAMPLE, [0, 0]),
+                        generator(0, [0, 0]),
+                    ]
+                    .concat(),
+                ),
+                chunk(
+                    b"shdr",
+                    &[named("Sample", 46, &header), named("EOS", 46, &[])].concat(),
+                ),
+            ],
+        );
+        let sdta = list(b"sdta", &[chunk(b"smpl", &samples)]);
+        chunk(
+            b"RIFF",
+            &[b"sfbk".to_vec(), list(b"INFO", &[]), sdta, pdta].concat(),
+        )
+    }
+
+    #[test]
+    fn test_reads_preset() {
+        let found = regions(&bank(), 0, 5).unwrap();
+        assert_eq!(found.len(), 1);
+        let region = &found[0];
+        assert_eq!(
+            (region.keys.clone(), region.velocities.clone()),
+            (40..=80, 0..=127)
+        );
+        assert!((region.root - 64.2).abs() < 1e-9);
+        assert!((region.volume + 6.0).abs() < 1e-9);
+        assert_eq!(region.loop_mode, LoopMode::Continuous);
+        assert_eq!(
+            (region.loop_start, region.loop_end, region.end),
+            (100, 900, 1000)
+        );
+        assert_eq!(region.wave.sample_rate(), 22050.0);
+        assert!((region.wave.at(0, 10) - 160.0 / 32768.0).abs() < 1e-12);
+        assert!(regions(&bank(), 0, 6).is_err());
+        assert!(regions(b"RIFF\x04\x00\x00\x00WAVE", 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_parse_preset() {
+        assert_eq!(parse_preset("gm.sf2").unwrap(), ("gm.sf2", 0, 0));
+        assert_eq!(parse_preset("gm.sf2@25").unwrap(), ("gm.sf2", 0, 25));
+        assert_eq!(parse_preset("gm.sf2@128:0").unwrap(), ("gm.sf2", 128, 0));
+        assert!(parse_preset("gm.sf2@200").is_err());
+    }
+}
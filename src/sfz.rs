@@ -0,0 +1,325 @@
+//! Reading of SFZ instruments, see <https://sfzformat.com>: regions of
+//! recordings with the keys and velocities that play them, the key they are
+//! at, their level and their loops, inheriting the opcodes of the groups and
+//! the globals around them.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+
+use crate::sampler::{Crossfade, LoopMode, Region, Sampler};
+
+/// Opcodes of a region, with those it inherits.
+type Opcodes = HashMap<String, String>;
+
+/// The headers opcodes are given under, each inheriting from the ones
+/// before it.
+const HEADERS: [&str; 4] = ["global", "master", "group", "region"];
+
+/// Loads the instrument at `path`, with its recordings relative to it.
+pub fn load(path: &str) -> Result<Sampler, anyhow::Error> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| anyhow!("cannot read {}: {}", path, err))?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut waves: HashMap<PathBuf, Arc<Wave64>> = HashMap::new();
+    let regions = parse(&text)?
+        .iter()
+        .map(|opcodes| {
+            let sample = opcodes
+                .get("sample")
+                .ok_or_else(|| anyhow!("a region has no sample"))?;
+            let default_path = opcodes.get("default_path").map_or("", String::as_str);
+            let sample = directory.join(format!("{}{}", default_path, sample).replace('\\', "/"));
+            let wave = match waves.get(&sample) {
+                Some(wave) => wave.clone(),
+                None => {
+                    let wave = Wave64::load(&sample)
+                        .map_err(|err| anyhow!("{}: {}", sample.display(), err))?;
+                    waves.entry(sample).or_insert(Arc::new(wave)).clone()
+                }
+            };
+            region(opcodes, wave)
+        })
+        .collect::<Result<Vec<Region>, anyhow::Error>>()?;
+    Ok(Sampler::with_regions(regions))
+}
+
+/// The region of `wave` that `opcodes` describe.
+fn region(opcodes: &Opcodes, wave: Arc<Wave64>) -> Result<Region, anyhow::Error> {
+    let mut region = Region::new(wave);
+    let number = |name: &str| -> Result<Option<f64>, anyhow::Error> {
+        match opcodes.get(name) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(Some(number)),
+                _ => bail!("{}={} is not a number", name, value),
+            },
+            None => Ok(None),
+        }
+    };
+    let frame = |name: &str| -> Result<Option<usize>, anyhow::Error> {
+        Ok(number(name)?.map(|frame| frame.max(0.0) as usize))
+    };
+    let key_of = |name: &str| opcodes.get(name).map(|value| key(value)).transpose();
+    let velocity = |name: &str| -> Result<Option<u8>, anyhow::Error> {
+        match number(name)? {
+            Some(velocity) if (0.0..=127.0).contains(&velocity) => Ok(Some(velocity as u8)),
+            Some(velocity) => bail!("{}={} is not a MIDI velocity", name, velocity),
+            None => Ok(None),
+        }
+    };
+
+    if let Some(key) = key_of("key")? {
+        region.keys = key..=key;
+        region.root = key as f64;
+    }
+    let lokey = key_of("lokey")?.unwrap_or(*region.keys.start());
+    let hikey = key_of("hikey")?.unwrap_or(*region.keys.end());
+    region.keys = lokey..=hikey;
+    let lovel = velocity("lovel")?.unwrap_or(*region.velocities.start());
+    let hivel = velocity("hivel")?.unwrap_or(*region.velocities.end());
+    region.velocities = lovel..=hivel;
+    // Crossfades over ranges of keys and velocities, both ends given.
+    region.key_fade = Crossfade {
+        fade_in: key_of("xfin_lokey")?.zip(key_of("xfin_hikey")?),
+        fade_out: key_of("xfout_lokey")?.zip(key_of("xfout_hikey")?),
+    };
+    region.velocity_fade = Crossfade {
+        fade_in: velocity("xfin_lovel")?.zip(velocity("xfin_hivel")?),
+        fade_out: velocity("xfout_lovel")?.zip(velocity("xfout_hivel")?),
+    };
+    if let Some(root) = key_of("pitch_keycenter")? {
+        region.root = root as f64;
+    }
+    // Transposing up plays the recording on lower keys.
+    region.root -= number("transpose")?.unwrap_or(0.0) + number("tune")?.unwrap_or(0.0) / 100.0;
+    region.volume = number("volume")?.unwrap_or(0.0);
+
+    let length = region.wave.len();
+    region.start = min(frame("offset")?.unwrap_or(0), length);
+    // The last frames played and looped are included.
+    region.end = min(frame("end")?.map_or(length, |end| end + 1), length);
+    region.loop_start = frame("loop_start")?
+        .or(frame("loopstart")?)
+        .unwrap_or(region.start);
+    region.loop_end = match frame("loop_end")?.or(frame("loopend")?) {
+        Some(end) => min(end + 1, region.end),
+        None => region.end,
+    };
+    let loop_mode = opcodes.get("loop_mode").or(opcodes.get("loopmode"));
+    if let Some(loop_mode) = loop_mode {
+        region.loop_mode = LoopMode::parse(loop_mode)?;
+    }
+    if region.start >= region.end {
+        bail!("a region of {} ends before it starts", opcodes["sample"]);
+    }
+    Ok(region)
+}
+
+/// The MIDI key given as a number or a note name such as `c#4`, where `c4`
+/// is middle C.
+pub fn key(value: &str) -> Result<u8, anyhow::Error> {
+    let key = match value.parse::<i32>() {
+        Ok(key) => key,
+        Err(_) => {
+            let lower = value.to_ascii_lowercase();
+            let mut chars = lower.chars();
+            let pitch_class = match chars.next() {
+                Some('c') => 0,
+                Some('d') => 2,
+                Some('e') => 4,
+                Some('f') => 5,
+                Some('g') => 7,
+                Some('a') => 9,
+                Some('b') => 11,
+                _ => bail!("invalid key: {}", value),
+            };
+            let rest = chars.as_str();
+            let (accidental, octave) = match rest.strip_prefix('#') {
+                Some(octave) => (1, octave),
+                None => match rest.strip_prefix('b') {
+                    Some(octave) => (-1, octave),
+                    None => (0, rest),
+                },
+            };
+            let octave: i32 = octave
+                .parse()
+                .map_err(|_| anyhow!("invalid key: {}", value))?;
+            12 * (octave + 1) + pitch_class + accidental
+        }
+    };
+    u8::try_from(key)
+        .ok()
+        .filter(|&key| key <= 127)
+        .ok_or_else(|| anyhow!("{} is not a MIDI key", value))
+}
+
+/// The opcodes of each region of `text`, with those they inherit and those
+/// of `<control>`.
+fn parse(text: &str) -> Result<Vec<Opcodes>, anyhow::Error> {
+    let mut control = Opcodes::new();
+    // Opcodes of the latest of each of `HEADERS`, and the header that
+    // opcodes go to, none before the first.
+    let mut levels: [Option<Opcodes>; 4] = Default::default();
+    let mut current: Option<usize> = None;
+    let mut regions = vec![];
+    let mut in_control = false;
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or("");
+        for token in tokens(line) {
+            match token {
+                Token::Header(header) => {
+                    flush(&control, &mut levels, &mut regions);
+                    in_control = header == "control";
+                    current = HEADERS.iter().position(|&found| found == header);
+                    if let Some(level) = current {
+                        // A header starts over the ones inheriting from it.
+                        for opcodes in &mut levels[level..] {
+                            *opcodes = None;
+                        }
+                        levels[level] = Some(Opcodes::new());
+                    }
+                }
+                Token::Opcode(name, value) => {
+                    if in_control {
+                        control.insert(name.to_string(), value.to_string());
+                    } else if let Some(level) = current {
+                        if let Some(opcodes) = &mut levels[level] {
+                            opcodes.insert(name.to_string(), value.to_string());
+                        }
+                    } else {
+                        bail!("opcode {} before any header", name);
+                    }
+                }
+            }
+        }
+    }
+    flush(&control, &mut levels, &mut regions);
+    Ok(regions)
+}
+
+/// Adds the region being read to `regions`, if there is one, with the
+/// opcodes it inherits.
+fn flush(control: &Opcodes, levels: &mut [Option<Opcodes>; 4], regions: &mut Vec<Opcodes>) {
+    if let Some(region) = levels[3].take() {
+        let mut opcodes = control.clone();
+        for level in levels[..3].iter().flatten().chain([&region]) {
+            opcodes.extend(level.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        regions.push(opcodes);
+    }
+}
+
+enum Token<'a> {
+    Header(&'a str),
+    Opcode(&'a str, &'a str),
+}
+
+/// The headers and opcodes of a line. Values end where the next opcode or
+/// header starts, so that the paths of samples can hold spaces.
+fn tokens(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if let Some(header) = rest.strip_prefix('<') {
+            let (name, after) = header.split_once('>').unwrap_or((header, ""));
+            tokens.push(Token::Header(name.trim()));
+            rest = after.trim_start();
+            continue;
+        }
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
+        };
+        let end = after
+            .char_indices()
+            .filter(|&(_, c)| c.is_whitespace() || c == '<')
+            .map(|(i, _)| i)
+            .find(|&i| {
+                let next = after[i..].trim_start();
+                next.starts_with('<') || starts_with_opcode(next)
+            })
+            .unwrap_or(after.len());
+        tokens.push(Token::Opcode(name.trim(), after[..end].trim()));
+        rest = after[end..].trim_start();
+    }
+    tokens
+}
+
+/// Whether `text` starts with the name of an opcode and its `=`.
+fn starts_with_opcode(text: &str) -> bool {
+    let name = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    name > 0 && text[name..].starts_with('=')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_regions_with_inheritance() {
+        let text = "
+            <control> default_path=samples\\
+            // Two layers of a piano.
+            <global> volume=-6
+            <group> lovel=1 hivel=63
+            <region> sample=piano soft c4.wav key=c4
+            <region> sample=piano soft e4.wav lokey=62 hikey=65 pitch_keycenter=e4
+            <group> lovel=64 volume=-3
+            <region>sample=piano loud.wav key=60 loop_mode=loop_continuous loop_start=10
+        ";
+        let regions = parse(text).unwrap();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0]["sample"], "piano soft c4.wav");
+        assert_eq!(regions[0]["default_path"], "samples\\");
+        assert_eq!(regions[0]["volume"], "-6");
+        assert_eq!(regions[1]["hivel"], "63");
+        assert_eq!(regions[1]["pitch_keycenter"], "e4");
+        assert_eq!(regions[2].get("hivel"), None);
+        assert_eq!(regions[2]["volume"], "-3");
+        assert_eq!(regions[2]["loop_start"], "10");
+        assert!(parse("sample=a.wav").is_err());
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(key("c4").unwrap(), 60);
+        assert_eq!(key("C#4").unwrap(), 61);
+        assert_eq!(key("eb3").unwrap(), 51);
+        assert_eq!(key("c-1").unwrap(), 0);
+        assert_eq!(key("69").unwrap(), 69);
+        assert!(key("h2").is_err() && key("c10").is_err());
+    }
+
+    #[test]
+    fn test_loads_instrument() {
+        let directory = std::env::temp_dir().join("playground-sfz-test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut wave = Wave64::new(1, 8000.0);
+        for i in 0..800 {
+            wave.push(sin(TAU * 100.0 * i as f64 / 8000.0));
+        }
+        wave.save_wav32(directory.join("tone.wav")).unwrap();
+        let path = directory.join("tone.sfz");
+        std::fs::write(
+            &path,
+            "<region> sample=tone.wav lokey=48 hikey=72 pitch_keycenter=a3 tune=50 \
+             end=399 loop_mode=loop_sustain loop_start=100 loop_end=199 \
+             xfin_lovel=20 xfin_hivel=40",
+        )
+        .unwrap();
+        let sampler = Sampler::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let region = &sampler.regions()[0];
+        assert_eq!(region.keys, 48..=72);
+        assert!((region.root - 56.5).abs() < 1e-9);
+        assert_eq!((region.start, region.end), (0, 400));
+        assert_eq!((region.loop_start, region.loop_end), (100, 200));
+        assert_eq!(region.loop_mode, LoopMode::Sustain);
+        assert_eq!(region.velocity_fade.fade_in, Some((20, 40)));
+        assert_eq!(region.key_fade, Crossfade::default());
+    }
+}
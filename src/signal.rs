@@ -0,0 +1,204 @@
+//! Test signals for checking devices and rooms, played in place of a song.
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+
+/// Level of the continuous signals, the -18 dBFS that interfaces are
+/// calibrated to.
+const LEVEL_DB: f64 = -18.0;
+/// Seconds of a sweep, before it starts over.
+const SWEEP_SECONDS: f64 = 10.0;
+/// Seconds each channel is identified for in turn, by bursts at a pitch of
+/// its own.
+pub const CHANNEL_SECONDS: f64 = 2.0;
+/// Seconds of a burst and of the pause after it.
+const BURST_SECONDS: f64 = 0.15;
+/// Pitch of the bursts on the first channel, going up a whole tone with
+/// each channel after it.
+const FIRST_CHANNEL_HZ: f64 = 440.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    /// A sine at this frequency.
+    Sine(f64),
+    /// A sine sweeping evenly in octaves from the first frequency to the
+    /// second, over and over.
+    Sweep(f64, f64),
+    /// Pink noise, with the same energy in every octave.
+    Pink,
+    /// Single full scale samples, this many a second.
+    Impulses(f64),
+}
+
+impl Signal {
+    /// Parses `sine,HZ`, `sweep,FROM,TO`, `pink` or `impulses,PER_SECOND`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut parts = value.split(',');
+        let kind = parts.next().unwrap_or_default();
+        let numbers = parts
+            .map(|part| {
+                part.parse::<f64>()
+                    .ok()
+                    .filter(|&number| number > 0.0)
+                    .ok_or_else(|| anyhow!("expected a positive number, got {}", part))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let signal = match (kind, &numbers[..]) {
+            ("sine", &[frequency]) => Signal::Sine(frequency),
+            ("sweep", &[from, to]) => Signal::Sweep(from, to),
+            ("pink", []) => Signal::Pink,
+            ("impulses", &[rate]) => Signal::Impulses(rate),
+            _ => bail!(
+                "expected sine,HZ, sweep,FROM,TO, pink or impulses,PER_SECOND, got {}",
+                value
+            ),
+        };
+        Ok(signal)
+    }
+
+    /// The signal, mono.
+    pub fn unit(self) -> Box<dyn AudioUnit64> {
+        let level = db_amp(LEVEL_DB);
+        match self {
+            Signal::Sine(frequency) => Box::new(sine_hz(frequency) * level),
+            Signal::Sweep(from, to) => Box::new(
+                lfo(move |t| from * (to / from).powf(t % SWEEP_SECONDS / SWEEP_SECONDS))
+                    >> sine()
+                    >> mul(level),
+            ),
+            Signal::Pink => Box::new(pink() * level),
+            Signal::Impulses(rate) => Box::new(An(Impulses {
+                rate,
+                frame: 0,
+                sent: 0,
+                sample_rate: DEFAULT_SR,
+            })),
+        }
+    }
+}
+
+/// Pitch of the bursts identifying the zero based `channel`.
+pub fn channel_hz(channel: usize) -> f64 {
+    FIRST_CHANNEL_HZ * semitone_ratio(2.0 * channel as f64)
+}
+
+/// The zero based channel of `channels` identified at `time` seconds.
+pub fn channel_at(time: f64, channels: usize) -> usize {
+    (time / CHANNEL_SECONDS) as usize % channels
+}
+
+/// Bursts identifying the zero based `channel` of `channels`, in turn with
+/// the other channels.
+pub fn channel_bursts(channel: usize, channels: usize) -> Box<dyn AudioUnit64> {
+    let level = db_amp(LEVEL_DB);
+    let gate = lfo(move |t: f64| {
+        let burst = (t % CHANNEL_SECONDS / BURST_SECONDS) as usize;
+        if channel_at(t, channels) == channel && burst.is_multiple_of(2) {
+            level
+        } else {
+            0.0
+        }
+    });
+    Box::new(sine_hz(channel_hz(channel)) * gate)
+}
+
+/// A train of single full scale samples.
+#[derive(Clone)]
+pub struct Impulses {
+    /// Impulses a second.
+    rate: f64,
+    frame: u64,
+    /// Impulses sent so far.
+    sent: u64,
+    sample_rate: f64,
+}
+
+impl AudioNode for Impulses {
+    const ID: u64 = 0x496d_7073;
+    type Sample = f64;
+    type Inputs = U0;
+    type Outputs = U1;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.frame = 0;
+        self.sent = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn tick(&mut self, _input: &Frame<f64, U0>) -> Frame<f64, U1> {
+        // The first impulse comes right away.
+        let due = (self.frame as f64 * self.rate / self.sample_rate) as u64;
+        self.frame += 1;
+        let value = if due >= self.sent {
+            self.sent += 1;
+            1.0
+        } else {
+            0.0
+        };
+        [value].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Signal::parse("sine,1000").unwrap(), Signal::Sine(1000.0));
+        assert_eq!(
+            Signal::parse("sweep,20,20000").unwrap(),
+            Signal::Sweep(20.0, 20000.0)
+        );
+        assert_eq!(Signal::parse("pink").unwrap(), Signal::Pink);
+        assert_eq!(Signal::parse("impulses,2").unwrap(), Signal::Impulses(2.0));
+        assert!(Signal::parse("sine").is_err());
+        assert!(Signal::parse("sine,-5").is_err());
+        assert!(Signal::parse("square,100").is_err());
+    }
+
+    #[test]
+    fn test_impulses_are_single_samples() {
+        let mut unit = Signal::Impulses(4.0).unit();
+        unit.set_sample_rate(100.0);
+        let impulses: Vec<usize> = (0..100).filter(|_| unit.get_mono() == 1.0).collect();
+        assert_eq!(impulses, [0, 25, 50, 75]);
+    }
+
+    #[test]
+    fn test_channels_take_turns() {
+        assert_eq!(channel_at(0.5, 4), 0);
+        assert_eq!(channel_at(CHANNEL_SECONDS * 5.5, 4), 1);
+        assert!(channel_hz(1) > channel_hz(0));
+        let heard = |channel: usize, from: f64| {
+            let mut unit = channel_bursts(channel, 2);
+            unit.set_sample_rate(1000.0);
+            let frames = (from * 1000.0) as usize;
+            (0..frames + 100)
+                .map(|_| unit.get_mono().abs())
+                .skip(frames)
+                .fold(0.0, f64::max)
+                > 0.01
+        };
+        assert!(heard(0, 0.0));
+        assert!(!heard(1, 0.0));
+        assert!(heard(1, CHANNEL_SECONDS));
+        assert!(!heard(0, CHANNEL_SECONDS));
+    }
+
+    #[test]
+    fn test_sine_is_at_level() {
+        let mut unit = Signal::Sine(1000.0).unit();
+        unit.set_sample_rate(48000.0);
+        let peak = (0..4800).map(|_| unit.get_mono().abs()).fold(0.0, f64::max);
+        assert!((amp_db(peak) - LEVEL_DB).abs() < 0.1);
+    }
+}
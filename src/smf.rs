@@ -0,0 +1,189 @@
+//! Reading the notes of Standard MIDI Files.
+
+use anyhow::{anyhow, bail};
+
+use crate::meter::TimeSignature;
+
+/// A note-on of a MIDI file, in beats from its start.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteOn {
+    pub beat: f64,
+    pub key: u8,
+    pub velocity: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiFile {
+    /// Note-ons of every track, in order.
+    pub notes: Vec<NoteOn>,
+    /// The first time signature of the file, 4/4 if it has none.
+    pub signature: TimeSignature,
+}
+
+/// Reads the bytes of a MIDI file one field at a time.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], anyhow::Error> {
+        if count > self.bytes.len() {
+            bail!("MIDI file ends early");
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, anyhow::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, anyhow::Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, anyhow::Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A variable length quantity, seven bits per byte with the top bit set on all but the last.
+    fn quantity(&mut self) -> Result<u32, anyhow::Error> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("MIDI quantity longer than four bytes")
+    }
+
+    /// The body of the next chunk, which has to be of `kind`.
+    fn chunk(&mut self, kind: &[u8; 4]) -> Result<Reader<'a>, anyhow::Error> {
+        if self.take(4)? != kind {
+            bail!("expected a {} chunk", String::from_utf8_lossy(kind));
+        }
+        let length = self.u32()? as usize;
+        Ok(Reader {
+            bytes: self.take(length)?,
+        })
+    }
+}
+
+impl MidiFile {
+    pub fn parse(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut file = Reader { bytes };
+        let mut header = file.chunk(b"MThd")?;
+        let _format = header.u16()?;
+        let tracks = header.u16()?;
+        let division = header.u16()?;
+        if division & 0x8000 != 0 || division == 0 {
+            bail!("only MIDI files timed in ticks per beat are supported");
+        }
+        let mut notes = vec![];
+        let mut signature = None;
+        for _ in 0..tracks {
+            let mut track = file.chunk(b"MTrk")?;
+            let mut tick = 0u64;
+            let mut status = None;
+            while !track.bytes.is_empty() {
+                tick += track.quantity()? as u64;
+                let byte = track.byte()?;
+                // With running status the byte is the first data byte of the
+                // same message as before.
+                let (kind, first) = match byte {
+                    0x80.. => (byte, None),
+                    _ => (
+                        status.ok_or_else(|| anyhow!("MIDI data without a status"))?,
+                        Some(byte),
+                    ),
+                };
+                match kind {
+                    0xff => {
+                        let kind = track.byte()?;
+                        let length = track.quantity()? as usize;
+                        let data = track.take(length)?;
+                        // The unit is given as a power of two.
+                        if kind == 0x58 && data.len() >= 2 && data[0] > 0 && data[1] < 32 {
+                            let found = TimeSignature::new(data[0] as u32, 1 << data[1]);
+                            signature = signature.or(Some(found));
+                        }
+                        if kind == 0x2f {
+                            break;
+                        }
+                    }
+                    0xf0 | 0xf7 => {
+                        let length = track.quantity()? as usize;
+                        track.take(length)?;
+                        status = None;
+                    }
+                    0x80..=0xef => {
+                        status = Some(kind);
+                        let length = match kind >> 4 {
+                            0xc | 0xd => 1,
+                            _ => 2,
+                        };
+                        let mut data = [0; 2];
+                        for (i, value) in data.iter_mut().take(length).enumerate() {
+                            *value = match first {
+                                Some(first) if i == 0 => first,
+                                _ => track.byte()?,
+                            };
+                        }
+                        if kind >> 4 == 0x9 && data[1] > 0 {
+                            notes.push(NoteOn {
+                                beat: tick as f64 / division as f64,
+                                key: data[0] & 0x7f,
+                                velocity: data[1] & 0x7f,
+                            });
+                        }
+                    }
+                    _ => bail!("unknown MIDI status {:#x}", kind),
+                }
+            }
+        }
+        notes.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+        Ok(Self {
+            notes,
+            signature: signature.unwrap_or(TimeSignature::COMMON),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A MIDI file of one track with `events` after its header.
+    fn file(events: &[u8]) -> Vec<u8> {
+        let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xe0MTrk".to_vec();
+        bytes.extend((events.len() as u32).to_be_bytes());
+        bytes.extend(events);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        // 6/8 at the start.
+        let signature = [0x00, 0xff, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08];
+        // A note-on at tick 0, and one with running status half a beat of 480 ticks later.
+        let notes = [0x00, 0x90, 0x24, 0x64, 0x81, 0x70, 0x26, 0x20];
+        // A note-off written as a note-on without velocity, and the end of the track.
+        let end = [0x10, 0x26, 0x00, 0x00, 0xff, 0x2f, 0x00];
+        let bytes = file(&[&signature[..], &notes, &end].concat());
+        let midi = MidiFile::parse(&bytes).unwrap();
+        assert_eq!(midi.signature, TimeSignature::new(6, 8));
+        let notes: Vec<(f64, u8, u8)> = midi
+            .notes
+            .iter()
+            .map(|note| (note.beat, note.key, note.velocity))
+            .collect();
+        assert_eq!(notes, [(0.0, 36, 100), (0.5, 38, 32)]);
+        assert!(MidiFile::parse(&bytes[..bytes.len() - 2]).is_err());
+        assert!(MidiFile::parse(&file(&[0x00, 0x24, 0x64])).is_err());
+    }
+}
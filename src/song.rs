@@ -0,0 +1,70 @@
+//! The song played by default.
+
+use crate::arrangement::Arrangement;
+use crate::instrument::Instrument;
+use crate::note::{Accord, BaseNote, Note};
+use crate::pattern::Pattern;
+
+/// "Alle meine Entchen" over a held C major accord and a three beat bass loop.
+pub fn song() -> Arrangement {
+    use BaseNote::*;
+
+    let mut song = Arrangement::default();
+
+    let note = |note, beats| (Note::base(note), beats);
+    let climb = song.pattern(Pattern::melody(&[
+        note(C, 1.0),
+        note(D, 1.0),
+        note(E, 1.0),
+        note(F, 1.0),
+        note(G, 2.0),
+        note(G, 2.0),
+    ]));
+    let swim = song.pattern(Pattern::melody(&[
+        note(A, 1.0),
+        note(A, 1.0),
+        note(A, 1.0),
+        note(A, 1.0),
+        note(G, 2.0),
+    ]));
+    let dive = song.pattern(Pattern::melody(&[
+        note(F, 1.0),
+        note(F, 1.0),
+        note(F, 1.0),
+        note(F, 1.0),
+        note(E, 2.0),
+        note(E, 2.0),
+    ]));
+    let home = song.pattern(Pattern::melody(&[
+        note(D, 1.0),
+        note(D, 1.0),
+        note(D, 1.0),
+        note(D, 1.0),
+        note(C, 3.0),
+    ]));
+    let melody = song.track(
+        Instrument::Pluck,
+        &[(climb, 1), (swim, 2), (dive, 1), (home, 1)],
+    );
+    song.tracks[melody].pan = -0.3;
+
+    let accord = Accord {
+        notes: vec![Note::new(C, 0), Note::new(E, 0), Note::new(C, 1)],
+    };
+    let chord = song.pattern(Pattern::accord(&accord, 4.0));
+    let organ = song.track(Instrument::Organ, &[(chord, 9)]);
+    song.tracks[organ].pan = 0.3;
+
+    // Three beats against four, falling back in line every third bar.
+    let bass = song.pattern(
+        Pattern::melody(&[
+            (Note::new(C, -1), 1.0),
+            (Note::new(G, -1), 1.0),
+            (Note::new(E, -1), 1.0),
+        ])
+        .ratchet(2, 2),
+    );
+    song.loop_track(Instrument::Pluck, &[(bass, 1)], 3.0);
+
+    song
+}
@@ -0,0 +1,79 @@
+//! Spectrograms of rendered audio, to compare patch experiments by eye.
+
+use fundsp::hacker::*;
+use image::{Rgb, RgbImage};
+use realfft::RealFftPlanner;
+
+/// Samples per analysis window, a power of two.
+pub const WINDOW: usize = 1024;
+/// Quietest level shown, in decibels below a full scale sine.
+const FLOOR_DB: f64 = -90.0;
+
+/// Magnitudes in decibels of the `WINDOW / 2` lowest frequency bins of every
+/// Hann windowed frame, `hop` samples apart.
+pub fn spectrogram(samples: &[f64], hop: usize) -> Vec<Vec<f64>> {
+    let fft = RealFftPlanner::new().plan_fft_forward(WINDOW);
+    let hann: Vec<f64> = (0..WINDOW)
+        .map(|i| 0.5 - 0.5 * cos(TAU * i as f64 / WINDOW as f64))
+        .collect();
+    // A full scale sine peaks at a quarter of the window with the Hann window.
+    let full_scale = WINDOW as f64 / 4.0;
+    (0..samples.len().saturating_sub(WINDOW) / hop + 1)
+        .map(|frame| {
+            let mut windowed: Vec<f64> = (0..WINDOW)
+                .map(|i| samples.get(frame * hop + i).copied().unwrap_or(0.0) * hann[i])
+                .collect();
+            let mut spectrum = fft.make_output_vec();
+            fft.process(&mut windowed, &mut spectrum).unwrap();
+            spectrum[..WINDOW / 2]
+                .iter()
+                .map(|bin| amp_db(bin.norm() / full_scale).max(FLOOR_DB))
+                .collect()
+        })
+        .collect()
+}
+
+/// Draws a spectrogram with time running to the right and low frequencies at
+/// the bottom, from black at -90 dB through red and yellow to white at full scale.
+pub fn image(frames: &[Vec<f64>]) -> RgbImage {
+    let height = WINDOW / 2;
+    RgbImage::from_fn(frames.len() as u32, height as u32, |x, y| {
+        let db = frames[x as usize][height - 1 - y as usize];
+        let level = clamp01(1.0 - db / FLOOR_DB) * 3.0;
+        Rgb(std::array::from_fn(|channel| {
+            (clamp01(level - channel as f64) * 255.0).round() as u8
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sine_peaks_in_its_bin() {
+        // Exactly on bin 64 at a sample rate equal to the window length.
+        let samples: Vec<f64> = (0..WINDOW * 2)
+            .map(|i| sin(TAU * 64.0 * i as f64 / WINDOW as f64))
+            .collect();
+        let frames = spectrogram(&samples, WINDOW / 2);
+        assert_eq!(frames.len(), 3);
+        let frame = &frames[1];
+        let loudest = (0..frame.len())
+            .max_by(|&a, &b| frame[a].total_cmp(&frame[b]))
+            .unwrap();
+        assert_eq!(loudest, 64);
+        assert!(frame[64].abs() < 0.1);
+        assert_eq!(frame[300], FLOOR_DB);
+    }
+
+    #[test]
+    fn test_image() {
+        let mut frames = vec![vec![FLOOR_DB; WINDOW / 2]; 3];
+        frames[1][0] = 0.0;
+        let image = image(&frames);
+        assert_eq!(image.dimensions(), (3, 512));
+        assert_eq!(image[(0, 511)], Rgb([0, 0, 0]));
+        assert_eq!(image[(1, 511)], Rgb([255, 255, 255]));
+    }
+}
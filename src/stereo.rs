@@ -0,0 +1,73 @@
+//! Stereo image processing on the master bus.
+
+use fundsp::hacker::*;
+
+use crate::param::{Param, Spec};
+
+/// Widest setting, doubling the side signal.
+pub const MAX_WIDTH: f64 = 2.0;
+/// Width of the master bus, 0 for mono, 1 to leave the signal unchanged,
+/// and up to `MAX_WIDTH` to widen it.
+pub const WIDTH: Spec = Spec::linear(0.0, MAX_WIDTH, 1.0);
+/// Pitch heard in the center by pitch dependent panning, middle C.
+const CENTER_HZ: f64 = 261.63;
+/// Octaves from the center to either side, the range of a piano.
+const SIDE_OCTAVES: f64 = 3.5;
+
+/// Offset from -1 to 1 of a note at `frequency` when panning by pitch like a
+/// piano seen from the player's seat, low notes left and high notes right.
+pub fn pitch_offset(frequency: f64) -> f64 {
+    ((frequency / CENTER_HZ).log2() / SIDE_OCTAVES).clamp(-1.0, 1.0)
+}
+
+/// Mid/side width control following `width`, a parameter of the `WIDTH` spec.
+pub fn width(width: &Param) -> An<impl AudioNode<Sample = f64, Inputs = U2, Outputs = U2>> {
+    (multipass::<U2>() | width.unit()) >> An(Width)
+}
+
+/// Mid/side width of the first two inputs, set by the third.
+#[derive(Clone)]
+pub struct Width;
+
+impl AudioNode for Width {
+    const ID: u64 = 0x5769_6474;
+    type Sample = f64;
+    type Inputs = U3;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn tick(&mut self, input: &Frame<f64, U3>) -> Frame<f64, U2> {
+        let mid = (input[0] + input[1]) * 0.5;
+        let side = (input[0] - input[1]) * 0.5 * input[2];
+        [mid + side, mid - side].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        Routing::Arbitrary(0.0).propagate(input, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pitch_offset() {
+        assert!(pitch_offset(CENTER_HZ).abs() < 1e-3);
+        assert!((pitch_offset(CENTER_HZ * 2.0) - 1.0 / SIDE_OCTAVES).abs() < 1e-9);
+        assert_eq!(pitch_offset(20.0), -1.0);
+        assert_eq!(pitch_offset(20000.0), 1.0);
+    }
+
+    #[test]
+    fn test_width_scales_side_signal() {
+        let mut node = An(Width);
+        let mut tick = |width| {
+            let output = node.tick(&[1.0, 0.0, width].into());
+            (output[0], output[1])
+        };
+        assert_eq!(tick(1.0), (1.0, 0.0));
+        assert_eq!(tick(0.0), (0.5, 0.5));
+        assert_eq!(tick(2.0), (1.5, -0.5));
+    }
+}
@@ -0,0 +1,397 @@
+//! Streaming of long recordings from disk. Only the header of a WAV file
+//! and its first moments are loaded; the voices playing it read on from
+//! ring buffers that a thread of their own fills from the file ahead of
+//! them, so that the audio thread neither waits on the disk nor allocates,
+//! and each voice holds a bounded stretch of the recording at a time.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+
+/// Seconds of a recording loaded up front, which voices play while their
+/// ring buffer fills.
+pub const HEAD_SECONDS: f64 = 0.25;
+/// Seconds of a recording that the ring buffer of a voice holds.
+const RING_SECONDS: f64 = 0.5;
+/// Frames read from the file at a time, and how long the thread waits
+/// between rounds of the voices.
+const CHUNK_FRAMES: usize = 4096;
+const POLL: Duration = Duration::from_millis(5);
+
+/// How the samples of a WAV file are stored, with their size in bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Int(usize),
+    Float(usize),
+}
+
+/// What a WAV file holds and where its frames are.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub path: PathBuf,
+    pub channels: usize,
+    pub sample_rate: f64,
+    pub frames: usize,
+    format: Format,
+    /// Offset in bytes of the first frame.
+    data: u64,
+}
+
+impl Header {
+    /// Reads the header of the WAV file at `path`, up to its frames.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut riff = [0; 12];
+        file.read_exact(&mut riff)?;
+        if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+            bail!("{} is not a WAV file", path.display());
+        }
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            file.read_exact(&mut chunk)
+                .map_err(|_| anyhow!("{} has no frames", path.display()))?;
+            let size = u32::from_le_bytes(chunk[4..8].try_into().unwrap()) as usize;
+            match &chunk[0..4] {
+                b"fmt " => {
+                    let mut fmt = vec![0; size];
+                    file.read_exact(&mut fmt)?;
+                    format = Some(parse_format(&fmt)?);
+                }
+                b"data" => {
+                    let (channels, sample_rate, format) =
+                        format.ok_or_else(|| anyhow!("{} has no format", path.display()))?;
+                    let bytes = match format {
+                        Format::Int(bytes) | Format::Float(bytes) => bytes,
+                    };
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                        channels,
+                        sample_rate,
+                        frames: size / (channels * bytes),
+                        format,
+                        data: file.stream_position()?,
+                    });
+                }
+                // Chunks are padded to an even size.
+                _ => {
+                    file.seek(SeekFrom::Current((size + size % 2) as i64))?;
+                }
+            }
+        }
+    }
+
+    fn bytes_per_frame(&self) -> usize {
+        match self.format {
+            Format::Int(bytes) | Format::Float(bytes) => bytes * self.channels,
+        }
+    }
+
+    /// Reads `count` frames from frame `start` of `file`, mixed down to mono.
+    pub fn frames(&self, file: &mut File, start: usize, count: usize) -> io::Result<Vec<f64>> {
+        file.seek(SeekFrom::Start(
+            self.data + (start * self.bytes_per_frame()) as u64,
+        ))?;
+        let mut bytes = vec![0; count * self.bytes_per_frame()];
+        file.read_exact(&mut bytes)?;
+        let sample = |sample: &[u8]| match self.format {
+            Format::Int(1) => (sample[0] as f64 - 128.0) / 128.0,
+            Format::Int(2) => i16::from_le_bytes([sample[0], sample[1]]) as f64 / 32768.0,
+            Format::Int(3) => {
+                i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) as f64 / 2147483648.0
+            }
+            Format::Int(_) => i32::from_le_bytes(sample.try_into().unwrap()) as f64 / 2147483648.0,
+            Format::Float(4) => f32::from_le_bytes(sample.try_into().unwrap()) as f64,
+            Format::Float(_) => f64::from_le_bytes(sample.try_into().unwrap()),
+        };
+        Ok(bytes
+            .chunks(self.bytes_per_frame())
+            .map(|frame| {
+                frame
+                    .chunks(self.bytes_per_frame() / self.channels)
+                    .map(sample)
+                    .sum::<f64>()
+                    / self.channels as f64
+            })
+            .collect())
+    }
+
+    /// The first `seconds` of the recording, in mono.
+    pub fn head(&self, seconds: f64) -> Result<Wave64, anyhow::Error> {
+        let count = Ord::min((seconds * self.sample_rate) as usize, self.frames);
+        let mut wave = Wave64::new(1, self.sample_rate);
+        for x in self.frames(&mut File::open(&self.path)?, 0, count)? {
+            wave.push(x);
+        }
+        Ok(wave)
+    }
+}
+
+/// Channels, sample rate and format of the `fmt ` chunk of a WAV file.
+fn parse_format(fmt: &[u8]) -> Result<(usize, f64, Format), anyhow::Error> {
+    if fmt.len() < 16 {
+        bail!("the format of the WAV file is cut short");
+    }
+    let u16_at = |offset: usize| u16::from_le_bytes([fmt[offset], fmt[offset + 1]]);
+    let channels = u16_at(2) as usize;
+    let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap()) as f64;
+    let bytes = u16_at(14) as usize / 8;
+    // The extensible format gives the actual one first in its subformat.
+    let tag = match u16_at(0) {
+        0xfffe if fmt.len() >= 26 => u16_at(24),
+        tag => tag,
+    };
+    let format = match (tag, bytes) {
+        (1, 1..=4) => Format::Int(bytes),
+        (3, 4 | 8) => Format::Float(bytes),
+        _ => bail!(
+            "cannot stream WAV files of format {} at {} bits",
+            tag,
+            bytes * 8
+        ),
+    };
+    if channels == 0 {
+        bail!("the WAV file has no channels");
+    }
+    Ok((channels, sample_rate, format))
+}
+
+/// A ring buffer of samples that one thread writes and another reads,
+/// without locking.
+pub struct Ring {
+    /// Samples as `f64` bits.
+    samples: Vec<AtomicU64>,
+    /// Samples written and read so far.
+    written: AtomicUsize,
+    read: AtomicUsize,
+}
+
+impl Ring {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: (0..capacity).map(|_| AtomicU64::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Samples that can be written before the ring is full.
+    pub fn free(&self) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        self.samples.len() - (written - self.read.load(Ordering::Acquire))
+    }
+
+    /// Writes `x` unless the ring is full.
+    pub fn push(&self, x: f64) -> bool {
+        if self.free() == 0 {
+            return false;
+        }
+        let written = self.written.load(Ordering::Relaxed);
+        self.samples[written % self.samples.len()].store(x.to_bits(), Ordering::Relaxed);
+        self.written.store(written + 1, Ordering::Release);
+        true
+    }
+
+    /// Reads the oldest sample, if there is any.
+    pub fn pop(&self) -> Option<f64> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.written.load(Ordering::Acquire) {
+            return None;
+        }
+        let x = f64::from_bits(self.samples[read % self.samples.len()].load(Ordering::Relaxed));
+        self.read.store(read + 1, Ordering::Release);
+        Some(x)
+    }
+}
+
+/// Reads the frames of a recording from its ring buffer, in order.
+#[derive(Clone)]
+pub struct Reader {
+    ring: Arc<Ring>,
+    /// Frames that were given as silence because they hadn't been read from
+    /// the disk yet, and that are skipped once they are.
+    skipped: usize,
+}
+
+impl Reader {
+    /// The next frame, or silence if the disk hasn't kept up.
+    pub fn frame(&mut self) -> f64 {
+        while self.skipped > 0 && self.ring.pop().is_some() {
+            self.skipped -= 1;
+        }
+        match self.ring.pop() {
+            Some(x) => x,
+            None => {
+                self.skipped += 1;
+                0.0
+            }
+        }
+    }
+}
+
+/// A recording being read into a ring buffer, from the frame it got to.
+struct Filling {
+    header: Arc<Header>,
+    file: File,
+    ring: Arc<Ring>,
+    next: usize,
+}
+
+impl Filling {
+    /// Fills the ring as far as it has room, returning whether there is
+    /// more to read for a voice that still plays.
+    fn fill(&mut self) -> bool {
+        // The voice is gone once the reader is.
+        if Arc::strong_count(&self.ring) == 1 {
+            return false;
+        }
+        let count = Ord::min(self.ring.free(), self.header.frames - self.next);
+        let count = Ord::min(count, CHUNK_FRAMES);
+        if count > 0 {
+            match self.header.frames(&mut self.file, self.next, count) {
+                Ok(frames) => {
+                    for x in frames {
+                        self.ring.push(x);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("cannot stream {}: {}", self.header.path.display(), err);
+                    return false;
+                }
+            }
+            self.next += count;
+        }
+        self.next < self.header.frames
+    }
+}
+
+/// Streams recordings into the ring buffers of voices from a background
+/// thread, which ends once every streamer and the voices they started are
+/// gone.
+#[derive(Clone)]
+pub struct Streamer {
+    sender: Sender<Filling>,
+}
+
+impl Streamer {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || fill(receiver));
+        Self { sender }
+    }
+
+    /// Starts reading the recording of `header` from frame `start` into a
+    /// ring buffer, returning its reader.
+    pub fn stream(&self, header: &Arc<Header>, start: usize) -> Result<Reader, anyhow::Error> {
+        let ring = Arc::new(Ring::new(Ord::max(
+            (RING_SECONDS * header.sample_rate) as usize,
+            CHUNK_FRAMES,
+        )));
+        let filling = Filling {
+            header: header.clone(),
+            file: File::open(&header.path)?,
+            ring: ring.clone(),
+            next: Ord::min(start, header.frames),
+        };
+        self.sender
+            .send(filling)
+            .map_err(|_| anyhow!("the streaming thread has stopped"))?;
+        Ok(Reader { ring, skipped: 0 })
+    }
+}
+
+/// Fills the rings of the recordings received until the sender is gone and
+/// they are all read.
+fn fill(receiver: Receiver<Filling>) {
+    let mut fillings: Vec<Filling> = vec![];
+    loop {
+        // Waits for a recording while there is nothing to read.
+        if fillings.is_empty() {
+            match receiver.recv() {
+                Ok(filling) => fillings.push(filling),
+                Err(_) => return,
+            }
+        }
+        fillings.extend(receiver.try_iter());
+        fillings.retain_mut(Filling::fill);
+        std::thread::sleep(POLL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stereo ramp with the right channel silent, saved as 16 bit WAV.
+    fn ramp(name: &str, frames: usize) -> (PathBuf, Wave64) {
+        let mut wave = Wave64::new(2, 8000.0);
+        for i in 0..frames {
+            wave.push(((i % 1000) as f64 / 1000.0, 0.0));
+        }
+        let path = std::env::temp_dir().join(name);
+        wave.save_wav16(&path).unwrap();
+        (path, wave)
+    }
+
+    #[test]
+    fn test_reads_header_and_frames() {
+        let (path, wave) = ramp("playground_test_reads_header.wav", 3000);
+        let header = Header::read(&path).unwrap();
+        assert_eq!((header.channels, header.sample_rate), (2, 8000.0));
+        assert_eq!((header.frames, header.format), (3000, Format::Int(2)));
+        let mut file = File::open(&path).unwrap();
+        let frames = header.frames(&mut file, 1500, 10).unwrap();
+        let head = header.head(0.1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (i, x) in frames.into_iter().enumerate() {
+            assert!((x - wave.at(0, 1500 + i) * 0.5).abs() < 1e-4);
+        }
+        assert_eq!((head.len(), head.channels()), (800, 1));
+        assert!(Header::read("Cargo.toml").is_err());
+    }
+
+    #[test]
+    fn test_ring_wraps() {
+        let ring = Ring::new(4);
+        for x in 0..4 {
+            assert!(ring.push(x as f64));
+        }
+        assert!(!ring.push(4.0));
+        assert_eq!(
+            (ring.pop(), ring.pop(), ring.free()),
+            (Some(0.0), Some(1.0), 2)
+        );
+        assert!(ring.push(4.0) && ring.push(5.0));
+        let rest: Vec<f64> = std::iter::from_fn(|| ring.pop()).collect();
+        assert_eq!(rest, [2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_streams_in_order() {
+        let (path, wave) = ramp("playground_test_streams_in_order.wav", 20000);
+        let header = Arc::new(Header::read(&path).unwrap());
+        let streamer = Streamer::spawn();
+        let reader = streamer.stream(&header, 100).unwrap();
+        let mut frames = vec![];
+        // More than the ring holds, read as it fills.
+        while frames.len() < 10000 {
+            match reader.ring.pop() {
+                Some(x) => frames.push(x),
+                None => std::thread::sleep(POLL),
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+        for (i, x) in frames.into_iter().enumerate() {
+            assert!((x - wave.at(0, 100 + i) * 0.5).abs() < 1e-4);
+        }
+        assert_eq!(reader.skipped, 0);
+    }
+}
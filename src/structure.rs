@@ -0,0 +1,211 @@
+//! Song structure: intros, verses, choruses and the like, each choosing
+//! what every track plays, and finding them to jump to.
+
+use anyhow::bail;
+
+use crate::arrangement::{Arrangement, Section};
+use crate::instrument::Instrument;
+use crate::pattern::Pattern;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Intro,
+    Verse,
+    Chorus,
+    Bridge,
+    Outro,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "intro" => Ok(Role::Intro),
+            "verse" => Ok(Role::Verse),
+            "chorus" => Ok(Role::Chorus),
+            "bridge" => Ok(Role::Bridge),
+            "outro" => Ok(Role::Outro),
+            _ => bail!("unknown song part: {}", value),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Intro => "intro",
+            Role::Verse => "verse",
+            Role::Chorus => "chorus",
+            Role::Bridge => "bridge",
+            Role::Outro => "outro",
+        }
+    }
+}
+
+/// What a track plays during a part.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    /// Index into `Arrangement::patterns`, repeated for as long as the part lasts.
+    pub pattern: usize,
+    /// Level of the notes, from 0 to 1.
+    pub level: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Part {
+    pub role: Role,
+    pub bars: usize,
+    /// What each track plays, silent where there is none.
+    pub slots: Vec<Option<Slot>>,
+}
+
+/// The parts of a song one after another.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Structure {
+    pub parts: Vec<Part>,
+}
+
+impl Structure {
+    /// The bar that the part named by `target` starts on: the first part
+    /// playing a role such as `chorus`, or a part counted from 1.
+    pub fn start_bar(&self, target: &str) -> Result<usize, anyhow::Error> {
+        let index = match target.parse::<usize>() {
+            Ok(0) => bail!("parts are counted from 1"),
+            Ok(number) => number - 1,
+            Err(_) => {
+                let role = Role::parse(target)?;
+                match self.parts.iter().position(|part| part.role == role) {
+                    Some(index) => index,
+                    None => bail!("the song has no {}", role.name()),
+                }
+            }
+        };
+        if index >= self.parts.len() {
+            bail!("there are {} parts", self.parts.len());
+        }
+        Ok(self.parts[..index].iter().map(|part| part.bars).sum())
+    }
+
+    /// The part playing in song bar `bar`, if any.
+    pub fn part_at(&self, bar: usize) -> Option<&Part> {
+        let mut start = 0;
+        self.parts.iter().find(|part| {
+            start += part.bars;
+            bar < start
+        })
+    }
+}
+
+impl Arrangement {
+    /// Replaces the tracks by ones playing `instruments` through the parts
+    /// of `structure`. A pattern that doesn't fill its part evenly is cut
+    /// short by rests, so that every part starts on its bar.
+    pub fn arrange(&mut self, instruments: &[Instrument], structure: Structure) {
+        let mut tracks: Vec<Vec<Section>> = vec![vec![]; instruments.len()];
+        let mut bar = 0;
+        for part in &structure.parts {
+            let length = self.meter.bar_start(bar + part.bars) - self.meter.bar_start(bar);
+            bar += part.bars;
+            for (index, sections) in tracks.iter_mut().enumerate() {
+                let slot = part.slots.get(index).copied().flatten();
+                let (pattern, level) = match slot {
+                    Some(slot) => (slot.pattern, slot.level),
+                    None => (self.rest(length), 1.0),
+                };
+                let pattern_length = self.patterns[pattern].length;
+                let repeat = (length / pattern_length + 1e-9).floor() as usize;
+                if repeat > 0 {
+                    sections.push(Section {
+                        pattern,
+                        repeat,
+                        level,
+                    });
+                }
+                let rest = length - repeat as f64 * pattern_length;
+                if rest > 1e-9 {
+                    let rest = self.rest(rest);
+                    sections.push(Section::new(rest, 1));
+                }
+            }
+        }
+        self.tracks.clear();
+        for (instrument, sections) in instruments.iter().zip(tracks) {
+            let track = self.track(*instrument, &[]);
+            self.tracks[track].sections = sections;
+        }
+        self.structure = structure;
+    }
+
+    /// Adds a pattern of silence lasting `length` beats and returns its index.
+    fn rest(&mut self, length: f64) -> usize {
+        self.pattern(Pattern {
+            length,
+            steps: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meter::{Meter, TimeSignature};
+    use crate::note::{BaseNote::*, Note};
+
+    fn structure() -> Structure {
+        let slot = |pattern, level| Some(Slot { pattern, level });
+        Structure {
+            parts: vec![
+                Part {
+                    role: Role::Intro,
+                    bars: 1,
+                    slots: vec![None, slot(1, 1.0)],
+                },
+                Part {
+                    role: Role::Verse,
+                    bars: 2,
+                    slots: vec![slot(0, 0.5), slot(1, 1.0)],
+                },
+                Part {
+                    role: Role::Chorus,
+                    bars: 1,
+                    slots: vec![slot(0, 1.0)],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_jump_targets() {
+        let structure = structure();
+        assert_eq!(structure.start_bar("intro").unwrap(), 0);
+        assert_eq!(structure.start_bar("chorus").unwrap(), 3);
+        assert_eq!(structure.start_bar("2").unwrap(), 1);
+        assert!(structure.start_bar("bridge").is_err());
+        assert!(structure.start_bar("4").is_err());
+        assert_eq!(structure.part_at(2).unwrap().role, Role::Verse);
+        assert_eq!(structure.part_at(4), None);
+    }
+
+    #[test]
+    fn test_arrange_parts() {
+        let mut song = Arrangement {
+            meter: Meter::new(TimeSignature::new(3, 4)),
+            ..Arrangement::default()
+        };
+        song.pattern(Pattern::melody(&[(Note::base(C), 3.0)]));
+        song.pattern(Pattern::melody(&[(Note::base(E), 2.0)]));
+        song.arrange(&[Instrument::Pluck, Instrument::Organ], structure());
+        assert_eq!(song.length(), 12.0);
+        // Every part starts on its bar, however long the patterns are.
+        assert_eq!(song.pattern_at(1, 3.0), Some((1, 0.0)));
+        assert_eq!(song.pattern_at(1, 9.5).map(|(pattern, _)| pattern), Some(4));
+        let sections = &song.tracks[0].sections;
+        assert_eq!(
+            sections[1],
+            Section {
+                pattern: 0,
+                repeat: 2,
+                level: 0.5
+            }
+        );
+        assert!(song.patterns[sections[0].pattern].steps.is_empty());
+        assert_eq!(song.structure.parts.len(), 3);
+    }
+}
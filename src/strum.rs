@@ -0,0 +1,91 @@
+//! Strumming, which staggers the notes of an accord like a hand across strings.
+
+use anyhow::bail;
+
+use crate::humanize::VELOCITY_UNITS;
+
+/// Longest time between two strummed notes.
+const MAX_MS: f64 = 200.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// From the lowest note up.
+    Up,
+    /// From the highest note down.
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Strum {
+    /// Time between successive notes.
+    pub seconds: f64,
+    pub direction: Direction,
+    /// Velocity every note is played softer than the one before, from 0 to 1.
+    pub ramp: f64,
+}
+
+impl Strum {
+    /// Parses `MS[,up|down[,RAMP]]`, with the ramp in MIDI velocity units, such
+    /// as `30,down,10`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let mut fields = value.split(',').map(str::trim);
+        let ms: f64 = fields.next().unwrap_or_default().parse()?;
+        if !(0.0..=MAX_MS).contains(&ms) {
+            bail!("strum time must be between 0 and {} ms", MAX_MS);
+        }
+        let direction = match fields.next() {
+            None | Some("up") => Direction::Up,
+            Some("down") => Direction::Down,
+            Some(direction) => bail!("unknown strum direction: {}", direction),
+        };
+        let ramp: f64 = fields.next().map_or(Ok(0.0), str::parse)?;
+        if !(0.0..=VELOCITY_UNITS).contains(&ramp) {
+            bail!("strum ramp must be between 0 and {}", VELOCITY_UNITS);
+        }
+        if fields.next().is_some() {
+            bail!("too many strum fields: {}", value);
+        }
+        Ok(Self {
+            seconds: ms / 1000.0,
+            direction,
+            ramp: ramp / VELOCITY_UNITS,
+        })
+    }
+
+    /// Delay in seconds and velocity of the `index`th lowest of `count` notes
+    /// starting together.
+    pub fn note(&self, index: usize, count: usize) -> (f64, f64) {
+        let order = match self.direction {
+            Direction::Up => index,
+            Direction::Down => count - 1 - index,
+        } as f64;
+        (order * self.seconds, (1.0 - order * self.ramp).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_strum() {
+        let strum = Strum::parse("30,down,12.7").unwrap();
+        assert_eq!(strum.direction, Direction::Down);
+        assert!((strum.seconds - 0.03).abs() < 1e-12);
+        assert!((strum.ramp - 0.1).abs() < 1e-12);
+        assert_eq!(Strum::parse("20").unwrap().direction, Direction::Up);
+        assert!(Strum::parse("20,sideways").is_err());
+        assert!(Strum::parse("1000").is_err());
+        assert!(Strum::parse("20,up,1,2").is_err());
+    }
+
+    #[test]
+    fn test_down_strum_starts_at_the_top() {
+        let strum = Strum::parse("10,down,12.7").unwrap();
+        let (delay, velocity) = strum.note(2, 3);
+        assert_eq!((delay, velocity), (0.0, 1.0));
+        let (delay, velocity) = strum.note(0, 3);
+        assert!((delay - 0.02).abs() < 1e-12);
+        assert!((velocity - 0.8).abs() < 1e-12);
+    }
+}
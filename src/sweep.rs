@@ -0,0 +1,81 @@
+//! The DJ filter on the master bus, one knob sweeping a lowpass down below
+//! its center and a highpass up above it.
+
+use fundsp::hacker::*;
+
+use crate::param::{Param, Spec};
+
+/// Cutoff of the lowpass with the knob in the center or above, open wide.
+const OPEN_HZ: f64 = 20000.0;
+/// Cutoff of the lowpass with the knob all the way down.
+const LOWPASS_HZ: f64 = 150.0;
+/// Cutoff of the highpass with the knob in the center or below.
+const CLOSED_HZ: f64 = 10.0;
+/// Cutoff of the highpass with the knob all the way up.
+const HIGHPASS_HZ: f64 = 6000.0;
+/// A little resonance, for the sweep to be heard.
+const Q: f64 = 1.2;
+/// Response time of the knob, so that turning it doesn't zipper.
+const GLIDE_SECONDS: f64 = 0.02;
+
+/// The knob, from -1 for the lowest lowpass over 0, which leaves the signal
+/// all but unchanged, to 1 for the highest highpass.
+pub const KNOB: Spec = Spec {
+    smoothing: GLIDE_SECONDS,
+    random: (0.25, 0.75),
+    ..Spec::linear(-1.0, 1.0, 0.0)
+};
+
+/// Cutoffs of the lowpass and the highpass with the knob at `position`, from
+/// -1 all the way down to 1 all the way up. Both sweep evenly in octaves.
+pub fn cutoffs(position: f64) -> (f64, f64) {
+    let position = position.clamp(-1.0, 1.0);
+    let lowpass = OPEN_HZ * (LOWPASS_HZ / OPEN_HZ).powf((-position).max(0.0));
+    let highpass = CLOSED_HZ * (HIGHPASS_HZ / CLOSED_HZ).powf(position.max(0.0));
+    (lowpass, highpass)
+}
+
+/// The filter of one channel following the knob `position`.
+fn channel(position: &Param) -> An<impl AudioNode<Sample = f64, Inputs = U1, Outputs = U1>> {
+    (pass() | position.unit() >> map(|f| cutoffs(f[0]).0))
+        >> lowpass_q(Q)
+        >> (pass() | position.unit() >> map(|f| cutoffs(f[0]).1))
+        >> highpass_q(Q)
+}
+
+/// Stereo DJ filter turned by `position`, a parameter of the `KNOB` spec.
+pub fn filter(position: &Param) -> An<impl AudioNode<Sample = f64, Inputs = U2, Outputs = U2>> {
+    channel(position) | channel(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak output of the filter after settling on a sine at `frequency`.
+    fn peak(position: f64, frequency: f64) -> f64 {
+        let knob = Param::new(KNOB);
+        knob.set(position);
+        let mut node = filter(&knob);
+        node.set_sample_rate(44100.0);
+        let mut peak = 0.0;
+        for i in 0..44100 {
+            let x = (i as f64 * frequency / 44100.0 * std::f64::consts::TAU).sin();
+            let (left, _) = node.filter_stereo(x, x);
+            if i > 22050 {
+                peak = f64::max(peak, left.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_knob_sweeps_either_way() {
+        assert_eq!(cutoffs(0.0), (OPEN_HZ, CLOSED_HZ));
+        assert!((cutoffs(-1.0).0 - LOWPASS_HZ).abs() < 1e-6);
+        assert!((cutoffs(5.0).1 - HIGHPASS_HZ).abs() < 1e-6);
+        assert!((peak(0.0, 1000.0) - 1.0).abs() < 0.01);
+        assert!(peak(-1.0, 4000.0) < 0.01);
+        assert!(peak(1.0, 100.0) < 0.01);
+    }
+}
@@ -0,0 +1,186 @@
+//! MIDI Time Code, read to chase the position of a DAW.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rate {
+    Fps24,
+    Fps25,
+    /// 29.97 frames a second, dropping frame numbers to keep up with the clock.
+    Fps30Drop,
+    Fps30,
+}
+
+impl Rate {
+    /// The rate of the two bits sent with the hours.
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Rate::Fps24,
+            1 => Rate::Fps25,
+            2 => Rate::Fps30Drop,
+            _ => Rate::Fps30,
+        }
+    }
+
+    pub fn fps(self) -> f64 {
+        match self {
+            Rate::Fps24 => 24.0,
+            Rate::Fps25 => 25.0,
+            Rate::Fps30Drop => 30000.0 / 1001.0,
+            Rate::Fps30 => 30.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: Rate,
+}
+
+impl Timecode {
+    /// The timecode of a full frame message, the hours with the rate in
+    /// bits 5 and 6 followed by the minutes, seconds and frames.
+    pub fn from_full_frame(bytes: [u8; 4]) -> Self {
+        Self {
+            hours: bytes[0] & 0x1f,
+            minutes: bytes[1] & 0x3f,
+            seconds: bytes[2] & 0x3f,
+            frames: bytes[3] & 0x1f,
+            rate: Rate::from_bits(bytes[0] >> 5),
+        }
+    }
+
+    /// Seconds from 00:00:00:00, counting drop frame timecode by its frame
+    /// numbers rather than the wall clock.
+    pub fn seconds(&self) -> f64 {
+        let whole = (self.hours as u32 * 60 + self.minutes as u32) * 60 + self.seconds as u32;
+        let nominal = match self.rate {
+            Rate::Fps30Drop => 30.0,
+            rate => rate.fps(),
+        };
+        whole as f64 + self.frames as f64 / nominal
+    }
+}
+
+/// Puts timecodes together from the eight quarter frames they are sent in,
+/// four pieces a frame from the low nibble of the frames up to the rate and
+/// high bit of the hours.
+#[derive(Default)]
+pub struct QuarterFrames {
+    pieces: [u8; 8],
+    /// Bit mask of the pieces received since the last timecode.
+    received: u8,
+}
+
+impl QuarterFrames {
+    /// Registers the data byte of a quarter frame message, and returns the
+    /// timecode now playing after the last piece.
+    pub fn push(&mut self, data: u8) -> Option<Timecode> {
+        let piece = (data >> 4 & 0x7) as usize;
+        self.pieces[piece] = data & 0xf;
+        self.received |= 1 << piece;
+        if piece != 7 {
+            return None;
+        }
+        let complete = std::mem::take(&mut self.received) == 0xff;
+        let byte = |piece: usize| self.pieces[piece] | self.pieces[piece + 1] << 4;
+        let mut timecode = Timecode::from_full_frame([byte(6), byte(4), byte(2), byte(0)]);
+        // The pieces took two frames to send.
+        timecode.frames += 2;
+        complete.then_some(timecode)
+    }
+}
+
+/// How the transport follows the timecode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Follow {
+    /// Starts at the position in seconds.
+    Start(f64),
+    /// Moves to the position in seconds, playing on if it was.
+    Locate(f64),
+    /// Runs on as it does.
+    Run,
+}
+
+/// Follows timecode as it runs, jumps and stops.
+#[derive(Default)]
+pub struct Chase {
+    /// The latest position and when it arrived, while running.
+    last: Option<(Instant, f64)>,
+}
+
+impl Chase {
+    /// Timecode that stops for longer than this has stopped.
+    const TIMEOUT: Duration = Duration::from_millis(200);
+    /// Timecode further than this from where it should be has jumped.
+    const TOLERANCE: f64 = 0.5;
+
+    /// Registers running timecode at `seconds` that arrived `at`.
+    pub fn run(&mut self, at: Instant, seconds: f64) -> Follow {
+        let last = self.last.replace((at, seconds));
+        match last {
+            None => Follow::Start(seconds),
+            Some((then, position)) => {
+                let expected = position + at.saturating_duration_since(then).as_secs_f64();
+                if (seconds - expected).abs() > Self::TOLERANCE {
+                    Follow::Locate(seconds)
+                } else {
+                    Follow::Run
+                }
+            }
+        }
+    }
+
+    /// Whether the timecode was running and has stopped by `now`.
+    pub fn stopped(&mut self, now: Instant) -> bool {
+        match self.last {
+            Some((then, _)) if now.saturating_duration_since(then) > Self::TIMEOUT => {
+                self.last = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarter_frames() {
+        let mut frames = QuarterFrames::default();
+        // 01:02:03:04 at 25 fps.
+        let data = [0x04, 0x10, 0x23, 0x30, 0x42, 0x50, 0x61, 0x72];
+        let timecodes: Vec<_> = data.iter().filter_map(|&byte| frames.push(byte)).collect();
+        let timecode = Timecode {
+            hours: 1,
+            minutes: 2,
+            seconds: 3,
+            frames: 6,
+            rate: Rate::Fps25,
+        };
+        assert_eq!(timecodes, [timecode]);
+        assert_eq!(timecode.seconds(), 3723.0 + 6.0 / 25.0);
+        // Starting halfway through gives nothing until the next full round.
+        assert_eq!(frames.push(0x72), None);
+    }
+
+    #[test]
+    fn test_chase() {
+        let mut chase = Chase::default();
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs_f64(seconds);
+        assert_eq!(chase.run(at(0.0), 10.0), Follow::Start(10.0));
+        assert_eq!(chase.run(at(0.1), 10.1), Follow::Run);
+        assert_eq!(chase.run(at(0.2), 60.0), Follow::Locate(60.0));
+        assert!(!chase.stopped(at(0.3)));
+        assert!(chase.stopped(at(1.0)));
+        assert!(!chase.stopped(at(2.0)));
+        assert_eq!(chase.run(at(2.0), 70.0), Follow::Start(70.0));
+    }
+}
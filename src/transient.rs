@@ -0,0 +1,138 @@
+//! Transients in audio, the hits of drums or of pads played with sticks or
+//! fingers: where the level jumps well above what it was just before, and
+//! how hard, from the peak that follows.
+
+use fundsp::hacker::*;
+
+/// Quietest level in decibels a hit is found at, which is also where
+/// velocities start from.
+const FLOOR_DB: f64 = -40.0;
+/// How many times as loud as just before the level has to get.
+const RATIO: f64 = 4.0;
+/// Release in seconds of the peak level followed, and time in seconds that
+/// the level just before is averaged over.
+const FAST_SECONDS: f64 = 0.005;
+const SLOW_SECONDS: f64 = 0.02;
+/// Seconds after a hit that its peak is looked for in, which it is reported
+/// late by, and that no other hit is found in.
+const PEAK_SECONDS: f64 = 0.003;
+const HOLD_SECONDS: f64 = 0.04;
+
+/// A hit, at a velocity from 0 to 1 and `ago` samples before it is reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub velocity: f64,
+    pub ago: usize,
+}
+
+/// Finds the hits in a signal, a sample at a time.
+pub struct Transients {
+    fast: f64,
+    slow: f64,
+    fast_release: f64,
+    slow_release: f64,
+    /// Samples spent on the peak of the latest hit and its peak so far, while
+    /// it is looked for.
+    peak: Option<(usize, f64)>,
+    peak_samples: usize,
+    /// Samples left until hits are found again.
+    hold: usize,
+    hold_samples: usize,
+}
+
+impl Transients {
+    pub fn new(sample_rate: f64) -> Self {
+        let release = |seconds: f64| exp(-1.0 / (seconds * sample_rate));
+        Self {
+            fast: 0.0,
+            slow: 0.0,
+            fast_release: release(FAST_SECONDS),
+            slow_release: release(SLOW_SECONDS),
+            peak: None,
+            peak_samples: (PEAK_SECONDS * sample_rate) as usize,
+            hold: 0,
+            hold_samples: (HOLD_SECONDS * sample_rate) as usize,
+        }
+    }
+
+    /// Follows the signal on with `x`, returning the hit whose peak has just
+    /// been found, if any.
+    pub fn push(&mut self, x: f64) -> Option<Hit> {
+        let level = x.abs();
+        let before = self.slow;
+        self.fast = level.max(self.fast * self.fast_release);
+        self.slow = lerp(level, self.slow, self.slow_release);
+        self.hold = self.hold.saturating_sub(1);
+        if let Some((elapsed, peak)) = &mut self.peak {
+            *elapsed += 1;
+            *peak = peak.max(level);
+            if *elapsed < self.peak_samples {
+                return None;
+            }
+            let (ago, peak) = (*elapsed, *peak);
+            self.peak = None;
+            let velocity = (amp_db(peak) - FLOOR_DB) / -FLOOR_DB;
+            return Some(Hit {
+                velocity: velocity.clamp(0.0, 1.0),
+                ago,
+            });
+        }
+        if self.hold == 0 && self.fast > db_amp(FLOOR_DB) && self.fast > RATIO * before {
+            self.peak = Some((0, level));
+            self.hold = self.hold_samples;
+        }
+        None
+    }
+}
+
+/// Samples that the hits of `samples` at `sample_rate` start at.
+pub fn onsets(samples: &[f64], sample_rate: f64) -> Vec<usize> {
+    let mut transients = Transients::new(sample_rate);
+    samples
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &x)| Some(i - transients.push(x)?.ago))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hits at `amplitudes`, a tenth of a second apart.
+    fn hits(amplitudes: &[f64], sample_rate: f64) -> Vec<f64> {
+        let spacing = (0.1 * sample_rate) as usize;
+        (0..amplitudes.len() * spacing)
+            .map(|i| {
+                let t = (i % spacing) as f64 / sample_rate;
+                amplitudes[i / spacing] * exp(-t * 80.0) * sin(TAU * 200.0 * t + 0.5)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hits_with_velocities() {
+        let sample_rate = 48000.0;
+        let samples = hits(&[1.0, 0.1, 0.0, 0.001, 0.5], sample_rate);
+        let mut transients = Transients::new(sample_rate);
+        let found: Vec<(usize, Hit)> = samples
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &x)| Some((i, transients.push(x)?)))
+            .collect();
+        assert_eq!(found.len(), 3);
+        let velocities: Vec<f64> = found.iter().map(|(_, hit)| hit.velocity).collect();
+        assert!(velocities[0] > 0.95 && (velocities[1] - 0.5).abs() < 0.05);
+        assert!(velocities[0] > velocities[2] && velocities[2] > velocities[1]);
+        assert_eq!(onsets(&samples, sample_rate), [0, 4800, 19200]);
+    }
+
+    #[test]
+    fn test_no_hits_in_ringing() {
+        let sample_rate = 48000.0;
+        let samples: Vec<f64> = (0..48000)
+            .map(|i| 0.5 * sin(TAU * 100.0 * i as f64 / sample_rate))
+            .collect();
+        assert_eq!(onsets(&samples, sample_rate).len(), 1);
+    }
+}
@@ -0,0 +1,480 @@
+//! Tempo, bars and the song position.
+
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use crate::meter::{Meter, TimeSignature};
+
+/// Tempos the song may be played at.
+pub const BPM: RangeInclusive<f64> = 1.0..=999.0;
+/// Beats over which a tempo change covers half the remaining distance.
+const GLIDE_BEATS: f64 = 1.0;
+
+/// A range of bars, zero based, from `start` up to but not including `end`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopRegion {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LoopRegion {
+    /// Parses a one based, inclusive bar range such as `3-4`, or a single bar.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (first, last) = value.split_once('-').unwrap_or((value, value));
+        let (first, last) = (
+            first.trim().parse::<usize>()?,
+            last.trim().parse::<usize>()?,
+        );
+        if first == 0 || last < first {
+            anyhow::bail!("invalid bar range: {}", value);
+        }
+        Ok(Self {
+            start: first - 1,
+            end: last,
+        })
+    }
+}
+
+/// What the next bar is used for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bar {
+    /// A metronome bar before the song starts.
+    CountIn(TimeSignature),
+    /// A bar passing in silence while stopped.
+    Stopped(TimeSignature),
+    /// The song range `from..to` in beats.
+    Song {
+        from: f64,
+        to: f64,
+        signature: TimeSignature,
+    },
+}
+
+impl Bar {
+    pub fn signature(&self) -> TimeSignature {
+        match *self {
+            Bar::CountIn(signature) | Bar::Stopped(signature) | Bar::Song { signature, .. } => {
+                signature
+            }
+        }
+    }
+}
+
+pub struct Transport {
+    bpm: f64,
+    /// Tempo that `bpm` glides towards.
+    target_bpm: f64,
+    /// Beat and time in seconds from which the current tempo applies.
+    anchor_beat: f64,
+    anchor_time: f64,
+    meter: Meter,
+    /// Song bar played next.
+    bar: usize,
+    loop_region: Option<LoopRegion>,
+    /// Count-in bars left before the song continues.
+    count_in: usize,
+    /// Whether recording is armed once the count-in is over.
+    arming: bool,
+    /// Whether the song waits to be started, as by an external clock.
+    stopped: bool,
+}
+
+impl Transport {
+    pub fn new(bpm: f64, meter: Meter) -> Self {
+        Self {
+            bpm,
+            target_bpm: bpm,
+            anchor_beat: 0.0,
+            anchor_time: 0.0,
+            meter,
+            bar: 0,
+            loop_region: None,
+            count_in: 0,
+            arming: false,
+            stopped: false,
+        }
+    }
+
+    pub fn seconds_per_beat(&self) -> f64 {
+        60.0 / self.bpm
+    }
+
+    /// Time in seconds at which the playback `beat` falls, following tempo changes so far.
+    pub fn time_of(&self, beat: f64) -> f64 {
+        self.anchor_time + (beat - self.anchor_beat) * self.seconds_per_beat()
+    }
+
+    /// Playback beat falling at `time` seconds, the inverse of `time_of`.
+    pub fn beat_at(&self, time: f64) -> f64 {
+        self.anchor_beat + (time - self.anchor_time) / self.seconds_per_beat()
+    }
+
+    /// Changes the tempo immediately from the playback `beat` on. Beats that have
+    /// not been played yet are rescaled to the new tempo.
+    pub fn set_bpm(&mut self, beat: f64, bpm: f64) {
+        if bpm.is_finite() {
+            self.glide_to(beat, bpm);
+            self.bpm = self.target_bpm;
+        }
+    }
+
+    /// Starts gliding the tempo towards `bpm` from the playback `beat` on,
+    /// within the tempos that can be played, such as those tapped or read
+    /// off a clock. A tempo that isn't finite is ignored.
+    pub fn glide_to(&mut self, beat: f64, bpm: f64) {
+        if !bpm.is_finite() {
+            return;
+        }
+        let bpm = bpm.clamp(*BPM.start(), *BPM.end());
+        self.anchor_time = self.time_of(beat);
+        self.anchor_beat = beat;
+        self.target_bpm = bpm;
+    }
+
+    /// Advances a tempo glide to the playback `beat`. Beats must not go backwards.
+    pub fn update(&mut self, beat: f64) {
+        let elapsed = beat - self.anchor_beat;
+        if self.bpm == self.target_bpm || elapsed <= 0.0 {
+            return;
+        }
+        self.anchor_time = self.time_of(beat);
+        self.anchor_beat = beat;
+        self.bpm =
+            self.target_bpm + (self.bpm - self.target_bpm) * 0.5f64.powf(elapsed / GLIDE_BEATS);
+        if (self.bpm - self.target_bpm).abs() < 0.1 {
+            self.bpm = self.target_bpm;
+        }
+    }
+
+    /// Holds the playback back by `beats` from the playback `beat` on, or
+    /// moves it ahead for negative ones, to bring it into step.
+    pub fn delay(&mut self, beat: f64, beats: f64) {
+        self.anchor_time = self.time_of(beat);
+        self.anchor_beat = beat - beats;
+    }
+
+    /// Sets or clears the loop. A playhead past the end of the new loop
+    /// wraps to its start at the next bar.
+    pub fn set_loop(&mut self, region: Option<LoopRegion>) {
+        self.loop_region = region;
+    }
+
+    /// The song bar played next.
+    pub fn bar(&self) -> usize {
+        self.bar
+    }
+
+    /// Continues the song from `bar` once the bar playing now is over.
+    pub fn jump(&mut self, bar: usize) {
+        self.bar = bar;
+    }
+
+    /// Plays `bars` bars of metronome before the song continues.
+    pub fn count_in(&mut self, bars: usize) {
+        self.count_in = bars;
+    }
+
+    /// Plays `bars` bars of metronome before the song continues and the
+    /// recording is armed, as `take_armed` tells.
+    pub fn count_in_recording(&mut self, bars: usize) {
+        self.count_in(bars);
+        self.arming = true;
+    }
+
+    /// Whether the count-in of a recording is over, once, the recording
+    /// being armed from the song bar played next on.
+    pub fn take_armed(&mut self) -> bool {
+        self.count_in == 0 && std::mem::take(&mut self.arming)
+    }
+
+    /// Stops the song from the next bar on, or goes on with it.
+    pub fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+    }
+
+    /// Whether there is anything left to play in a song of `length` beats,
+    /// or to wait for while stopped.
+    pub fn is_playing(&self, length: f64) -> bool {
+        self.stopped
+            || self.count_in > 0
+            || self.loop_region.is_some()
+            || self.meter.bar_start(self.bar) < length
+    }
+
+    /// Returns what to play in the next bar and advances past it,
+    /// wrapping around at the end of the loop.
+    pub fn next_bar(&mut self) -> Bar {
+        if self.stopped {
+            return Bar::Stopped(self.meter.signature(self.bar));
+        }
+        if self.count_in > 0 {
+            self.count_in -= 1;
+            return Bar::CountIn(self.meter.signature(self.bar));
+        }
+        if let Some(region) = self.loop_region {
+            if self.bar >= region.end {
+                self.bar = region.start;
+            }
+        }
+        let bar = self.bar;
+        self.bar += 1;
+        Bar::Song {
+            from: self.meter.bar_start(bar),
+            to: self.meter.bar_start(bar + 1),
+            signature: self.meter.signature(bar),
+        }
+    }
+}
+
+/// Derives a tempo from MIDI clock ticks, 24 to the beat. A tempo is given
+/// once a beat, the median of the intervals of the beat before, which
+/// ticks arriving late or early hardly move.
+#[derive(Default)]
+pub struct ClockTempo {
+    /// Seconds between the ticks of the latest beat.
+    intervals: Vec<f64>,
+    last: Option<Instant>,
+}
+
+impl ClockTempo {
+    pub const TICKS_PER_BEAT: usize = 24;
+    /// A pause longer than this means that the clock had stopped.
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// Registers a tick and returns the tempo after every full beat.
+    pub fn tick(&mut self, at: Instant) -> Option<f64> {
+        let last = self.last.replace(at)?;
+        let interval = at.saturating_duration_since(last);
+        if interval > Self::TIMEOUT {
+            self.intervals.clear();
+            return None;
+        }
+        self.intervals.push(interval.as_secs_f64());
+        if self.intervals.len() < Self::TICKS_PER_BEAT {
+            return None;
+        }
+        self.intervals.sort_by(f64::total_cmp);
+        let median = self.intervals[Self::TICKS_PER_BEAT / 2];
+        self.intervals.clear();
+        (median > 0.0).then(|| 60.0 / (median * Self::TICKS_PER_BEAT as f64))
+    }
+}
+
+/// Derives a tempo from the intervals between recent taps.
+#[derive(Default)]
+pub struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    const MAX_TAPS: usize = 5;
+    /// A pause longer than this starts a new series of taps.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Registers a tap and returns the averaged tempo once there are at least two taps.
+    pub fn tap(&mut self, at: Instant) -> Option<f64> {
+        if let Some(&last) = self.taps.last() {
+            if at.saturating_duration_since(last) > Self::TIMEOUT {
+                self.taps.clear();
+            }
+        }
+        self.taps.push(at);
+        if self.taps.len() > Self::MAX_TAPS {
+            self.taps.remove(0);
+        }
+        let span = at.saturating_duration_since(self.taps[0]).as_secs_f64();
+        if self.taps.len() < 2 || span <= 0.0 {
+            return None;
+        }
+        Some(60.0 * (self.taps.len() - 1) as f64 / span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_loop_region() {
+        assert_eq!(
+            LoopRegion::parse("3-4").unwrap(),
+            LoopRegion { start: 2, end: 4 }
+        );
+        assert_eq!(
+            LoopRegion::parse("2").unwrap(),
+            LoopRegion { start: 1, end: 2 }
+        );
+        assert!(LoopRegion::parse("0-1").is_err());
+        assert!(LoopRegion::parse("4-3").is_err());
+    }
+
+    #[test]
+    fn test_loop_wraps_at_end() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.set_loop(Some(LoopRegion { start: 1, end: 3 }));
+        let bars: Vec<_> = (0..5).map(|_| start(transport.next_bar())).collect();
+        assert_eq!(bars, [0.0, 4.0, 8.0, 4.0, 8.0]);
+        assert!(transport.is_playing(0.0));
+
+        transport.set_loop(None);
+        assert_eq!(start(transport.next_bar()), 12.0);
+        assert!(!transport.is_playing(12.0));
+    }
+
+    #[test]
+    fn test_loop_points_follow_time_signatures() {
+        let mut meter = Meter::default();
+        meter.change(1, TimeSignature::new(3, 4));
+        let mut transport = Transport::new(120.0, meter);
+        transport.set_loop(Some(LoopRegion { start: 1, end: 3 }));
+        let bars: Vec<_> = (0..4).map(|_| transport.next_bar()).collect();
+        let waltz = TimeSignature::new(3, 4);
+        assert_eq!(
+            bars[1..],
+            [
+                Bar::Song {
+                    from: 4.0,
+                    to: 7.0,
+                    signature: waltz
+                },
+                Bar::Song {
+                    from: 7.0,
+                    to: 10.0,
+                    signature: waltz
+                },
+                Bar::Song {
+                    from: 4.0,
+                    to: 7.0,
+                    signature: waltz
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delay_moves_later_beats() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.delay(4.0, 0.5);
+        assert_eq!(transport.time_of(8.0), 4.25);
+        transport.delay(8.0, -1.0);
+        assert_eq!(transport.time_of(8.0), 3.75);
+        assert_eq!(transport.beat_at(3.75), 8.0);
+    }
+
+    #[test]
+    fn test_jump_at_next_bar() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        assert_eq!(start(transport.next_bar()), 0.0);
+        transport.jump(5);
+        assert_eq!(start(transport.next_bar()), 20.0);
+        assert_eq!(start(transport.next_bar()), 24.0);
+    }
+
+    #[test]
+    fn test_count_in_comes_before_song() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.count_in(2);
+        assert!(transport.is_playing(0.0));
+        assert_eq!(transport.next_bar(), Bar::CountIn(TimeSignature::COMMON));
+        assert_eq!(transport.next_bar(), Bar::CountIn(TimeSignature::COMMON));
+        assert_eq!(start(transport.next_bar()), 0.0);
+    }
+
+    #[test]
+    fn test_recording_arms_after_count_in() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.next_bar();
+        transport.count_in_recording(1);
+        assert!(!transport.take_armed());
+        assert_eq!(transport.next_bar(), Bar::CountIn(TimeSignature::COMMON));
+        assert!(transport.take_armed());
+        assert!(!transport.take_armed());
+        // The song goes on from where it was.
+        assert_eq!(start(transport.next_bar()), 4.0);
+    }
+
+    #[test]
+    fn test_stopped_until_going_on() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.set_stopped(true);
+        assert!(transport.is_playing(0.0));
+        assert_eq!(transport.next_bar(), Bar::Stopped(TimeSignature::COMMON));
+        transport.set_stopped(false);
+        assert_eq!(start(transport.next_bar()), 0.0);
+    }
+
+    #[test]
+    fn test_clock_tempo_filters_jitter() {
+        let mut clock = ClockTempo::default();
+        let start = Instant::now();
+        // 120 bpm, 1/48 s a tick, with every third tick a millisecond late.
+        let tick = |i: u64| {
+            start + Duration::from_micros(i * 1_000_000 / 48 + i.is_multiple_of(3) as u64 * 1000)
+        };
+        assert_eq!(clock.tick(tick(0)), None);
+        let tempos: Vec<f64> = (1..=48).filter_map(|i| clock.tick(tick(i))).collect();
+        assert_eq!(tempos.len(), 2);
+        assert!(tempos.iter().all(|bpm| (bpm - 120.0).abs() < 0.1));
+        assert_eq!(clock.tick(tick(48) + Duration::from_secs(1)), None);
+    }
+
+    fn start(bar: Bar) -> f64 {
+        match bar {
+            Bar::Song { from, .. } => from,
+            _ => panic!("unexpected bar {:?}", bar),
+        }
+    }
+
+    #[test]
+    fn test_tempo_glides_without_moving_past_beats() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        assert_eq!(transport.time_of(4.0), 2.0);
+
+        transport.glide_to(4.0, 60.0);
+        assert_eq!(transport.time_of(4.0), 2.0);
+        transport.update(5.0);
+        assert_eq!(transport.time_of(5.0), 2.5);
+        assert_eq!(transport.seconds_per_beat(), 60.0 / 90.0);
+
+        for beat in 6..20 {
+            transport.update(beat as f64);
+        }
+        assert_eq!(transport.seconds_per_beat(), 1.0);
+    }
+
+    #[test]
+    fn test_set_bpm_rescales_later_beats() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.set_bpm(2.0, 60.0);
+        assert_eq!(transport.time_of(2.0), 1.0);
+        assert_eq!(transport.time_of(4.0), 3.0);
+    }
+
+    #[test]
+    fn test_tempo_stays_playable() {
+        let mut transport = Transport::new(120.0, Meter::default());
+        transport.set_bpm(0.0, 60000.0);
+        assert_eq!(transport.seconds_per_beat(), 60.0 / 999.0);
+        transport.set_bpm(1.0, 0.0);
+        assert_eq!(transport.seconds_per_beat(), 60.0);
+        transport.set_bpm(2.0, f64::NAN);
+        transport.glide_to(2.0, f64::INFINITY);
+        transport.update(10.0);
+        assert_eq!(transport.seconds_per_beat(), 60.0);
+        transport.glide_to(10.0, -5.0);
+        transport.update(20.0);
+        assert_eq!(transport.seconds_per_beat(), 60.0);
+    }
+
+    #[test]
+    fn test_tap_tempo_averages_intervals() {
+        let mut tap = TapTempo::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(tap.tap(at(0)), None);
+        assert_eq!(tap.tap(at(500)), Some(120.0));
+        assert_eq!(tap.tap(at(1100)), Some(60.0 * 2.0 / 1.1));
+        // A long pause starts over.
+        assert_eq!(tap.tap(at(5000)), None);
+    }
+}
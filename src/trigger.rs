@@ -0,0 +1,89 @@
+//! Trigger and clock pulses from modular hardware, read off an audio input.
+
+use anyhow::bail;
+
+use crate::note::Note;
+
+/// Levels a pulse has to rise above to start and fall below to end, apart so
+/// that noise on an edge doesn't count twice.
+const HIGH: f64 = 0.5;
+const LOW: f64 = 0.25;
+
+/// What the pulses do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// Clock the tempo at this many pulses per beat.
+    Clock(u32),
+    /// Play a note on every pulse.
+    Note(Note),
+}
+
+impl Trigger {
+    /// Parses `clock[,PULSES]`, at 4 pulses per beat unless given, or
+    /// `note,NOTE`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (kind, setting) = match value.split_once(',') {
+            Some((kind, setting)) => (kind, Some(setting.trim())),
+            None => (value, None),
+        };
+        match (kind.trim(), setting) {
+            ("clock", None) => Ok(Trigger::Clock(4)),
+            ("clock", Some(pulses)) => match pulses.parse()? {
+                0 => bail!("a clock needs at least 1 pulse per beat"),
+                pulses => Ok(Trigger::Clock(pulses)),
+            },
+            ("note", Some(note)) => Ok(Trigger::Note(Note::parse(note)?)),
+            ("note", None) => bail!("which note to trigger?"),
+            _ => bail!("unknown trigger: {}", value),
+        }
+    }
+}
+
+/// Finds the rising edges of pulses in a signal.
+#[derive(Default)]
+pub struct Detector {
+    high: bool,
+}
+
+impl Detector {
+    /// Whether a pulse starts at `sample`.
+    pub fn push(&mut self, sample: f64) -> bool {
+        let was_high = self.high;
+        if sample > HIGH {
+            self.high = true;
+        } else if sample < LOW {
+            self.high = false;
+        }
+        self.high && !was_high
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::BaseNote::*;
+
+    #[test]
+    fn test_parse_trigger() {
+        assert_eq!(Trigger::parse("clock").unwrap(), Trigger::Clock(4));
+        assert_eq!(Trigger::parse("clock,24").unwrap(), Trigger::Clock(24));
+        assert_eq!(
+            Trigger::parse("note, C").unwrap(),
+            Trigger::Note(Note::base(C))
+        );
+        assert!(Trigger::parse("clock,0").is_err());
+        assert!(Trigger::parse("note").is_err());
+        assert!(Trigger::parse("gate").is_err());
+    }
+
+    #[test]
+    fn test_edges_with_hysteresis() {
+        let mut detector = Detector::default();
+        let signal = [0.0, 0.8, 0.9, 0.4, 0.6, 0.1, 0.7, 0.0];
+        let edges: Vec<bool> = signal.iter().map(|&x| detector.push(x)).collect();
+        assert_eq!(
+            edges,
+            [false, true, false, false, false, false, true, false]
+        );
+    }
+}
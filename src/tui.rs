@@ -0,0 +1,47 @@
+//! Panels drawn at the top of the terminal, above the console messages and
+//! typed commands, which scroll in the rest of the window.
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Shortest time between redraws.
+const FRAME: Duration = Duration::from_millis(50);
+
+pub struct Tui {
+    /// Rows reserved for the panels.
+    rows: usize,
+    drawn: Option<Instant>,
+}
+
+impl Tui {
+    pub fn new(rows: usize) -> Self {
+        // Clear the screen, keep scrolling below the panels and move there.
+        eprint!("\x1b[2J\x1b[{};r\x1b[{};1H", rows + 1, rows + 1);
+        Self { rows, drawn: None }
+    }
+
+    /// Redraws the panel `lines`, unless the last frame is too recent.
+    pub fn draw(&mut self, lines: &[String]) {
+        if self.drawn.is_some_and(|drawn| drawn.elapsed() < FRAME) {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        // Save the cursor, draw every reserved row and restore the cursor.
+        let mut frame = String::from("\x1b7");
+        for row in 0..self.rows {
+            let line = lines.get(row).map_or("", String::as_str);
+            frame.push_str(&format!("\x1b[{};1H\x1b[2K{}", row + 1, line));
+        }
+        frame.push_str("\x1b8");
+        let mut stderr = std::io::stderr().lock();
+        let _ = stderr.write_all(frame.as_bytes());
+        let _ = stderr.flush();
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        // Scroll the whole window again.
+        eprint!("\x1b[r");
+    }
+}
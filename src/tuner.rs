@@ -0,0 +1,97 @@
+//! The tuner, showing the pitch played on the audio input as the nearest
+//! degree of the tuning and how far it is off.
+
+use std::io::Write;
+use std::time::Duration;
+
+use playground::bridge::Bridge;
+use playground::pitch::{Yin, WINDOW};
+use playground::tuning::Tuning;
+
+use crate::input;
+
+/// Time between pitches shown.
+const POLL: Duration = Duration::from_millis(100);
+/// Cents off that still count as in tune.
+const IN_TUNE_CENTS: f64 = 3.0;
+/// Width of the needle showing how far off the pitch is, 50 cents each way.
+const NEEDLE: usize = 21;
+
+/// Shows the pitch on the zero based `channel` of the default input device of
+/// `host` against `tuning`, until enter is pressed.
+pub fn run(host: &cpal::Host, channel: usize, tuning: &Tuning) -> Result<(), anyhow::Error> {
+    let sample_rate = input::sample_rate(host)?;
+    let bridge = Bridge::new(sample_rate);
+    let _stream = input::record(host, channel, sample_rate, bridge.clone())?;
+
+    let (sender, stop) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = std::io::stdin().read_line(&mut String::new());
+        let _ = sender.send(());
+    });
+    eprintln!("tuning input channel {}, press enter to stop", channel + 1);
+    let mut yin = Yin::default();
+    let mut frames = vec![];
+    let mut samples = vec![0.0; WINDOW];
+    while stop.recv_timeout(POLL).is_err() {
+        bridge.take(&mut frames);
+        let new = frames.len().min(WINDOW);
+        samples.drain(..new);
+        let old = frames.len() - new;
+        samples.extend(frames.drain(..).skip(old).map(|(x, _)| x));
+        let line = match yin.detect(&samples, sample_rate) {
+            Some(frequency) => display(tuning, frequency),
+            None => String::new(),
+        };
+        eprint!("\r{:60}", line);
+        std::io::stderr().flush()?;
+    }
+    eprintln!();
+    Ok(())
+}
+
+/// The line showing `frequency` against `tuning`, such as
+/// `A0 +4 cents 441.0 Hz [..........|+.........]`.
+fn display(tuning: &Tuning, frequency: f64) -> String {
+    let (degree, cents) = tuning.nearest(frequency);
+    let position = ((cents / 50.0 + 1.0) / 2.0 * (NEEDLE - 1) as f64).round() as usize;
+    let needle: String = (0..NEEDLE)
+        .map(|i| match i {
+            _ if i == position => {
+                if cents.abs() <= IN_TUNE_CENTS {
+                    '|'
+                } else {
+                    '+'
+                }
+            }
+            _ if i == NEEDLE / 2 => ':',
+            _ => '.',
+        })
+        .collect();
+    format!(
+        "{} {:+.0} cents {:.1} Hz [{}]",
+        tuning.name(degree),
+        cents,
+        frequency,
+        needle
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let tuning = Tuning::default();
+        assert_eq!(
+            display(&tuning, 440.0),
+            "A0 +0 cents 440.0 Hz [..........|..........]"
+        );
+        let sharp = 440.0 * 2f64.powf(25.0 / 1200.0);
+        assert_eq!(
+            display(&tuning, sharp),
+            format!("A0 +25 cents {:.1} Hz [..........:....+.....]", sharp)
+        );
+    }
+}
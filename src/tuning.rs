@@ -0,0 +1,280 @@
+//! Tuning systems, mapping scale degrees counted from middle C to frequencies.
+
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, bail};
+use fundsp::hacker::*;
+
+use crate::note::{BaseNote, Note};
+
+/// Frequency of middle C, degree zero, with A at 440 Hz in twelve tone equal
+/// temperament.
+pub const MIDDLE_C: f64 = 261.625_565_300_598_6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    /// Cents above middle C of the degrees of the first period, starting at 0.
+    pub pitches: Vec<f64>,
+    /// Cents spanned by the pitches before they repeat, 1200 for an octave.
+    pub period: f64,
+    /// Frequency of degree zero.
+    pub root: f64,
+    /// Degrees that MIDI keys and notes play, if not the nearest ones.
+    pub keyboard: Option<Keyboard>,
+}
+
+/// Assignment of MIDI keys to scale degrees, as in Scala keyboard mappings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyboard {
+    /// Keys that sound, the others are silent.
+    pub keys: RangeInclusive<i32>,
+    /// Key playing degree zero.
+    pub middle: i32,
+    /// Degrees of the keys from `middle` on, or `None` for silent ones. The
+    /// map repeats `octave` degrees higher, and keys play degrees one after
+    /// another when it is empty.
+    pub map: Vec<Option<i32>>,
+    pub octave: i32,
+    /// Key tuned to `frequency`.
+    pub reference: i32,
+    pub frequency: f64,
+}
+
+impl Keyboard {
+    /// Degree played by `key`, if it sounds.
+    pub fn degree(&self, key: i32) -> Option<i32> {
+        if !self.keys.contains(&key) {
+            return None;
+        }
+        let offset = key - self.middle;
+        if self.map.is_empty() {
+            return Some(offset);
+        }
+        let size = self.map.len() as i32;
+        let degree = self.map[offset.rem_euclid(size) as usize]?;
+        offset
+            .div_euclid(size)
+            .checked_mul(self.octave)?
+            .checked_add(degree)
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::edo(12)
+    }
+}
+
+impl Tuning {
+    /// Equal division of the octave into `divisions` steps, such as 19, 24 or 31.
+    pub fn edo(divisions: usize) -> Self {
+        assert!(divisions > 0);
+        Self {
+            pitches: (0..divisions)
+                .map(|step| step as f64 * 1200.0 / divisions as f64)
+                .collect(),
+            period: 1200.0,
+            root: MIDDLE_C,
+            keyboard: None,
+        }
+    }
+
+    /// Plays the degrees that `keyboard` maps keys to, and tunes its
+    /// reference key to its frequency.
+    pub fn with_keyboard(self, keyboard: Keyboard) -> Result<Self, anyhow::Error> {
+        let reference = keyboard
+            .degree(keyboard.reference)
+            .ok_or_else(|| anyhow!("the reference key {} is not mapped", keyboard.reference))?;
+        let root = keyboard.frequency / 2.0.pow(self.cents(reference) / 1200.0);
+        if !(root > 0.0 && root.is_finite()) {
+            bail!("the reference key {} is out of range", keyboard.reference);
+        }
+        Ok(Self {
+            root,
+            keyboard: Some(keyboard),
+            ..self
+        })
+    }
+
+    /// Degrees per period.
+    pub fn divisions(&self) -> usize {
+        self.pitches.len()
+    }
+
+    /// Cents of `degree` above degree zero.
+    fn cents(&self, degree: i32) -> f64 {
+        let divisions = self.divisions() as i32;
+        degree.div_euclid(divisions) as f64 * self.period
+            + self.pitches[degree.rem_euclid(divisions) as usize]
+    }
+
+    /// Frequency of `degree`, negative below the root.
+    pub fn frequency(&self, degree: i32) -> f64 {
+        self.root * 2.0.pow(self.cents(degree) / 1200.0)
+    }
+
+    /// Degree that `note` plays, ignoring its cents: the one its MIDI key is
+    /// mapped to with a keyboard, otherwise the one nearest to its twelve tone
+    /// equal tempered pitch.
+    pub fn degree(&self, note: &Note) -> Option<i32> {
+        if let Some(keyboard) = &self.keyboard {
+            return keyboard.degree(note.midi());
+        }
+        let semitone = BaseNote::ALL.iter().position(|&base| base == note.note);
+        let cents = semitone.unwrap() as f64 * 100.0;
+        // The first degree of the next period may be nearer than the last one.
+        let pitch = |degree: usize| self.pitches.get(degree).copied().unwrap_or(self.period);
+        let nearest = (0..=self.divisions())
+            .min_by(|&a, &b| {
+                (pitch(a) - cents)
+                    .abs()
+                    .total_cmp(&(pitch(b) - cents).abs())
+            })
+            .unwrap();
+        Some(nearest as i32 + note.octave * self.divisions() as i32)
+    }
+
+    /// Frequency of the degree of `note`, detuned by its cents.
+    pub fn note_frequency(&self, note: &Note) -> Option<f64> {
+        Some(self.frequency(self.degree(note)?) * 2.0.pow(note.cents / 1200.0))
+    }
+
+    /// The degree nearest to `frequency`, and how many cents it is off.
+    pub fn nearest(&self, frequency: f64) -> (i32, f64) {
+        let cents = 1200.0 * (frequency / self.root).log2();
+        let base = (cents / self.period).floor() as i32 * self.divisions() as i32;
+        // The first degree of the next period may be nearer than the last one.
+        let degree = (base..=base + self.divisions() as i32)
+            .min_by(|&a, &b| {
+                (self.cents(a) - cents)
+                    .abs()
+                    .total_cmp(&(self.cents(b) - cents).abs())
+            })
+            .unwrap();
+        (degree, cents - self.cents(degree))
+    }
+
+    /// The MIDI key playing nearest to `frequency`, if any does.
+    pub fn nearest_key(&self, frequency: f64) -> Option<u8> {
+        let off = |key: u8| Some((self.key_frequency(key)? / frequency).log2().abs());
+        (0..=127)
+            .filter_map(|key| Some((key, off(key)?)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(key, _)| key)
+    }
+
+    /// Name of `degree`: the note and its octave in twelve tone equal
+    /// temperament, the degree within its period and the period otherwise.
+    pub fn name(&self, degree: i32) -> String {
+        let divisions = self.divisions() as i32;
+        let (period, step) = (degree.div_euclid(divisions), degree.rem_euclid(divisions));
+        let twelve = Tuning::edo(12);
+        if self.pitches == twelve.pitches && self.period == twelve.period {
+            format!("{:?}{}", BaseNote::ALL[step as usize], period)
+        } else {
+            format!("{}/{} in period {}", step, divisions, period)
+        }
+    }
+
+    /// Frequency of MIDI `key`. Without a keyboard the keys play the degrees
+    /// in order with key 60 on the root, so that every degree can be reached.
+    pub fn key_frequency(&self, key: u8) -> Option<f64> {
+        match &self.keyboard {
+            Some(keyboard) => Some(self.frequency(keyboard.degree(key as i32)?)),
+            None => Some(self.frequency(key as i32 - 60)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::get_note_frequency;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_nearest_degree() {
+        let tuning = Tuning::default();
+        let (degree, cents) = tuning.nearest(445.0);
+        assert_eq!((degree, tuning.name(degree).as_str()), (9, "A0"));
+        assert!((cents - 1200.0 * (445.0f64 / 440.0).log2()).abs() < 1e-6);
+        let (degree, cents) = tuning.nearest(MIDDLE_C * 0.99);
+        assert_eq!((tuning.name(degree).as_str(), cents < 0.0), ("C0", true));
+        assert_eq!(tuning.name(-1), "H-1");
+        assert_eq!(tuning.nearest_key(445.0), Some(69));
+        let tuning = Tuning::edo(19);
+        let (degree, cents) = tuning.nearest(tuning.frequency(21));
+        assert_eq!(
+            (tuning.name(degree).as_str(), cents.abs() < 1e-6),
+            ("2/19 in period 1", true)
+        );
+    }
+
+    #[test]
+    fn test_19_edo_degrees() {
+        let tuning = Tuning::edo(19);
+        assert_eq!(tuning.degree(&Note::base(BaseNote::D)), Some(3));
+        assert_eq!(tuning.degree(&Note::base(BaseNote::A)), Some(14));
+        assert_eq!(tuning.degree(&Note::new(BaseNote::C, -1)), Some(-19));
+        assert!((tuning.frequency(19) / MIDDLE_C - 2.0).abs() < 1e-12);
+        let step = tuning.key_frequency(61).unwrap() / MIDDLE_C;
+        assert!((step - 2.0.pow(1.0 / 19.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_keyboard_mapping() {
+        // Five keys per octave from middle C on, the second one silent, with
+        // key 69 at 432 Hz.
+        let keyboard = Keyboard {
+            keys: 0..=127,
+            middle: 60,
+            map: vec![Some(0), None, Some(2), Some(4), Some(7)],
+            octave: 12,
+            reference: 69,
+            frequency: 432.0,
+        };
+        let tuning = Tuning::default().with_keyboard(keyboard.clone()).unwrap();
+        assert_eq!(keyboard.degree(61), None);
+        assert_eq!(keyboard.degree(65), Some(12));
+        assert_eq!(keyboard.degree(59), Some(-5));
+        assert_eq!(keyboard.degree(128), None);
+        assert!((tuning.key_frequency(69).unwrap() - 432.0).abs() < 1e-9);
+        assert_eq!(tuning.note_frequency(&Note::base(BaseNote::Cis)), None);
+        let reference = Keyboard {
+            reference: 61,
+            ..keyboard
+        };
+        assert!(Tuning::default().with_keyboard(reference).is_err());
+    }
+
+    fn any_note() -> impl Strategy<Value = Note> {
+        (0..12usize, -5..=5i32, -100.0..=100.0f64).prop_map(|(note, octave, cents)| {
+            Note::new(BaseNote::ALL[note], octave).with_cents(cents)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_12_edo_matches_note_frequency(note in any_note()) {
+            let frequency = Tuning::default().note_frequency(&note).unwrap();
+            let ratio = frequency / get_note_frequency(&note);
+            prop_assert!((ratio - 1.0).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_steps_are_equal(divisions in 1..=72usize, degree in -200..=200i32) {
+            let tuning = Tuning::edo(divisions);
+            let ratio = tuning.frequency(degree + 1) / tuning.frequency(degree);
+            prop_assert!((ratio - 2.0.pow(1.0 / divisions as f64)).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_degrees_are_nearest(divisions in 1..=72usize, note in any_note()) {
+            let tuning = Tuning::edo(divisions);
+            let exact = get_note_frequency(&note.with_cents(0.0));
+            let nearest = tuning.frequency(tuning.degree(&note).unwrap());
+            let error = 1200.0 * (nearest / exact).log2().abs();
+            prop_assert!(error <= 600.0 / divisions as f64 + 1e-6);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Dynamics of tracks: velocity response curves and accent patterns.
+
+use anyhow::bail;
+
+use crate::humanize::VELOCITY_UNITS;
+
+/// How the velocities of the notes of a track are shaped.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Soft notes are raised, for an even level.
+    Soft,
+    /// Soft notes are lowered further, for more contrast.
+    Hard,
+    /// Every note at full velocity.
+    Fixed,
+}
+
+impl Curve {
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "linear" => Ok(Curve::Linear),
+            "soft" => Ok(Curve::Soft),
+            "hard" => Ok(Curve::Hard),
+            "fixed" => Ok(Curve::Fixed),
+            _ => bail!("unknown velocity curve: {}", value),
+        }
+    }
+
+    /// The velocity that `velocity` from 0 to 1 is played at.
+    pub fn apply(self, velocity: f64) -> f64 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => velocity,
+            Curve::Soft => velocity.sqrt(),
+            Curve::Hard => velocity * velocity,
+            Curve::Fixed => 1.0,
+        }
+    }
+}
+
+/// Steps of a pattern played louder than the others, counted by the beats
+/// notes start on so that the notes of an accord share a step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accent {
+    /// Which steps are accented, repeating over the pattern.
+    pub steps: Vec<bool>,
+    /// Velocity the other steps are played softer by, from 0 to 1.
+    pub amount: f64,
+}
+
+impl Accent {
+    /// Parses `STEPS[,AMOUNT]`, with `x` for accented steps and `.` for the
+    /// others and the amount in MIDI velocity units, such as `x.x.,40` for
+    /// accents on every first and third of four steps. The amount defaults to 32.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let (steps, amount) = match value.split_once(',') {
+            Some((steps, amount)) => (steps, amount.trim().parse()?),
+            None => (value, 32.0),
+        };
+        let steps = steps
+            .trim()
+            .chars()
+            .map(|step| match step {
+                'x' => Ok(true),
+                '.' => Ok(false),
+                _ => bail!("accent steps are x or ., not {}", step),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if steps.is_empty() {
+            bail!("missing accent steps");
+        }
+        if !(0.0..=VELOCITY_UNITS).contains(&amount) {
+            bail!("accent amount must be between 0 and {}", VELOCITY_UNITS);
+        }
+        Ok(Self {
+            steps,
+            amount: amount / VELOCITY_UNITS,
+        })
+    }
+
+    /// Velocity of the notes of the `step`th step of a pattern.
+    pub fn velocity(&self, step: usize) -> f64 {
+        if self.steps[step % self.steps.len()] {
+            1.0
+        } else {
+            1.0 - self.amount
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curves() {
+        assert_eq!(Curve::default().apply(0.25), 0.25);
+        assert_eq!(Curve::parse("soft").unwrap().apply(0.25), 0.5);
+        assert_eq!(Curve::parse("hard").unwrap().apply(0.5), 0.25);
+        assert_eq!(Curve::parse("fixed").unwrap().apply(0.1), 1.0);
+        assert!(Curve::parse("steep").is_err());
+    }
+
+    #[test]
+    fn test_accents_repeat() {
+        let accent = Accent::parse("x.x.,63.5").unwrap();
+        let velocities: Vec<f64> = (0..6).map(|step| accent.velocity(step)).collect();
+        assert_eq!(velocities, [1.0, 0.5, 1.0, 0.5, 1.0, 0.5]);
+        assert_eq!(Accent::parse("x..").unwrap().steps.len(), 3);
+        assert!(Accent::parse("x-x").is_err());
+        assert!(Accent::parse("x,200").is_err());
+    }
+}
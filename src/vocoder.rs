@@ -0,0 +1,155 @@
+//! A channel vocoder, speaking the live audio input through a stack of
+//! detuned saws played at the pitch of the latest live note: the input is
+//! split into bands whose levels are followed, and each of them opens the
+//! same band of the saws.
+
+use fundsp::hacker::*;
+
+use crate::param::{Param, ParamRegistry, Spec};
+
+/// Bands split into, by default and at most.
+pub const BANDS: usize = 16;
+pub const MIN_BANDS: usize = 4;
+pub const MAX_BANDS: usize = 32;
+/// Shift in semitones of the bands of the saws from those of the input,
+/// higher for a smaller voice and lower for a bigger one.
+pub const FORMANT: Spec = Spec::linear(-12.0, 12.0, 0.0);
+/// Level of the vocoder in the mix.
+pub const LEVEL: Spec = Spec {
+    random: (0.25, 0.75),
+    ..Spec::linear(0.0, 2.0, 1.0)
+};
+
+/// Centers of the lowest and the highest band, spaced evenly in between.
+const LOW_HZ: f64 = 100.0;
+const HIGH_HZ: f64 = 8000.0;
+/// Highest a band of the saws is shifted to.
+const MAX_HZ: f64 = 16000.0;
+/// Attack and release in seconds of the followed levels.
+const ATTACK: f64 = 0.002;
+const RELEASE: f64 = 0.02;
+/// Detuning in cents of the saws of the stack.
+const UNISON: [f64; 5] = [-14.0, -6.0, 0.0, 6.0, 14.0];
+/// Gain making up for how little of the saws each narrow band lets through.
+const MAKEUP: f64 = 1.5;
+/// Pitch of the saws before a note is played.
+const FREQUENCY: f64 = 110.0;
+
+/// The vocoder and its knobs, set by name as `vocoder_formant` and
+/// `vocoder_level`.
+pub struct Vocoder {
+    bands: usize,
+    formant: Param,
+    level: Param,
+    frequency: Shared<f64>,
+}
+
+impl Vocoder {
+    pub fn new(params: &mut ParamRegistry, bands: usize) -> Self {
+        assert!((MIN_BANDS..=MAX_BANDS).contains(&bands));
+        Self {
+            bands,
+            formant: params.register("vocoder_formant", FORMANT),
+            level: params.register("vocoder_level", LEVEL),
+            frequency: shared(FREQUENCY),
+        }
+    }
+
+    /// Plays the saws at `frequency` from now on.
+    pub fn play(&self, frequency: f64) {
+        self.frequency.set_value(frequency);
+    }
+
+    /// A unit from the mono input to the stereo output of the saws.
+    pub fn unit(&self) -> Net64 {
+        let carrier = UNISON
+            .iter()
+            .map(|&cents| {
+                let ratio = exp2(cents / 1200.0);
+                Net64::wrap(Box::new(
+                    var_fn(&self.frequency, move |frequency| frequency * ratio) >> saw(),
+                ))
+            })
+            .reduce(|stack, saw| stack + saw)
+            .unwrap()
+            * (MAKEUP / UNISON.len() as f64);
+        let shift = self.formant.unit() >> map(|f: &Frame<f64, U1>| exp2(f[0] / 12.0));
+
+        // Each band, from the input, the saws and the shift, to the saws
+        // at the level of the input in the band.
+        let centers = centers(self.bands);
+        let q = q(self.bands);
+        let bands = centers
+            .into_iter()
+            .map(|center| {
+                let level = bandpass_hz(center, q)
+                    >> map(|f: &Frame<f64, U1>| f[0].abs())
+                    >> follow((ATTACK, RELEASE));
+                let shifted =
+                    pass() | map(move |f: &Frame<f64, U1>| min(f[0] * center, MAX_HZ)) | dc(q);
+                Net64::wrap(Box::new(level * (shifted >> bandpass())))
+            })
+            .reduce(|bands, band| bands & band)
+            .unwrap();
+        (Net64::wrap(Box::new(pass())) | carrier | Net64::wrap(Box::new(shift)))
+            >> bands
+            >> Net64::wrap(Box::new(pass() * self.level.unit() >> split::<U2>()))
+    }
+}
+
+/// Centers of `bands` bands, evenly spaced in octaves.
+fn centers(bands: usize) -> Vec<f64> {
+    (0..bands)
+        .map(|band| LOW_HZ * (HIGH_HZ / LOW_HZ).powf(band as f64 / (bands - 1) as f64))
+        .collect()
+}
+
+/// Q of `bands` bands, wide enough that neighbors meet where they are
+/// half as loud.
+fn q(bands: usize) -> f64 {
+    let half_step = (HIGH_HZ / LOW_HZ).powf(0.5 / (bands - 1) as f64);
+    (half_step - half_step.recip()).recip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak of the left output of `vocoder` speaking a sine at `frequency`.
+    fn peak(vocoder: &Vocoder, frequency: f64, amplitude: f64) -> f64 {
+        let mut unit = vocoder.unit();
+        unit.set_sample_rate(48000.0);
+        let mut output = [0.0; 2];
+        (0..9600)
+            .map(|i| {
+                let x = amplitude * sin(TAU * frequency * i as f64 / 48000.0);
+                unit.tick(&[x], &mut output);
+                output[0].abs()
+            })
+            .skip(4800)
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_bands() {
+        let centers = centers(BANDS);
+        assert_eq!(centers.len(), BANDS);
+        assert!((centers[0] - LOW_HZ).abs() < 1e-9);
+        assert!((centers[BANDS - 1] - HIGH_HZ).abs() < 1e-6);
+        assert!(q(MIN_BANDS) < q(BANDS) && q(BANDS) < q(MAX_BANDS));
+    }
+
+    #[test]
+    fn test_speaks_input_through_saws() {
+        let mut params = ParamRegistry::default();
+        let vocoder = Vocoder::new(&mut params, BANDS);
+        vocoder.play(220.0);
+        assert_eq!(peak(&vocoder, 1000.0, 0.0), 0.0);
+        let (quiet, loud) = (peak(&vocoder, 1000.0, 0.05), peak(&vocoder, 1000.0, 0.5));
+        assert!(quiet > 0.0 && loud > 5.0 * quiet);
+        assert!(loud < 1.0);
+        params.set("vocoder_formant", 12.0).unwrap();
+        assert!(peak(&vocoder, 1000.0, 0.5) > 0.0);
+        assert!(params.set("vocoder_formant", 24.0).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Voice allocation on top of a sequencer.
+
+use fundsp::hacker::*;
+
+use crate::stereo;
+
+/// Fade-in time that declicks note starts.
+const ATTACK_FADE: f64 = 0.001;
+/// Fade-out time for the end of a note and for the tail of a stolen note.
+const TAIL_FADE: f64 = 0.05;
+
+#[derive(Clone, Copy)]
+struct Voice {
+    event: EventId,
+    start: f64,
+    end: f64,
+}
+
+/// Limits the number of overlapping sequencer events. A note that would exceed
+/// the limit steals the voice of the oldest note, which is faded out quickly
+/// instead of being cut.
+pub struct VoicePool {
+    voices: Vec<Option<Voice>>,
+    pending: Option<usize>,
+    /// How far voices are moved to the side by their pitch, from 0 to 1.
+    pitch_pan: f64,
+}
+
+impl VoicePool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            voices: vec![None; size],
+            pending: None,
+            pitch_pan: 0.0,
+        }
+    }
+
+    /// Spreads voices across the stereo field by pitch, by up to `amount` from 0 to 1.
+    pub fn set_pitch_pan(&mut self, amount: f64) {
+        self.pitch_pan = amount;
+    }
+
+    /// Pan position of a voice at `frequency` on a track panned to `position`.
+    pub fn pan(&self, position: f64, frequency: f64) -> f64 {
+        (position + self.pitch_pan * stereo::pitch_offset(frequency)).clamp(-1.0, 1.0)
+    }
+
+    pub fn size(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Requests a new pool size. It takes effect at the next `apply_pending`.
+    pub fn request_size(&mut self, size: usize) {
+        self.pending = Some(size);
+    }
+
+    /// Resizes the pool if requested. Meant to be called at bar boundaries:
+    /// notes on removed voices are faded out starting at `time`.
+    /// Returns whether the pool changed.
+    pub fn apply_pending(&mut self, sequencer: &mut Sequencer64, time: f64) -> bool {
+        match self.pending.take() {
+            Some(size) if size != self.size() => {
+                let keep = min(size, self.voices.len());
+                for voice in self.voices.drain(keep..).flatten() {
+                    Self::fade_out(sequencer, voice, time);
+                }
+                self.voices.resize(size, None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn fade_out(sequencer: &mut Sequencer64, voice: Voice, time: f64) {
+        let end = time.max(voice.start) + TAIL_FADE;
+        if end < voice.end {
+            sequencer.edit(voice.event, end, TAIL_FADE);
+        }
+    }
+
+    /// Plays `unit` from `start` to `end` seconds. Notes must be pushed in order of start time.
+    /// A note held with an infinite `end` sounds until `release`d.
+    pub fn note(
+        &mut self,
+        sequencer: &mut Sequencer64,
+        start: f64,
+        end: f64,
+        unit: Box<dyn AudioUnit64>,
+    ) -> EventId {
+        // Prefer the voice that went silent first, then the oldest sounding one.
+        let key = |voice: &Option<Voice>| match voice {
+            Some(voice) if voice.end > start => (true, voice.start),
+            Some(voice) => (false, voice.end),
+            None => (false, f64::NEG_INFINITY),
+        };
+        let index = (0..self.voices.len())
+            .min_by(|&i, &j| {
+                let (busy_i, time_i) = key(&self.voices[i]);
+                let (busy_j, time_j) = key(&self.voices[j]);
+                busy_i.cmp(&busy_j).then(time_i.total_cmp(&time_j))
+            })
+            .unwrap();
+        if let Some(voice) = self.voices[index] {
+            Self::fade_out(sequencer, voice, start);
+        }
+        let fade_out = TAIL_FADE.min(end - start);
+        let event = sequencer.push(start, end, Fade::Smooth, ATTACK_FADE, fade_out, unit);
+        self.voices[index] = Some(Voice { event, start, end });
+        event
+    }
+
+    /// Fades out every note quickly from `time` on, cutting those that
+    /// haven't started by then, and frees all voices.
+    pub fn silence(&mut self, sequencer: &mut Sequencer64, time: f64) {
+        for voice in self.voices.iter_mut().filter_map(Option::take) {
+            if voice.start >= time {
+                sequencer.edit(voice.event, voice.start, 0.0);
+            } else {
+                Self::fade_out(sequencer, voice, time);
+            }
+        }
+    }
+
+    /// Fades out the note of `event` over `fade` seconds from `time` on, if it
+    /// still holds a voice.
+    pub fn release(&mut self, sequencer: &mut Sequencer64, event: EventId, time: f64, fade: f64) {
+        let voice = self
+            .voices
+            .iter_mut()
+            .flatten()
+            .find(|voice| voice.event == event);
+        if let Some(voice) = voice {
+            voice.end = time.max(voice.start) + fade;
+            sequencer.edit(event, voice.end, fade);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders `sequencer` up to `time` seconds and returns the last sample.
+    fn render_until(sequencer: &mut Sequencer64, time: f64) -> f64 {
+        let mut output = [0.0];
+        while sequencer.time() < time {
+            sequencer.tick(&[], &mut output);
+        }
+        output[0]
+    }
+
+    #[test]
+    fn test_notes_within_limit_overlap() {
+        let mut sequencer = Sequencer64::new(false, 1);
+        let mut pool = VoicePool::new(2);
+        pool.note(&mut sequencer, 0.0, 1.0, Box::new(dc(0.5)));
+        pool.note(&mut sequencer, 0.1, 1.0, Box::new(dc(0.25)));
+        assert_eq!(render_until(&mut sequencer, 0.5), 0.75);
+    }
+
+    #[test]
+    fn test_oldest_note_is_stolen_with_fade() {
+        let mut sequencer = Sequencer64::new(false, 1);
+        let mut pool = VoicePool::new(2);
+        pool.note(&mut sequencer, 0.0, 1.0, Box::new(dc(1.0)));
+        pool.note(&mut sequencer, 0.1, 1.0, Box::new(dc(2.0)));
+        pool.note(&mut sequencer, 0.2, 1.0, Box::new(dc(4.0)));
+
+        let fading = render_until(&mut sequencer, 0.2 + TAIL_FADE / 2.0);
+        assert!(fading > 6.0 && fading < 7.0);
+        assert_eq!(render_until(&mut sequencer, 0.5), 6.0);
+    }
+
+    #[test]
+    fn test_held_note_sounds_until_released() {
+        let mut sequencer = Sequencer64::new(false, 1);
+        let mut pool = VoicePool::new(1);
+        let event = pool.note(&mut sequencer, 0.0, f64::INFINITY, Box::new(dc(1.0)));
+        assert_eq!(render_until(&mut sequencer, 1.0), 1.0);
+        pool.release(&mut sequencer, event, 1.1, 0.1);
+        assert_eq!(render_until(&mut sequencer, 1.5), 0.0);
+    }
+
+    #[test]
+    fn test_silence_ends_every_note() {
+        let mut sequencer = Sequencer64::new(false, 1);
+        let mut pool = VoicePool::new(3);
+        pool.note(&mut sequencer, 0.0, f64::INFINITY, Box::new(dc(1.0)));
+        pool.note(&mut sequencer, 0.0, 2.0, Box::new(dc(2.0)));
+        pool.note(&mut sequencer, 0.5, 2.0, Box::new(dc(4.0)));
+        assert_eq!(render_until(&mut sequencer, 0.2), 3.0);
+        pool.silence(&mut sequencer, 0.3);
+        assert_eq!(render_until(&mut sequencer, 1.0), 0.0);
+        // The voices are free for new notes.
+        pool.note(&mut sequencer, 1.0, 2.0, Box::new(dc(0.5)));
+        assert_eq!(render_until(&mut sequencer, 1.5), 0.5);
+    }
+
+    #[test]
+    fn test_pitch_pan_moves_high_notes_right() {
+        let mut pool = VoicePool::new(1);
+        assert_eq!(pool.pan(0.5, 4000.0), 0.5);
+        pool.set_pitch_pan(1.0);
+        assert!(pool.pan(0.0, 4000.0) > 0.5);
+        assert!(pool.pan(0.0, 60.0) < -0.5);
+        assert_eq!(pool.pan(0.8, 20000.0), 1.0);
+    }
+
+    #[test]
+    fn test_shrinking_fades_out_removed_voices() {
+        let mut sequencer = Sequencer64::new(false, 1);
+        let mut pool = VoicePool::new(2);
+        pool.note(&mut sequencer, 0.0, 1.0, Box::new(dc(1.0)));
+        pool.note(&mut sequencer, 0.0, 1.0, Box::new(dc(2.0)));
+
+        pool.request_size(1);
+        assert_eq!(pool.size(), 2);
+        assert!(pool.apply_pending(&mut sequencer, 0.1));
+        assert_eq!(pool.size(), 1);
+        assert!(!pool.apply_pending(&mut sequencer, 0.1));
+        assert_eq!(render_until(&mut sequencer, 0.5), 1.0);
+    }
+}
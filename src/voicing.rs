@@ -0,0 +1,158 @@
+//! Voicings of accords, the octaves their notes are played in, and leading
+//! from one accord to the next with as little movement as possible.
+
+use crate::note::{Accord, Note};
+
+/// Octaves around a chord that led voicings are searched in.
+const LEAD_OCTAVES: i32 = 2;
+
+impl Accord {
+    /// The notes from the lowest up.
+    fn sorted(&self) -> Vec<Note> {
+        let mut notes = self.notes.clone();
+        notes.sort_by_key(|note| note.midi());
+        notes
+    }
+
+    fn closed_order(self) -> Accord {
+        Accord {
+            notes: self.sorted(),
+        }
+    }
+
+    /// Every note within an octave above the lowest one.
+    pub fn closed(&self) -> Accord {
+        let notes = self.sorted();
+        let Some(bass) = notes.first().map(|note| note.midi()) else {
+            return Accord { notes };
+        };
+        let mut notes: Vec<Note> = notes
+            .iter()
+            .map(|note| note.transpose(-12 * (note.midi() - bass).div_euclid(12)))
+            .collect();
+        notes.sort_by_key(|note| note.midi());
+        Accord { notes }
+    }
+
+    /// Closed voicing with its `nth` highest note, counted from 1, an octave lower.
+    fn drop(&self, nth: usize) -> Accord {
+        let mut notes = self.closed().notes;
+        if let Some(index) = notes.len().checked_sub(nth) {
+            notes[index] = notes[index].transpose(-12);
+        }
+        Accord { notes }.closed_order()
+    }
+
+    /// Closed voicing with its second highest note an octave lower.
+    pub fn drop2(&self) -> Accord {
+        self.drop(2)
+    }
+
+    /// Closed voicing with its third highest note an octave lower.
+    pub fn drop3(&self) -> Accord {
+        self.drop(3)
+    }
+
+    /// Closed voicing with every second note from the bass up an octave higher,
+    /// spanning about two octaves.
+    pub fn spread(&self) -> Accord {
+        let notes = self.closed().notes;
+        Accord {
+            notes: notes
+                .iter()
+                .enumerate()
+                .map(|(i, note)| note.transpose(12 * (i % 2) as i32))
+                .collect(),
+        }
+        .closed_order()
+    }
+
+    /// Without the lowest note, which is the root of a chord in root position,
+    /// as left to the bass player.
+    pub fn rootless(&self) -> Accord {
+        Accord {
+            notes: self.sorted().into_iter().skip(1).collect(),
+        }
+    }
+
+    /// The notes of `next` in the inversion and octave closest to this voicing,
+    /// so that the voices move as little as possible.
+    pub fn lead_to(&self, next: &Accord) -> Accord {
+        let from = self.sorted();
+        let closed = next.closed().notes;
+        if from.is_empty() || closed.is_empty() {
+            return next.closed();
+        }
+        // Semitones from every note of one voicing to the nearest of the other.
+        let distance = |a: &[Note], b: &[Note]| -> i32 {
+            a.iter()
+                .map(|a| b.iter().map(|b| (a.midi() - b.midi()).abs()).min().unwrap())
+                .sum()
+        };
+        let center = from[0].midi() - closed[0].midi();
+        let mut best: Option<(i32, Vec<Note>)> = None;
+        for inversion in 0..closed.len() {
+            let mut notes: Vec<Note> = closed.clone();
+            for note in &mut notes[..inversion] {
+                *note = note.transpose(12);
+            }
+            for octave in -LEAD_OCTAVES..=LEAD_OCTAVES {
+                let shift = 12 * (center.div_euclid(12) + octave);
+                let candidate: Vec<Note> = notes.iter().map(|note| note.transpose(shift)).collect();
+                let cost = distance(&candidate, &from) + distance(&from, &candidate);
+                if best.as_ref().is_none_or(|(best, _)| cost < *best) {
+                    best = Some((cost, candidate));
+                }
+            }
+        }
+        Accord {
+            notes: best.unwrap().1,
+        }
+        .closed_order()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::BaseNote::{self, *};
+
+    fn accord(notes: &[(BaseNote, i32)]) -> Accord {
+        Accord {
+            notes: notes
+                .iter()
+                .map(|&(note, octave)| Note::new(note, octave))
+                .collect(),
+        }
+    }
+
+    fn keys(accord: &Accord) -> Vec<i32> {
+        accord.notes.iter().map(|note| note.midi()).collect()
+    }
+
+    #[test]
+    fn test_voicings() {
+        // C major seventh spread over three octaves.
+        let cmaj7 = accord(&[(C, 0), (H, 1), (E, 0), (G, -1)]);
+        assert_eq!(keys(&cmaj7.closed()), [55, 59, 60, 64]);
+        assert_eq!(keys(&cmaj7.drop2()), [48, 55, 59, 64]);
+        let closed = accord(&[(C, 0), (E, 0), (G, 0), (H, 0)]);
+        assert_eq!(keys(&closed.closed()), [60, 64, 67, 71]);
+        assert_eq!(keys(&closed.drop2()), [55, 60, 64, 71]);
+        assert_eq!(keys(&closed.drop3()), [52, 60, 67, 71]);
+        assert_eq!(keys(&closed.spread()), [60, 67, 76, 83]);
+        assert_eq!(keys(&closed.rootless()), [64, 67, 71]);
+    }
+
+    #[test]
+    fn test_voice_leading_moves_little() {
+        let c = accord(&[(C, 0), (E, 0), (G, 0)]);
+        let f = accord(&[(F, 0), (A, 0), (C, 1)]);
+        let g = accord(&[(G, 0), (H, 0), (D, 1)]);
+        // C E G to C F A, holding the C.
+        assert_eq!(keys(&c.lead_to(&f)), [60, 65, 69]);
+        // C E G to H D G, holding the G.
+        assert_eq!(keys(&c.lead_to(&g)), [59, 62, 67]);
+        assert_eq!(keys(&Accord { notes: vec![] }.lead_to(&f)), [65, 69, 72]);
+    }
+}
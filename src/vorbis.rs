@@ -0,0 +1,680 @@
+//! Just enough of Vorbis to encode lossily what is rendered and streamed:
+//! long blocks only, a floor of straight lines through fixed posts on a
+//! scale of frequency close to logarithmic, and the spectrum quantized
+//! against the floor in partitions of four classes, coded with codebooks
+//! built once from a model of how often their values come up. There is no
+//! psychoacoustic model; the floor sits a fixed margin below the loudest
+//! coefficient around each post, so that the noise of the quantization
+//! follows the spectrum.
+
+use std::sync::Arc;
+
+use fundsp::hacker::db_amp;
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+
+/// Samples of a block, the second half of which overlaps the next one.
+pub const BLOCK: usize = 2048;
+/// Coefficients of a block, and samples that each block adds.
+const HALF: usize = BLOCK / 2;
+/// Short blocks have to be declared although they are never used.
+const SHORT_BLOCK: usize = 256;
+
+/// Multiplier of the floor, its values stepping by about 1.1 dB over 128 steps.
+const MULTIPLIER: u32 = 2;
+const FLOOR_RANGE: u32 = 128;
+/// Bits of the positions of the posts, which go up to the number of
+/// coefficients.
+const RANGE_BITS: u32 = 10;
+/// Posts of the floor besides those at either end, closer together at low
+/// frequencies.
+const POSTS: [u16; 31] = [
+    2, 4, 6, 8, 11, 14, 18, 23, 28, 34, 41, 49, 58, 68, 80, 94, 110, 128, 150, 175, 205, 240, 280,
+    330, 390, 460, 540, 640, 760, 880, 960,
+];
+/// Ratio between neighbouring values of the floor's table of decibels,
+/// which ends at 1.
+const FLOOR_STEP: f64 = 1.0649863;
+/// Decibels the floor is under the loudest coefficient around a post, and
+/// the lowest it goes, relative to a full scale sine.
+const MARGIN_DB: f64 = 30.0;
+const QUIETEST_DB: f64 = -100.0;
+
+/// Coefficients classified together, and their classes: silent, within
+/// one step of zero, within four, and larger, coded in a coarse pass in
+/// steps of nine and a fine one.
+const PARTITION: usize = 16;
+const CLASSES: usize = 4;
+const COARSE: i32 = 9;
+const LARGEST: i32 = 15 * COARSE + 4;
+
+/// Codebooks by number, as the setup header lists them.
+const FLOOR_BOOK: usize = 0;
+const CLASS_BOOK: usize = 1;
+const SMALL_BOOK: usize = 2;
+const MEDIUM_BOOK: usize = 3;
+const COARSE_BOOK: usize = 4;
+
+/// Bits written the way Vorbis packs them, from the lowest bit of each byte.
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    used: usize,
+}
+
+impl Bits {
+    fn bit(&mut self, bit: bool) {
+        if self.used.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 1 << (self.used % 8);
+        }
+        self.used += 1;
+    }
+
+    /// The lowest `bits` bits of `value`, lowest first.
+    fn write(&mut self, value: u32, bits: u32) {
+        for i in 0..bits {
+            self.bit(value.checked_shr(i).unwrap_or(0) & 1 != 0);
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte as u32, 8);
+        }
+    }
+
+    /// A codeword, from its first bit down the tree.
+    fn code(&mut self, (word, length): (u32, u8)) {
+        for i in (0..length).rev() {
+            self.bit(word >> i & 1 != 0);
+        }
+    }
+}
+
+/// Bits that `value` takes.
+fn ilog(value: u32) -> u32 {
+    32 - value.leading_zeros()
+}
+
+/// `value` as a Vorbis float, exactly for integers of 21 bits.
+fn float32(value: i32) -> u32 {
+    let sign = if value < 0 { 0x8000_0000 } else { 0 };
+    sign | 788 << 21 | value.unsigned_abs()
+}
+
+/// Lengths of the codewords of a Huffman code for entries as likely as
+/// `weights`.
+fn huffman(weights: &[f64]) -> Vec<u8> {
+    let mut lengths = vec![0u8; weights.len()];
+    // Subtrees with their weight and the entries in them.
+    let mut trees: Vec<(f64, Vec<usize>)> = weights
+        .iter()
+        .enumerate()
+        .map(|(entry, &weight)| (weight, vec![entry]))
+        .collect();
+    while trees.len() > 1 {
+        trees.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let (weight, entries) = trees.pop().unwrap();
+        let (other, others) = trees.pop().unwrap();
+        for &entry in entries.iter().chain(&others) {
+            lengths[entry] += 1;
+        }
+        trees.push((weight + other, [entries, others].concat()));
+    }
+    lengths
+}
+
+/// The codewords of `lengths` that Vorbis decoders assign, each the first
+/// left free for its length in the order of the entries.
+fn codewords(lengths: &[u8]) -> Vec<u32> {
+    let mut marker = [0u32; 33];
+    lengths
+        .iter()
+        .map(|&length| {
+            let length = length as usize;
+            let word = marker[length];
+            for j in (1..=length).rev() {
+                if marker[j] & 1 != 0 {
+                    marker[j] = if j == 1 {
+                        marker[1] + 1
+                    } else {
+                        marker[j - 1] << 1
+                    };
+                    break;
+                }
+                marker[j] += 1;
+            }
+            let mut entry = word;
+            for j in length + 1..33 {
+                if marker[j] >> 1 != entry {
+                    break;
+                }
+                entry = marker[j];
+                marker[j] = marker[j - 1] << 1;
+            }
+            word
+        })
+        .collect()
+}
+
+struct Codebook {
+    dimensions: usize,
+    lengths: Vec<u8>,
+    words: Vec<u32>,
+    /// The least value, the step and the number of values of each scalar
+    /// of the vectors of a book that holds them.
+    lookup: Option<(i32, i32, u32)>,
+}
+
+impl Codebook {
+    /// A book of entries as likely as `weights`.
+    fn scalar(weights: &[f64]) -> Self {
+        let lengths = huffman(weights);
+        Self {
+            dimensions: 1,
+            words: codewords(&lengths),
+            lengths,
+            lookup: None,
+        }
+    }
+
+    /// A book of the vectors of `dimensions` scalars from `min` in steps of
+    /// `delta`, `values` of them, each scalar as likely as `weight` says.
+    fn vectors(
+        dimensions: usize,
+        min: i32,
+        delta: i32,
+        values: u32,
+        weight: impl Fn(i32) -> f64,
+    ) -> Self {
+        let entries = values.pow(dimensions as u32);
+        let weights: Vec<f64> = (0..entries)
+            .map(|entry| {
+                (0..dimensions)
+                    .map(|j| weight(min + delta * (entry / values.pow(j as u32) % values) as i32))
+                    .product()
+            })
+            .collect();
+        Self {
+            dimensions,
+            lookup: Some((min, delta, values)),
+            ..Self::scalar(&weights)
+        }
+    }
+
+    fn header(&self, bits: &mut Bits) {
+        bits.write(0x56_4342, 24);
+        bits.write(self.dimensions as u32, 16);
+        bits.write(self.lengths.len() as u32, 24);
+        // Neither ordered nor sparse.
+        bits.write(0, 2);
+        for &length in &self.lengths {
+            bits.write(length as u32 - 1, 5);
+        }
+        match self.lookup {
+            None => bits.write(0, 4),
+            Some((min, delta, values)) => {
+                bits.write(1, 4);
+                bits.write(float32(min), 32);
+                bits.write(float32(delta), 32);
+                let value_bits = ilog(values - 1);
+                bits.write(value_bits - 1, 4);
+                bits.write(0, 1);
+                for value in 0..values {
+                    bits.write(value, value_bits);
+                }
+            }
+        }
+    }
+
+    fn code(&self, entry: usize) -> (u32, u8) {
+        (self.words[entry], self.lengths[entry])
+    }
+
+    /// Writes the entry of the vector `scalars`, each one of the values.
+    fn vector(&self, bits: &mut Bits, scalars: &[i32]) {
+        let (min, delta, values) = self.lookup.unwrap();
+        let entry = scalars
+            .iter()
+            .rev()
+            .fold(0, |entry, &x| entry * values + ((x - min) / delta) as u32);
+        bits.code(self.code(entry as usize));
+    }
+}
+
+/// The position of the closest post below `posts[i]` among those before it,
+/// and of the closest one above.
+fn neighbours(posts: &[u16], i: usize) -> (usize, usize) {
+    let before = &posts[..i];
+    let low = (0..i)
+        .filter(|&j| before[j] < posts[i])
+        .max_by_key(|&j| before[j]);
+    let high = (0..i)
+        .filter(|&j| before[j] > posts[i])
+        .min_by_key(|&j| before[j]);
+    (low.unwrap(), high.unwrap())
+}
+
+/// The value at `x` on the line from `(x0, y0)` to `(x1, y1)`, rounded as
+/// decoders round it.
+fn render_point(x0: i32, y0: i32, x1: i32, y1: i32, x: i32) -> i32 {
+    let dy = y1 - y0;
+    let offset = dy.abs() * (x - x0) / (x1 - x0);
+    if dy < 0 {
+        y0 - offset
+    } else {
+        y0 + offset
+    }
+}
+
+/// Draws the line from `(x0, y0)` up to `x1` into `floor`, as decoders do.
+fn render_line(x0: i32, y0: i32, x1: i32, y1: i32, floor: &mut [i32]) {
+    let dy = y1 - y0;
+    let adx = x1 - x0;
+    let base = dy / adx;
+    let step = if dy < 0 { base - 1 } else { base + 1 };
+    let ady = dy.abs() - base.abs() * adx;
+    let (mut y, mut err) = (y0, 0);
+    for x in x0..x1.min(floor.len() as i32) {
+        if x > x0 {
+            err += ady;
+            if err >= adx {
+                err -= adx;
+                y += step;
+            } else {
+                y += base;
+            }
+        }
+        floor[x as usize] = y;
+    }
+}
+
+/// Posts ordered for each to be predicted from the two it falls between:
+/// the middle of the posts, then the middles of either side in turn.
+fn bisect(sorted: &[u16], order: &mut Vec<u16>) {
+    if !sorted.is_empty() {
+        let middle = sorted.len() / 2;
+        order.push(sorted[middle]);
+        bisect(&sorted[..middle], order);
+        bisect(&sorted[middle + 1..], order);
+    }
+}
+
+/// A forward MDCT of a block, windowed with the window of Vorbis, as the
+/// odd bins of a real FFT four times as long.
+struct Mdct {
+    fft: Arc<dyn RealToComplex<f64>>,
+    window: Vec<f64>,
+    /// The shift of the samples by a quarter block and half a sample, as a
+    /// rotation of the odd bins, with the scale.
+    twiddles: Vec<Complex<f64>>,
+    input: Vec<f64>,
+    spectrum: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Mdct {
+    fn new() -> Self {
+        let fft = RealFftPlanner::new().plan_fft_forward(4 * HALF);
+        let window = (0..BLOCK)
+            .map(|n| {
+                let x = (std::f64::consts::PI * (n as f64 + 0.5) / BLOCK as f64).sin();
+                (std::f64::consts::FRAC_PI_2 * x * x).sin()
+            })
+            .collect();
+        let shift = 0.5 + HALF as f64 / 2.0;
+        let twiddles = (0..HALF)
+            .map(|k| {
+                let phase = std::f64::consts::PI * shift * (2 * k + 1) as f64 / BLOCK as f64;
+                Complex::from_polar(2.0 / HALF as f64, -phase)
+            })
+            .collect();
+        Self {
+            input: fft.make_input_vec(),
+            spectrum: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            window,
+            twiddles,
+        }
+    }
+
+    fn transform(&mut self, block: &[f64], coefficients: &mut [f64]) {
+        for ((input, &x), &w) in self.input.iter_mut().zip(block).zip(&self.window) {
+            *input = x * w;
+        }
+        self.input[BLOCK..].fill(0.0);
+        self.fft
+            .process_with_scratch(&mut self.input, &mut self.spectrum, &mut self.scratch)
+            .unwrap();
+        for (k, coefficient) in coefficients.iter_mut().enumerate() {
+            *coefficient = (self.twiddles[k] * self.spectrum[2 * k + 1]).re;
+        }
+    }
+}
+
+/// Encodes frames of samples into Vorbis packets as they come, each block
+/// half overlapping the one before.
+pub struct Encoder {
+    sample_rate: u32,
+    books: Vec<Codebook>,
+    /// Posts of the floor in the order they are coded, starting with those
+    /// at either end.
+    posts: Vec<u16>,
+    mdct: Mdct,
+    /// Samples of each channel of the block being filled, the first half
+    /// of them from the block before.
+    blocks: Vec<Vec<f64>>,
+    coefficients: Vec<f64>,
+}
+
+impl Encoder {
+    pub fn new(sample_rate: f64, channels: usize) -> Self {
+        let laplace = |scale: f64| move |x: i32| (-(x.abs() as f64) / scale).exp();
+        let floor: Vec<f64> = (0..FLOOR_RANGE)
+            .map(|value| (-(value as f64) / 6.0).exp() + 0.002)
+            .collect();
+        let class = [0.35, 0.3, 0.2, 0.15];
+        let classes: Vec<f64> = (0..CLASSES * CLASSES)
+            .map(|entry| class[entry / CLASSES] * class[entry % CLASSES])
+            .collect();
+        let books = vec![
+            Codebook::scalar(&floor),
+            Codebook {
+                dimensions: 2,
+                ..Codebook::scalar(&classes)
+            },
+            Codebook::vectors(4, -1, 1, 3, |x| if x == 0 { 2.0 } else { 1.0 }),
+            Codebook::vectors(2, -4, 1, 9, laplace(1.2)),
+            Codebook::vectors(1, -15 * COARSE, COARSE, 31, |x| laplace(2.5)(x / COARSE)),
+        ];
+        let mut posts = vec![0, HALF as u16];
+        bisect(&POSTS, &mut posts);
+        Self {
+            sample_rate: sample_rate.round() as u32,
+            books,
+            posts,
+            mdct: Mdct::new(),
+            blocks: vec![vec![0.0; HALF]; channels],
+            coefficients: vec![0.0; HALF],
+        }
+    }
+
+    /// The identification, comment and setup header packets.
+    pub fn headers(&self) -> [Vec<u8>; 3] {
+        let start = |kind: u8| {
+            let mut bits = Bits::default();
+            bits.write(kind as u32, 8);
+            bits.bytes(b"vorbis");
+            bits
+        };
+
+        let mut identification = start(1);
+        identification.write(0, 32);
+        identification.write(self.blocks.len() as u32, 8);
+        identification.write(self.sample_rate, 32);
+        // No bitrates to keep to.
+        identification.write(0, 96);
+        identification.write(SHORT_BLOCK.trailing_zeros(), 4);
+        identification.write(BLOCK.trailing_zeros(), 4);
+        identification.write(1, 1);
+
+        let mut comments = start(3);
+        let vendor = concat!("playground ", env!("CARGO_PKG_VERSION"));
+        comments.write(vendor.len() as u32, 32);
+        comments.bytes(vendor.as_bytes());
+        comments.write(0, 32);
+        comments.write(1, 1);
+
+        let mut setup = start(5);
+        setup.write(self.books.len() as u32 - 1, 8);
+        for book in &self.books {
+            book.header(&mut setup);
+        }
+        // The placeholder of the time domain transforms.
+        setup.write(0, 6);
+        setup.write(0, 16);
+        // A floor of type 1 with a partition of a post for each post but
+        // those at either end, all of one class.
+        setup.write(0, 6);
+        setup.write(1, 16);
+        setup.write(POSTS.len() as u32, 5);
+        setup.write(0, 4 * POSTS.len() as u32);
+        setup.write(0, 3);
+        setup.write(0, 2);
+        setup.write(FLOOR_BOOK as u32 + 1, 8);
+        setup.write(MULTIPLIER - 1, 2);
+        setup.write(RANGE_BITS, 4);
+        for &post in &self.posts[2..] {
+            setup.write(post as u32, RANGE_BITS);
+        }
+        // A residue of type 1 over every coefficient, with the books of the
+        // passes of each class.
+        setup.write(0, 6);
+        setup.write(1, 16);
+        setup.write(0, 24);
+        setup.write(HALF as u32, 24);
+        setup.write(PARTITION as u32 - 1, 24);
+        setup.write(CLASSES as u32 - 1, 6);
+        setup.write(CLASS_BOOK as u32, 8);
+        let passes: [&[usize]; CLASSES] = [
+            &[],
+            &[SMALL_BOOK],
+            &[MEDIUM_BOOK],
+            &[COARSE_BOOK, MEDIUM_BOOK],
+        ];
+        for books in passes {
+            setup.write((1 << books.len()) - 1, 3);
+            setup.write(0, 1);
+        }
+        for books in passes {
+            for &book in books {
+                setup.write(book as u32, 8);
+            }
+        }
+        // A mapping of every channel to the floor and the residue, uncoupled.
+        setup.write(0, 6);
+        setup.write(0, 16);
+        setup.write(0, 4);
+        setup.write(0, 24);
+        // A single mode of long blocks.
+        setup.write(0, 6);
+        setup.write(1, 1);
+        setup.write(0, 40);
+        setup.write(1, 1);
+
+        [identification.bytes, comments.bytes, setup.bytes]
+    }
+
+    /// Adds a frame of a sample for each channel, and returns the packet of
+    /// the block it fills, if it does.
+    pub fn push(&mut self, frame: &[f64]) -> Option<Vec<u8>> {
+        for (block, &x) in self.blocks.iter_mut().zip(frame) {
+            block.push(x);
+        }
+        (self.blocks[0].len() == BLOCK).then(|| self.packet())
+    }
+
+    /// The packets that end the stream, padded with silence, after which
+    /// every frame pushed has been heard.
+    pub fn finish(&mut self) -> Vec<Vec<u8>> {
+        // The frames in the second half of the last block are heard with
+        // the next one, and those past it with the one after.
+        let count = if self.blocks[0].len() > HALF { 2 } else { 1 };
+        (0..count)
+            .map(|_| {
+                for block in &mut self.blocks {
+                    block.resize(BLOCK, 0.0);
+                }
+                self.packet()
+            })
+            .collect()
+    }
+
+    fn packet(&mut self) -> Vec<u8> {
+        let mut bits = Bits::default();
+        // An audio packet of the only mode, between long blocks.
+        bits.write(0, 1);
+        bits.write(0b11, 2);
+        let mut quantized = vec![];
+        for block in &mut self.blocks {
+            self.mdct.transform(block, &mut self.coefficients);
+            block.drain(..HALF);
+            let floor = floor(&self.books, &self.posts, &self.coefficients, &mut bits);
+            quantized.push(
+                self.coefficients
+                    .iter()
+                    .zip(&floor)
+                    .map(|(&x, &floor)| ((x / floor).round() as i32).clamp(-LARGEST, LARGEST))
+                    .collect::<Vec<i32>>(),
+            );
+        }
+        residue(&self.books, &quantized, &mut bits);
+        bits.bytes
+    }
+}
+
+/// Writes the floor of `coefficients` through `posts`, and returns it as
+/// decoders render it.
+fn floor(books: &[Codebook], posts: &[u16], coefficients: &[f64], bits: &mut Bits) -> Vec<f64> {
+    let mut sorted = posts.to_vec();
+    sorted.sort_unstable();
+    // The loudest coefficient from halfway to the post below to halfway to
+    // the one above, under the margin.
+    let quietest = db_amp(QUIETEST_DB);
+    let targets: Vec<i32> = posts
+        .iter()
+        .map(|&post| {
+            let i = sorted.binary_search(&post).unwrap();
+            let from = if i == 0 {
+                0
+            } else {
+                (sorted[i - 1] + post) / 2
+            };
+            let to = sorted
+                .get(i + 1)
+                .map_or(HALF as u16, |&next| (post + next) / 2);
+            let loudest = coefficients[from as usize..(to as usize).max(from as usize + 1)]
+                .iter()
+                .fold(0.0f64, |loudest, x| loudest.max(x.abs()));
+            let level = (loudest * db_amp(-MARGIN_DB)).max(quietest);
+            let index = 255.0 + level.ln() / FLOOR_STEP.ln();
+            ((index / MULTIPLIER as f64).round() as i32).clamp(0, FLOOR_RANGE as i32 - 1)
+        })
+        .collect();
+
+    bits.write(1, 1);
+    let value_bits = ilog(FLOOR_RANGE - 1);
+    bits.write(targets[0] as u32, value_bits);
+    bits.write(targets[1] as u32, value_bits);
+    let range = FLOOR_RANGE as i32;
+    // Each post is coded by how far it is from the line between the two it
+    // falls between, which are drawn through once a post is off it.
+    let mut drawn = vec![true, true];
+    drawn.resize(posts.len(), false);
+    for i in 2..posts.len() {
+        let (low, high) = neighbours(posts, i);
+        let x = |j: usize| posts[j] as i32;
+        let predicted = render_point(x(low), targets[low], x(high), targets[high], x(i));
+        let target = targets[i];
+        let (high_room, low_room) = (range - predicted, predicted);
+        let room = 2 * high_room.min(low_room);
+        let value = if target > predicted {
+            let up = 2 * (target - predicted);
+            if up < room {
+                up
+            } else {
+                target - predicted + low_room
+            }
+        } else if target < predicted {
+            let down = 2 * (predicted - target) - 1;
+            if down < room {
+                down
+            } else {
+                predicted - target + high_room - 1
+            }
+        } else {
+            0
+        };
+        if value != 0 {
+            drawn[low] = true;
+            drawn[high] = true;
+            drawn[i] = true;
+        }
+        bits.code(books[FLOOR_BOOK].code(value as usize));
+    }
+
+    let mut order: Vec<usize> = (0..posts.len()).collect();
+    order.sort_unstable_by_key(|&i| posts[i]);
+    let mut curve = vec![0; HALF];
+    let (mut x0, mut y0) = (0, targets[0] * MULTIPLIER as i32);
+    for &i in order[1..].iter().filter(|&&i| drawn[i]) {
+        let (x1, y1) = (posts[i] as i32, targets[i] * MULTIPLIER as i32);
+        render_line(x0, y0, x1, y1, &mut curve);
+        (x0, y0) = (x1, y1);
+    }
+    curve
+        .into_iter()
+        .map(|index| FLOOR_STEP.powi(index - 255))
+        .collect()
+}
+
+/// Writes the residue of the quantized coefficients of every channel, the
+/// classes of the partitions of all of them before the values of each
+/// pass, a partition of each channel at a time.
+fn residue(books: &[Codebook], quantized: &[Vec<i32>], bits: &mut Bits) {
+    let classes: Vec<Vec<usize>> = quantized
+        .iter()
+        .map(|coefficients| {
+            coefficients
+                .chunks(PARTITION)
+                .map(|partition| match partition.iter().map(|x| x.abs()).max() {
+                    Some(0) => 0,
+                    Some(1) => 1,
+                    Some(2..=4) => 2,
+                    _ => 3,
+                })
+                .collect()
+        })
+        .collect();
+    let partitions = HALF / PARTITION;
+    let per_word = books[CLASS_BOOK].dimensions;
+    for pass in 0..2 {
+        for first in (0..partitions).step_by(per_word) {
+            if pass == 0 {
+                for classes in &classes {
+                    let word = classes[first..first + per_word]
+                        .iter()
+                        .fold(0, |word, &class| word * CLASSES + class);
+                    bits.code(books[CLASS_BOOK].code(word));
+                }
+            }
+            for partition in first..first + per_word {
+                for (classes, coefficients) in classes.iter().zip(quantized) {
+                    let values = &coefficients[partition * PARTITION..][..PARTITION];
+                    let coarse = |x: i32| (x as f64 / COARSE as f64).round() as i32;
+                    let (book, values): (usize, Vec<i32>) = match (classes[partition], pass) {
+                        (1, 0) => (SMALL_BOOK, values.to_vec()),
+                        (2, 0) => (MEDIUM_BOOK, values.to_vec()),
+                        (3, 0) => (
+                            COARSE_BOOK,
+                            values.iter().map(|&x| coarse(x) * COARSE).collect(),
+                        ),
+                        (3, 1) => (
+                            MEDIUM_BOOK,
+                            values.iter().map(|&x| x - coarse(x) * COARSE).collect(),
+                        ),
+                        _ => continue,
+                    };
+                    let book = &books[book];
+                    for vector in values.chunks(book.dimensions) {
+                        book.vector(bits, vector);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Level metering of stereo buses. The audio thread publishes the RMS level
+//! and the held peak of a bus together in one atomic, so that the display
+//! never reads a half updated pair.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use fundsp::hacker::*;
+
+/// Time constant of the RMS average in seconds.
+const RMS_SECONDS: f64 = 0.3;
+/// Time a peak is held before it falls.
+const HOLD_SECONDS: f64 = 1.5;
+/// Fall of a held peak in decibels per second.
+const FALL_DB: f64 = 20.0;
+/// Quietest level shown.
+const FLOOR_DB: f64 = -60.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Level {
+    /// Amplitude of the RMS average, of the louder channel.
+    pub rms: f64,
+    /// Highest recent sample amplitude, held for a while.
+    pub peak: f64,
+}
+
+impl Level {
+    fn pack(self) -> u64 {
+        (self.rms as f32).to_bits() as u64 | ((self.peak as f32).to_bits() as u64) << 32
+    }
+
+    fn unpack(bits: u64) -> Self {
+        Self {
+            rms: f32::from_bits(bits as u32) as f64,
+            peak: f32::from_bits((bits >> 32) as u32) as f64,
+        }
+    }
+
+    /// Draws a bar `width` characters wide from -60 to 0 dB, filled to the RMS
+    /// level with the held peak marked by `|`, followed by the peak in decibels.
+    pub fn bar(self, width: usize) -> String {
+        let column = |amplitude: f64| {
+            let db = amp_db(amplitude).max(FLOOR_DB);
+            ((1.0 - db / FLOOR_DB) * width as f64).round() as usize
+        };
+        let (rms, peak) = (column(self.rms), column(self.peak));
+        let bar: String = (0..width)
+            .map(|i| match i {
+                i if i + 1 == max(peak, 1) && self.peak > 0.0 => '|',
+                i if i < rms => '#',
+                _ => '-',
+            })
+            .collect();
+        let clip = if self.peak >= 1.0 { '!' } else { ' ' };
+        format!(
+            "[{}]{}{:6.1} dB",
+            bar,
+            clip,
+            amp_db(self.peak).max(FLOOR_DB)
+        )
+    }
+}
+
+/// Reads the level of a bus measured by its meter unit.
+#[derive(Clone, Default)]
+pub struct VuMeter {
+    level: Arc<AtomicU64>,
+}
+
+impl VuMeter {
+    pub fn level(&self) -> Level {
+        Level::unpack(self.level.load(Ordering::Relaxed))
+    }
+
+    /// A unit measuring the stereo bus on inputs 0 and 1 and mixing it into
+    /// the stereo signal on inputs 2 and 3, so that meters can be chained into
+    /// a mix of many buses.
+    pub fn unit(&self) -> An<VuNode> {
+        let mut node = VuNode {
+            level: self.level.clone(),
+            square: [0.0; 2],
+            peak: 0.0,
+            held: 0,
+            rms_pole: 0.0,
+            fall: 0.0,
+            hold_samples: 0,
+        };
+        node.set_sample_rate(DEFAULT_SR);
+        An(node)
+    }
+}
+
+#[derive(Clone)]
+pub struct VuNode {
+    level: Arc<AtomicU64>,
+    /// Running mean squares of both channels.
+    square: [f64; 2],
+    peak: f64,
+    /// Samples left until the peak falls.
+    held: usize,
+    rms_pole: f64,
+    /// Gain applied to a falling peak per sample.
+    fall: f64,
+    hold_samples: usize,
+}
+
+impl AudioNode for VuNode {
+    const ID: u64 = 0x5675_4d74;
+    type Sample = f64;
+    type Inputs = U4;
+    type Outputs = U2;
+    type Setting = ();
+
+    fn reset(&mut self) {
+        self.square = [0.0; 2];
+        self.peak = 0.0;
+        self.held = 0;
+        self.level.store(Level::default().pack(), Ordering::Relaxed);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.rms_pole = exp(-1.0 / (RMS_SECONDS * sample_rate));
+        self.fall = db_amp(-FALL_DB / sample_rate);
+        self.hold_samples = (HOLD_SECONDS * sample_rate) as usize;
+    }
+
+    fn tick(&mut self, input: &Frame<f64, U4>) -> Frame<f64, U2> {
+        for channel in 0..2 {
+            let x = input[channel];
+            self.square[channel] = lerp(x * x, self.square[channel], self.rms_pole);
+            if x.abs() >= self.peak {
+                self.peak = x.abs();
+                self.held = self.hold_samples;
+            }
+        }
+        if self.held > 0 {
+            self.held -= 1;
+        } else {
+            self.peak *= self.fall;
+        }
+        let level = Level {
+            rms: self.square[0].max(self.square[1]).sqrt(),
+            peak: self.peak,
+        };
+        self.level.store(level.pack(), Ordering::Relaxed);
+        [input[0] + input[2], input[1] + input[3]].into()
+    }
+
+    fn route(&mut self, input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        let mut output = new_signal_frame(2);
+        output[0] = input[0].combine_linear(input[2], 0.0, |x, y| x + y, |x, y| x + y);
+        output[1] = input[1].combine_linear(input[3], 0.0, |x, y| x + y, |x, y| x + y);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_is_held_then_falls() {
+        let meter = VuMeter::default();
+        let mut unit = meter.unit();
+        unit.set_sample_rate(1000.0);
+        let output = unit.tick(&Frame::from([0.5, -0.75, 0.25, 0.25]));
+        assert_eq!((output[0], output[1]), (0.75, -0.5));
+        assert_eq!(meter.level().peak, 0.75);
+
+        for _ in 0..1000 {
+            unit.tick(&Frame::from([0.1, 0.1, 0.0, 0.0]));
+        }
+        let level = meter.level();
+        assert_eq!(level.peak, 0.75);
+        assert!((level.rms - 0.1).abs() < 0.01);
+        for _ in 0..1000 {
+            unit.tick(&Frame::from([0.0; 4]));
+        }
+        assert!(meter.level().peak < 0.75 * db_amp(-FALL_DB * 0.4));
+    }
+
+    #[test]
+    fn test_bar_marks_peak() {
+        let level = Level {
+            rms: db_amp(-30.0),
+            peak: db_amp(-6.0),
+        };
+        assert_eq!(level.bar(10), "[#####---|-]   -6.0 dB");
+        assert!(Level {
+            rms: 1.0,
+            peak: 1.2
+        }
+        .bar(4)
+        .contains('!'));
+    }
+}
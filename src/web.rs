@@ -0,0 +1,30 @@
+//! Browser build: the song rendered from a WebAudio worklet through plain
+//! WebAssembly exports, in place of the cpal stream of the native player.
+//!
+//! Build it with
+//! `cargo build --lib --release --target wasm32-unknown-unknown --features web`
+//! and serve `wasm/` together with `target/wasm32-unknown-unknown/release/playground.wasm`.
+
+use std::cell::RefCell;
+
+use crate::engine::Engine;
+
+thread_local! {
+    static ENGINE: RefCell<Option<Engine>> = const { RefCell::new(None) };
+}
+
+/// Starts the song from the beginning.
+#[no_mangle]
+pub extern "C" fn playground_start(sample_rate: f64, bpm: f64) {
+    ENGINE.with(|engine| *engine.borrow_mut() = Some(Engine::new(sample_rate, bpm)));
+}
+
+/// Renders `frames` interleaved stereo frames and returns where they are in
+/// memory. They stay valid until the next call.
+#[no_mangle]
+pub extern "C" fn playground_render(frames: usize) -> *const f32 {
+    ENGINE.with(|engine| match engine.borrow_mut().as_mut() {
+        Some(engine) => engine.render(frames).as_ptr(),
+        None => std::ptr::null(),
+    })
+}
@@ -0,0 +1,266 @@
+//! WebSocket endpoint that broadcasts engine state and accepts JSON commands.
+//! The web UI is served over HTTP on the address given, and connects back to
+//! the WebSocket on the port after it.
+//!
+//! Clients send the requests of the JSON server as text messages, and receive
+//! a state message after every bar and note:
+//! `{"beat":12.5,"bpm":120,"level":0.31,"notes":[60,64,67],"chord":"C"}`.
+//! Browsers connect only from the web UI served here, so that other pages
+//! open in them can't drive the engine.
+
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use tiny_http::{Header, Response, Server};
+use tungstenite::handshake::server::{ErrorResponse, Request};
+use tungstenite::http::StatusCode;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+use crate::control::Command;
+use crate::server::{parse_request, response};
+use playground::json::Value;
+
+/// The web UI, a single page without dependencies, which is told the port
+/// of the WebSocket in place of its placeholder.
+const INDEX: &str = include_str!("web/index.html");
+const PORT_PLACEHOLDER: &str = "SOCKET_PORT";
+/// Longest a client waits for a message before the states sent to it are.
+const POLL: Duration = Duration::from_millis(5);
+/// States queued for a client, beyond which it has stalled.
+const QUEUE: usize = 64;
+/// Largest message accepted from a client.
+const MAX_MESSAGE: usize = 64 * 1024;
+/// Snapshot of the engine that is broadcast to clients.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct State {
+    pub beat: f64,
+    pub bpm: f64,
+    /// Smoothed peak level of the main output.
+    pub level: f64,
+    /// MIDI keys of the live notes held down.
+    pub notes: Vec<u8>,
+    /// Name of the chord they form, if any.
+    pub chord: Option<String>,
+}
+
+impl State {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("beat".into(), Value::Number(self.beat)),
+            ("bpm".into(), Value::Number(self.bpm)),
+            ("level".into(), Value::Number(self.level)),
+            (
+                "notes".into(),
+                Value::Array(
+                    self.notes
+                        .iter()
+                        .map(|&key| Value::Number(key as f64))
+                        .collect(),
+                ),
+            ),
+            (
+                "chord".into(),
+                self.chord.clone().map_or(Value::Null, Value::String),
+            ),
+        ])
+    }
+}
+
+/// Sends state messages to every connected client.
+#[derive(Clone, Default)]
+pub struct Broadcast {
+    clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+}
+
+impl Broadcast {
+    /// Queues `state` for every client, dropping those that went away or
+    /// stalled, which then hang up. It never blocks on a client.
+    pub fn send(&self, state: &State) {
+        let text = state.to_json().to_string();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|client| match client.try_send(text.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+/// Serves the web UI on `address` and accepts WebSocket clients on the
+/// port after it, on background threads.
+pub fn spawn(address: &str, sender: Sender<Command>) -> Result<Broadcast, anyhow::Error> {
+    let server = Server::http(address).map_err(|err| anyhow!("{}: {}", address, err))?;
+    let page = server
+        .server_addr()
+        .to_ip()
+        .ok_or_else(|| anyhow!("the web UI needs an IP address, not {}", address))?;
+    let listener = TcpListener::bind(SocketAddr::new(page.ip(), page.port() + 1))?;
+    eprintln!("web ui on http://{}", page);
+    let index = INDEX.replace(PORT_PLACEHOLDER, &(page.port() + 1).to_string());
+    std::thread::spawn(move || {
+        let html = Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap();
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/" => Response::from_string(index.clone()).with_header(html.clone()),
+                _ => Response::from_string("not found").with_status_code(404),
+            };
+            let _ = request.respond(response);
+        }
+    });
+    let broadcast = Broadcast::default();
+    let clients = broadcast.clone();
+    std::thread::spawn(move || accept(listener, page.port(), clients, sender));
+    Ok(broadcast)
+}
+
+/// Upgrades the connections to `listener` from the web UI on `page_port`,
+/// or from clients other than browsers, and serves each on a thread.
+fn accept(listener: TcpListener, page_port: u16, broadcast: Broadcast, sender: Sender<Command>) {
+    let config = WebSocketConfig::default().max_message_size(Some(MAX_MESSAGE));
+    for stream in listener.incoming().flatten() {
+        let sender = sender.clone();
+        let clients = broadcast.clients.clone();
+        std::thread::spawn(move || -> Result<(), anyhow::Error> {
+            // The refusal is as large as tungstenite makes it.
+            #[allow(clippy::result_large_err)]
+            let check = |request: &Request, response| {
+                let header = |name| {
+                    request
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                };
+                if is_same_origin(header("origin"), header("host"), page_port) {
+                    Ok(response)
+                } else {
+                    let mut refusal = ErrorResponse::new(None);
+                    *refusal.status_mut() = StatusCode::FORBIDDEN;
+                    Err(refusal)
+                }
+            };
+            let socket = tungstenite::accept_hdr_with_config(stream, check, Some(config))
+                .map_err(|err| anyhow!("{}", err))?;
+            // Reads time out for the states queued to be sent in between.
+            socket.get_ref().set_read_timeout(Some(POLL))?;
+            let (states, queued) = std::sync::mpsc::sync_channel(QUEUE);
+            clients.lock().unwrap().push(states);
+            serve(socket, queued, sender)
+        });
+    }
+}
+
+/// Whether a WebSocket request to `host` comes from a page on `page_port`
+/// of the same host, or from a client other than a browser, which sends no
+/// origin.
+fn is_same_origin(origin: Option<&str>, host: Option<&str>, page_port: u16) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+    let origin = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"));
+    let name = host
+        .and_then(|host| host.rsplit_once(':'))
+        .map(|(name, _)| name);
+    match (origin, name) {
+        (Some(origin), Some(name)) => origin == format!("{}:{}", name, page_port),
+        _ => false,
+    }
+}
+
+/// Answers the requests of a client and sends it the states queued, until
+/// either hangs up.
+fn serve(
+    mut socket: WebSocket<TcpStream>,
+    queued: Receiver<String>,
+    sender: Sender<Command>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        loop {
+            match queued.try_recv() {
+                Ok(state) => socket.send(Message::text(state))?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    socket.close(None)?;
+                    return Ok(());
+                }
+            }
+        }
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let result = match parse_request(&text) {
+            Ok(command) => {
+                sender.send(command)?;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        };
+        socket.send(Message::text(response(result).to_string()))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::client::IntoClientRequest;
+
+    #[test]
+    fn test_same_origin() {
+        let host = Some("localhost:8081");
+        assert!(is_same_origin(Some("http://localhost:8080"), host, 8080));
+        assert!(is_same_origin(None, host, 8080));
+        assert!(!is_same_origin(Some("http://localhost:8081"), host, 8080));
+        assert!(!is_same_origin(Some("https://example.com"), host, 8080));
+        let spoofed = Some("http://localhost:8080.example.com");
+        assert!(!is_same_origin(spoofed, host, 8080));
+        assert!(!is_same_origin(Some("null"), host, 8080));
+        assert!(!is_same_origin(Some("http://localhost:8080"), None, 8080));
+    }
+
+    #[test]
+    fn test_answers_and_broadcasts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let broadcast = Broadcast::default();
+        let (sender, commands) = std::sync::mpsc::channel();
+        let clients = broadcast.clone();
+        std::thread::spawn(move || accept(listener, 8080, clients, sender));
+        let url = format!("ws://{}/", address);
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        let origin = "http://example.com".parse().unwrap();
+        request.headers_mut().insert("origin", origin);
+        let stream = TcpStream::connect(address).unwrap();
+        assert!(tungstenite::client(request, stream).is_err());
+
+        let stream = TcpStream::connect(address).unwrap();
+        let (mut socket, _) = tungstenite::client(url.as_str(), stream).unwrap();
+        socket
+            .send(Message::text(r#"{"command":"bpm","value":100}"#))
+            .unwrap();
+        assert_eq!(socket.read().unwrap(), Message::text(r#"{"ok":true}"#));
+        assert_eq!(commands.recv().unwrap(), Command::Bpm(100.0));
+        let state = State {
+            bpm: 100.0,
+            ..State::default()
+        };
+        broadcast.send(&state);
+        assert_eq!(
+            socket.read().unwrap(),
+            Message::text(state.to_json().to_string())
+        );
+    }
+}
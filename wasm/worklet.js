@@ -0,0 +1,23 @@
+// Renders the song from the WebAssembly module passed in the processor options.
+class PlaygroundProcessor extends AudioWorkletProcessor {
+  constructor(options) {
+    super();
+    const { module, bpm } = options.processorOptions;
+    this.exports = new WebAssembly.Instance(module, {}).exports;
+    this.exports.playground_start(sampleRate, bpm);
+  }
+
+  process(inputs, outputs) {
+    const [left, right] = outputs[0];
+    const frames = left.length;
+    const pointer = this.exports.playground_render(frames);
+    const samples = new Float32Array(this.exports.memory.buffer, pointer, frames * 2);
+    for (let i = 0; i < frames; i++) {
+      left[i] = samples[2 * i];
+      right[i] = samples[2 * i + 1];
+    }
+    return true;
+  }
+}
+
+registerProcessor("playground", PlaygroundProcessor);